@@ -0,0 +1,26 @@
+//! Times scanning, parsing, and evaluating each of the [`jon::bench`]
+//! workloads.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use jon::bench::{self, Phase};
+use jon::Interpreter;
+
+fn pipeline(c: &mut Criterion) {
+    for workload in bench::workloads() {
+        let mut group = c.benchmark_group(workload.name);
+        for phase in Phase::ALL {
+            group.bench_function(phase.name(), |b| {
+                // a fresh interpreter for each run, built outside the timing
+                b.iter_batched_ref(
+                    Interpreter::new,
+                    |interpreter| bench::run(phase, &workload.source, interpreter).unwrap(),
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
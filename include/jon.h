@@ -0,0 +1,45 @@
+/* C API of the jon interpreter, built into libjon with
+ * `cargo rustc --lib --release --features capi --crate-type cdylib`.
+ * Values cross as jon source text. */
+
+#ifndef JON_H
+#define JON_H
+
+#include <stdbool.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct JonInterpreter JonInterpreter;
+typedef struct JonResult JonResult;
+
+/* A host function. `args` is the printed list of arguments, such as
+ * `(1 "a")`. Returns the jon text of the result, which must stay valid
+ * until the next call, or NULL to fail the call. */
+typedef const char *(*JonCallback)(void *user_data, const char *args);
+
+/* An interpreter with the prelude loaded, freed with jon_free. */
+JonInterpreter *jon_new(void);
+void jon_free(JonInterpreter *jon);
+
+/* Evaluates UTF-8 `source`. The result, freed with jon_result_free, holds
+ * the printed value of the last form or a rendered error. NULL if an
+ * argument is NULL. */
+JonResult *jon_eval(JonInterpreter *jon, const char *source);
+bool jon_result_is_error(const JonResult *result);
+/* A copy of the result's text, freed with jon_string_free. */
+char *jon_result_to_string(const JonResult *result);
+void jon_result_free(JonResult *result);
+void jon_string_free(char *text);
+
+/* Binds `name` to `callback`, passed `user_data` on every call. Returns
+ * false if `name` is NULL or not UTF-8. */
+bool jon_register_fn(JonInterpreter *jon, const char *name,
+                     JonCallback callback, void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* JON_H */
@@ -0,0 +1,187 @@
+//! Actors behind the `actor`, `send!`, and `ask` builtins. An actor is a
+//! handler and a state on a thread of their own, in an isolated copy of the
+//! interpreter that made it. It takes messages from its mailbox one at a
+//! time, calling `(handler state message)` for each and keeping the result
+//! as its new state, so scripts share no data with it and need no locks.
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::eval::{EvalError, Interpreter, STACK_SIZE};
+use crate::isolated::Isolated;
+use crate::parallel::is_data;
+use crate::value::Value;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// A handle to an actor, printed as `#<actor N>`. Copies address the same
+/// actor, which runs until every copy is dropped or its handler fails.
+#[derive(Clone)]
+pub struct Actor {
+    id: usize,
+    mailbox: Sender<Envelope>,
+}
+
+/// A value that holds no lambda, and so no `Rc`, checked by [`Data::new`].
+struct Data(Value);
+
+// SAFETY: data reaches no `Rc` or `RefCell`. Host functions in it are
+// `Send + Sync`, as are host values, and actor handles hold only a
+// `Sender`.
+unsafe impl Send for Data {}
+
+impl Data {
+    fn new(value: Value) -> Result<Self, EvalError> {
+        if is_data(&value) {
+            Ok(Self(value))
+        } else {
+            Err(EvalError::Host(
+                "values sent between actors cannot hold functions".into(),
+            ))
+        }
+    }
+}
+
+/// A message, with where to send the actor's new state for `ask`.
+struct Envelope {
+    message: Data,
+    reply: Option<Sender<Result<Data, EvalError>>>,
+}
+
+impl Actor {
+    /// Starts an actor with the state `init` that handles messages with
+    /// `handler`, both copied along with the interpreter's globals.
+    pub(crate) fn spawn(
+        interpreter: &Interpreter,
+        init: Value,
+        handler: Value,
+    ) -> Result<Self, EvalError> {
+        if !matches!(handler, Value::Builtin(_) | Value::Lambda(_)) {
+            return Err(EvalError::TypeMismatch {
+                expected: "function",
+                found: handler.type_name(),
+            });
+        }
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let (mailbox, inbox) = mpsc::channel();
+        let job = interpreter.clone_isolated_with(&[handler, init]);
+        thread::Builder::new()
+            .name(format!("jon actor {}", id))
+            .stack_size(STACK_SIZE)
+            .spawn(move || run(job, inbox))
+            .map_err(|err| EvalError::Host(format!("cannot start an actor: {}", err)))?;
+        Ok(Self { id, mailbox })
+    }
+
+    /// Puts `message` in the mailbox without waiting for it to be handled.
+    pub(crate) fn send(&self, message: Value) -> Result<(), EvalError> {
+        self.post(message, None)
+    }
+
+    /// Sends `message` and waits up to `timeout` for the state the actor
+    /// is left in after handling it.
+    pub(crate) fn ask(&self, message: Value, timeout: Duration) -> Result<Value, EvalError> {
+        let (reply, answer) = mpsc::channel();
+        self.post(message, Some(reply))?;
+        match answer.recv_timeout(timeout) {
+            Ok(state) => state.map(|Data(state)| state),
+            Err(RecvTimeoutError::Timeout) => Err(EvalError::Host(format!(
+                "{:?} did not answer in time",
+                self
+            ))),
+            Err(RecvTimeoutError::Disconnected) => Err(self.stopped()),
+        }
+    }
+
+    fn post(
+        &self,
+        message: Value,
+        reply: Option<Sender<Result<Data, EvalError>>>,
+    ) -> Result<(), EvalError> {
+        let message = Data::new(message)?;
+        self.mailbox
+            .send(Envelope { message, reply })
+            .map_err(|_| self.stopped())
+    }
+
+    fn stopped(&self) -> EvalError {
+        EvalError::Host(format!("{:?} has stopped", self))
+    }
+}
+
+impl fmt::Debug for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#<actor {}>", self.id)
+    }
+}
+
+impl PartialEq for Actor {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+/// The actor's thread: handles messages until every handle is dropped, or
+/// stops at the first error, which an `ask` of that message receives.
+fn run(job: Isolated, inbox: Receiver<Envelope>) {
+    let (mut interpreter, values) = job.into_parts();
+    let mut values = values.into_iter();
+    let handler = values.next().unwrap();
+    let mut state = values.next().unwrap();
+    for Envelope { message, reply } in inbox {
+        let result = interpreter.apply(&handler, vec![state, message.0]);
+        let failed = result.is_err();
+        state = match &result {
+            Ok(next) => next.clone(),
+            Err(_) => Value::Nil,
+        };
+        if let Some(reply) = reply {
+            // the asker may have given up waiting
+            let _ = reply.send(result.and_then(Data::new));
+        }
+        if failed {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_actor() {
+        let mut x = Interpreter::new();
+        x.eval_str("(def counter (actor 0 (lambda (n step) (+ n step))))")
+            .unwrap();
+        assert_eq!(
+            x.eval_str("(send! counter 2) (send! counter 3) (ask counter 0 5)"),
+            Ok(Value::Number(5.0))
+        );
+        assert_eq!(
+            x.eval_str("(list (= counter counter) (ask counter 1 5))"),
+            x.eval_str("(list true 6)")
+        );
+        assert!(x
+            .eval_str("(send! counter (lambda () 1))")
+            .is_err_and(|err| err.to_string().contains("cannot hold functions")));
+
+        // an actor asking itself waits on its own mailbox, so never hears back
+        x.eval_str("(def echo (actor nil (lambda (_ self) (ask self 'x 0.01))))")
+            .unwrap();
+        let Ok(Value::Actor(echo)) = x.eval_str("echo") else {
+            unreachable!()
+        };
+        assert_eq!(
+            x.eval_str("(ask echo echo 5)"),
+            Err(EvalError::Host(format!("{:?} did not answer in time", echo)).into())
+        );
+        assert_eq!(
+            x.eval_str("(ask echo 1 5)"),
+            Err(EvalError::Host(format!("{:?} has stopped", echo)).into())
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! A record of every native function a script called, builtin or
+//! registered by the host, for reviewing what third-party code did. Add an
+//! [`AuditLog`] with `Interpreter::add_hook`. Calls `pmap` makes on other
+//! threads are not recorded; they only reach pure builtins.
+
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crate::eval::{EvalError, Hook, Interpreter};
+use crate::parser::push_json_string;
+use crate::value::Value;
+
+/// How many characters of an argument a record keeps.
+const PREVIEW_WIDTH: usize = 40;
+
+/// One call to a native function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub name: String,
+    /// The arguments as they print, cut short past 40 characters.
+    pub args: Vec<String>,
+    pub duration: Duration,
+    /// The error the call failed with, if it did.
+    pub error: Option<String>,
+}
+
+impl Call {
+    /// The call as a line of JSON, such as
+    /// `{"name":"load","args":["\"a.jon\""],"micros":120,"error":null}`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"name\":");
+        push_json_string(&mut json, &self.name);
+        json.push_str(",\"args\":[");
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            push_json_string(&mut json, arg);
+        }
+        json.push_str(&format!(
+            "],\"micros\":{},\"error\":",
+            self.duration.as_micros()
+        ));
+        match &self.error {
+            Some(error) => push_json_string(&mut json, error),
+            None => json.push_str("null"),
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// Where an [`AuditLog`] sends its records.
+enum Sink {
+    /// A line of JSON per call.
+    Writer(Box<dyn Write>),
+    Host(Box<dyn FnMut(&Call)>),
+}
+
+/// A hook recording each call to a native function.
+pub struct AuditLog {
+    sink: Sink,
+    /// For each call being evaluated, its arguments and when it started if
+    /// it is native.
+    calls: Vec<Option<(Vec<String>, Instant)>>,
+}
+
+impl AuditLog {
+    /// Writes each call to `output` as a line of JSON.
+    pub fn writer(output: impl Write + 'static) -> Self {
+        Self::with_sink(Sink::Writer(Box::new(output)))
+    }
+
+    /// Gives each call to `callback` once it finishes.
+    pub fn host(callback: impl FnMut(&Call) + 'static) -> Self {
+        Self::with_sink(Sink::Host(Box::new(callback)))
+    }
+
+    fn with_sink(sink: Sink) -> Self {
+        Self {
+            sink,
+            calls: Vec::new(),
+        }
+    }
+}
+
+impl Hook for AuditLog {
+    fn enter_call(&mut self, _interpreter: &mut Interpreter, callee: &Value, args: &[Value]) {
+        let call = matches!(callee, Value::Builtin(_)).then(|| {
+            let args = args.iter().map(|arg| arg.preview(PREVIEW_WIDTH)).collect();
+            (args, Instant::now())
+        });
+        self.calls.push(call);
+    }
+
+    fn exit_call(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        callee: &Value,
+        result: &Result<Value, EvalError>,
+    ) {
+        let (Some(Some((args, start))), Value::Builtin(builtin)) = (self.calls.pop(), callee)
+        else {
+            return;
+        };
+        let call = Call {
+            name: builtin.name.into(),
+            args,
+            duration: start.elapsed(),
+            error: result.as_ref().err().map(ToString::to_string),
+        };
+        match &mut self.sink {
+            Sink::Writer(output) => {
+                let _ = writeln!(output, "{}", call.to_json());
+            }
+            Sink::Host(callback) => callback(&call),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn test_audit_native_calls() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let seen = calls.clone();
+        let mut interpreter = Interpreter::new();
+        interpreter.register_fn("host/fetch", |_, _| Ok(Value::Nil));
+        interpreter.add_hook(Box::new(AuditLog::host(move |call| {
+            seen.borrow_mut()
+                .push((call.name.clone(), call.args.clone(), call.error.clone()))
+        })));
+        let _ =
+            interpreter.eval_str("(def (twice x) (* 2 x)) (host/fetch \"a\" (twice 3)) (car 1)");
+        assert_eq!(
+            *calls.borrow(),
+            [
+                (
+                    "*".to_string(),
+                    vec!["2".to_string(), "3".to_string()],
+                    None
+                ),
+                ("host/fetch".into(), vec!["\"a\"".into(), "6".into()], None),
+                (
+                    "car".into(),
+                    vec!["1".into()],
+                    Some("Expected list, found number".into())
+                ),
+            ]
+        );
+        let call = Call {
+            name: "load".into(),
+            args: vec!["\"a.jon\"".into()],
+            duration: Duration::from_micros(120),
+            error: None,
+        };
+        assert_eq!(
+            call.to_json(),
+            r#"{"name":"load","args":["\"a.jon\""],"micros":120,"error":null}"#
+        );
+    }
+}
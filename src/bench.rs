@@ -0,0 +1,125 @@
+//! Workloads for measuring the scanner, parser, and evaluator, shared by
+//! the criterion benchmarks in `benches/` and `jon bench`, so that changes
+//! to how values or environments are represented can be compared on the
+//! same programs.
+
+use alloc::{format, string::String, vec::Vec};
+
+use crate::error::Error;
+use crate::eval::Interpreter;
+use crate::parser::Parser;
+use crate::scanner::{ScanError, Scanner};
+
+/// A representative program, which evaluates without output. Lists are
+/// walked with `loop`, as the recursive prelude functions would overflow
+/// the stack on them.
+pub struct Workload {
+    pub name: &'static str,
+    pub source: String,
+}
+
+/// A stage of the pipeline a workload is run through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Scan,
+    Parse,
+    Eval,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 3] = [Phase::Scan, Phase::Parse, Phase::Eval];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Scan => "scan",
+            Phase::Parse => "parse",
+            Phase::Eval => "eval",
+        }
+    }
+}
+
+/// Deeply nested and long lists, a tight arithmetic loop with recursion,
+/// and string-heavy data.
+pub fn workloads() -> Vec<Workload> {
+    let deep = format!("{}1{}", "(".repeat(200), ")".repeat(200));
+    let wide: Vec<String> = (0..1000).map(|n| format!("(item {} {}.5)", n, n)).collect();
+    let words: Vec<String> = (0..200).map(|n| format!("\"word-{}\"", n)).collect();
+    Vec::from([
+        Workload {
+            name: "lists",
+            source: format!(
+                "(def deep '{})
+                 (def wide '({}))
+                 (loop ((xs wide) (sum 0))
+                   (if (empty? xs) sum (recur (cdr xs) (+ sum (second (car xs))))))",
+                deep,
+                wide.join("\n  ")
+            ),
+        },
+        Workload {
+            name: "arithmetic",
+            source: String::from(
+                "(def (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+                 (fib 15)
+                 (loop ((i 0) (sum 0))
+                   (if (= i 10000) sum (recur (inc i) (- (* i 2) sum))))",
+            ),
+        },
+        Workload {
+            name: "strings",
+            source: format!(
+                "(def words '({}))
+                 (loop ((cs (string->list (apply string/append words))) (n 0))
+                   (if (empty? cs) n (recur (cdr cs) (if (= (car cs) \"-\") (inc n) n))))
+                 (loop ((ws words) (symbols '()))
+                   (if (empty? ws)
+                       symbols
+                       (recur (cdr ws) (cons (string->symbol (car ws)) symbols))))",
+                words.join(" ")
+            ),
+        },
+    ])
+}
+
+/// Runs `phase` over `source` once. Only [`Phase::Eval`] uses
+/// `interpreter`, in which definitions made by earlier runs remain.
+pub fn run(phase: Phase, source: &str, interpreter: &mut Interpreter) -> Result<(), Error> {
+    match phase {
+        Phase::Scan => {
+            let mut scanner = Scanner::new(source);
+            loop {
+                match scanner.next_token() {
+                    Ok(_) => {}
+                    Err(ScanError::Eof) => return Ok(()),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+        Phase::Parse => {
+            Parser::new(&mut Scanner::new(source)).parse_forms()?;
+            Ok(())
+        }
+        Phase::Eval => interpreter.eval_str(source).map(|_| ()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workloads_run() {
+        let mut interpreter = Interpreter::new();
+        for workload in workloads() {
+            for phase in Phase::ALL {
+                assert_eq!(
+                    run(phase, &workload.source, &mut interpreter),
+                    Ok(()),
+                    "{} {}",
+                    workload.name,
+                    phase.name()
+                );
+            }
+        }
+    }
+}
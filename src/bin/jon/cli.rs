@@ -0,0 +1,740 @@
+use jon::capability::Capability;
+use jon::formatter;
+use jon::logging::Level;
+
+use crate::manifest::Manifest;
+use crate::packages::Package;
+
+pub const USAGE: &str = "\
+usage: jon [OPTIONS] [COMMAND]
+
+commands:
+  repl              start the interactive REPL (the default)
+  run [FILE]        run a script, the main file of jon.toml by default;
+                    FILE may be - for standard input
+  run --literate FILE
+                    run the ```jon code blocks of the Markdown file FILE
+      --write       write what each block printed and returned under it
+  eval EXPR         evaluate EXPR and print the result
+  check [FILE...]   report unbound symbols and arity errors without running,
+                    in the source files of jon.toml by default
+      --lint        also warn about likely mistakes, configured in .jonlint
+  ast FILE          print the parsed forms of FILE instead of running it
+      --json        as a JSON array, with each form's span, line, and column
+  fmt [FILE...]     format files in place, or standard input to standard output
+      --check       list files that would change and fail instead of writing
+      --width N     wrap lines longer than N columns (default 80)
+  image OUT [FILE...]
+                    write an image of the prelude with FILEs loaded to OUT
+  test [PATH...]    run the deftest tests of each *_test.jon file in PATHs
+                    (default the tests of jon.toml, or tests), each file in
+                    a fresh interpreter
+      --coverage    also report which forms of the files loaded never ran
+      --lcov FILE   also write the coverage to FILE in lcov format
+  doc PATH...       write Markdown reference pages from the docstrings of
+                    the .jon files in PATHs, searching directories
+      --out DIR     write the pages to DIR (default docs)
+      --html        write HTML pages instead of Markdown
+  build FILE        write an executable that runs FILE with its arguments
+                    bound to *args*, needing no jon installed
+      -o OUT        name it OUT (default FILE without its extension)
+  compile FILE      write FILE as a Rust program using jon as a library
+                    (experimental; see jon::compile for what it takes)
+      --target rust the language to write, only Rust for now
+      -o OUT        write it to OUT instead of standard output
+  nrepl             serve editors over the nREPL protocol on localhost
+      --port N      listen on port N (default any free port, printed)
+  --jupyter-kernel FILE
+                    serve as a Jupyter kernel, connecting as the connection
+                    file FILE says (needs the jupyter feature)
+  --jupyter-install register jon as a Jupyter kernel for the current user
+  add SOURCE        add the jon library at SOURCE, a git URL or a directory,
+                    to the dependencies of jon.toml and fetch it
+      --name NAME   call it NAME (default the last part of SOURCE)
+      --rev REV     use the commit, branch, or tag REV of a git library
+  bench [FILTER]    time scanning, parsing, and evaluating the built-in
+                    workloads, those whose name contains FILTER if given
+
+  jon FILE and jon -e EXPR are shorthands for run and eval. The jon.toml
+  of the project, found in the current directory or above it, also names
+  directories load searches, files loaded after the prelude, and lints.
+
+options:
+  --no-prelude      start without the jon prelude
+  --image FILE      start from the globals saved in FILE by jon image
+                    instead of loading the prelude
+  --deny CAPS       keep the program from using the capabilities in the
+                    comma-separated list CAPS, of fs, net, env, and proc
+  --no-init         start the REPL without loading ~/.config/jon/init.jon
+  --show-types      show the type of each REPL result
+  --print-width N   break REPL results wider than N columns across lines
+                    (default 80)
+  --time            print how long each evaluation took
+  --cache-dir DIR   keep the parsed forms of files run or loaded in DIR
+                    instead of ~/.cache/jon
+  --no-cache        parse every file, without reading or writing the cache
+  --debug           step through the program in the debugger from the start
+  --trace           log every function call and its result to stderr
+  --log-level LEVEL log messages from log/debug, log/info, and log/warn at
+                    LEVEL or above: debug, info (the default), or warn
+  --log-file FILE   append log messages to FILE instead of stderr
+  --tokens          print the token stream of the program instead of running it
+  --ast             print the parsed forms of the program instead of running it
+  -h, --help        print this message
+  -V, --version     print the version";
+
+/// Flags shared by every command.
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub no_prelude: bool,
+    pub no_init: bool,
+    pub show_types: bool,
+    /// The width REPL results are broken to, 80 if `None`.
+    pub print_width: Option<usize>,
+    pub time: bool,
+    pub debug: bool,
+    pub trace: bool,
+    pub dump: Option<Dump>,
+    pub cache_dir: Option<String>,
+    pub no_cache: bool,
+    pub image: Option<String>,
+    /// The capabilities the program may not use.
+    pub deny: Vec<Capability>,
+    pub log_level: Option<Level>,
+    pub log_file: Option<String>,
+    /// The arguments of the program, bound to `*args*`.
+    pub args: Vec<String>,
+    /// The project's `jon.toml`, which the caller reads.
+    pub manifest: Option<Manifest>,
+    /// The libraries the project depends on, which the caller resolves.
+    pub packages: Vec<Package>,
+}
+
+/// What `run` and `eval` print instead of evaluating the program.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dump {
+    Tokens,
+    Ast,
+    AstJson,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Repl,
+    Run(String),
+    /// `jon run` without a file, which runs the main file of `jon.toml`.
+    RunMain,
+    Literate(Literate),
+    Eval(String),
+    Check(Check),
+    Fmt(Fmt),
+    Image(Image),
+    Doc(Doc),
+    Build(Build),
+    Compile(Compile),
+    Test(Test),
+    Add(Add),
+    /// `jon nrepl`, with the port to listen on, 0 for any.
+    Nrepl(u16),
+    /// `jon --jupyter-kernel`, with the connection file.
+    Jupyter(String),
+    JupyterInstall,
+    /// `jon bench`, with the filter on workload names.
+    Bench(Option<String>),
+    Help,
+    Version,
+}
+
+/// Arguments of `jon run --literate`.
+#[derive(Debug, PartialEq)]
+pub struct Literate {
+    pub path: String,
+    pub write: bool,
+}
+
+/// Arguments of `jon check`.
+#[derive(Debug, PartialEq)]
+pub struct Check {
+    /// The files to check, none for the source files of `jon.toml`.
+    pub files: Vec<String>,
+    pub lint: bool,
+}
+
+/// Arguments of `jon image`.
+#[derive(Debug, PartialEq)]
+pub struct Image {
+    pub output: String,
+    pub files: Vec<String>,
+}
+
+/// Arguments of `jon test`.
+#[derive(Debug, PartialEq)]
+pub struct Test {
+    /// The files and directories to search for tests, none for those
+    /// `jon.toml` names or `tests`.
+    pub paths: Vec<String>,
+    pub coverage: bool,
+    pub lcov: Option<String>,
+}
+
+/// Arguments of `jon add`.
+#[derive(Debug, PartialEq)]
+pub struct Add {
+    /// A git URL or a directory.
+    pub source: String,
+    pub name: Option<String>,
+    pub rev: Option<String>,
+}
+
+/// Arguments of `jon build`.
+#[derive(Debug, PartialEq)]
+pub struct Build {
+    pub script: String,
+    pub output: String,
+}
+
+/// Arguments of `jon compile`.
+#[derive(Debug, PartialEq)]
+pub struct Compile {
+    pub script: String,
+    /// Standard output if `None`.
+    pub output: Option<String>,
+}
+
+/// Arguments of `jon doc`.
+#[derive(Debug, PartialEq)]
+pub struct Doc {
+    pub paths: Vec<String>,
+    pub out: String,
+    pub html: bool,
+}
+
+/// Arguments of `jon fmt`.
+#[derive(Debug, PartialEq)]
+pub struct Fmt {
+    pub files: Vec<String>,
+    pub check: bool,
+    pub width: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    /// `None` when no command was given, leaving the choice between the
+    /// REPL and reading a piped program to the caller.
+    pub command: Option<Command>,
+    pub options: Options,
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Cli, String> {
+    let mut options = Options::default();
+    let mut command = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let next = match arg.as_str() {
+            "--no-prelude" => {
+                options.no_prelude = true;
+                continue;
+            }
+            "--no-init" => {
+                options.no_init = true;
+                continue;
+            }
+            "--show-types" => {
+                options.show_types = true;
+                continue;
+            }
+            "--time" => {
+                options.time = true;
+                continue;
+            }
+            "--print-width" => {
+                let width = args
+                    .next()
+                    .and_then(|width| width.parse().ok())
+                    .ok_or("--print-width needs a number")?;
+                options.print_width = Some(width);
+                continue;
+            }
+            "--debug" => {
+                options.debug = true;
+                continue;
+            }
+            "--trace" => {
+                options.trace = true;
+                continue;
+            }
+            "--cache-dir" => {
+                let dir = args.next().ok_or("--cache-dir needs a directory")?;
+                options.cache_dir = Some(dir);
+                continue;
+            }
+            "--image" => {
+                options.image = Some(args.next().ok_or("--image needs a file")?);
+                continue;
+            }
+            "--deny" => {
+                let names = args.next().ok_or("--deny needs a list of capabilities")?;
+                for name in names.split(',') {
+                    let capability = Capability::from_name(name)
+                        .ok_or_else(|| format!("unknown capability {}", name))?;
+                    options.deny.push(capability);
+                }
+                continue;
+            }
+            "--log-level" => {
+                let level = args.next().ok_or("--log-level needs a level")?;
+                let level = Level::from_name(&level)
+                    .ok_or_else(|| format!("unknown log level {}", level))?;
+                options.log_level = Some(level);
+                continue;
+            }
+            "--log-file" => {
+                options.log_file = Some(args.next().ok_or("--log-file needs a file")?);
+                continue;
+            }
+            "--no-cache" => {
+                options.no_cache = true;
+                continue;
+            }
+            "--tokens" => {
+                options.dump = Some(Dump::Tokens);
+                continue;
+            }
+            "--ast" => {
+                options.dump = Some(Dump::Ast);
+                continue;
+            }
+            "-h" | "--help" => Command::Help,
+            "-V" | "--version" => Command::Version,
+            "-e" | "--eval" | "eval" => {
+                let expr = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs an expression", arg))?;
+                Command::Eval(expr)
+            }
+            "repl" => Command::Repl,
+            "check" => Command::Check(parse_check(&mut args)?),
+            "fmt" => Command::Fmt(parse_fmt(&mut args)?),
+            "doc" => Command::Doc(parse_doc(&mut args)?),
+            "build" => Command::Build(parse_build(&mut args)?),
+            "compile" => Command::Compile(parse_compile(&mut args)?),
+            "test" => Command::Test(parse_test(&mut args)?),
+            "add" => Command::Add(parse_add(&mut args)?),
+            "nrepl" => Command::Nrepl(parse_nrepl(&mut args)?),
+            "--jupyter-kernel" => Command::Jupyter(
+                args.next()
+                    .ok_or("--jupyter-kernel needs a connection file")?,
+            ),
+            "--jupyter-install" => Command::JupyterInstall,
+            "bench" => Command::Bench(args.next()),
+            "image" => Command::Image(Image {
+                output: args.next().ok_or("image needs an output file")?,
+                files: args.by_ref().collect(),
+            }),
+            "ast" => {
+                let (dump, path) = parse_ast(&mut args)?;
+                options.dump = Some(dump);
+                Command::Run(path)
+            }
+            "run" => parse_run(&mut args)?,
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            _ => Command::Run(arg.clone()),
+        };
+        if command.is_some() {
+            return Err(format!("unexpected argument {}", arg));
+        }
+        command = Some(next);
+    }
+    Ok(Cli { command, options })
+}
+
+/// Parses the rest of the command line as `run` arguments.
+fn parse_run(args: &mut impl Iterator<Item = String>) -> Result<Command, String> {
+    match args.next() {
+        None => Ok(Command::RunMain),
+        Some(flag) if flag == "--literate" => {
+            let mut literate = Literate {
+                path: String::new(),
+                write: false,
+            };
+            for arg in args {
+                match arg.as_str() {
+                    "--write" => literate.write = true,
+                    flag if flag.starts_with("--") => {
+                        return Err(format!("unknown run option {}", flag))
+                    }
+                    _ if !literate.path.is_empty() => {
+                        return Err(format!("unexpected argument {}", arg))
+                    }
+                    _ => literate.path = arg,
+                }
+            }
+            if literate.path.is_empty() {
+                return Err("--literate needs a Markdown file".into());
+            }
+            Ok(Command::Literate(literate))
+        }
+        Some(path) => Ok(Command::Run(path)),
+    }
+}
+
+/// Parses the rest of the command line as `check` arguments.
+fn parse_check(args: &mut impl Iterator<Item = String>) -> Result<Check, String> {
+    let mut check = Check {
+        files: Vec::new(),
+        lint: false,
+    };
+    for arg in args {
+        match arg.as_str() {
+            "--lint" => check.lint = true,
+            flag if flag.starts_with("--") => return Err(format!("unknown check option {}", flag)),
+            _ => check.files.push(arg),
+        }
+    }
+    Ok(check)
+}
+
+/// Parses the rest of the command line as `ast` arguments, which run the
+/// file with the AST dump in place of evaluation.
+fn parse_ast(args: &mut impl Iterator<Item = String>) -> Result<(Dump, String), String> {
+    let mut dump = Dump::Ast;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => dump = Dump::AstJson,
+            flag if flag.starts_with("--") => return Err(format!("unknown ast option {}", flag)),
+            _ if path.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => path = Some(arg),
+        }
+    }
+    Ok((dump, path.ok_or("ast needs a file")?))
+}
+
+/// Parses the rest of the command line as `test` arguments.
+fn parse_test(args: &mut impl Iterator<Item = String>) -> Result<Test, String> {
+    let mut test = Test {
+        paths: Vec::new(),
+        coverage: false,
+        lcov: None,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--coverage" => test.coverage = true,
+            "--lcov" => test.lcov = Some(args.next().ok_or("--lcov needs a file")?),
+            flag if flag.starts_with("--") => return Err(format!("unknown test option {}", flag)),
+            _ => test.paths.push(arg),
+        }
+    }
+    Ok(test)
+}
+
+/// Parses the rest of the command line as `nrepl` arguments: the port.
+fn parse_nrepl(args: &mut impl Iterator<Item = String>) -> Result<u16, String> {
+    let mut port = 0;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                port = args
+                    .next()
+                    .and_then(|port| port.parse().ok())
+                    .ok_or("--port needs a port number")?
+            }
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    Ok(port)
+}
+
+/// Parses the rest of the command line as `add` arguments.
+fn parse_add(args: &mut impl Iterator<Item = String>) -> Result<Add, String> {
+    let (mut source, mut name, mut rev) = (None, None, None);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => name = Some(args.next().ok_or("--name needs a name")?),
+            "--rev" => rev = Some(args.next().ok_or("--rev needs a revision")?),
+            flag if flag.starts_with("--") => return Err(format!("unknown add option {}", flag)),
+            _ if source.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => source = Some(arg),
+        }
+    }
+    Ok(Add {
+        source: source.ok_or("add needs a git URL or a directory")?,
+        name,
+        rev,
+    })
+}
+
+/// Parses the rest of the command line as `build` arguments.
+fn parse_build(args: &mut impl Iterator<Item = String>) -> Result<Build, String> {
+    let mut script = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(args.next().ok_or("-o needs a file")?),
+            flag if flag.starts_with('-') => return Err(format!("unknown build option {}", flag)),
+            _ if script.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => script = Some(arg),
+        }
+    }
+    let script: String = script.ok_or("build needs a file")?;
+    let output = output.unwrap_or_else(|| {
+        let stem = std::path::Path::new(&script).with_extension("");
+        stem.to_string_lossy().into_owned()
+    });
+    Ok(Build { script, output })
+}
+
+/// Parses the rest of the command line as `compile` arguments.
+fn parse_compile(args: &mut impl Iterator<Item = String>) -> Result<Compile, String> {
+    let mut script = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target" => match args.next().as_deref() {
+                Some("rust") => {}
+                Some(target) => return Err(format!("unknown compile target {}", target)),
+                None => return Err("--target needs a language".into()),
+            },
+            "-o" | "--output" => output = Some(args.next().ok_or("-o needs a file")?),
+            flag if flag.starts_with('-') => {
+                return Err(format!("unknown compile option {}", flag))
+            }
+            _ if script.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => script = Some(arg),
+        }
+    }
+    Ok(Compile {
+        script: script.ok_or("compile needs a file")?,
+        output,
+    })
+}
+
+/// Parses the rest of the command line as `doc` arguments.
+fn parse_doc(args: &mut impl Iterator<Item = String>) -> Result<Doc, String> {
+    let mut doc = Doc {
+        paths: Vec::new(),
+        out: "docs".into(),
+        html: false,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--html" => doc.html = true,
+            "--out" => doc.out = args.next().ok_or("--out needs a directory")?,
+            flag if flag.starts_with("--") => return Err(format!("unknown doc option {}", flag)),
+            _ => doc.paths.push(arg),
+        }
+    }
+    if doc.paths.is_empty() {
+        return Err("doc needs a file or directory".into());
+    }
+    Ok(doc)
+}
+
+/// Parses the rest of the command line as `fmt` arguments.
+fn parse_fmt(args: &mut impl Iterator<Item = String>) -> Result<Fmt, String> {
+    let mut fmt = Fmt {
+        files: Vec::new(),
+        check: false,
+        width: formatter::DEFAULT_WIDTH,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => fmt.check = true,
+            "--width" => {
+                fmt.width = args
+                    .next()
+                    .and_then(|width| width.parse().ok())
+                    .ok_or("--width needs a number")?
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown fmt option {}", flag)),
+            _ => fmt.files.push(arg),
+        }
+    }
+    Ok(fmt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        assert_eq!(
+            parse(&["--no-prelude", "run", "a.jon"]),
+            Ok(Cli {
+                command: Some(Command::Run("a.jon".into())),
+                options: Options {
+                    no_prelude: true,
+                    ..Options::default()
+                }
+            })
+        );
+        assert_eq!(
+            parse(&["eval", "(+ 1 2)"]).unwrap().command,
+            Some(Command::Eval("(+ 1 2)".into()))
+        );
+        assert_eq!(parse(&[]).unwrap().command, None);
+        assert_eq!(parse(&["run"]).unwrap().command, Some(Command::RunMain));
+        assert_eq!(
+            parse(&["nrepl", "--port", "7888"]).unwrap().command,
+            Some(Command::Nrepl(7888))
+        );
+        assert_eq!(
+            parse(&["--jupyter-kernel", "kernel-1.json"])
+                .unwrap()
+                .command,
+            Some(Command::Jupyter("kernel-1.json".into()))
+        );
+        assert_eq!(
+            parse(&["run", "--literate", "notes.md", "--write"])
+                .unwrap()
+                .command,
+            Some(Command::Literate(Literate {
+                path: "notes.md".into(),
+                write: true
+            }))
+        );
+        assert_eq!(
+            parse(&["add", "https://example.com/strings.git", "--rev", "v1"])
+                .unwrap()
+                .command,
+            Some(Command::Add(Add {
+                source: "https://example.com/strings.git".into(),
+                name: None,
+                rev: Some("v1".into())
+            }))
+        );
+        assert_eq!(
+            parse(&[
+                "--log-level",
+                "debug",
+                "--log-file",
+                "a.log",
+                "--print-width",
+                "100",
+                "--deny",
+                "fs,proc",
+                "a.jon"
+            ])
+            .unwrap()
+            .options,
+            Options {
+                log_level: Some(Level::Debug),
+                deny: vec![Capability::Fs, Capability::Proc],
+                log_file: Some("a.log".into()),
+                print_width: Some(100),
+                ..Options::default()
+            }
+        );
+        assert_eq!(
+            parse(&["image", "base.img", "a.jon", "b.jon"])
+                .unwrap()
+                .command,
+            Some(Command::Image(Image {
+                output: "base.img".into(),
+                files: vec!["a.jon".into(), "b.jon".into()]
+            }))
+        );
+        assert_eq!(
+            parse(&["doc", "src", "--out", "site", "--html"])
+                .unwrap()
+                .command,
+            Some(Command::Doc(Doc {
+                paths: vec!["src".into()],
+                out: "site".into(),
+                html: true
+            }))
+        );
+        assert!(parse(&["doc"]).is_err());
+        assert!(parse(&["compile", "--target", "js", "a.jon"]).is_err());
+        assert_eq!(
+            parse(&["build", "tools/greet.jon"]).unwrap().command,
+            Some(Command::Build(Build {
+                script: "tools/greet.jon".into(),
+                output: "tools/greet".into()
+            }))
+        );
+        assert_eq!(
+            parse(&["test", "--lcov", "lcov.info"]).unwrap().command,
+            Some(Command::Test(Test {
+                paths: Vec::new(),
+                coverage: false,
+                lcov: Some("lcov.info".into())
+            }))
+        );
+        assert_eq!(
+            parse(&["bench", "lists/eval"]).unwrap().command,
+            Some(Command::Bench(Some("lists/eval".into())))
+        );
+        assert_eq!(
+            parse(&["--ast", "-e", "1"]).unwrap().options.dump,
+            Some(Dump::Ast)
+        );
+        assert_eq!(
+            parse(&["--cache-dir", "/tmp/jon", "a.jon"])
+                .unwrap()
+                .options
+                .cache_dir,
+            Some("/tmp/jon".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_shorthands() {
+        assert_eq!(
+            parse(&["a.jon"]).unwrap().command,
+            Some(Command::Run("a.jon".into()))
+        );
+        assert_eq!(
+            parse(&["-e", "1"]).unwrap().command,
+            Some(Command::Eval("1".into()))
+        );
+    }
+
+    #[test]
+    fn test_parse_fmt() {
+        assert_eq!(
+            parse(&["fmt", "--check", "a.jon", "--width", "60", "b.jon"])
+                .unwrap()
+                .command,
+            Some(Command::Fmt(Fmt {
+                files: vec!["a.jon".into(), "b.jon".into()],
+                check: true,
+                width: 60,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_check() {
+        assert_eq!(
+            parse(&["check", "--lint", "a.jon"]).unwrap().command,
+            Some(Command::Check(Check {
+                files: vec!["a.jon".into()],
+                lint: true,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_ast() {
+        let cli = parse(&["ast", "--json", "a.jon"]).unwrap();
+        assert_eq!(cli.command, Some(Command::Run("a.jon".into())));
+        assert_eq!(cli.options.dump, Some(Dump::AstJson));
+        assert_eq!(parse(&["ast", "-"]).unwrap().options.dump, Some(Dump::Ast));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["a.jon", "b.jon"]).is_err());
+        assert!(parse(&["fmt", "--width", "wide"]).is_err());
+        assert!(parse(&["ast", "--json"]).is_err());
+        assert!(parse(&["--log-level", "loud", "a.jon"]).is_err());
+        assert!(parse(&["--deny", "fs,time", "a.jon"]).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use std::process::ExitCode;
+
+use jon::diagnostic::Diagnostic;
+use jon::parser::{ParseError, Parser};
+use jon::scanner::{line_column, Scanner};
+
+/// Prints every token of `source` with its line and column, including
+/// whitespace and comments.
+pub fn tokens(name: &str, source: &str) -> ExitCode {
+    let mut scanner = Scanner::new(source);
+    while let Ok(tok) = scanner.next_token() {
+        let (line, column) = line_column(source, tok.position);
+        println!("{}:{}\t{:?}", line, column, tok.token);
+    }
+    if scanner.position() < source.len() {
+        let position = scanner.position();
+        let Err(err) = scanner.next_token() else {
+            unreachable!("the scanner stopped before the end of the input");
+        };
+        let err = ParseError::Scan {
+            position,
+            source: err,
+        };
+        Diagnostic::parse(&err, source).emit(name, source);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// Pretty-prints every top-level form of `source` under the line and column
+/// it starts at.
+pub fn ast(name: &str, source: &str) -> ExitCode {
+    let mut scanner = Scanner::new(source);
+    let mut parser = Parser::new(&mut scanner);
+    match parser.parse_forms() {
+        Ok(forms) => {
+            for (span, expr) in forms {
+                let (line, column) = line_column(source, span.start);
+                println!("{}:{}\n{:#?}", line, column, expr);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            Diagnostic::parse(&err, source).emit(name, source);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Prints every top-level form of `source` as one JSON array of
+/// `{"span":[start,end],"line":l,"column":c,"expr":...}` objects, with
+/// byte offsets for the span and each expression as
+/// [`Expr::to_json`](jon::parser::Expr::to_json) writes it.
+pub fn ast_json(name: &str, source: &str) -> ExitCode {
+    let mut scanner = Scanner::new(source);
+    let mut parser = Parser::new(&mut scanner);
+    match parser.parse_forms() {
+        Ok(forms) => {
+            let forms: Vec<String> = forms
+                .iter()
+                .map(|(span, expr)| {
+                    let (line, column) = line_column(source, span.start);
+                    format!(
+                        "{{\"span\":[{},{}],\"line\":{},\"column\":{},\"expr\":{}}}",
+                        span.start,
+                        span.end,
+                        line,
+                        column,
+                        expr.to_json()
+                    )
+                })
+                .collect();
+            println!("[{}]", forms.join(","));
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            Diagnostic::parse(&err, source).emit(name, source);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::borrow::Cow;
+
+use rustyline::completion::Completer;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::history::SearchDirection;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use jon::eval::SPECIAL_FORMS;
+use jon::scanner::{is_identifier, ScanError, Scanner, Token};
+
+/// How many of the latest history entries hints draw symbols from.
+const HINT_HISTORY: usize = 100;
+
+/// The rustyline helper driving completion, hints, and highlighting in the
+/// REPL.
+#[derive(Default)]
+pub struct JonHelper {
+    /// Every name that can be completed: special forms plus the global
+    /// bindings as of the last evaluation.
+    names: Vec<String>,
+}
+
+impl JonHelper {
+    pub fn set_names(&mut self, globals: impl IntoIterator<Item = String>) {
+        self.names = SPECIAL_FORMS.iter().map(|s| s.to_string()).collect();
+        self.names.extend(globals);
+        self.names.sort();
+        self.names.dedup();
+    }
+
+    fn complete_word(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| is_identifier(*c))
+            .last()
+            .map_or(pos, |(i, _)| i);
+        let prefix = &line[start..pos];
+        let candidates = self
+            .names
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect();
+        (start, candidates)
+    }
+
+    /// The ghost text to show after `line`, the line up to the cursor: the
+    /// rest of the symbol being typed, taken from the `recent` history
+    /// entries, latest first, or else from the known names, shortest
+    /// first; failing that, the parens still open, unless the line ends in
+    /// a space or inside a string.
+    fn suggest(&self, line: &str, recent: &[String]) -> Option<String> {
+        let (start, _) = self.complete_word(line, line.len());
+        let prefix = &line[start..];
+        let mut scanner = Scanner::new(line);
+        let mut depth: usize = 0;
+        loop {
+            match scanner.next_token() {
+                Ok(tok) if tok.token == Token::LParen => depth += 1,
+                Ok(tok) if tok.token == Token::RParen => depth = depth.saturating_sub(1),
+                Ok(_) => {}
+                Err(ScanError::Eof) => break,
+                Err(_) => return None,
+            }
+        }
+        let used = recent.iter().flat_map(|entry| {
+            let mut scanner = Scanner::new(entry);
+            std::iter::from_fn(move || scanner.next_token().ok()).filter_map(|tok| {
+                match tok.token {
+                    Token::Symbol(symbol) => Some(symbol.to_string()),
+                    _ => None,
+                }
+            })
+        });
+        let mut known: Vec<&String> = self.names.iter().collect();
+        known.sort_by_key(|name| name.len());
+        let symbol = match prefix {
+            "" => None,
+            _ if self.names.iter().any(|name| name == prefix) => None,
+            _ => used
+                .chain(known.into_iter().cloned())
+                .find(|symbol| symbol.len() > prefix.len() && symbol.starts_with(prefix)),
+        };
+        match symbol {
+            Some(symbol) => Some(symbol[prefix.len()..].to_string()),
+            None if depth > 0 && !line.ends_with(char::is_whitespace) => Some(")".repeat(depth)),
+            None => None,
+        }
+    }
+
+    /// The ANSI color used for a token, if it is colored at all.
+    fn color(&self, token: Token) -> Option<&'static str> {
+        match token {
+            Token::String(_) => Some("32"),
+            Token::Number(_) | Token::Bytes(_) => Some("33"),
+            Token::Comment(_) => Some("90"),
+            Token::Quote => Some("35"),
+            Token::Symbol(s) if SPECIAL_FORMS.contains(&s) => Some("1;35"),
+            Token::Symbol(s) if self.names.binary_search_by(|n| n.as_str().cmp(s)).is_ok() => {
+                Some("36")
+            }
+            _ => None,
+        }
+    }
+
+    /// Colors `line` token by token as the scanner sees it. Anything after a
+    /// scanner error is left as typed, except an unterminated string. The
+    /// parens at the `brackets` positions are emphasized.
+    fn highlight_source(&self, line: &str, brackets: Option<(usize, usize)>) -> String {
+        let mut out = String::with_capacity(line.len() * 2);
+        let mut scanner = Scanner::new(line);
+        loop {
+            let start = scanner.position();
+            match scanner.next_token() {
+                Ok(tok) => {
+                    let text = &line[start..scanner.position()];
+                    let is_bracket =
+                        brackets.is_some_and(|(open, close)| start == open || start == close);
+                    let color = if is_bracket {
+                        Some("1;34")
+                    } else {
+                        self.color(tok.token)
+                    };
+                    match color {
+                        Some(color) => paint(&mut out, color, text),
+                        None => out.push_str(text),
+                    }
+                }
+                Err(_) => {
+                    let rest = &line[start..];
+                    if rest.starts_with('"') {
+                        paint(&mut out, "32", rest);
+                    } else {
+                        out.push_str(rest);
+                    }
+                    return out;
+                }
+            }
+        }
+    }
+}
+
+/// The positions of the `(` matching the `)` under or just before the cursor
+/// and of that `)`, found over the scanner's tokens so parens inside strings
+/// and comments are ignored.
+fn matching_paren(line: &str, pos: usize) -> Option<(usize, usize)> {
+    let mut scanner = Scanner::new(line);
+    let mut open = Vec::new();
+    let mut before_cursor = None;
+    while let Ok(tok) = scanner.next_token() {
+        match tok.token {
+            Token::LParen => open.push(tok.position),
+            Token::RParen => {
+                let pair = open.pop().map(|start| (start, tok.position));
+                if tok.position == pos {
+                    return pair;
+                }
+                if tok.position + 1 == pos {
+                    before_cursor = pair;
+                }
+            }
+            _ => {}
+        }
+    }
+    before_cursor
+}
+
+fn paint(out: &mut String, color: &str, text: &str) {
+    out.push_str("\x1b[");
+    out.push_str(color);
+    out.push('m');
+    out.push_str(text);
+    out.push_str("\x1b[0m");
+}
+
+impl Completer for JonHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.complete_word(line, pos))
+    }
+}
+
+impl Hinter for JonHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        let history = ctx.history();
+        let recent: Vec<String> = (0..history.len())
+            .rev()
+            .take(HINT_HISTORY)
+            .filter_map(|i| history.get(i, SearchDirection::Reverse).ok().flatten())
+            .map(|found| found.entry.into_owned())
+            .collect();
+        self.suggest(line, &recent)
+    }
+}
+
+impl Highlighter for JonHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        Cow::Owned(self.highlight_source(line, matching_paren(line, pos)))
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        let mut dim = String::new();
+        paint(&mut dim, "2", hint);
+        Cow::Owned(dim)
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        true
+    }
+}
+
+impl Validator for JonHelper {}
+
+impl Helper for JonHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete_word() {
+        let mut helper = JonHelper::default();
+        helper.set_names(vec!["string-append".into(), "second".into()]);
+        assert_eq!(
+            helper.complete_word("(str", 4),
+            (1, vec!["string-append".into()])
+        );
+        assert_eq!(
+            helper.complete_word("(de", 3),
+            (1, vec!["def".into(), "defmacro".into()])
+        );
+    }
+
+    #[test]
+    fn test_suggest() {
+        let mut helper = JonHelper::default();
+        helper.set_names(vec![
+            "string-append".into(),
+            "string/ref".into(),
+            "str".into(),
+        ]);
+        let recent = vec!["(def string-count 3)".to_string()];
+        assert_eq!(
+            helper.suggest("(print string-", &recent),
+            Some("count".into())
+        );
+        assert_eq!(helper.suggest("(print string-", &[]), Some("append".into()));
+        assert_eq!(helper.suggest("(str", &[]), Some(")".into()));
+        assert_eq!(helper.suggest("(+ 1 (* 2 3", &[]), Some("))".into()));
+        assert_eq!(helper.suggest("(+ 1 ", &[]), None);
+        assert_eq!(helper.suggest("(print \"(str", &[]), None);
+        assert_eq!(helper.suggest("(+ 1 2)", &[]), None);
+    }
+
+    #[test]
+    fn test_highlight_source() {
+        let mut helper = JonHelper::default();
+        helper.set_names(vec!["inc".into()]);
+        assert_eq!(
+            helper.highlight_source("(if (inc 1) \"a", None),
+            "(\x1b[1;35mif\x1b[0m (\x1b[36minc\x1b[0m \x1b[33m1\x1b[0m) \x1b[32m\"a\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_matching_paren() {
+        assert_eq!(matching_paren("(a (b) \")\")", 6), Some((3, 5)));
+        assert_eq!(matching_paren("(a (b) \")\")", 5), Some((3, 5)));
+        assert_eq!(matching_paren("(a (b) \")\")", 2), None);
+        assert_eq!(matching_paren("(a (b) \")\")", 11), Some((0, 10)));
+        assert_eq!(matching_paren("(())", 3), Some((0, 3)));
+    }
+}
@@ -0,0 +1,631 @@
+//! `jon --jupyter-kernel FILE`: a kernel speaking version 5.3 of the
+//! Jupyter messaging protocol, so notebooks can evaluate jon cells. Cells
+//! share one interpreter, as REPL entries do; a cell's `print` output is
+//! streamed and its last value shown, as a table when it is a list of
+//! equally long lists. `jon --jupyter-install` registers the kernel.
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value as Json};
+use sha2::Sha256;
+
+use jon::diagnostic::Diagnostic;
+use jon::eval::{EvalError, Interpreter, SPECIAL_FORMS};
+use jon::parser::Parser;
+use jon::scanner::{self, is_identifier, Scanner};
+use jon::value::Value;
+
+use crate::cli::Options;
+use crate::repl::{completions, describe_doc};
+use crate::zmtp::{Endpoint, Multipart, SocketType};
+use crate::{eval_protected, new_runtime, SharedOutput};
+
+const PROTOCOL_VERSION: &str = "5.3";
+
+/// The frame between a message's routing ids and its signature.
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// A message of the protocol, without its signature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// The frames before the delimiter, sent back with each reply.
+    pub ids: Multipart,
+    pub header: Json,
+    pub parent_header: Json,
+    pub metadata: Json,
+    pub content: Json,
+}
+
+impl Message {
+    pub fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or_default()
+    }
+}
+
+/// Signs and checks messages with the connection's key, and makes headers.
+pub struct Session {
+    key: Vec<u8>,
+    id: String,
+    sent: usize,
+}
+
+impl Session {
+    pub fn new(key: &str) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            key: key.as_bytes().to_vec(),
+            id: format!("{:x}-{:x}", std::process::id(), now.as_nanos()),
+            sent: 0,
+        }
+    }
+
+    /// The hex HMAC-SHA256 of `parts`, or nothing if the key is empty, which
+    /// turns signing off.
+    fn sign(&self, parts: &[&[u8]]) -> String {
+        if self.key.is_empty() {
+            return String::new();
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("any key length works");
+        for part in parts {
+            mac.update(part);
+        }
+        let digest = mac.finalize().into_bytes();
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// The message in `frames`, or why it is not one.
+    pub fn decode(&self, mut frames: Multipart) -> Result<Message, String> {
+        let delimiter = frames
+            .iter()
+            .position(|frame| frame == DELIMITER)
+            .ok_or("no delimiter")?;
+        let parts = frames.split_off(delimiter + 1);
+        if parts.len() < 5 {
+            return Err("too few frames".into());
+        }
+        frames.pop();
+        let signed: Vec<&[u8]> = parts[1..5].iter().map(Vec::as_slice).collect();
+        if parts[0] != self.sign(&signed).as_bytes() {
+            return Err("bad signature".into());
+        }
+        let json = |part: &[u8]| serde_json::from_slice(part).map_err(|err| err.to_string());
+        Ok(Message {
+            ids: frames,
+            header: json(signed[0])?,
+            parent_header: json(signed[1])?,
+            metadata: json(signed[2])?,
+            content: json(signed[3])?,
+        })
+    }
+
+    /// A message of `msg_type` answering `parent`, addressed to `ids`.
+    pub fn message(
+        &mut self,
+        ids: Multipart,
+        msg_type: &str,
+        parent: &Message,
+        content: Json,
+    ) -> Message {
+        self.sent += 1;
+        Message {
+            ids,
+            header: json!({
+                "msg_id": format!("{}-{}", self.id, self.sent),
+                "session": self.id,
+                "username": "jon",
+                "date": iso_date(SystemTime::now()),
+                "msg_type": msg_type,
+                "version": PROTOCOL_VERSION,
+            }),
+            parent_header: parent.header.clone(),
+            metadata: json!({}),
+            content,
+        }
+    }
+
+    /// The frames of `message`, signed.
+    pub fn encode(&self, message: &Message) -> Multipart {
+        let parts: Vec<Vec<u8>> = [
+            &message.header,
+            &message.parent_header,
+            &message.metadata,
+            &message.content,
+        ]
+        .iter()
+        .map(|part| part.to_string().into_bytes())
+        .collect();
+        let signed: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+        let mut frames = message.ids.clone();
+        frames.push(DELIMITER.to_vec());
+        frames.push(self.sign(&signed).into_bytes());
+        frames.extend(parts);
+        frames
+    }
+}
+
+/// `time` in ISO 8601, in UTC to the microsecond.
+fn iso_date(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, seconds) = (since.as_secs() / 86400, since.as_secs() % 86400);
+    // days to a civil date, after Howard Hinnant's days_from_civil inverse
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        since.subsec_micros()
+    )
+}
+
+/// What a cell's value looks like in the notebook: as it prints, and as an
+/// HTML table if it is a list of equally long lists.
+fn display_data(interpreter: &Interpreter, value: &Value) -> Json {
+    let floats = interpreter.float_format().unwrap_or_default();
+    let mut data = json!({ "text/plain": floats.value(value) });
+    if let Value::List(rows) = value {
+        let cells: Option<Vec<&Vec<Value>>> = rows
+            .iter()
+            .map(|row| match row {
+                Value::List(cells) => Some(cells),
+                _ => None,
+            })
+            .collect();
+        let width = cells
+            .as_ref()
+            .and_then(|cells| cells.first())
+            .map(|row| row.len());
+        if let (Some(cells), Some(width)) = (cells, width) {
+            if width > 0 && cells.iter().all(|row| row.len() == width) {
+                let mut html = String::from("<table>");
+                for row in cells {
+                    html.push_str("<tr>");
+                    for cell in row {
+                        let text = match cell {
+                            Value::String(s) => s.to_string(),
+                            other => floats.value(other),
+                        };
+                        html.push_str(&format!("<td>{}</td>", escape_html(&text)));
+                    }
+                    html.push_str("</tr>");
+                }
+                html.push_str("</table>");
+                data["text/html"] = Json::String(html);
+            }
+        }
+    }
+    data
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// The identifier in `code` around the character `cursor`, as the byte
+/// range it spans.
+fn word_at(code: &str, cursor: usize) -> (usize, usize) {
+    let pos = code
+        .char_indices()
+        .nth(cursor)
+        .map_or(code.len(), |(i, _)| i);
+    let start = code[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_identifier(*c))
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let end = code[pos..]
+        .char_indices()
+        .find(|(_, c)| !is_identifier(*c))
+        .map_or(code.len(), |(i, _)| pos + i);
+    (start, end)
+}
+
+/// The interpreter cells are evaluated in.
+pub struct Kernel {
+    interpreter: Interpreter,
+    output: SharedOutput,
+    execution_count: usize,
+}
+
+impl Kernel {
+    pub fn new(mut interpreter: Interpreter) -> Self {
+        let output = SharedOutput::default();
+        interpreter.set_output(output.clone());
+        interpreter.set_input(io::empty());
+        Self {
+            interpreter,
+            output,
+            execution_count: 0,
+        }
+    }
+
+    /// Answers the request `msg_type` with `content`, giving `publish` the
+    /// type and content of each message for the notebook, and returns the
+    /// reply's, if the request has one.
+    pub fn handle(
+        &mut self,
+        msg_type: &str,
+        content: &Json,
+        publish: &mut dyn FnMut(&str, Json),
+    ) -> Option<(String, Json)> {
+        let reply = match msg_type {
+            "kernel_info_request" => json!({
+                "status": "ok",
+                "protocol_version": PROTOCOL_VERSION,
+                "implementation": "jon",
+                "implementation_version": env!("CARGO_PKG_VERSION"),
+                "language_info": {
+                    "name": "jon",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "mimetype": "text/x-jon",
+                    "file_extension": ".jon",
+                    "codemirror_mode": "scheme",
+                    "pygments_lexer": "scheme",
+                },
+                "banner": format!("jon {}", env!("CARGO_PKG_VERSION")),
+                "help_links": [],
+            }),
+            "execute_request" => {
+                let code = content["code"].as_str().unwrap_or_default();
+                self.execute(code, content["silent"].as_bool().unwrap_or(false), publish)
+            }
+            "is_complete_request" => {
+                let code = content["code"].as_str().unwrap_or_default();
+                let status = if scanner::open_depth(code) > 0 {
+                    "incomplete"
+                } else if Parser::new(&mut Scanner::new(code)).parse_forms().is_err() {
+                    "invalid"
+                } else {
+                    "complete"
+                };
+                json!({ "status": status, "indent": "  " })
+            }
+            "complete_request" => {
+                let code = content["code"].as_str().unwrap_or_default();
+                let cursor = content["cursor_pos"].as_u64().unwrap_or(0) as usize;
+                let (start, _) = word_at(code, cursor);
+                let pos = code
+                    .char_indices()
+                    .nth(cursor)
+                    .map_or(code.len(), |(i, _)| i);
+                let matches: Vec<String> = completions(&self.interpreter, &code[start..pos])
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .collect();
+                json!({
+                    "status": "ok",
+                    "matches": matches,
+                    "cursor_start": code[..start].chars().count(),
+                    "cursor_end": cursor,
+                    "metadata": {},
+                })
+            }
+            "inspect_request" => {
+                let code = content["code"].as_str().unwrap_or_default();
+                let cursor = content["cursor_pos"].as_u64().unwrap_or(0) as usize;
+                let (start, end) = word_at(code, cursor);
+                let name = &code[start..end];
+                let found =
+                    SPECIAL_FORMS.contains(&name) || self.interpreter.get_global(name).is_some();
+                let data = if found {
+                    json!({ "text/plain": describe_doc(&self.interpreter, name) })
+                } else {
+                    json!({})
+                };
+                json!({ "status": "ok", "found": found, "data": data, "metadata": {} })
+            }
+            "comm_info_request" => json!({ "status": "ok", "comms": {} }),
+            "history_request" => json!({ "status": "ok", "history": [] }),
+            "interrupt_request" => json!({ "status": "ok" }),
+            "shutdown_request" => json!({
+                "status": "ok",
+                "restart": content["restart"].as_bool().unwrap_or(false),
+            }),
+            _ => return None,
+        };
+        Some((msg_type.replace("_request", "_reply"), reply))
+    }
+
+    /// Evaluates the cell `code`, publishing its input, output, and value
+    /// or error, and returns the content of the reply.
+    fn execute(&mut self, code: &str, silent: bool, publish: &mut dyn FnMut(&str, Json)) -> Json {
+        if !silent {
+            self.execution_count += 1;
+        }
+        let count = self.execution_count;
+        publish(
+            "execute_input",
+            json!({ "code": code, "execution_count": count }),
+        );
+        let name = format!("In[{}]", count);
+        let error =
+            |publish: &mut dyn FnMut(&str, Json), diagnostic: Diagnostic, evalue: String| {
+                let rendered = diagnostic.render(&name, code, true);
+                let mut error = json!({
+                    "ename": diagnostic.code,
+                    "evalue": evalue,
+                    "traceback": rendered.lines().collect::<Vec<_>>(),
+                });
+                publish("error", error.clone());
+                error["status"] = json!("error");
+                error["execution_count"] = json!(count);
+                error
+            };
+        let forms = match Parser::new(&mut Scanner::new(code)).parse_forms() {
+            Ok(forms) => forms,
+            Err(err) => {
+                let diagnostic = Diagnostic::parse(&err, code);
+                let message = diagnostic.message.clone();
+                return error(publish, diagnostic, message);
+            }
+        };
+        let mut value = Value::Nil;
+        for (span, expr) in forms {
+            self.interpreter
+                .set_origin(&name, scanner::line_column(code, span.start).0);
+            let result = eval_protected(&mut self.interpreter, &expr);
+            let out = self.output.take();
+            if !out.is_empty() {
+                publish("stream", json!({ "name": "stdout", "text": out }));
+            }
+            match result {
+                Ok(result) => value = result,
+                Err(err) => {
+                    let diagnostic = Diagnostic::eval(&err, span)
+                        .with_expansions(self.interpreter.error_expansions(), code);
+                    let evalue = match err {
+                        EvalError::Interrupted => "interrupted".to_string(),
+                        err => err.to_string(),
+                    };
+                    return error(publish, diagnostic, evalue);
+                }
+            }
+        }
+        if !silent && !matches!(value, Value::Nil) {
+            publish(
+                "execute_result",
+                json!({
+                    "execution_count": count,
+                    "data": display_data(&self.interpreter, &value),
+                    "metadata": {},
+                }),
+            );
+        }
+        json!({
+            "status": "ok",
+            "execution_count": count,
+            "user_expressions": {},
+            "payload": [],
+        })
+    }
+}
+
+/// The socket a request came in on.
+#[derive(Debug, Clone, Copy)]
+enum Channel {
+    Shell,
+    Control,
+}
+
+/// Serves as the kernel the connection file at `path` describes until a
+/// shutdown request. Interrupts arrive as SIGINT, which stops the cell
+/// being evaluated.
+pub fn serve(path: &str, options: &Options) -> Result<(), String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| format!("cannot read {}: {}", path, err))?;
+    let info: Json = serde_json::from_str(&text).map_err(|err| format!("{}: {}", path, err))?;
+    let address = |port: &str| {
+        let transport = info["transport"].as_str().unwrap_or("tcp");
+        if transport != "tcp" {
+            return Err(format!("unsupported transport {}", transport));
+        }
+        let ip = info["ip"].as_str().unwrap_or("127.0.0.1");
+        let port = info[port]
+            .as_u64()
+            .ok_or_else(|| format!("{} missing from {}", port, path))?;
+        Ok(format!("{}:{}", ip, port))
+    };
+    if !matches!(
+        info["signature_scheme"].as_str(),
+        None | Some("hmac-sha256")
+    ) {
+        return Err("only hmac-sha256 signatures are supported".into());
+    }
+    let mut session = Session::new(info["key"].as_str().unwrap_or_default());
+    let (send, requests) = mpsc::channel();
+    let bind = |port: &str, kind, channel: Option<Channel>| {
+        let send = send.clone();
+        let on_message = move |peer, frames| {
+            if let Some(channel) = channel {
+                let _ = send.send((channel, peer, frames));
+            }
+        };
+        Endpoint::bind(&address(port)?, kind, on_message)
+            .map_err(|err| format!("cannot listen on {}: {}", port, err))
+    };
+    let shell = bind("shell_port", SocketType::Router, Some(Channel::Shell))?;
+    let control = bind("control_port", SocketType::Router, Some(Channel::Control))?;
+    let iopub = bind("iopub_port", SocketType::Pub, None)?;
+    let _stdin = bind("stdin_port", SocketType::Router, None)?;
+    let _heartbeat = bind("hb_port", SocketType::Rep, None)?;
+
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let set = interrupt.clone();
+    ctrlc::set_handler(move || set.store(true, Ordering::Relaxed))
+        .map_err(|err| err.to_string())?;
+    let mut interpreter = new_runtime(options);
+    interpreter.set_interrupt(interrupt.clone());
+    let mut kernel = Kernel::new(interpreter);
+
+    let publish = |session: &mut Session, parent: &Message, msg_type: &str, content: Json| {
+        let message = session.message(
+            vec![msg_type.as_bytes().to_vec()],
+            msg_type,
+            parent,
+            content,
+        );
+        iopub.broadcast(&session.encode(&message));
+    };
+    for (channel, peer, frames) in requests {
+        let Ok(request) = session.decode(frames) else {
+            continue;
+        };
+        publish(
+            &mut session,
+            &request,
+            "status",
+            json!({ "execution_state": "busy" }),
+        );
+        // an interrupt that came between cells is not for the next one
+        interrupt.store(false, Ordering::Relaxed);
+        let mut published = Vec::new();
+        let reply = kernel.handle(
+            request.msg_type(),
+            &request.content,
+            &mut |msg_type, content| published.push((msg_type.to_string(), content)),
+        );
+        for (msg_type, content) in published {
+            publish(&mut session, &request, &msg_type, content);
+        }
+        if let Some((msg_type, content)) = reply {
+            let message = session.message(request.ids.clone(), &msg_type, &request, content);
+            let endpoint = match channel {
+                Channel::Shell => &shell,
+                Channel::Control => &control,
+            };
+            let _ = endpoint.send_to(peer, &session.encode(&message));
+        }
+        publish(
+            &mut session,
+            &request,
+            "status",
+            json!({ "execution_state": "idle" }),
+        );
+        if request.msg_type() == "shutdown_request" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the kernel spec telling Jupyter how to start jon, returning the
+/// directory it went in: `$JUPYTER_DATA_DIR`, or the user's data directory,
+/// under `kernels/jon`.
+pub fn install() -> Result<PathBuf, String> {
+    let data = match std::env::var_os("JUPYTER_DATA_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) => PathBuf::from(dir).join("jupyter"),
+            None => {
+                let home = std::env::var_os("HOME").ok_or("HOME is not set")?;
+                let home = PathBuf::from(home);
+                if cfg!(target_os = "macos") {
+                    home.join("Library/Jupyter")
+                } else {
+                    home.join(".local/share/jupyter")
+                }
+            }
+        },
+    };
+    let dir = data.join("kernels").join("jon");
+    let exe = std::env::current_exe().map_err(|err| err.to_string())?;
+    let spec = json!({
+        "argv": [exe, "--jupyter-kernel", "{connection_file}"],
+        "display_name": "jon",
+        "language": "jon",
+        "interrupt_mode": "signal",
+    });
+    std::fs::create_dir_all(&dir)
+        .and_then(|()| std::fs::write(dir.join("kernel.json"), format!("{:#}\n", spec)))
+        .map_err(|err| format!("cannot write {}: {}", dir.display(), err))?;
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_and_cells() {
+        let mut session = Session::new("secret");
+        let request = Message {
+            ids: vec![b"client".to_vec()],
+            header: json!({ "msg_type": "execute_request" }),
+            parent_header: json!({}),
+            metadata: json!({}),
+            content: json!({ "code": "(+ 1 2)" }),
+        };
+        let frames = session.encode(&request);
+        assert_eq!(frames[1], DELIMITER);
+        assert_eq!(session.decode(frames.clone()), Ok(request.clone()));
+        let mut forged = frames;
+        forged[6] = br#"{"code":"(exit)"}"#.to_vec();
+        assert_eq!(session.decode(forged), Err("bad signature".into()));
+        let reply = session.message(request.ids.clone(), "execute_reply", &request, json!({}));
+        assert_eq!(reply.parent_header, request.header);
+
+        let mut kernel = Kernel::new(Interpreter::new());
+        let mut published = Vec::new();
+        let code = json!({ "code": "(print \"rows\") (list (list 1 \"<a>\") (list 2 \"b\"))" });
+        let reply = kernel.handle("execute_request", &code, &mut |msg_type, content| {
+            published.push((msg_type.to_string(), content))
+        });
+        assert_eq!(reply.unwrap().1["status"], "ok");
+        let types: Vec<&str> = published
+            .iter()
+            .map(|(msg_type, _)| msg_type.as_str())
+            .collect();
+        assert_eq!(types, ["execute_input", "stream", "execute_result"]);
+        assert_eq!(published[1].1["text"], "rows\n");
+        assert_eq!(
+            published[2].1["data"]["text/html"],
+            "<table><tr><td>1</td><td>&lt;a&gt;</td></tr><tr><td>2</td><td>b</td></tr></table>"
+        );
+        let reply = kernel.handle(
+            "execute_request",
+            &json!({ "code": "(car 1)" }),
+            &mut |_, _| {},
+        );
+        let (msg_type, content) = reply.unwrap();
+        assert_eq!(
+            (msg_type.as_str(), &content["ename"]),
+            ("execute_reply", &json!("E0102"))
+        );
+        let reply = kernel.handle(
+            "complete_request",
+            &json!({ "code": "(str", "cursor_pos": 4 }),
+            &mut |_, _| {},
+        );
+        let content = reply.unwrap().1;
+        assert_eq!(content["cursor_start"], 1);
+        assert!(content["matches"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("string-append")));
+        assert_eq!(
+            iso_date(UNIX_EPOCH + std::time::Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00.000000Z"
+        );
+    }
+}
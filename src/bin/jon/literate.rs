@@ -0,0 +1,151 @@
+//! Markdown documents as programs, for `jon run --literate`: the code of
+//! their ```` ```jon ```` fenced blocks is evaluated in order, and with
+//! `--write` what each block printed and returned is written back in a
+//! ```` ```jon-output ```` block right under it, replacing the one a
+//! previous run wrote.
+
+use std::ops::Range;
+
+/// The info string of the blocks results are written to.
+const OUTPUT_INFO: &str = "jon-output";
+
+/// A ```` ```jon ```` block of a document.
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    /// The code between the fences.
+    pub code: Range<usize>,
+    /// Where the line after the closing fence starts.
+    pub end: usize,
+    /// The output block right under it, if there is one.
+    pub output: Option<Range<usize>>,
+}
+
+/// A fenced block: its info string, the code between its fences, and
+/// where the line after its closing fence starts. An unclosed block runs
+/// to the end of the text.
+struct Fence<'a> {
+    info: &'a str,
+    code: Range<usize>,
+    end: usize,
+}
+
+/// The fenced block whose opening line starts at `start`, if it is one.
+fn fence_at(text: &str, start: usize) -> Option<Fence<'_>> {
+    let line_end = text[start..]
+        .find('\n')
+        .map_or(text.len(), |i| start + i + 1);
+    let opening = text[start..line_end].trim();
+    let marker = opening.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let width = opening.chars().take_while(|c| *c == marker).count();
+    if width < 3 {
+        return None;
+    }
+    let info = opening[width..].split_whitespace().next().unwrap_or("");
+    let mut line = line_end;
+    while line < text.len() {
+        let next = text[line..].find('\n').map_or(text.len(), |i| line + i + 1);
+        let closing = text[line..next].trim();
+        if closing.len() >= width && closing.chars().all(|c| c == marker) {
+            return Some(Fence {
+                info,
+                code: line_end..line,
+                end: next,
+            });
+        }
+        line = next;
+    }
+    Some(Fence {
+        info,
+        code: line_end..text.len(),
+        end: text.len(),
+    })
+}
+
+/// The ```` ```jon ```` blocks of `text`, in order.
+pub fn blocks(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut line = 0;
+    while line < text.len() {
+        let Some(fence) = fence_at(text, line) else {
+            line = text[line..].find('\n').map_or(text.len(), |i| line + i + 1);
+            continue;
+        };
+        if fence.info == "jon" {
+            let output = fence_at(text, fence.end)
+                .filter(|output| output.info == OUTPUT_INFO)
+                .map(|output| fence.end..output.end);
+            blocks.push(Block {
+                code: fence.code,
+                end: fence.end,
+                output,
+            });
+        }
+        line = fence.end;
+    }
+    blocks
+}
+
+/// The code of `block` as a program whose spans are those of `text`, so
+/// that diagnostics point into the document: everything before the block
+/// is blanked out, keeping its line breaks.
+pub fn source(text: &str, block: &Block) -> String {
+    let before = text[..block.code.start]
+        .bytes()
+        .map(|byte| if byte == b'\n' { '\n' } else { ' ' });
+    before.chain(text[block.code.clone()].chars()).collect()
+}
+
+/// `text` with `results[i]` written in an output block under the `i`th of
+/// `blocks`, or no output block if it is empty. Blocks past the end of
+/// `results` keep what they had.
+pub fn write_results(text: &str, blocks: &[Block], results: &[String]) -> String {
+    let mut written = String::new();
+    let mut copied = 0;
+    for (block, result) in blocks.iter().zip(results) {
+        written.push_str(&text[copied..block.end]);
+        copied = block.output.as_ref().map_or(block.end, |output| output.end);
+        if result.is_empty() {
+            continue;
+        }
+        if !text[..block.end].ends_with('\n') {
+            written.push('\n');
+        }
+        written.push_str("```");
+        written.push_str(OUTPUT_INFO);
+        written.push('\n');
+        written.push_str(result);
+        if !result.ends_with('\n') {
+            written.push('\n');
+        }
+        written.push_str("```\n");
+    }
+    written.push_str(&text[copied..]);
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_and_results() {
+        let text = "# Notes\n\n```jon\n(+ 1 2)\n```\n\n```sh\nls\n```\n\n~~~~ jon\n(print 1)\n~~~~\n```jon-output\nold\n```\nend\n";
+        let found = blocks(text);
+        assert_eq!(found.len(), 2);
+        assert_eq!(&text[found[0].code.clone()], "(+ 1 2)\n");
+        assert_eq!(found[0].output, None);
+        assert_eq!(&text[found[1].code.clone()], "(print 1)\n");
+        assert!(found[1].output.is_some());
+        assert_eq!(source(text, &found[0]), "       \n\n      \n(+ 1 2)\n");
+        let written = write_results(text, &found, &["3".into(), "1\n".into()]);
+        assert_eq!(
+            written,
+            "# Notes\n\n```jon\n(+ 1 2)\n```\n```jon-output\n3\n```\n\n```sh\nls\n```\n\n~~~~ jon\n(print 1)\n~~~~\n```jon-output\n1\n```\nend\n"
+        );
+        let again = blocks(&written);
+        assert_eq!(
+            write_results(&written, &again, &["3".into(), "1\n".into()]),
+            written
+        );
+    }
+}
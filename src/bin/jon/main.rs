@@ -0,0 +1,1032 @@
+use std::cell::RefCell;
+use std::io::{self, IsTerminal, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use cli::{Add, Build, Check, Command, Compile, Doc, Dump, Fmt, Image, Literate, Options, Test};
+use jon::cache::ParseCache;
+use jon::coverage::{self, Coverage, CoverageHook};
+use jon::diagnostic::Diagnostic;
+use jon::{
+    bench, check, compile, debugger, docgen, eval, formatter, lint, logging, parser, scanner,
+    testing, trace, value,
+};
+use manifest::Manifest;
+
+mod cli;
+mod dump;
+mod editor;
+#[cfg(feature = "jupyter")]
+mod jupyter;
+mod literate;
+mod manifest;
+mod nrepl;
+mod packages;
+mod repl;
+mod standalone;
+#[cfg(feature = "jupyter")]
+mod zmtp;
+
+/// An interpreter started from `--image`, or else with the prelude unless
+/// `--no-prelude` was given, with the program's arguments bound to
+/// `*args*`, the capabilities `--deny` lists denied, and logging as
+/// `--log-level` and `--log-file` say. In a project, `load` searches its
+/// source directories and those of its libraries, and their prelude files
+/// follow the prelude, the libraries' first. An image, log file, or
+/// project prelude file that cannot be loaded ends the program.
+fn new_interpreter(options: &Options) -> eval::Interpreter {
+    let mut interpreter = match &options.image {
+        Some(path) => load_image(path),
+        None if options.no_prelude => eval::Interpreter::bare(),
+        None => eval::Interpreter::new(),
+    };
+    if let Some(manifest) = &options.manifest {
+        let packages = &options.packages;
+        let mut load_path = manifest.sources.clone();
+        load_path.extend(packages.iter().flat_map(|p| p.sources.iter().cloned()));
+        interpreter.set_load_path(load_path);
+        if options.image.is_none() && !options.no_prelude {
+            let package_prelude = packages.iter().flat_map(|p| &p.prelude);
+            for path in package_prelude.chain(&manifest.prelude) {
+                if let Err(err) = interpreter.load_file(&path.to_string_lossy()) {
+                    eprintln!("error: cannot load {}: {}", path.display(), err);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    interpreter.set_global("*args*", options.args.clone());
+    for &capability in &options.deny {
+        interpreter.deny(capability);
+    }
+    if let Some(level) = options.log_level {
+        interpreter.set_log_level(level);
+    }
+    if let Some(path) = &options.log_file {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path);
+        match file {
+            Ok(file) => interpreter.set_log_target(logging::Target::File(file)),
+            Err(err) => {
+                eprintln!("error: cannot open log file {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    }
+    interpreter
+}
+
+fn load_image(path: &str) -> eval::Interpreter {
+    let mut interpreter = eval::Interpreter::bare();
+    let loaded = std::fs::read(path)
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| {
+            interpreter
+                .load_image(&bytes)
+                .map_err(|err| err.to_string())
+        });
+    if let Err(err) = loaded {
+        eprintln!("error: cannot load image {}: {}", path, err);
+        std::process::exit(1);
+    }
+    interpreter
+}
+
+/// An interpreter for running programs, which stops in the debugger at a
+/// `(breakpoint)`, or from the start with `--debug`, and logs traced calls.
+fn new_runtime(options: &Options) -> eval::Interpreter {
+    let mut interpreter = new_interpreter(options);
+    interpreter.add_hook(Box::new(debugger::Debugger::stdio(options.debug)));
+    interpreter.add_hook(Box::new(trace::Tracer::stderr(options.trace)));
+    interpreter.set_parse_cache(parse_cache(options));
+    interpreter
+}
+
+/// Where fetched libraries are kept: `--cache-dir`, or the user's cache
+/// directory.
+fn package_cache(options: &Options) -> Option<PathBuf> {
+    options
+        .cache_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(ParseCache::default_dir)
+}
+
+/// Whether `command` runs a project's code, and so needs its libraries
+/// fetched first. Other commands work offline. `jon add` fetches its
+/// library itself once it has added it.
+fn loads_packages(command: Option<&Command>) -> bool {
+    matches!(
+        command,
+        None | Some(
+            Command::Repl
+                | Command::Run(_)
+                | Command::RunMain
+                | Command::Literate(_)
+                | Command::Test(_)
+                | Command::Nrepl(_)
+                | Command::Jupyter(_)
+        )
+    )
+}
+
+/// Adds the library `add` names to the dependencies of the project's
+/// `jon.toml`, creating one in the current directory if there is no
+/// project, and fetches it.
+fn run_add(add: &Add, options: &Options) -> ExitCode {
+    match add_package(add, options) {
+        Ok(name) => {
+            println!("added {}", name);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn add_package(add: &Add, options: &Options) -> Result<String, String> {
+    let root = options
+        .manifest
+        .as_ref()
+        .map_or_else(PathBuf::new, |manifest| manifest.root.clone());
+    let name = match &add.name {
+        Some(name) => name.clone(),
+        None => packages::default_name(&add.source)
+            .ok_or_else(|| format!("cannot name {}; give --name", add.source))?,
+    };
+    manifest::check_name(&name)?;
+    if let Some(manifest) = &options.manifest {
+        if manifest.dependencies.iter().any(|d| d.name == name) {
+            return Err(format!("{} is already a dependency", name));
+        }
+    }
+    let quote = |text: &str| toml::Value::String(text.into()).to_string();
+    let entry = if Path::new(&add.source).is_dir() {
+        if add.rev.is_some() {
+            return Err("--rev only applies to git libraries".into());
+        }
+        // relative to the manifest, as the paths in it are
+        let path = std::path::absolute(&add.source).map_err(|err| err.to_string())?;
+        let cwd = std::env::current_dir().map_err(|err| err.to_string())?;
+        let base = cwd.join(&root);
+        let path = path.strip_prefix(&base).unwrap_or(&path);
+        format!("{{ path = {} }}", quote(&path.to_string_lossy()))
+    } else {
+        match &add.rev {
+            Some(rev) => format!("{{ git = {}, rev = {} }}", quote(&add.source), quote(rev)),
+            None => format!("{{ git = {} }}", quote(&add.source)),
+        }
+    };
+    let path = root.join(manifest::MANIFEST_FILE);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(format!("cannot read {}: {}", path.display(), err)),
+    };
+    let text = manifest::add_dependency(&text, &name, &entry);
+    let updated = Manifest::parse(&root, &text)?;
+    packages::resolve(&updated, package_cache(options).as_deref())?;
+    std::fs::write(&path, text)
+        .map_err(|err| format!("cannot write {}: {}", path.display(), err))?;
+    Ok(name)
+}
+
+/// Serves as a Jupyter kernel, connecting as the file at `path` says.
+#[cfg(feature = "jupyter")]
+fn run_jupyter(path: &str, options: &Options) -> ExitCode {
+    match jupyter::serve(path, options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Registers jon with Jupyter.
+#[cfg(feature = "jupyter")]
+fn run_jupyter_install() -> ExitCode {
+    match jupyter::install() {
+        Ok(dir) => {
+            println!("installed the jon kernel in {}", dir.display());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(feature = "jupyter"))]
+fn run_jupyter(_path: &str, _options: &Options) -> ExitCode {
+    run_jupyter_install()
+}
+
+#[cfg(not(feature = "jupyter"))]
+fn run_jupyter_install() -> ExitCode {
+    eprintln!(
+        "error: this jon was built without Jupyter support; rebuild it with --features jupyter"
+    );
+    ExitCode::FAILURE
+}
+
+/// Where parsed files are cached: `--cache-dir`, or the user's cache
+/// directory, or nowhere with `--no-cache`.
+fn parse_cache(options: &Options) -> Option<ParseCache> {
+    if options.no_cache {
+        return None;
+    }
+    let dir = options
+        .cache_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(ParseCache::default_dir)?;
+    Some(ParseCache::new(dir))
+}
+
+/// Evaluates `expr`, turning a panic inside the evaluator into an error.
+fn eval_protected(
+    interpreter: &mut eval::Interpreter,
+    expr: &parser::Expr,
+) -> Result<value::Value, eval::EvalError> {
+    panic::catch_unwind(AssertUnwindSafe(|| interpreter.eval(expr)))
+        .unwrap_or(Err(eval::EvalError::Internal))
+}
+
+/// Runs the script at `path` in a fresh interpreter, reporting the first
+/// error with its file, line, and column.
+fn run_file(path: &str, options: &Options) -> ExitCode {
+    match std::fs::read_to_string(path) {
+        Ok(source) => {
+            let cache = parse_cache(options);
+            run_source(path, &source, options, cache.as_ref(), false)
+        }
+        Err(err) => {
+            eprintln!("error: cannot read {}: {}", path, err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs the whole of standard input as a program.
+fn run_stdin(options: &Options) -> ExitCode {
+    let mut source = String::new();
+    match io::stdin().read_to_string(&mut source) {
+        Ok(_) => run_source("<stdin>", &source, options, None, false),
+        Err(err) => {
+            eprintln!("error: cannot read standard input: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Evaluates the code blocks of the Markdown file `literate` names in order
+/// in one interpreter, stopping at the first error. With `--write`, what
+/// each block printed, and its value unless nil, is written back under it,
+/// the error for the block that failed.
+fn run_literate(literate: &Literate, options: &Options) -> ExitCode {
+    let path = &literate.path;
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("error: cannot read {}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let blocks = literate::blocks(&text);
+    let output = SharedOutput::default();
+    let mut interpreter = new_runtime(options);
+    if literate.write {
+        interpreter.set_output(output.clone());
+    }
+    let mut results = Vec::new();
+    let mut status = ExitCode::SUCCESS;
+    'blocks: for block in &blocks {
+        let source = literate::source(&text, block);
+        let forms = match parser::Parser::new(&mut scanner::Scanner::new(&source)).parse_forms() {
+            Ok(forms) => forms,
+            Err(err) => {
+                let diagnostic = Diagnostic::parse(&err, &source);
+                diagnostic.emit(path, &text);
+                results.push(format!("error: {}", diagnostic.message));
+                status = ExitCode::FAILURE;
+                break;
+            }
+        };
+        let mut value = value::Value::Nil;
+        for (span, expr) in forms {
+            interpreter.set_origin(path, scanner::line_column(&text, span.start).0);
+            match eval_protected(&mut interpreter, &expr) {
+                Ok(result) => value = result,
+                Err(err) => {
+                    Diagnostic::eval(&err, span)
+                        .with_expansions(interpreter.error_expansions(), &text)
+                        .emit(path, &text);
+                    results.push(format!("{}error: {}", output.take(), err));
+                    status = ExitCode::FAILURE;
+                    break 'blocks;
+                }
+            }
+        }
+        let mut result = output.take();
+        if !matches!(value, value::Value::Nil) {
+            let floats = interpreter.float_format().unwrap_or_default();
+            result.push_str(&floats.value(&value));
+        }
+        results.push(result);
+    }
+    if literate.write {
+        let written = literate::write_results(&text, &blocks, &results);
+        if let Err(err) = std::fs::write(path, written) {
+            eprintln!("error: cannot write {}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    }
+    status
+}
+
+/// What `print` writes, kept to be taken.
+#[derive(Clone, Default)]
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl SharedOutput {
+    /// What was written since the last call.
+    fn take(&self) -> String {
+        String::from_utf8_lossy(&std::mem::take(&mut *self.0.borrow_mut())).into_owned()
+    }
+}
+
+impl io::Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Evaluates every form of `source` in a fresh interpreter, stopping at the
+/// first error. `name` labels error locations; the forms come from `cache`
+/// if given, and with `print_result` the value of the last form is printed.
+fn run_source(
+    name: &str,
+    source: &str,
+    options: &Options,
+    cache: Option<&ParseCache>,
+    print_result: bool,
+) -> ExitCode {
+    match options.dump {
+        Some(Dump::Tokens) => return dump::tokens(name, source),
+        Some(Dump::Ast) => return dump::ast(name, source),
+        Some(Dump::AstJson) => return dump::ast_json(name, source),
+        None => {}
+    }
+    let parsed = match cache {
+        Some(cache) => cache.parse(source),
+        None => parser::Parser::new(&mut scanner::Scanner::new(source)).parse_forms(),
+    };
+    let forms = match parsed {
+        Ok(forms) => forms,
+        Err(err) => {
+            Diagnostic::parse(&err, source).emit(name, source);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut interpreter = new_runtime(options);
+    let start = Instant::now();
+    let mut result = value::Value::Nil;
+    for (span, expr) in forms {
+        interpreter.set_origin(name, scanner::line_column(source, span.start).0);
+        match eval_protected(&mut interpreter, &expr) {
+            Ok(value) => result = value,
+            Err(err) => {
+                Diagnostic::eval(&err, span)
+                    .with_expansions(interpreter.error_expansions(), source)
+                    .emit(name, source);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if print_result {
+        let floats = interpreter.float_format().unwrap_or_default();
+        println!("{}", floats.value(&result));
+    }
+    if options.time {
+        eprintln!("time: {:?}", start.elapsed());
+    }
+    ExitCode::SUCCESS
+}
+
+/// Statically checks each file, printing a diagnostic for every problem
+/// found, and with `--lint` a warning for every likely mistake. Fails if any
+/// file has an error; warnings alone do not fail.
+fn run_check(check: &Check, options: &Options) -> ExitCode {
+    let config = match lint_config(options) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let files = match project_files(&check.files, options) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut status = ExitCode::SUCCESS;
+    for path in &files {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("error: cannot read {}: {}", path, err);
+                status = ExitCode::FAILURE;
+                continue;
+            }
+        };
+        let mut warnings = Vec::new();
+        if check.lint {
+            match config.for_file(&source) {
+                Ok(config) => {
+                    let interpreter = new_interpreter(options);
+                    if let Ok(found) = lint::lint_source(&interpreter, &config, &source) {
+                        warnings = found;
+                    }
+                }
+                Err(err) => {
+                    eprintln!("error: {}: {}", path, err);
+                    status = ExitCode::FAILURE;
+                    continue;
+                }
+            }
+        }
+        let mut interpreter = new_interpreter(options);
+        match check::check_source(&mut interpreter, &source) {
+            Ok(mut diagnostics) => {
+                if !diagnostics.is_empty() {
+                    status = ExitCode::FAILURE;
+                }
+                diagnostics.extend(warnings);
+                diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+                for diagnostic in &diagnostics {
+                    diagnostic.emit(path, &source);
+                }
+            }
+            Err(err) => {
+                Diagnostic::parse(&err, &source).emit(path, &source);
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+/// Formats `fmt.files` in place, or standard input to standard output when
+/// no file is given. With `--check` nothing is written and the command fails
+/// if any file is not already formatted.
+fn run_fmt(fmt: &Fmt) -> ExitCode {
+    if fmt.files.is_empty() {
+        let mut source = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut source) {
+            eprintln!("error: cannot read standard input: {}", err);
+            return ExitCode::FAILURE;
+        }
+        return match formatter::format_source(&source, fmt.width) {
+            Ok(formatted) if fmt.check && formatted != source => ExitCode::FAILURE,
+            Ok(_) if fmt.check => ExitCode::SUCCESS,
+            Ok(formatted) => {
+                print!("{}", formatted);
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                Diagnostic::parse(&err, &source).emit("<stdin>", &source);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let mut status = ExitCode::SUCCESS;
+    for path in &fmt.files {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("error: cannot read {}: {}", path, err);
+                status = ExitCode::FAILURE;
+                continue;
+            }
+        };
+        let formatted = match formatter::format_source(&source, fmt.width) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                Diagnostic::parse(&err, &source).emit(path, &source);
+                status = ExitCode::FAILURE;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if fmt.check {
+            println!("{}", path);
+            status = ExitCode::FAILURE;
+        } else if let Err(err) = std::fs::write(path, formatted) {
+            eprintln!("error: cannot write {}: {}", path, err);
+            status = ExitCode::FAILURE;
+        }
+    }
+    status
+}
+
+/// Loads `image.files` into a fresh interpreter and writes its globals to
+/// `image.output`.
+fn run_image(image: &Image, options: &Options) -> ExitCode {
+    let mut interpreter = new_interpreter(options);
+    interpreter.set_parse_cache(parse_cache(options));
+    for path in &image.files {
+        if let Err(err) = interpreter.load_file(path) {
+            eprintln!("error: {}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    }
+    let written = interpreter
+        .save_image()
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| std::fs::write(&image.output, bytes).map_err(|err| err.to_string()));
+    match written {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: cannot write image {}: {}", image.output, err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The files under the directory `path` whose names end with `suffix`,
+/// sorted, or `path` itself if it is not a directory.
+fn files_under(path: &Path, suffix: &str) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let file = entry?.path();
+            if file.is_dir() {
+                dirs.push(file);
+            } else if file.to_string_lossy().ends_with(suffix) {
+                files.push(file);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The `.jon` files under `path` with their module names, the path
+/// relative to `path` without the extension, or the file itself with its
+/// stem as the name.
+fn modules(path: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let modules = files_under(path, ".jon")?.into_iter().map(|file| {
+        let module = match file.strip_prefix(path) {
+            Ok(relative) if relative != Path::new("") => relative.with_extension(""),
+            _ => PathBuf::from(file.file_stem().unwrap_or_default()),
+        };
+        let name = module
+            .to_string_lossy()
+            .replace(std::path::MAIN_SEPARATOR, "/");
+        (name, file)
+    });
+    Ok(modules.collect())
+}
+
+/// The lint configuration of `.jonlint` with the directives of the
+/// project's `jon.toml` applied on top.
+fn lint_config(options: &Options) -> Result<lint::LintConfig, String> {
+    let mut config = lint::LintConfig::load()?;
+    if let Some(manifest) = &options.manifest {
+        for directive in &manifest.lint {
+            config
+                .apply(directive)
+                .map_err(|err| format!("{}: {}", manifest::MANIFEST_FILE, err))?;
+        }
+    }
+    Ok(config)
+}
+
+/// `files`, or when there are none the `.jon` files under the source
+/// directories of the project's `jon.toml`.
+fn project_files(files: &[String], options: &Options) -> Result<Vec<String>, String> {
+    if !files.is_empty() {
+        return Ok(files.to_vec());
+    }
+    let Some(manifest) = options.manifest.as_ref().filter(|m| !m.sources.is_empty()) else {
+        return Err("check needs a file, or a jon.toml naming sources".into());
+    };
+    let mut found = Vec::new();
+    for dir in &manifest.sources {
+        let files = files_under(dir, ".jon")
+            .map_err(|err| format!("cannot read {}: {}", dir.display(), err))?;
+        found.extend(files.iter().map(|file| file.to_string_lossy().into_owned()));
+    }
+    Ok(found)
+}
+
+/// Runs the tests in each `*_test.jon` file under `test.paths`, or the
+/// test directories of the project, each file in a fresh interpreter, and
+/// prints how each went and the totals, then the coverage if asked for.
+/// Fails if any test or file does.
+fn run_test(test: &Test, options: &Options) -> ExitCode {
+    let coverage = Rc::new(RefCell::new(Coverage::new()));
+    let (mut passed, mut failed, mut broken) = (0, 0, 0);
+    let paths: Vec<PathBuf> = match &options.manifest {
+        _ if !test.paths.is_empty() => test.paths.iter().map(PathBuf::from).collect(),
+        Some(manifest) if !manifest.tests.is_empty() => manifest.tests.clone(),
+        Some(manifest) => vec![manifest.root.join("tests")],
+        None => vec![PathBuf::from("tests")],
+    };
+    for path in &paths {
+        let files = match files_under(path, "_test.jon") {
+            Ok(files) => files,
+            Err(err) => {
+                eprintln!("error: cannot read {}: {}", path.display(), err);
+                broken += 1;
+                continue;
+            }
+        };
+        for file in files {
+            let file = file.to_string_lossy();
+            let mut interpreter = new_interpreter(options);
+            interpreter.set_parse_cache(parse_cache(options));
+            if test.coverage || test.lcov.is_some() {
+                interpreter.add_hook(Box::new(CoverageHook::new(coverage.clone())));
+            }
+            let outcomes = match testing::run_file(&mut interpreter, &file) {
+                Ok(outcomes) => outcomes,
+                Err(err) => {
+                    println!("{}: error: {}", file, err);
+                    broken += 1;
+                    continue;
+                }
+            };
+            for outcome in outcomes {
+                match outcome.failure {
+                    None => {
+                        println!("{}: {} ... ok", file, outcome.name);
+                        passed += 1;
+                    }
+                    Some(failure) => {
+                        let failure = failure.to_string().replace('\n', "\n  ");
+                        println!("{}: {} ... FAILED\n  {}", file, outcome.name, failure);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+    }
+    print!("\n{} passed, {} failed", passed, failed);
+    if broken > 0 {
+        print!(", {} files could not be run", broken);
+    }
+    println!();
+    if test.coverage || test.lcov.is_some() {
+        report_coverage(&coverage.borrow(), test);
+    }
+    if failed + broken > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Prints a summary of `coverage` for each file loaded, with the forms
+/// that never ran if `--coverage` was given, and writes it to the
+/// `--lcov` file if one was.
+fn report_coverage(coverage: &Coverage, test: &Test) {
+    let mut files = Vec::new();
+    for path in coverage.files() {
+        let Ok(source) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        // files that do not parse have been reported by the tests already
+        if let Ok(file) = coverage.file(path, &source) {
+            files.push(file);
+        }
+    }
+    let percent = |hit: usize, total: usize| {
+        if total == 0 {
+            100.0
+        } else {
+            hit as f64 * 100.0 / total as f64
+        }
+    };
+    if test.coverage {
+        println!("\ncoverage:");
+        for file in &files {
+            let forms = file.forms.iter().filter(|form| form.hits > 0).count();
+            let lines = file.lines();
+            let lines_hit = lines.iter().filter(|(_, hits)| *hits > 0).count();
+            println!(
+                "  {}: {}/{} forms ({:.1}%), {}/{} lines ({:.1}%)",
+                file.path,
+                forms,
+                file.forms.len(),
+                percent(forms, file.forms.len()),
+                lines_hit,
+                lines.len(),
+                percent(lines_hit, lines.len())
+            );
+            for form in file.missed() {
+                println!("    {}:{}: {}", file.path, form.line, form.text);
+            }
+        }
+    }
+    if let Some(lcov) = &test.lcov {
+        if let Err(err) = std::fs::write(lcov, coverage::lcov(&files)) {
+            eprintln!("error: cannot write {}: {}", lcov, err);
+        }
+    }
+}
+
+/// Writes an executable that runs `build.script`, after checking that the
+/// script parses.
+fn run_build(build: &Build) -> ExitCode {
+    let source = match std::fs::read_to_string(&build.script) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: cannot read {}: {}", build.script, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = parser::Parser::new(&mut scanner::Scanner::new(&source)).parse_forms() {
+        Diagnostic::parse(&err, &source).emit(&build.script, &source);
+        return ExitCode::FAILURE;
+    }
+    let script = standalone::Script {
+        name: build.script.clone(),
+        source,
+    };
+    match standalone::build(&script, Path::new(&build.output)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: cannot write {}: {}", build.output, err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Writes `compile.script` as a Rust program.
+fn run_compile(compile: &Compile) -> ExitCode {
+    let source = match std::fs::read_to_string(&compile.script) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: cannot read {}: {}", compile.script, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let program = match compile::to_rust(&source) {
+        Ok(program) => program,
+        Err(compile::CompileError::Parse(err)) => {
+            Diagnostic::parse(&err, &source).emit(&compile.script, &source);
+            return ExitCode::FAILURE;
+        }
+        Err(err) => {
+            eprintln!("error: {}: {}", compile.script, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let Some(output) = &compile.output else {
+        print!("{}", program);
+        return ExitCode::SUCCESS;
+    };
+    match std::fs::write(output, program) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: cannot write {}: {}", output, err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Writes a reference page for each module in `doc.paths` to `doc.out`,
+/// named after the module with `/` as `.`, and an index linking them.
+fn run_doc(doc: &Doc) -> ExitCode {
+    let extension = if doc.html { "html" } else { "md" };
+    let mut status = ExitCode::SUCCESS;
+    let mut pages = Vec::new();
+    for path in &doc.paths {
+        let modules = match modules(Path::new(path)) {
+            Ok(modules) => modules,
+            Err(err) => {
+                eprintln!("error: cannot read {}: {}", path, err);
+                status = ExitCode::FAILURE;
+                continue;
+            }
+        };
+        for (module, file) in modules {
+            let source = match std::fs::read_to_string(&file) {
+                Ok(source) => source,
+                Err(err) => {
+                    eprintln!("error: cannot read {}: {}", file.display(), err);
+                    status = ExitCode::FAILURE;
+                    continue;
+                }
+            };
+            match docgen::entries(&source) {
+                Ok(entries) => pages.push((module, entries)),
+                Err(err) => {
+                    Diagnostic::parse(&err, &source).emit(&file.to_string_lossy(), &source);
+                    status = ExitCode::FAILURE;
+                }
+            }
+        }
+    }
+
+    let mut links = Vec::new();
+    let mut files = Vec::new();
+    for (module, entries) in &pages {
+        let page = format!("{}.{}", module.replace('/', "."), extension);
+        let text = if doc.html {
+            docgen::html(module, entries)
+        } else {
+            docgen::markdown(module, entries)
+        };
+        links.push((module.clone(), page.clone()));
+        files.push((page, text));
+    }
+    let index = if doc.html {
+        docgen::html_index(&links)
+    } else {
+        docgen::markdown_index(&links)
+    };
+    files.push((format!("index.{}", extension), index));
+
+    let out = Path::new(&doc.out);
+    if let Err(err) = std::fs::create_dir_all(out) {
+        eprintln!("error: cannot create {}: {}", doc.out, err);
+        return ExitCode::FAILURE;
+    }
+    for (page, text) in files {
+        if let Err(err) = std::fs::write(out.join(&page), text) {
+            eprintln!("error: cannot write {}: {}", out.join(&page).display(), err);
+            status = ExitCode::FAILURE;
+        }
+    }
+    status
+}
+
+/// How long `jon bench` runs each workload and phase for, at least.
+const BENCH_TIME: Duration = Duration::from_millis(500);
+
+/// Times each phase of the workloads whose `name/phase` contains `filter`,
+/// printing the fastest and median run. Each run gets a fresh interpreter,
+/// made outside the timing.
+fn run_bench(filter: Option<&str>) -> ExitCode {
+    for workload in bench::workloads() {
+        for phase in bench::Phase::ALL {
+            let name = format!("{}/{}", workload.name, phase.name());
+            if filter.is_some_and(|filter| !name.contains(filter)) {
+                continue;
+            }
+            let mut times = Vec::new();
+            let start = Instant::now();
+            while times.len() < 10 || start.elapsed() < BENCH_TIME {
+                let mut interpreter = eval::Interpreter::new();
+                let run = Instant::now();
+                if let Err(err) = bench::run(phase, &workload.source, &mut interpreter) {
+                    eprintln!("error: {}: {}", name, err);
+                    return ExitCode::FAILURE;
+                }
+                times.push(run.elapsed());
+            }
+            times.sort();
+            println!(
+                "{:<20} fastest {:>12?}  median {:>12?}  ({} runs)",
+                name,
+                times[0],
+                times[times.len() / 2],
+                times.len()
+            );
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Runs the program on a thread with room for calls nested as deeply as
+/// the interpreter allows, so that runaway recursion fails with a
+/// `stack-overflow` condition rather than aborting.
+fn main() -> ExitCode {
+    let program = std::thread::Builder::new()
+        .name("main".into())
+        .stack_size(eval::STACK_SIZE)
+        .spawn(run_program);
+    match program.map(|program| program.join()) {
+        Ok(Ok(code)) => code,
+        // the panic has been reported
+        Ok(Err(_)) => ExitCode::from(101),
+        Err(err) => {
+            eprintln!("error: cannot start: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_program() -> ExitCode {
+    if let Some(script) = standalone::embedded() {
+        let options = Options {
+            no_cache: true,
+            args: std::env::args().skip(1).collect(),
+            ..Options::default()
+        };
+        return run_source(&script.name, &script.source, &options, None, false);
+    }
+    let mut cli = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, cli::USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match Manifest::find() {
+        Ok(manifest) => cli.options.manifest = manifest,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(manifest) = cli
+        .options
+        .manifest
+        .as_ref()
+        .filter(|_| loads_packages(cli.command.as_ref()))
+    {
+        match packages::resolve(manifest, package_cache(&cli.options).as_deref()) {
+            Ok(packages) => cli.options.packages = packages,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let options = &cli.options;
+    match cli.command {
+        Some(Command::Help) => {
+            println!("{}", cli::USAGE);
+            ExitCode::SUCCESS
+        }
+        Some(Command::Version) => {
+            println!("jon {}", env!("CARGO_PKG_VERSION"));
+            ExitCode::SUCCESS
+        }
+        Some(Command::Eval(expr)) => run_source("<eval>", &expr, options, None, true),
+        Some(Command::Check(check)) => run_check(&check, options),
+        Some(Command::Fmt(fmt)) => run_fmt(&fmt),
+        Some(Command::Bench(filter)) => run_bench(filter.as_deref()),
+        Some(Command::Image(image)) => run_image(&image, options),
+        Some(Command::Doc(doc)) => run_doc(&doc),
+        Some(Command::Build(build)) => run_build(&build),
+        Some(Command::Compile(compile)) => run_compile(&compile),
+        Some(Command::Test(test)) => run_test(&test, options),
+        Some(Command::Add(add)) => run_add(&add, options),
+        Some(Command::Jupyter(path)) => run_jupyter(&path, options),
+        Some(Command::JupyterInstall) => run_jupyter_install(),
+        Some(Command::Nrepl(port)) => match nrepl::serve(port, options) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                ExitCode::FAILURE
+            }
+        },
+        Some(Command::Run(path)) if path == "-" => run_stdin(options),
+        Some(Command::Run(path)) => run_file(&path, options),
+        Some(Command::Literate(literate)) => run_literate(&literate, options),
+        Some(Command::RunMain) => match options.manifest.as_ref().and_then(|m| m.main.as_ref()) {
+            Some(main) => run_file(&main.to_string_lossy(), options),
+            None => {
+                eprintln!(
+                    "error: run needs a file, or a jon.toml naming main\n\n{}",
+                    cli::USAGE
+                );
+                ExitCode::FAILURE
+            }
+        },
+        None if !io::stdin().is_terminal() => run_stdin(options),
+        Some(Command::Repl) | None => match repl::run(options) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
@@ -0,0 +1,251 @@
+//! The project manifest, `jon.toml`, found by walking up from the current
+//! directory. It names the project's files so that commands need none:
+//!
+//! ```toml
+//! main = "src/main.jon"          # what jon run runs without a file
+//! sources = ["src"]              # searched by load, checked by jon check
+//! prelude = ["src/prelude.jon"]  # loaded after the prelude
+//! tests = ["tests"]              # searched by jon test
+//!
+//! [lint]
+//! allow = ["unused-binding"]     # on top of .jonlint
+//!
+//! [dependencies]                 # libraries, added with jon add
+//! strings = { git = "https://example.com/strings.git", rev = "v1" }
+//! utils = { path = "../utils" }
+//! ```
+//!
+//! Paths are relative to the directory holding the manifest.
+
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "jon.toml";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Manifest {
+    /// The directory holding the manifest, empty for the current one.
+    pub root: PathBuf,
+    pub main: Option<PathBuf>,
+    pub sources: Vec<PathBuf>,
+    pub prelude: Vec<PathBuf>,
+    pub tests: Vec<PathBuf>,
+    /// Lint directives such as `allow unused-binding`.
+    pub lint: Vec<String>,
+    /// The libraries the project uses, sorted by name.
+    pub dependencies: Vec<Dependency>,
+}
+
+/// A library the project uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    pub source: Source,
+}
+
+/// Where a library comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// A git repository, at the commit, branch, or tag `rev` if given.
+    Git { url: String, rev: Option<String> },
+    /// A directory, relative to the current one like the other paths.
+    Path(PathBuf),
+}
+
+impl Manifest {
+    /// The manifest of the project the current directory is in, if any.
+    pub fn find() -> Result<Option<Self>, String> {
+        let cwd = std::env::current_dir().map_err(|err| err.to_string())?;
+        for dir in cwd.ancestors() {
+            if let Some(manifest) = Self::read(dir.strip_prefix(&cwd).unwrap_or(dir))? {
+                return Ok(Some(manifest));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The manifest in the directory `root`, if it has one.
+    pub fn read(root: &Path) -> Result<Option<Self>, String> {
+        let path = root.join(MANIFEST_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(&path)
+            .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+        Self::parse(root, &text)
+            .map(Some)
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// The manifest `text` of the project in `root`.
+    pub fn parse(root: &Path, text: &str) -> Result<Self, String> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|err: toml::de::Error| err.message().to_string())?;
+        let mut manifest = Manifest {
+            root: root.to_path_buf(),
+            ..Manifest::default()
+        };
+        for (key, value) in &table {
+            match key.as_str() {
+                "main" => manifest.main = Some(root.join(string(key, value)?)),
+                "sources" => manifest.sources = paths(root, key, value)?,
+                "prelude" => manifest.prelude = paths(root, key, value)?,
+                "tests" => manifest.tests = paths(root, key, value)?,
+                "lint" => {
+                    let Some(lint) = value.as_table() else {
+                        return Err("lint must be a table".into());
+                    };
+                    for (directive, names) in lint {
+                        let names = strings(directive, names)?;
+                        manifest
+                            .lint
+                            .push(format!("{} {}", directive, names.join(" ")));
+                    }
+                }
+                "dependencies" => {
+                    let Some(dependencies) = value.as_table() else {
+                        return Err("dependencies must be a table".into());
+                    };
+                    for (name, source) in dependencies {
+                        manifest.dependencies.push(Dependency {
+                            name: name.clone(),
+                            source: dependency_source(root, name, source)?,
+                        });
+                    }
+                }
+                _ => return Err(format!("unknown key {}", key)),
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+/// Fails unless `name` can name a dependency: letters, digits, `_`, and
+/// `-` only, as it names the library's directory in the cache.
+pub fn check_name(name: &str) -> Result<(), String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if name.is_empty() || !name.chars().all(valid) {
+        return Err(format!(
+            "dependency name {:?} may only have letters, digits, _ and -",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// The source of the dependency `name`: a table with a `git` URL and
+/// perhaps a `rev`, or with a `path`. A `rev` may not start with `-`, which
+/// git would read as an option.
+fn dependency_source(root: &Path, name: &str, value: &toml::Value) -> Result<Source, String> {
+    check_name(name)?;
+    let field = |key| value.get(key).map(|value| string(key, value)).transpose();
+    match (field("git")?, field("path")?) {
+        (Some(url), None) => match field("rev")? {
+            Some(rev) if rev.starts_with('-') => {
+                Err(format!("dependency {} has a rev starting with -", name))
+            }
+            rev => Ok(Source::Git {
+                url: url.into(),
+                rev: rev.map(Into::into),
+            }),
+        },
+        (None, Some(path)) => Ok(Source::Path(root.join(path))),
+        _ => Err(format!("dependency {} needs either git or path", name)),
+    }
+}
+
+/// `text`, a manifest, with the line `name = entry` added to its
+/// dependencies.
+pub fn add_dependency(text: &str, name: &str, entry: &str) -> String {
+    let line = format!("{} = {}\n", name, entry);
+    let mut lines: Vec<&str> = text.split_inclusive('\n').collect();
+    match lines
+        .iter()
+        .position(|line| line.trim() == "[dependencies]")
+    {
+        Some(header) => {
+            lines.insert(header + 1, &line);
+            lines.concat()
+        }
+        None => {
+            let mut text = text.to_string();
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            format!("{}[dependencies]\n{}", text, line)
+        }
+    }
+}
+
+fn string<'a>(key: &str, value: &'a toml::Value) -> Result<&'a str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("{} must be a string", key))
+}
+
+fn strings<'a>(key: &str, value: &'a toml::Value) -> Result<Vec<&'a str>, String> {
+    let error = || format!("{} must be a list of strings", key);
+    value
+        .as_array()
+        .ok_or_else(error)?
+        .iter()
+        .map(|item| item.as_str().ok_or_else(error))
+        .collect()
+}
+
+fn paths(root: &Path, key: &str, value: &toml::Value) -> Result<Vec<PathBuf>, String> {
+    Ok(strings(key, value)?
+        .into_iter()
+        .map(|path| root.join(path))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = Manifest::parse(
+            Path::new("app"),
+            "main = \"src/main.jon\"\nsources = [\"src\", \"lib\"]\n[lint]\nallow = [\"W0001\", \"empty-body\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            manifest,
+            Manifest {
+                root: "app".into(),
+                main: Some("app/src/main.jon".into()),
+                sources: vec!["app/src".into(), "app/lib".into()],
+                lint: vec!["allow W0001 empty-body".into()],
+                ..Manifest::default()
+            }
+        );
+        let text = add_dependency("main = \"a.jon\"", "utils", "{ path = \"../utils\" }");
+        let manifest = Manifest::parse(Path::new(""), &text).unwrap();
+        assert_eq!(
+            manifest.dependencies,
+            [Dependency {
+                name: "utils".into(),
+                source: Source::Path("../utils".into()),
+            }]
+        );
+        let text = add_dependency(&text, "strings", "{ git = \"https://a/s.git\" }");
+        assert_eq!(
+            Manifest::parse(Path::new(""), &text).unwrap().dependencies[0].source,
+            Source::Git {
+                url: "https://a/s.git".into(),
+                rev: None
+            }
+        );
+        let text = "[dependencies]\ns = { git = \"https://a/s.git\", rev = \"--upload-pack=x\" }";
+        assert!(Manifest::parse(Path::new(""), text).is_err());
+        let text = "[dependencies]\n\"../../x\" = { git = \"https://a/s.git\" }";
+        assert!(Manifest::parse(Path::new(""), text).is_err());
+        assert!(Manifest::parse(Path::new(""), "mian = \"a.jon\"").is_err());
+        assert!(Manifest::parse(Path::new(""), "sources = \"src\"").is_err());
+    }
+}
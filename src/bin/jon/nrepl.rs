@@ -0,0 +1,441 @@
+//! `jon nrepl`: a server speaking the bencoded nREPL protocol, so editor
+//! plugins can send forms from a buffer and show the results inline. It
+//! understands the ops `describe`, `clone`, `close`, `eval`, `load-file`,
+//! `complete`, `doc`, and `interrupt`. Clients are served one at a time,
+//! all in the same interpreter, and every session shares it.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+
+use jon::diagnostic::Diagnostic;
+use jon::eval::{EvalError, Interpreter};
+use jon::parser::Parser;
+use jon::scanner::{self, Scanner};
+
+use crate::cli::Options;
+use crate::repl::{completions, describe_doc};
+use crate::{eval_protected, new_runtime, SharedOutput};
+
+/// The longest string a message may hold, so that a bad length cannot
+/// exhaust memory.
+const MAX_STRING: usize = 64 << 20;
+
+/// The ops `describe` lists.
+const OPS: [&str; 8] = [
+    "clone",
+    "close",
+    "complete",
+    "describe",
+    "doc",
+    "eval",
+    "interrupt",
+    "load-file",
+];
+
+/// A bencoded value. Byte strings are read as UTF-8, replacing what is not.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bencode {
+    Int(i64),
+    Str(String),
+    List(Vec<Bencode>),
+    Dict(BTreeMap<String, Bencode>),
+}
+
+type Message = BTreeMap<String, Bencode>;
+
+impl Bencode {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Bencode::Int(n) => out.extend_from_slice(format!("i{}e", n).as_bytes()),
+            Bencode::Str(s) => {
+                out.extend_from_slice(format!("{}:", s.len()).as_bytes());
+                out.extend_from_slice(s.as_bytes());
+            }
+            Bencode::List(items) => {
+                out.push(b'l');
+                for item in items {
+                    item.encode(out);
+                }
+                out.push(b'e');
+            }
+            Bencode::Dict(entries) => {
+                out.push(b'd');
+                for (key, value) in entries {
+                    Bencode::Str(key.clone()).encode(out);
+                    value.encode(out);
+                }
+                out.push(b'e');
+            }
+        }
+    }
+
+    /// The next value of `input`, or `None` if it ends first.
+    pub fn decode(input: &mut impl BufRead) -> io::Result<Option<Self>> {
+        let mut first = [0];
+        match input.read(&mut first)? {
+            0 => Ok(None),
+            _ => Self::decode_from(first[0], input).map(Some),
+        }
+    }
+
+    fn decode_from(first: u8, input: &mut impl BufRead) -> io::Result<Self> {
+        match first {
+            b'i' => read_until(input, b'e')?
+                .parse()
+                .map(Bencode::Int)
+                .map_err(|_| invalid("malformed integer")),
+            b'l' => {
+                let mut items = Vec::new();
+                loop {
+                    match read_byte(input)? {
+                        b'e' => return Ok(Bencode::List(items)),
+                        byte => items.push(Self::decode_from(byte, input)?),
+                    }
+                }
+            }
+            b'd' => {
+                let mut entries = BTreeMap::new();
+                loop {
+                    let key = match read_byte(input)? {
+                        b'e' => return Ok(Bencode::Dict(entries)),
+                        byte => match Self::decode_from(byte, input)? {
+                            Bencode::Str(key) => key,
+                            _ => return Err(invalid("dictionary key is not a string")),
+                        },
+                    };
+                    let value = Self::decode_from(read_byte(input)?, input)?;
+                    entries.insert(key, value);
+                }
+            }
+            b'0'..=b'9' => {
+                let length = format!("{}{}", first as char, read_until(input, b':')?);
+                let length: usize = length.parse().map_err(|_| invalid("malformed length"))?;
+                if length > MAX_STRING {
+                    return Err(invalid("string too long"));
+                }
+                let mut bytes = vec![0; length];
+                input.read_exact(&mut bytes)?;
+                Ok(Bencode::Str(String::from_utf8_lossy(&bytes).into_owned()))
+            }
+            _ => Err(invalid("malformed value")),
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_byte(input: &mut impl BufRead) -> io::Result<u8> {
+    let mut byte = [0];
+    input.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+/// The text before the next `end` byte, which is consumed.
+fn read_until(input: &mut impl BufRead, end: u8) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    input.read_until(end, &mut bytes)?;
+    if bytes.pop() != Some(end) {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid("malformed number"))
+}
+
+fn str(s: impl Into<String>) -> Bencode {
+    Bencode::Str(s.into())
+}
+
+fn status(statuses: &[&str]) -> Bencode {
+    Bencode::List(statuses.iter().map(|s| str(*s)).collect())
+}
+
+/// The interpreter clients evaluate in, and what it prints.
+pub struct Server {
+    interpreter: Interpreter,
+    output: SharedOutput,
+    interrupt: Arc<AtomicBool>,
+    sessions: usize,
+}
+
+impl Server {
+    pub fn new(mut interpreter: Interpreter) -> Self {
+        let output = SharedOutput::default();
+        let interrupt = Arc::new(AtomicBool::new(false));
+        interpreter.set_output(output.clone());
+        interpreter.set_interrupt(interrupt.clone());
+        Self {
+            interpreter,
+            output,
+            interrupt,
+            sessions: 0,
+        }
+    }
+
+    /// Answers `request`, giving `respond` each response in turn; the last
+    /// has the status `done`.
+    pub fn handle(&mut self, request: &Message, respond: &mut dyn FnMut(Message)) {
+        let field = |key: &str| match request.get(key) {
+            Some(Bencode::Str(s)) => Some(s.as_str()),
+            _ => None,
+        };
+        // every response carries the id and session of its request
+        let mut reply = |mut response: Message| {
+            for key in ["id", "session"] {
+                if let Some(value) = request.get(key) {
+                    response.insert(key.into(), value.clone());
+                }
+            }
+            respond(response)
+        };
+        let mut done = vec!["done"];
+        match field("op").unwrap_or_default() {
+            "describe" => {
+                let ops = OPS
+                    .iter()
+                    .map(|op| (op.to_string(), Bencode::Dict(Message::new())));
+                let version =
+                    Message::from([("version-string".into(), str(env!("CARGO_PKG_VERSION")))]);
+                reply(Message::from([
+                    ("ops".into(), Bencode::Dict(ops.collect())),
+                    (
+                        "versions".into(),
+                        Bencode::Dict(Message::from([("jon".into(), Bencode::Dict(version))])),
+                    ),
+                ]));
+            }
+            "clone" => {
+                self.sessions += 1;
+                let session = format!("session-{}", self.sessions);
+                reply(Message::from([("new-session".into(), str(session))]));
+            }
+            "close" => done.push("session-closed"),
+            "eval" => match field("code") {
+                Some(code) => self.eval("<nrepl>", code, &mut reply),
+                None => done.extend(["error", "no-code"]),
+            },
+            "load-file" => {
+                let name = field("file-path")
+                    .or(field("file-name"))
+                    .unwrap_or("<nrepl>");
+                let code = match field("file") {
+                    Some(code) => Ok(code.to_string()),
+                    None => std::fs::read_to_string(name),
+                };
+                match code {
+                    Ok(code) => self.eval(name, &code, &mut reply),
+                    Err(err) => {
+                        reply(Message::from([("err".into(), str(format!("{}\n", err)))]));
+                        done.push("error");
+                    }
+                }
+            }
+            "complete" | "completions" => {
+                let prefix = field("prefix").unwrap_or_default();
+                let completions =
+                    completions(&self.interpreter, prefix)
+                        .into_iter()
+                        .map(|(name, kind)| {
+                            Bencode::Dict(Message::from([
+                                ("candidate".into(), str(name)),
+                                ("type".into(), str(kind)),
+                            ]))
+                        });
+                reply(Message::from([(
+                    "completions".into(),
+                    Bencode::List(completions.collect()),
+                )]));
+            }
+            "doc" => match field("symbol").or(field("sym")) {
+                Some(name) => {
+                    let doc = describe_doc(&self.interpreter, name);
+                    reply(Message::from([("doc".into(), str(doc))]));
+                }
+                None => done.extend(["error", "no-symbol"]),
+            },
+            // the flag was set as the request arrived; it only stops an
+            // evaluation already under way, so nothing is left to do
+            "interrupt" => self.interrupt.store(false, Ordering::Relaxed),
+            _ => done.extend(["error", "unknown-op"]),
+        }
+        reply(Message::from([("status".into(), status(&done))]));
+    }
+
+    /// Evaluates each form of `code` in turn, responding with what it
+    /// printed and its value, and stops at the first error.
+    fn eval(&mut self, name: &str, code: &str, reply: &mut dyn FnMut(Message)) {
+        let forms = match Parser::new(&mut Scanner::new(code)).parse_forms() {
+            Ok(forms) => forms,
+            Err(err) => {
+                let rendered = Diagnostic::parse(&err, code).render(name, code, false);
+                reply(Message::from([
+                    ("err".into(), str(rendered)),
+                    ("status".into(), status(&["eval-error"])),
+                ]));
+                return;
+            }
+        };
+        for (span, expr) in forms {
+            self.interpreter
+                .set_origin(name, scanner::line_column(code, span.start).0);
+            let result = eval_protected(&mut self.interpreter, &expr);
+            let out = self.output.take();
+            if !out.is_empty() {
+                reply(Message::from([("out".into(), str(out))]));
+            }
+            match result {
+                Ok(value) => {
+                    let floats = self.interpreter.float_format().unwrap_or_default();
+                    reply(Message::from([
+                        ("value".into(), str(floats.value(&value))),
+                        ("ns".into(), str("user")),
+                    ]));
+                }
+                Err(err) => {
+                    let rendered = Diagnostic::eval(&err, span)
+                        .with_expansions(self.interpreter.error_expansions(), code)
+                        .render(name, code, false);
+                    let state = match err {
+                        EvalError::Interrupted => "interrupted",
+                        _ => "eval-error",
+                    };
+                    reply(Message::from([
+                        ("err".into(), str(rendered)),
+                        ("ex".into(), str(err.code())),
+                        ("status".into(), status(&[state])),
+                    ]));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Listens on `port` of the loopback interface, any free one if 0, and
+/// serves clients until the program is stopped.
+pub fn serve(port: u16, options: &Options) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!(
+        "nREPL server started on port {} on host 127.0.0.1 - nrepl://127.0.0.1:{}",
+        listener.local_addr()?.port(),
+        listener.local_addr()?.port()
+    );
+    let mut server = Server::new(new_runtime(options));
+    for stream in listener.incoming() {
+        if let Err(err) = serve_client(&mut server, stream?) {
+            eprintln!("warning: nREPL client dropped: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// Answers the requests of one client until it disconnects. They are read
+/// on another thread, so that an `interrupt` can stop an evaluation.
+fn serve_client(server: &mut Server, stream: TcpStream) -> io::Result<()> {
+    let (send, requests) = mpsc::channel();
+    let mut input = BufReader::new(stream.try_clone()?);
+    let interrupt = server.interrupt.clone();
+    let reader = std::thread::spawn(move || -> io::Result<()> {
+        while let Some(request) = Bencode::decode(&mut input)? {
+            let Bencode::Dict(request) = request else {
+                return Err(invalid("request is not a dictionary"));
+            };
+            if request.get("op") == Some(&str("interrupt")) {
+                interrupt.store(true, Ordering::Relaxed);
+            }
+            if send.send(request).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    });
+    let mut output = stream;
+    for request in requests {
+        let mut written = Ok(());
+        server.handle(&request, &mut |response| {
+            let mut bytes = Vec::new();
+            Bencode::Dict(response).encode(&mut bytes);
+            if written.is_ok() {
+                written = output.write_all(&bytes);
+            }
+        });
+        if let Err(err) = written {
+            // which ends the reader too
+            let _ = output.shutdown(Shutdown::Both);
+            return Err(err);
+        }
+    }
+    reader
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("reader panicked")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(entries: &[(&str, &str)]) -> Message {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), str(*value)))
+            .collect()
+    }
+
+    #[test]
+    fn test_bencode_and_requests() {
+        let message = Bencode::Dict(Message::from([
+            ("id".into(), Bencode::Int(-7)),
+            ("ops".into(), Bencode::List(vec![str("eval"), str("né")])),
+        ]));
+        let mut bytes = Vec::new();
+        message.encode(&mut bytes);
+        assert_eq!(bytes, "d2:idi-7e3:opsl4:eval3:néee".as_bytes());
+        let mut input = io::Cursor::new(bytes);
+        assert_eq!(Bencode::decode(&mut input).unwrap(), Some(message));
+        assert_eq!(Bencode::decode(&mut input).unwrap(), None);
+        assert!(Bencode::decode(&mut io::Cursor::new("d2:id")).is_err());
+
+        let mut server = Server::new(Interpreter::new());
+        let mut responses = Vec::new();
+        let eval = request(&[
+            ("op", "eval"),
+            ("id", "1"),
+            ("code", "(def (sq x) (* x x)) (print \"hi\") (sq 3) (car 1)"),
+        ]);
+        server.handle(&eval, &mut |response| responses.push(response));
+        let fields: Vec<_> = responses
+            .iter()
+            .map(|response| {
+                let keys: Vec<&str> = response.keys().map(String::as_str).collect();
+                (keys.join(","), response.get("value").cloned())
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("id,ns,value".into(), Some(str("sq"))),
+                ("id,out".into(), None),
+                ("id,ns,value".into(), Some(str("nil"))),
+                ("id,ns,value".into(), Some(str("9"))),
+                ("err,ex,id,status".into(), None),
+                ("id,status".into(), None),
+            ]
+        );
+        assert_eq!(responses[1]["out"], str("hi\n"));
+        responses.clear();
+        server.handle(
+            &request(&[("op", "complete"), ("prefix", "sq")]),
+            &mut |response| responses.push(response),
+        );
+        assert_eq!(
+            responses[0]["completions"],
+            Bencode::List(vec![Bencode::Dict(Message::from([
+                ("candidate".into(), str("sq")),
+                ("type".into(), str("function")),
+            ]))])
+        );
+    }
+}
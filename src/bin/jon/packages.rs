@@ -0,0 +1,171 @@
+//! The libraries a project's `jon.toml` depends on. Git libraries are
+//! cloned into the `packages` directory of the cache once; each library's
+//! source directories join the project's for `load` to search, and its
+//! prelude files are loaded before the project's.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use jon::cache;
+
+use crate::manifest::{Dependency, Manifest, Source};
+
+/// A library ready to load from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub name: String,
+    pub root: PathBuf,
+    /// The library's source directories, or its root if it names none.
+    pub sources: Vec<PathBuf>,
+    pub prelude: Vec<PathBuf>,
+}
+
+/// The libraries `manifest` depends on, directly or through other
+/// libraries, each once, by name. Git libraries not yet in `cache` are
+/// cloned into it.
+pub fn resolve(manifest: &Manifest, cache: Option<&Path>) -> Result<Vec<Package>, String> {
+    let mut packages = Vec::new();
+    resolve_into(manifest, cache, &mut packages)?;
+    Ok(packages)
+}
+
+fn resolve_into(
+    manifest: &Manifest,
+    cache: Option<&Path>,
+    packages: &mut Vec<Package>,
+) -> Result<(), String> {
+    for dependency in &manifest.dependencies {
+        if packages
+            .iter()
+            .any(|package| package.name == dependency.name)
+        {
+            continue;
+        }
+        let root = match &dependency.source {
+            Source::Path(path) => path.clone(),
+            Source::Git { url, rev } => fetch(dependency, url, rev.as_deref(), cache)?,
+        };
+        let own = Manifest::read(&root)?.unwrap_or_default();
+        packages.push(Package {
+            name: dependency.name.clone(),
+            sources: if own.sources.is_empty() {
+                vec![root.clone()]
+            } else {
+                own.sources.clone()
+            },
+            prelude: own.prelude.clone(),
+            root,
+        });
+        resolve_into(&own, cache, packages)?;
+    }
+    Ok(())
+}
+
+/// The directory holding the git library `dependency`, cloning it at `rev`
+/// unless it is already in `cache`.
+fn fetch(
+    dependency: &Dependency,
+    url: &str,
+    rev: Option<&str>,
+    cache: Option<&Path>,
+) -> Result<PathBuf, String> {
+    let cache = cache.ok_or("no cache directory to fetch packages into; set --cache-dir")?;
+    let key = cache::hash(&format!("{} {}", url, rev.unwrap_or_default()));
+    let dir = cache
+        .join("packages")
+        .join(format!("{}-{:016x}", dependency.name, key));
+    if dir.is_dir() {
+        return Ok(dir);
+    }
+    eprintln!("fetching {} from {}", dependency.name, url);
+    // cloned aside and renamed, so that a failed clone leaves nothing
+    let partial = dir.with_extension(format!("{}.tmp", std::process::id()));
+    let mut clone = Command::new("git");
+    clone.args(["clone", "--quiet"]);
+    if rev.is_none() {
+        clone.args(["--depth", "1"]);
+    }
+    let cloned = std::fs::create_dir_all(cache.join("packages"))
+        .map_err(|err| err.to_string())
+        .and_then(|()| git(clone.arg("--").arg(url).arg(&partial)))
+        .and_then(|()| match rev {
+            Some(rev) => git(Command::new("git")
+                .arg("-C")
+                .arg(&partial)
+                .args(["checkout", "--quiet", rev])),
+            None => Ok(()),
+        })
+        .and_then(|()| std::fs::rename(&partial, &dir).map_err(|err| err.to_string()));
+    if let Err(err) = cloned {
+        let _ = std::fs::remove_dir_all(&partial);
+        return Err(format!("cannot fetch {}: {}", dependency.name, err));
+    }
+    Ok(dir)
+}
+
+/// Runs a git command, failing with what it printed if it fails.
+fn git(command: &mut Command) -> Result<(), String> {
+    let output = command
+        .output()
+        .map_err(|err| format!("cannot run git: {}", err))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// The name a library at `source`, a git URL or a path, goes by: its last
+/// path segment without `.git`.
+pub fn default_name(source: &str) -> Option<String> {
+    let last = source
+        .trim_end_matches('/')
+        .rsplit(['/', '\\', ':'])
+        .next()?;
+    let name = last.strip_suffix(".git").unwrap_or(last);
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_path_dependencies() {
+        let dir = std::env::temp_dir().join(format!("jon-packages-{}", std::process::id()));
+        let utils = dir.join("utils");
+        std::fs::create_dir_all(utils.join("lib")).unwrap();
+        std::fs::write(
+            utils.join("jon.toml"),
+            "sources = [\"lib\"]\nprelude = [\"lib/ext.jon\"]\n[dependencies]\nbase = { path = \"../base\" }\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.join("base")).unwrap();
+        let manifest =
+            Manifest::parse(&dir, "[dependencies]\nutils = { path = \"utils\" }\n").unwrap();
+        let packages = resolve(&manifest, None).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            packages,
+            [
+                Package {
+                    name: "utils".into(),
+                    root: utils.clone(),
+                    sources: vec![utils.join("lib")],
+                    prelude: vec![utils.join("lib/ext.jon")],
+                },
+                Package {
+                    name: "base".into(),
+                    root: utils.join("../base"),
+                    sources: vec![utils.join("../base")],
+                    prelude: Vec::new(),
+                },
+            ]
+        );
+        assert_eq!(
+            default_name("https://example.com/jon/strings.git"),
+            Some("strings".into())
+        );
+        assert_eq!(default_name("../utils/"), Some("utils".into()));
+    }
+}
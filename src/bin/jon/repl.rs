@@ -0,0 +1,704 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Config, Editor};
+
+use jon::builtins;
+use jon::diagnostic::Diagnostic;
+use jon::eval::{EvalError, Interpreter, Origin, Snapshot, SPECIAL_FORMS};
+use jon::formatter;
+use jon::parser::Parser;
+use jon::pretty;
+use jon::scanner::{self, Scanner};
+use jon::session;
+use jon::value::Value;
+
+use crate::cli::Options;
+use crate::editor::JonHelper;
+use crate::{eval_protected, new_runtime};
+
+const HISTORY_SIZE: usize = 1000;
+const DEFAULT_PROMPT: &str = "jon";
+/// How many characters of a value `:describe` shows.
+const PREVIEW_WIDTH: usize = 72;
+/// The starting `*print-length*`: how many items of a list a result shows.
+const PRINT_LENGTH: f32 = 100.0;
+/// The symbols holding the last three results and the last error.
+const RESULT_HISTORY: [&str; 4] = ["*1", "*2", "*3", "*e"];
+/// How many entries `:undo` can take back.
+const UNDO_LIMIT: usize = 50;
+
+/// The REPL history file: `$JON_HISTORY` if set, otherwise `~/.jon_history`.
+fn history_path() -> Option<PathBuf> {
+    match std::env::var_os("JON_HISTORY") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".jon_history")),
+    }
+}
+
+/// Evaluates every form of one complete REPL entry in order, printing each
+/// result, and reports rather than propagates any scanner, parser, or
+/// evaluation failure. Evaluation stops at the first error.
+fn eval_line(interpreter: &mut Interpreter, line: &str, options: &Options, settings: &Settings) {
+    let mut scanner = Scanner::new(line);
+    let mut parser = Parser::new(&mut scanner);
+    let forms = match parser.parse_forms() {
+        Ok(forms) => forms,
+        Err(err) => {
+            Diagnostic::parse(&err, line).emit("<repl>", line);
+            return;
+        }
+    };
+    // a Ctrl-C that came too late for the last entry is not for this one
+    interrupt_flag().store(false, Ordering::Relaxed);
+    let start = Instant::now();
+    for (span, expr) in forms {
+        interpreter.set_origin("<repl>", scanner::line_column(line, span.start).0);
+        match eval_protected(interpreter, &expr) {
+            Ok(value) => {
+                let limits = print_limits(interpreter);
+                let floats = interpreter.float_format().unwrap_or_default();
+                let text = pretty::pretty_limited(&value, settings.width, &limits, &floats);
+                if options.show_types {
+                    println!("{} \x1b[2m: {}\x1b[0m", text, value.type_name())
+                } else {
+                    println!("{}", text)
+                }
+                if limits.elide(&value) {
+                    println!("\x1b[2m(cut short; :more shows all of it)\x1b[0m");
+                }
+                record_result(interpreter, value);
+            }
+            Err(err) => {
+                Diagnostic::eval(&err, span)
+                    .with_expansions(interpreter.error_expansions(), line)
+                    .emit("<repl>", line);
+                record_error(interpreter, &err);
+                break;
+            }
+        }
+    }
+    if settings.time {
+        println!("\x1b[2mtime: {:?}\x1b[0m", start.elapsed());
+    }
+}
+
+/// The flag Ctrl-C sets, which stops the evaluation running in any REPL
+/// session. At the prompt the line editor reads Ctrl-C as a key instead.
+fn interrupt_flag() -> Arc<AtomicBool> {
+    static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    FLAG.get_or_init(|| {
+        let flag = Arc::new(AtomicBool::new(false));
+        let set = flag.clone();
+        if let Err(err) = ctrlc::set_handler(move || set.store(true, Ordering::Relaxed)) {
+            eprintln!("warning: Ctrl-C cannot interrupt evaluations: {}", err);
+        }
+        flag
+    })
+    .clone()
+}
+
+/// Binds the symbols holding the last three results and the last error.
+fn bind_result_history(interpreter: &mut Interpreter) {
+    for name in RESULT_HISTORY {
+        interpreter.set_global(name, Value::Nil);
+    }
+}
+
+/// Keeps `before`, taken ahead of an entry, for `:undo` if the entry
+/// defined anything besides recording its results.
+fn remember(undo: &mut Vec<Snapshot>, interpreter: &Interpreter, before: Snapshot) {
+    let changed = interpreter.changed_since(&before);
+    if changed
+        .iter()
+        .any(|name| !RESULT_HISTORY.contains(&name.as_str()))
+    {
+        if undo.len() == UNDO_LIMIT {
+            undo.remove(0);
+        }
+        undo.push(before);
+    }
+}
+
+/// How much of each result to print, from `*print-length*` and
+/// `*print-depth*`; anything but a whole number means no limit.
+fn print_limits(interpreter: &Interpreter) -> pretty::Limits {
+    let limit = |name| match interpreter.get_global(name) {
+        Some(Value::Number(n)) if n >= 0.0 && n % 1.0 == 0.0 => Some(n as usize),
+        _ => None,
+    };
+    pretty::Limits {
+        length: limit("*print-length*"),
+        depth: limit("*print-depth*"),
+    }
+}
+
+/// Shows `text` through `$PAGER` when it is set, printing it otherwise or
+/// if the pager cannot be run.
+fn page(text: &str) {
+    if let Some(pager) = std::env::var_os("PAGER").filter(|pager| !pager.is_empty()) {
+        let child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(pager)
+            .stdin(Stdio::piped())
+            .spawn();
+        if let Ok(mut child) = child {
+            if let Some(mut stdin) = child.stdin.take() {
+                // the pager may quit before reading everything
+                let _ = writeln!(stdin, "{}", text);
+            }
+            if child.wait().is_ok() {
+                return;
+            }
+        }
+    }
+    println!("{}", text);
+}
+
+/// The prompt built from the `*prompt*` string, showing how many parens are
+/// still open while a form spans several lines: `jon> `, then `jon(2)> `.
+fn prompt(interpreter: &Interpreter, depth: usize) -> String {
+    let name = match interpreter.get_global("*prompt*") {
+        Some(Value::String(name)) => name,
+        _ => DEFAULT_PROMPT.to_owned(),
+    };
+    if depth == 0 {
+        format!("{}> ", name)
+    } else {
+        format!("{}({})> ", name, depth)
+    }
+}
+
+/// Shifts `value` into `*1`, moving the older results down to `*2` and `*3`.
+fn record_result(interpreter: &mut Interpreter, value: Value) {
+    let first = interpreter.get_global("*1").unwrap_or(Value::Nil);
+    let second = interpreter.get_global("*2").unwrap_or(Value::Nil);
+    interpreter.set_global("*3", second);
+    interpreter.set_global("*2", first);
+    interpreter.set_global("*1", value);
+}
+
+/// Binds `*e` to the condition `err` was raised as, which `handler-case`
+/// would have caught. An interrupt is not a condition and leaves it as is.
+fn record_error(interpreter: &mut Interpreter, err: &EvalError) {
+    if let Some(condition) = interpreter.condition_of(err) {
+        interpreter.set_global("*e", condition);
+    }
+}
+
+/// Evaluates the forms of `text` without recording them as results,
+/// reporting any error. Returns the value of the last form.
+fn eval_quietly(interpreter: &mut Interpreter, text: &str) -> Option<Value> {
+    let mut scanner = Scanner::new(text);
+    let mut parser = Parser::new(&mut scanner);
+    let forms = match parser.parse_forms() {
+        Ok(forms) => forms,
+        Err(err) => {
+            Diagnostic::parse(&err, text).emit("<repl>", text);
+            return None;
+        }
+    };
+    let mut result = Value::Nil;
+    for (span, expr) in forms {
+        match eval_protected(interpreter, &expr) {
+            Ok(value) => result = value,
+            Err(err) => {
+                Diagnostic::eval(&err, span)
+                    .with_expansions(interpreter.error_expansions(), text)
+                    .emit("<repl>", text);
+                return None;
+            }
+        }
+    }
+    Some(result)
+}
+
+/// Prints the forms of `text` with every macro call in them expanded, laid
+/// out as `jon fmt` would.
+fn print_expansion(interpreter: &mut Interpreter, text: &str) {
+    let forms = match Parser::new(&mut Scanner::new(text)).parse_forms() {
+        Ok(forms) => forms,
+        Err(err) => return Diagnostic::parse(&err, text).emit("<repl>", text),
+    };
+    for (span, expr) in forms {
+        match interpreter.macroexpand_all(&Value::from(&expr)) {
+            Ok(expansion) => {
+                let code = expansion.to_string();
+                match formatter::format_source(&code, formatter::DEFAULT_WIDTH) {
+                    Ok(formatted) => print!("{}", formatted),
+                    Err(_) => println!("{}", code),
+                }
+            }
+            Err(err) => return Diagnostic::eval(&err, span).emit("<repl>", text),
+        }
+    }
+}
+
+/// The `:describe` report: the type of `value`, its size if it is a list or
+/// a string, and a preview on the next line.
+fn describe(value: &Value) -> String {
+    let size = match value {
+        Value::List(list) => format!(" of {} elements", list.len()),
+        Value::String(s) => format!(" of {} characters", s.chars().count()),
+        _ => String::new(),
+    };
+    format!(
+        "{}{}\n{}",
+        value.type_name(),
+        size,
+        value.preview(PREVIEW_WIDTH)
+    )
+}
+
+/// The special forms and globals starting with `prefix`, each with what
+/// kind of name it is, in order.
+pub fn completions(interpreter: &Interpreter, prefix: &str) -> Vec<(String, &'static str)> {
+    let mut names: Vec<(String, &'static str)> = SPECIAL_FORMS
+        .iter()
+        .map(|name| (name.to_string(), "special-form"))
+        .collect();
+    names.extend(interpreter.globals().into_iter().map(|(name, value)| {
+        let kind = match value {
+            Value::Macro(_) => "macro",
+            Value::Lambda(_) | Value::Builtin(_) => "function",
+            _ => "var",
+        };
+        (name, kind)
+    }));
+    names.retain(|(name, _)| name.starts_with(prefix));
+    names.sort();
+    names.dedup_by(|a, b| a.0 == b.0);
+    names
+}
+
+/// The `:doc` text for `name`: how to call it, its docstring, and where it
+/// was defined.
+pub fn describe_doc(interpreter: &Interpreter, name: &str) -> String {
+    if SPECIAL_FORMS.contains(&name) {
+        return format!("{} is a special form", name);
+    }
+    let Some(value) = interpreter.get_global(name) else {
+        return format!("{} is not defined", name);
+    };
+    let mut text = match &value {
+        Value::Lambda(lambda) | Value::Macro(lambda) => {
+            let mut params = vec![name.to_string()];
+            params.extend(lambda.param_list().iter().map(Value::to_string));
+            let kind = if matches!(value, Value::Macro(_)) {
+                "macro "
+            } else {
+                ""
+            };
+            format!("{}({})", kind, params.join(" "))
+        }
+        Value::Builtin(builtin) => match builtins::doc(builtin.name) {
+            Some((params, _)) => format!("({} {})", name, params),
+            None => format!("({} ...)", name),
+        },
+        other => format!("{} : {}", name, other.type_name()),
+    };
+    let doc = interpreter.doc(name);
+    if let Some(docstring) = doc.as_ref().and_then(|doc| doc.docstring.as_ref()) {
+        text.push_str(&format!("\n  {}", docstring));
+    }
+    match doc.and_then(|doc| doc.origin) {
+        Some(Origin::Builtin) => text.push_str("\n  builtin"),
+        Some(Origin::Prelude) => text.push_str("\n  defined in the prelude"),
+        Some(Origin::Source { name, line }) => {
+            text.push_str(&format!("\n  defined at {}:{}", name, line))
+        }
+        None => {}
+    }
+    text
+}
+
+const HELP: &str = "\
+:help           show this message
+:quit           leave the REPL
+:env            list the bindings in the global environment
+:doc NAME       show the parameters, docstring and origin of NAME
+:describe EXPR  show the type, size and a preview of the value of EXPR
+:type EXPR      show the type of the value of EXPR
+:expand FORM    show FORM with every macro call in it expanded
+:load FILE      evaluate every form in FILE
+:save-session FILE
+                save the definitions made in this session to FILE
+:load-session FILE
+                restore definitions saved with :save-session
+:undo           take back the definitions of the last entry that made any
+:reset          discard all definitions and start a fresh interpreter
+:clear          clear the screen
+:time           toggle printing how long each evaluation took
+:width N        break results wider than N columns across lines
+:more           show all of the last result, through $PAGER if it is set
+
+Ctrl-C stops the running evaluation, or discards the entry being typed;
+Ctrl-D leaves. Tab completes names; Right-arrow accepts the dim hint
+after the cursor, a likely name or the parens left to close. The last
+three results are bound to *1, *2 and *3, the condition of the last
+error to *e.
+Set *prompt* to a string to change the prompt. Results show at most
+*print-length* items of each list (100 at first) nested at most
+*print-depth* lists deep; set either to nil for no limit. Set
+*float-format* to options such as (:digits 6 :notation :scientific) to
+round floats and pick their notation.";
+
+/// REPL settings that can be changed from the prompt.
+struct Settings {
+    time: bool,
+    /// The width results are broken to.
+    width: usize,
+}
+
+enum Flow {
+    Continue,
+    Quit,
+}
+
+/// Evaluates every form of `path` in order, stopping at the first error.
+/// Returns whether the whole file was evaluated.
+fn load_file(interpreter: &mut Interpreter, path: &Path) -> bool {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: cannot read {}: {}", path.display(), err);
+            return false;
+        }
+    };
+    let mut scanner = Scanner::new(&source);
+    let mut parser = Parser::new(&mut scanner);
+    let name = path.display().to_string();
+    let forms = match parser.parse_forms() {
+        Ok(forms) => forms,
+        Err(err) => {
+            Diagnostic::parse(&err, &source).emit(&name, &source);
+            return false;
+        }
+    };
+    for (span, expr) in forms {
+        interpreter.set_origin(&name, scanner::line_column(&source, span.start).0);
+        if let Err(err) = eval_protected(interpreter, &expr) {
+            Diagnostic::eval(&err, span)
+                .with_expansions(interpreter.error_expansions(), &source)
+                .emit(&name, &source);
+            return false;
+        }
+    }
+    true
+}
+
+/// The user's init file: `$XDG_CONFIG_HOME/jon/init.jon` (by default
+/// `~/.config/jon/init.jon`), or else `~/.jonrc`, whichever exists first.
+fn init_file() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".config")));
+    let candidates = [
+        config.map(|config| config.join("jon").join("init.jon")),
+        home.map(|home| home.join(".jonrc")),
+    ];
+    candidates.into_iter().flatten().find(|path| path.is_file())
+}
+
+/// A fresh interpreter for the REPL, with the init file loaded unless
+/// `--no-init` was given.
+fn new_session(options: &Options) -> Interpreter {
+    let mut interpreter = new_runtime(options);
+    interpreter.set_interrupt(interrupt_flag());
+    bind_result_history(&mut interpreter);
+    interpreter.set_global("*prompt*", Value::String(DEFAULT_PROMPT.into()));
+    interpreter.set_global("*print-length*", Value::Number(PRINT_LENGTH));
+    interpreter.set_global("*print-depth*", Value::Nil);
+    if !options.no_init {
+        if let Some(path) = init_file() {
+            load_file(&mut interpreter, &path);
+        }
+    }
+    interpreter
+}
+
+/// Runs a `:command` line typed at the prompt.
+fn run_command(
+    interpreter: &mut Interpreter,
+    rl: &mut Editor<JonHelper, DefaultHistory>,
+    line: &str,
+    options: &Options,
+    settings: &mut Settings,
+    undo: &mut Vec<Snapshot>,
+) -> Flow {
+    let (command, argument) = match line.trim().split_once(char::is_whitespace) {
+        Some((command, argument)) => (command, Some(argument.trim())),
+        None => (line.trim(), None),
+    };
+    match (command, argument) {
+        (":help" | ":h", _) => println!("{}", HELP),
+        (":quit" | ":q", _) => return Flow::Quit,
+        (":env", _) => {
+            for (name, value) in interpreter.globals() {
+                println!("{} = {}", name, value);
+            }
+        }
+        (":doc", Some(name)) => println!("{}", describe_doc(interpreter, name)),
+        (":doc", None) => eprintln!("usage: :doc NAME"),
+        (":describe" | ":type", Some(text)) => {
+            if let Some(value) = eval_quietly(interpreter, text) {
+                if command == ":type" {
+                    println!("{}", value.type_name());
+                } else {
+                    println!("{}", describe(&value));
+                }
+            }
+        }
+        (":describe" | ":type", None) => eprintln!("usage: {} EXPR", command),
+        (":expand", Some(text)) => print_expansion(interpreter, text),
+        (":expand", None) => eprintln!("usage: :expand FORM"),
+        (":load", Some(path)) => {
+            let before = interpreter.snapshot();
+            if load_file(interpreter, Path::new(path)) {
+                println!("loaded {}", path);
+            }
+            remember(undo, interpreter, before);
+        }
+        (":load", None) => eprintln!("usage: :load FILE"),
+        (":save-session", Some(path)) => match std::fs::write(path, session::save(interpreter)) {
+            Ok(()) => println!("saved session to {}", path),
+            Err(err) => eprintln!("error: cannot write {}: {}", path, err),
+        },
+        (":load-session", Some(path)) => {
+            let before = interpreter.snapshot();
+            if load_file(interpreter, Path::new(path)) {
+                println!("restored session from {}", path);
+            }
+            remember(undo, interpreter, before);
+        }
+        (":save-session" | ":load-session", None) => eprintln!("usage: {} FILE", command),
+        (":undo", _) => match undo.pop() {
+            Some(snapshot) => {
+                let names = interpreter.changed_since(&snapshot);
+                interpreter.restore(&snapshot);
+                let names: Vec<_> = names
+                    .into_iter()
+                    .filter(|name| !RESULT_HISTORY.contains(&name.as_str()))
+                    .collect();
+                println!("undid {}", names.join(", "));
+            }
+            None => eprintln!("nothing to undo"),
+        },
+        (":reset", _) => {
+            *interpreter = new_session(options);
+            undo.clear();
+            println!("environment reset");
+        }
+        (":clear", _) => {
+            if let Err(err) = rl.clear_screen() {
+                eprintln!("error: {}", err);
+            }
+        }
+        (":time", _) => {
+            settings.time = !settings.time;
+            println!("timing {}", if settings.time { "on" } else { "off" });
+        }
+        (":width", Some(width)) => match width.parse() {
+            Ok(width) => settings.width = width,
+            Err(_) => eprintln!("usage: :width N"),
+        },
+        (":width", None) => println!("width {}", settings.width),
+        (":more", _) => {
+            let value = interpreter.get_global("*1").unwrap_or(Value::Nil);
+            let limits = pretty::Limits::default();
+            let floats = interpreter.float_format().unwrap_or_default();
+            page(&pretty::pretty_limited(
+                &value,
+                settings.width,
+                &limits,
+                &floats,
+            ));
+        }
+        _ => eprintln!("unknown command {}, try :help", command),
+    }
+    Flow::Continue
+}
+
+pub fn run(options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut interpreter = new_session(options);
+    let mut settings = Settings {
+        time: options.time,
+        width: options.print_width.unwrap_or(pretty::DEFAULT_WIDTH),
+    };
+    let mut undo = Vec::new();
+
+    let config = Config::builder().max_history_size(HISTORY_SIZE)?.build();
+    let mut rl = Editor::with_config(config)?;
+    rl.set_helper(Some(JonHelper::default()));
+    let history = history_path();
+    if let Some(path) = &history {
+        // a missing history file just means this is the first session
+        let _ = rl.load_history(path);
+    }
+
+    let mut input = String::new();
+    loop {
+        if let Some(helper) = rl.helper_mut() {
+            helper.set_names(interpreter.globals().into_iter().map(|(name, _)| name));
+        }
+        let prompt = prompt(&interpreter, scanner::open_depth(&input));
+        let readline = rl.readline(&prompt);
+        match readline {
+            Ok(line) if input.is_empty() && line.trim_start().starts_with(':') => {
+                rl.add_history_entry(&line)?;
+                if let Flow::Quit = run_command(
+                    &mut interpreter,
+                    &mut rl,
+                    &line,
+                    options,
+                    &mut settings,
+                    &mut undo,
+                ) {
+                    if let Some(path) = &history {
+                        rl.save_history(path)?;
+                    }
+                    return Ok(());
+                }
+            }
+            Ok(line) => {
+                if !input.is_empty() {
+                    input.push('\n');
+                }
+                input.push_str(&line);
+                if scanner::open_depth(&input) > 0 {
+                    continue;
+                }
+                let line = std::mem::take(&mut input);
+                if line.trim().is_empty() {
+                    continue;
+                }
+                rl.add_history_entry(&line)?;
+                let before = interpreter.snapshot();
+                eval_line(&mut interpreter, &line, options, &settings);
+                remember(&mut undo, &interpreter, before);
+            }
+            Err(ReadlineError::Interrupted) => input.clear(),
+            Err(err) => {
+                println!("Exiting: {}", err);
+                if let Some(path) = &history {
+                    rl.save_history(path)?;
+                }
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_shows_depth_and_custom_name() {
+        let mut interpreter = Interpreter::bare();
+        assert_eq!(prompt(&interpreter, 0), "jon> ");
+        interpreter.set_global("*prompt*", Value::String("λ".into()));
+        assert_eq!(prompt(&interpreter, 2), "λ(2)> ");
+    }
+
+    #[test]
+    fn test_undo_keeps_only_definitions() {
+        let mut interpreter = Interpreter::new();
+        bind_result_history(&mut interpreter);
+        let mut undo = Vec::new();
+        let before = interpreter.snapshot();
+        record_result(&mut interpreter, Value::Number(3.0));
+        remember(&mut undo, &interpreter, before);
+        assert!(undo.is_empty());
+        let before = interpreter.snapshot();
+        interpreter.eval_str("(def x 1)").unwrap();
+        remember(&mut undo, &interpreter, before);
+        interpreter.restore(&undo.pop().unwrap());
+        assert_eq!(interpreter.get_global("x"), None);
+        assert_eq!(interpreter.get_global("*1"), Some(Value::Number(3.0)));
+    }
+
+    #[test]
+    fn test_last_error_is_its_condition() {
+        let mut interpreter = Interpreter::new();
+        bind_result_history(&mut interpreter);
+        let err = interpreter
+            .eval_str("(raise 'bad-input '(1 2))")
+            .unwrap_err();
+        let jon::Error::Eval(err) = err else {
+            panic!("expected an evaluation error, got {:?}", err);
+        };
+        record_error(&mut interpreter, &err);
+        let data = Value::List(vec![Value::Number(1.0), Value::Number(2.0)]);
+        let condition = jon::eval::condition("bad-input", data);
+        assert_eq!(interpreter.get_global("*e"), Some(condition.clone()));
+        let reraised = interpreter
+            .eval_str(
+                "(handler-case (raise (car (cdr (car *e))) (car (cdr (car (cdr *e)))))
+                   ((bad-input c) c))",
+            )
+            .unwrap();
+        assert_eq!(reraised, condition);
+    }
+
+    #[test]
+    fn test_print_limits() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("*print-length*", Value::Number(2.0));
+        interpreter.set_global("*print-depth*", Value::Nil);
+        let limits = print_limits(&interpreter);
+        let floats = interpreter.float_format().unwrap();
+        let value = eval_quietly(&mut interpreter, "'(1 2 (3 4 5))").unwrap();
+        assert_eq!(
+            pretty::pretty_limited(&value, 80, &limits, &floats),
+            "(1 2 ...)"
+        );
+        interpreter.set_global("*print-depth*", Value::Number(1.0));
+        let limits = print_limits(&interpreter);
+        assert_eq!(
+            pretty::pretty_limited(&value, 80, &limits, &floats),
+            "(1 2 ...)"
+        );
+        interpreter.set_global("*print-length*", Value::Nil);
+        let limits = print_limits(&interpreter);
+        assert_eq!(
+            pretty::pretty_limited(&value, 80, &limits, &floats),
+            "(1 2 (...))"
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut interpreter = Interpreter::new();
+        let value = eval_quietly(&mut interpreter, "(collect (range 0 100))").unwrap();
+        let report = describe(&value);
+        assert!(report.starts_with("list of 100 elements\n(0 1 2 3"));
+        assert!(report.ends_with("..."));
+        assert_eq!(describe(&Value::Number(2.0)), "number\n2");
+    }
+
+    #[test]
+    fn test_describe_doc() {
+        let interpreter = Interpreter::new();
+        assert_eq!(
+            describe_doc(&interpreter, "map"),
+            "(map f xs)\n  The results of calling f on each item of the iterable xs, as a list.\n  defined in the prelude"
+        );
+        assert_eq!(
+            describe_doc(&interpreter, "car"),
+            "(car xs)\n  The first element of xs, or nil if it is empty.\n  builtin"
+        );
+        assert_eq!(
+            describe_doc(&interpreter, "when"),
+            "macro (when test & body)\n  Evaluates body if test is true.\n  defined in the prelude"
+        );
+        assert_eq!(describe_doc(&interpreter, "if"), "if is a special form");
+    }
+}
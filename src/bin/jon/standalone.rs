@@ -0,0 +1,111 @@
+//! Executables made by `jon build`: a copy of this binary with a script
+//! appended, which runs the script instead of reading its command line.
+//!
+//! The script follows the binary's own bytes, then its name and their
+//! lengths, then [`MAGIC`], so that a binary can find a script at its end
+//! without reading the rest of itself.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Ends every executable with a script.
+const MAGIC: &[u8; 16] = b"\0jon standalone1";
+
+/// The length of the lengths and [`MAGIC`] at the end.
+const TRAILER: u64 = 8 + 8 + MAGIC.len() as u64;
+
+/// A script appended to an executable.
+#[derive(Debug, PartialEq)]
+pub struct Script {
+    /// The name of the script it was built from, for error locations.
+    pub name: String,
+    pub source: String,
+}
+
+/// The script appended to the running executable, if there is one.
+pub fn embedded() -> Option<Script> {
+    read_script(&std::env::current_exe().ok()?).ok()?
+}
+
+/// The script appended to the executable at `path`, if there is one.
+fn read_script(path: &Path) -> io::Result<Option<Script>> {
+    let mut file = File::open(path)?;
+    let Some((start, name_len)) = read_trailer(&mut file)? else {
+        return Ok(None);
+    };
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    bytes.truncate(bytes.len() - TRAILER as usize);
+    let source = bytes.split_off(name_len);
+    match (String::from_utf8(bytes), String::from_utf8(source)) {
+        (Ok(name), Ok(source)) => Ok(Some(Script { name, source })),
+        _ => Ok(None),
+    }
+}
+
+/// Where the appended script of `file` starts and the length of its name,
+/// if `file` ends with one.
+fn read_trailer(file: &mut File) -> io::Result<Option<(u64, usize)>> {
+    let len = file.metadata()?.len();
+    if len < TRAILER {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::End(-(TRAILER as i64)))?;
+    let mut trailer = [0; TRAILER as usize];
+    file.read_exact(&mut trailer)?;
+    if &trailer[16..] != MAGIC {
+        return Ok(None);
+    }
+    let name_len = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    let source_len = u64::from_le_bytes(trailer[8..16].try_into().unwrap());
+    let start = name_len
+        .checked_add(source_len)
+        .and_then(|script_len| (len - TRAILER).checked_sub(script_len));
+    Ok(start.map(|start| (start, name_len as usize)))
+}
+
+/// Writes a copy of the running executable, without any script it has, to
+/// `output` with `script` appended, executable like the original.
+pub fn build(script: &Script, output: &Path) -> io::Result<()> {
+    let exe = std::env::current_exe()?;
+    let mut file = File::open(&exe)?;
+    let end = match read_trailer(&mut file)? {
+        Some((start, _)) => start,
+        None => file.metadata()?.len(),
+    };
+    file.seek(SeekFrom::Start(0))?;
+    let mut bytes = Vec::new();
+    file.take(end).read_to_end(&mut bytes)?;
+    bytes.extend_from_slice(script.name.as_bytes());
+    bytes.extend_from_slice(script.source.as_bytes());
+    bytes.extend_from_slice(&(script.name.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&(script.source.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(MAGIC);
+    fs::write(output, bytes)?;
+    fs::set_permissions(output, fs::metadata(&exe)?.permissions())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_appends_script() {
+        let output = std::env::temp_dir().join(format!("jon-build-{}", std::process::id()));
+        let script = Script {
+            name: "hello.jon".into(),
+            source: "(print \"hello\")".into(),
+        };
+        build(&script, &output).unwrap();
+        assert_eq!(read_script(&output).unwrap(), Some(script));
+        // the copy is the executable itself followed by the script
+        let exe_len = fs::metadata(std::env::current_exe().unwrap())
+            .unwrap()
+            .len();
+        let built_len = fs::metadata(&output).unwrap().len();
+        assert_eq!(built_len, exe_len + 9 + 15 + TRAILER);
+        fs::remove_file(output).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+//! Just enough of ZeroMQ's wire protocol, ZMTP 3.0 with the NULL mechanism
+//! over TCP, to bind the sockets a Jupyter kernel needs without linking
+//! libzmq. Each peer connection is served on its own thread; routing is by
+//! connection rather than by routing id, and a publisher sends everything
+//! to every subscriber, which is what Jupyter frontends subscribe to.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A message: its frames in order.
+pub type Multipart = Vec<Vec<u8>>;
+
+/// The longest frame accepted, so that a bad size cannot exhaust memory.
+const MAX_FRAME: u64 = 256 << 20;
+
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SocketType {
+    /// Receives requests from any peer and answers the one that sent them.
+    Router,
+    /// Sends to every peer.
+    Pub,
+    /// Sends each message back to its sender, as a heartbeat.
+    Rep,
+}
+
+impl SocketType {
+    fn name(self) -> &'static str {
+        match self {
+            SocketType::Router => "ROUTER",
+            SocketType::Pub => "PUB",
+            SocketType::Rep => "REP",
+        }
+    }
+}
+
+/// Exchanges the greeting and READY commands with a new peer.
+pub fn handshake(stream: &mut TcpStream, kind: SocketType) -> io::Result<()> {
+    let mut greeting = [0; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    stream.write_all(&greeting)?;
+    let mut theirs = [0; 64];
+    stream.read_exact(&mut theirs)?;
+    if theirs[0] != 0xff || theirs[9] != 0x7f || theirs[10] < 3 || &theirs[12..17] != b"NULL\0" {
+        return Err(invalid("not a ZMTP 3 peer using the NULL mechanism"));
+    }
+    let mut ready = vec![5];
+    ready.extend_from_slice(b"READY");
+    ready.push(11);
+    ready.extend_from_slice(b"Socket-Type");
+    ready.extend_from_slice(&(kind.name().len() as u32).to_be_bytes());
+    ready.extend_from_slice(kind.name().as_bytes());
+    write_frame(stream, COMMAND, &ready)?;
+    match read_frame(stream)? {
+        Some((flags, body)) if flags & COMMAND != 0 && body.starts_with(b"\x05READY") => Ok(()),
+        _ => Err(invalid("peer did not send READY")),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_frame(output: &mut impl Write, flags: u8, body: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(body.len() + 9);
+    match u8::try_from(body.len()) {
+        Ok(size) => frame.extend_from_slice(&[flags, size]),
+        Err(_) => {
+            frame.push(flags | LONG);
+            frame.extend_from_slice(&(body.len() as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(body);
+    output.write_all(&frame)
+}
+
+/// The next frame's flags and body, or `None` if the peer hung up.
+fn read_frame(input: &mut impl Read) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut flags = [0];
+    if input.read(&mut flags)? == 0 {
+        return Ok(None);
+    }
+    let flags = flags[0];
+    let size = if flags & LONG != 0 {
+        let mut size = [0; 8];
+        input.read_exact(&mut size)?;
+        u64::from_be_bytes(size)
+    } else {
+        let mut size = [0];
+        input.read_exact(&mut size)?;
+        u64::from(size[0])
+    };
+    if size > MAX_FRAME {
+        return Err(invalid("frame too large"));
+    }
+    let mut body = vec![0; size as usize];
+    input.read_exact(&mut body)?;
+    Ok(Some((flags, body)))
+}
+
+/// The next message, skipping commands, or `None` if the peer hung up.
+pub fn read_message(input: &mut impl Read) -> io::Result<Option<Multipart>> {
+    let mut message = Vec::new();
+    while let Some((flags, body)) = read_frame(input)? {
+        if flags & COMMAND != 0 {
+            continue;
+        }
+        message.push(body);
+        if flags & MORE == 0 {
+            return Ok(Some(message));
+        }
+    }
+    Ok(None)
+}
+
+pub fn write_message(output: &mut impl Write, message: &[Vec<u8>]) -> io::Result<()> {
+    for (i, frame) in message.iter().enumerate() {
+        let flags = if i + 1 < message.len() { MORE } else { 0 };
+        write_frame(output, flags, frame)?;
+    }
+    output.flush()
+}
+
+/// A bound socket and the peers connected to it, by id.
+#[derive(Clone)]
+pub struct Endpoint {
+    peers: Arc<Mutex<BTreeMap<usize, TcpStream>>>,
+}
+
+impl Endpoint {
+    /// Listens at `address` as a socket of `kind`, giving each message a
+    /// router receives to `on_message` with the id of the peer that sent
+    /// it. A publisher's peers only send subscriptions, which are dropped.
+    pub fn bind(
+        address: &str,
+        kind: SocketType,
+        on_message: impl Fn(usize, Multipart) + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let endpoint = Endpoint {
+            peers: Arc::default(),
+        };
+        let peers = endpoint.peers.clone();
+        let on_message = Arc::new(on_message);
+        std::thread::spawn(move || {
+            static NEXT_PEER: AtomicUsize = AtomicUsize::new(0);
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let (peers, on_message) = (peers.clone(), on_message.clone());
+                let id = NEXT_PEER.fetch_add(1, Ordering::Relaxed);
+                std::thread::spawn(move || {
+                    if handshake(&mut stream, kind).is_err() {
+                        return;
+                    }
+                    let Ok(writer) = stream.try_clone() else {
+                        return;
+                    };
+                    peers.lock().unwrap().insert(id, writer);
+                    while let Ok(Some(message)) = read_message(&mut stream) {
+                        match kind {
+                            SocketType::Router => on_message(id, message),
+                            SocketType::Rep => {
+                                if write_message(&mut stream, &message).is_err() {
+                                    break;
+                                }
+                            }
+                            SocketType::Pub => {}
+                        }
+                    }
+                    peers.lock().unwrap().remove(&id);
+                });
+            }
+        });
+        Ok(endpoint)
+    }
+
+    /// Sends `message` to the peer `id`, if it is still connected.
+    pub fn send_to(&self, id: usize, message: &[Vec<u8>]) -> io::Result<()> {
+        match self.peers.lock().unwrap().get_mut(&id) {
+            Some(peer) => write_message(peer, message),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends `message` to every peer, dropping those that hung up.
+    pub fn broadcast(&self, message: &[Vec<u8>]) {
+        self.peers
+            .lock()
+            .unwrap()
+            .retain(|_, peer| write_message(peer, message).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_echo_over_zmtp() {
+        let address = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        let _echo = Endpoint::bind(&address.to_string(), SocketType::Rep, |_, _| {}).unwrap();
+        let mut client = TcpStream::connect(address).unwrap();
+        handshake(&mut client, SocketType::Router).unwrap();
+        let message = vec![b"ping".to_vec(), vec![7; 300], Vec::new()];
+        write_message(&mut client, &message).unwrap();
+        assert_eq!(read_message(&mut client).unwrap(), Some(message));
+    }
+}
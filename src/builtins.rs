@@ -0,0 +1,2005 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::cmp::Ordering;
+
+use crate::complex::Complex;
+use crate::decimal::Decimal;
+use crate::encoding::Encoding;
+use crate::env::Env;
+use crate::eval::{is_keyword, EvalError, Interpreter};
+#[cfg(feature = "std")]
+use crate::logging::{Level, Record};
+use crate::scanner::{Scanner, Token};
+use crate::value::{Builtin, HostValue, NativeFn, Value};
+
+/// Each builtin with its parameter list and docstring, shown by `:doc`.
+#[rustfmt::skip]
+const BUILTINS: &[(&str, NativeFn, &str, &str)] = &[
+    ("+", add, "& numbers", "The sum of the numbers, 0 without any."),
+    ("-", sub, "& numbers", "The first number minus the rest, or a single number negated."),
+    ("*", mul, "& numbers", "The product of the numbers, 1 without any."),
+    ("/", div, "n & numbers", "n divided by the rest of the numbers, or 1/n alone."),
+    ("=", eq, "& values", "Whether all values are equal."),
+    ("<", lt, "& numbers", "Whether the numbers are strictly increasing."),
+    (">", gt, "& numbers", "Whether the numbers are strictly decreasing."),
+    ("<=", le, "& numbers", "Whether the numbers never decrease."),
+    (">=", ge, "& numbers", "Whether the numbers never increase."),
+    ("decimal", decimal, "x", "The exact decimal x is, from a number or a string such as \"19.99\", or nil if the string is not one."),
+    ("decimal?", is_decimal, "x", "Whether x is a decimal."),
+    ("decimal/round", decimal_round, "d places", "The decimal d rounded to places digits after the point, halves away from zero."),
+    ("complex", complex, "re im", "The complex number re + im·i."),
+    ("complex?", is_complex, "x", "Whether x is a complex number."),
+    ("real-part", real_part, "z", "The real part of the number z, which is z itself unless z is complex."),
+    ("imag-part", imag_part, "z", "The imaginary part of the number z, 0 unless z is complex."),
+    ("not", not, "x", "true if x is nil or false, false otherwise."),
+    ("list", list, "& values", "A list of the values."),
+    ("cons", cons, "x xs", "The list xs with x added in front."),
+    ("car", car, "xs", "The first element of xs, or nil if it is empty."),
+    ("cdr", cdr, "xs", "Every element of xs but the first."),
+    ("empty?", is_empty, "xs", "Whether xs has no elements."),
+    ("list?", is_list, "x", "Whether x is a list, which nil is not."),
+    ("range", range, "& bounds", "The numbers from start, 0 if left out, by step, 1 if left out, up to but not including end, given as end, start end, or start end step. They are made as they are iterated over, never held in a list."),
+    ("next", next, "coll", "nil once coll has no items left, or a list of its first item and the rest of it. This is the iteration protocol of map, filter, and reduce: lists, maps, strings, bytes, ranges, and nil are iterable."),
+    ("type-of", type_of, "x", "The type of x as a symbol: the name given to defrecord for a record, the host's name for a host value, or one such as number or list."),
+    ("assoc", assoc, "key alist", "The first pair of alist, a list of (key value) pairs, whose key equals key, or nil if there is none."),
+    ("assq", assq, "symbol alist", "Like assoc for a symbol key, compared by name alone."),
+    ("alist->map", alist_to_map, "alist", "alist as the maps the host passes: string keys, each once with the value assoc finds, sorted."),
+    ("map->alist", map_to_alist, "map", "A map from the host as an alist with symbol keys, for assq."),
+    ("multi-dispatch", multi_dispatch, "name dispatch methods args", "Calls the method for the key dispatch gives for args, as the function defmulti defines does."),
+    ("raise", raise, "type data", "Signals a condition of type, a symbol, holding data, for handler-case to catch."),
+    ("apply", apply, "f args", "Calls f with the elements of the list args."),
+    ("macroexpand-1", macroexpand_1, "form", "form with its macro call, if it is one, expanded once."),
+    ("macroexpand", macroexpand, "form", "form expanded until it is no longer a macro call, leaving the forms inside it."),
+    ("arity", arity, "f", "The number of arguments f requires, or nil if it is a builtin taking any number."),
+    ("function-params", function_params, "f", "The parameter list of the function or macro f as symbols, such as (x & more)."),
+    ("source", source, "f", "The form that defined the function or macro f, such as (def (f x) ...), or nil for a builtin."),
+    ("stack-depth", stack_depth, "", "How many function calls are being evaluated, counting the one that called it."),
+    ("pmap", pmap, "f xs", "Like map, calling f on several threads at once when it is pure. Calls on other threads are not traced."),
+    ("string/append", string_append, "& strings", "The strings joined together."),
+    ("string-append", string_append, "& strings", "Same as string/append."),
+    ("string->list", string_to_list, "s", "The characters of s, each a string of one Unicode code point."),
+    ("list->string", list_to_string, "chars", "The characters of the list chars joined into a string."),
+    ("string/ref", string_ref, "s i", "The character at index i of s, counting code points from 0, or nil if there is none."),
+    ("string-ref", string_ref, "s i", "Same as string/ref."),
+    ("string/length", string_length, "s", "The number of characters in s, counting code points."),
+    ("string/slice", string_slice, "s start & end", "The characters of s from index start up to end, or to the end of s, counting code points. nil if they are not all in s."),
+    ("string/reverse", string_reverse, "s", "The characters of s in reverse order."),
+    ("string/bytes", string_to_bytes, "s & encoding", "Same as string->bytes."),
+    ("char-code", char_code, "c", "The Unicode code point of the character c."),
+    ("code-char", code_char, "n", "The character with the Unicode code point n, or nil if there is none."),
+    ("bytes", bytes, "& values", "Bytes holding the values, whole numbers from 0 to 255."),
+    ("bytes?", is_bytes, "x", "Whether x is bytes."),
+    ("bytes-length", bytes_length, "b", "The number of bytes in b."),
+    ("bytes-ref", bytes_ref, "b i", "The byte at index i of b, or nil if there is none."),
+    ("bytes-slice", bytes_slice, "b start & end", "The bytes of b from index start up to end, or to the end of b. nil if they are not all in b."),
+    ("bytes-append", bytes_append, "& bs", "The bytes of each of bs, one after another."),
+    ("string->bytes", string_to_bytes, "s & encoding", "The bytes encoding s in encoding, one of :utf-8, the default, :latin-1, :ascii, :utf-16le, and :utf-16be, or nil if the encoding lacks a character of s."),
+    ("bytes->string", bytes_to_string, "b & encoding", "The string the bytes b encode in encoding, as for string->bytes, or nil if b is not valid in it."),
+    ("pretty", pretty, "x & options", "x as it prints, broken across lines with lists one item per line where it is wider than :width columns, 80 by default. Floats print as *float-format* says."),
+    ("number->string", number_to_string, "n & options", "n written out, in the base given by :base, 10 by default. nil if the base is not 10 and n is not a whole number."),
+    ("string->number", string_to_number, "s & options", "The number s is written as, in the base given by :base, 10 by default, or nil if it is not one."),
+    ("symbol->string", symbol_to_string, "s", "The name of the symbol s."),
+    ("string->symbol", string_to_symbol, "s", "The symbol named s, or nil if s does not read as a single symbol."),
+    ("print", print, "& values", "Prints the values separated by spaces, strings unquoted and floats as *float-format* says."),
+    ("read-line", read_line, "& file", "The next line of input, or of file, as a string, or nil at its end."),
+    ("host/close", host_close, "resource", "Releases a resource the host made, such as a file. The close method of Closeable calls it."),
+    ("breakpoint", breakpoint, "", "Stops in the debugger."),
+    ("trace", trace, "f", "Logs each call to f with its arguments and result."),
+    ("untrace", untrace, "f", "Stops logging calls to f."),
+];
+
+/// Builtins that need threads or files, which only std has.
+#[cfg(feature = "std")]
+#[rustfmt::skip]
+const STD_ONLY: &[(&str, NativeFn, &str, &str)] = &[
+    ("load", load, "path", "Evaluates the forms of the file at path, returning the value of the last. Parsed forms are cached when the host allows it."),
+    ("open-file", open_file, "path", "The file at path, opened for read-line and read-bytes. Close it with close, or open it with with-open."),
+    ("read-bytes", read_bytes, "file n", "Up to n bytes read from file, or nil at its end."),
+    ("actor", actor, "init handler", "Starts an actor on its own thread with the state init. It handles each message sent to it with (handler state message), which returns its new state."),
+    ("send!", send, "actor message", "Puts message in the actor's mailbox without waiting."),
+    ("ask", ask, "actor message timeout", "Sends message to the actor and waits up to timeout seconds for its state after handling it."),
+    ("magnitude", magnitude, "z", "The distance of the number z from zero: its absolute value, or the modulus of a complex number."),
+    ("log/debug", log_debug, "message & data", "Logs message, with data if given, at the debug level."),
+    ("log/info", log_info, "message & data", "Logs message, with data if given, at the info level."),
+    ("log/warn", log_warn, "message & data", "Logs message, with data if given, at the warn level."),
+    ("log/set-level", log_set_level, "level", "Drops log messages below level, one of :debug, :info, and :warn. The host's default is :info."),
+    #[cfg(feature = "toml")]
+    ("toml/parse", toml_parse, "s", "The TOML document s as a map, with tables as maps and arrays as lists."),
+    #[cfg(feature = "toml")]
+    ("toml/write", toml_write, "map", "map as a TOML document, taking lists of (key value) pairs as tables."),
+    #[cfg(feature = "yaml")]
+    ("yaml/parse", yaml_parse, "s", "The YAML document s as jon data, with mappings as maps, sequences as lists, and null as nil."),
+    #[cfg(feature = "yaml")]
+    ("yaml/write", yaml_write, "x", "x as a YAML document, taking lists of (key value) pairs as mappings."),
+];
+
+#[cfg(not(feature = "std"))]
+const STD_ONLY: &[(&str, NativeFn, &str, &str)] = &[];
+
+/// The parameter list and docstring of the builtin `name`.
+pub fn doc(name: &str) -> Option<(&'static str, &'static str)> {
+    BUILTINS
+        .iter()
+        .chain(STD_ONLY)
+        .find(|(builtin, ..)| *builtin == name)
+        .map(|(_, _, params, doc)| (*params, *doc))
+}
+
+/// Builtins with effects beyond computing their result, which `pmap` must
+/// not call off the interpreter's thread.
+const EFFECTFUL: &[&str] = &[
+    "print",
+    "read-line",
+    "read-bytes",
+    "open-file",
+    "load",
+    "host/close",
+    "breakpoint",
+    "trace",
+    "untrace",
+    "actor",
+    "send!",
+    "ask",
+    "raise",
+    "log/debug",
+    "log/info",
+    "log/warn",
+    "log/set-level",
+];
+
+/// Builtins kept for compatibility, with what to use instead. `jon check
+/// --lint` warns about calls to them.
+pub const DEPRECATED: &[(&str, &str)] = &[
+    ("string-append", "string/append"),
+    ("string-ref", "string/ref"),
+];
+
+pub fn install(env: &mut Env) {
+    env.define("nil", Value::Nil);
+    env.define("true", Value::Bool(true));
+    env.define("false", Value::Bool(false));
+    env.define("*float-format*", Value::Nil);
+    for (name, func, ..) in BUILTINS.iter().chain(STD_ONLY) {
+        let func = Arc::new(*func);
+        let pure = !EFFECTFUL.contains(name);
+        env.define(name, Value::Builtin(Builtin { name, func, pure }));
+    }
+}
+
+/// The number of arguments a builtin requires, for those taking a fixed
+/// number.
+pub fn fixed_arity(name: &str) -> Option<usize> {
+    match name {
+        "not" | "car" | "cdr" | "empty?" | "list?" | "type-of" | "arity" => Some(1),
+        "next" | "function-params" | "source" => Some(1),
+        "decimal" | "decimal?" => Some(1),
+        "bytes?" | "bytes-length" => Some(1),
+        "bytes-ref" | "read-bytes" => Some(2),
+        "decimal/round" | "complex" => Some(2),
+        "complex?" | "real-part" | "imag-part" | "magnitude" => Some(1),
+        "macroexpand-1" | "macroexpand" => Some(1),
+        "symbol->string" | "string->symbol" => Some(1),
+        "string->list" | "list->string" | "char-code" | "code-char" => Some(1),
+        "string/ref" | "string-ref" => Some(2),
+        "string/length" | "string/reverse" => Some(1),
+        "cons" | "apply" | "pmap" | "actor" | "send!" => Some(2),
+        "assoc" | "assq" => Some(2),
+        "multi-dispatch" => Some(4),
+        "alist->map" | "map->alist" => Some(1),
+        "ask" => Some(3),
+        "raise" => Some(2),
+        "breakpoint" | "stack-depth" => Some(0),
+        "open-file" | "host/close" | "load" => Some(1),
+        "trace" | "untrace" | "log/set-level" => Some(1),
+        "toml/parse" | "toml/write" | "yaml/parse" | "yaml/write" => Some(1),
+        _ => None,
+    }
+}
+
+fn expect_arity(name: &str, args: &[Value], expected: usize) -> Result<(), EvalError> {
+    if args.len() == expected {
+        Ok(())
+    } else {
+        Err(EvalError::ArityMismatch {
+            name: name.into(),
+            expected: expected.to_string(),
+            found: args.len(),
+        })
+    }
+}
+
+fn expect_number(value: &Value) -> Result<f32, EvalError> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        other => Err(EvalError::TypeMismatch {
+            expected: "number",
+            found: other.type_name(),
+        }),
+    }
+}
+
+fn expect_string(value: &Value) -> Result<&str, EvalError> {
+    match value {
+        Value::String(s) => Ok(s),
+        other => Err(EvalError::TypeMismatch {
+            expected: "string",
+            found: other.type_name(),
+        }),
+    }
+}
+
+/// A character, which is a string of exactly one code point.
+fn expect_char(value: &Value) -> Result<char, EvalError> {
+    let mut chars = expect_string(value)?.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(EvalError::TypeMismatch {
+            expected: "character",
+            found: "string",
+        }),
+    }
+}
+
+fn expect_list(value: Value) -> Result<Vec<Value>, EvalError> {
+    match value {
+        Value::List(list) => Ok(list),
+        Value::Nil => Ok(vec![]),
+        other => Err(EvalError::TypeMismatch {
+            expected: "list",
+            found: other.type_name(),
+        }),
+    }
+}
+
+/// The arguments of an arithmetic builtin, all of the highest kind among
+/// them: complex numbers if any is complex, else decimals if any is a
+/// decimal, so that mixing decimals with floats stays exact, else floats.
+enum Numbers {
+    Floats(Vec<f32>),
+    Decimals(Vec<Decimal>),
+    Complexes(Vec<Complex>),
+}
+
+fn numbers(args: &[Value]) -> Result<Numbers, EvalError> {
+    if args.iter().any(|arg| matches!(arg, Value::Complex(_))) {
+        args.iter()
+            .map(expect_complex)
+            .collect::<Result<_, _>>()
+            .map(Numbers::Complexes)
+    } else if args.iter().any(|arg| matches!(arg, Value::Decimal(_))) {
+        args.iter()
+            .map(expect_decimal)
+            .collect::<Result<_, _>>()
+            .map(Numbers::Decimals)
+    } else {
+        args.iter()
+            .map(expect_number)
+            .collect::<Result<_, _>>()
+            .map(Numbers::Floats)
+    }
+}
+
+/// A decimal, or a number as the decimal it prints as.
+fn expect_decimal(value: &Value) -> Result<Decimal, EvalError> {
+    match value {
+        Value::Decimal(d) => Ok(d.clone()),
+        Value::Number(n) => Decimal::from_f32(*n)
+            .ok_or_else(|| EvalError::Host(format!("{} has no decimal value", n))),
+        other => Err(EvalError::TypeMismatch {
+            expected: "number",
+            found: other.type_name(),
+        }),
+    }
+}
+
+/// Any number as a complex number.
+fn expect_complex(value: &Value) -> Result<Complex, EvalError> {
+    match value {
+        Value::Complex(c) => Ok(*c),
+        Value::Decimal(d) => Ok(Complex::new(d.to_f32(), 0.0)),
+        other => expect_number(other).map(|n| Complex::new(n, 0.0)),
+    }
+}
+
+/// The largest whole number below which every whole number is an f32.
+const EXACT: f32 = 16_777_216.0;
+
+/// `a op b` as `f` computes it, failing with [`EvalError::Overflow`] when
+/// both are whole numbers and so is the result, but past [`EXACT`], so that
+/// counting never silently rounds. Floats round as usual, however large.
+/// Unary `-` is not checked, as negating never rounds.
+fn checked(op: &'static str, a: f32, b: f32, f: fn(f64, f64) -> f64) -> Result<f32, EvalError> {
+    let whole = |n: f64| n % 1.0 == 0.0;
+    let exact = f(a.into(), b.into());
+    if whole(a.into()) && whole(b.into()) && whole(exact) && exact.abs() > f64::from(EXACT) {
+        return Err(EvalError::Overflow(op));
+    }
+    Ok(exact as f32)
+}
+
+fn add(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match numbers(&args)? {
+        Numbers::Floats(ns) => ns
+            .into_iter()
+            .try_fold(0.0, |acc, n| checked("+", acc, n, |a, b| a + b))
+            .map(Value::Number),
+        Numbers::Decimals(ds) => Ok(Value::Decimal(
+            ds.iter().fold(Decimal::zero(), |acc, d| acc.add(d)),
+        )),
+        Numbers::Complexes(cs) => Ok(Value::Complex(
+            cs.into_iter()
+                .fold(Complex::new(0.0, 0.0), |acc, z| acc + z),
+        )),
+    }
+}
+
+fn mul(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match numbers(&args)? {
+        Numbers::Floats(ns) => ns
+            .into_iter()
+            .try_fold(1.0, |acc, n| checked("*", acc, n, |a, b| a * b))
+            .map(Value::Number),
+        Numbers::Decimals(ds) => Ok(Value::Decimal(
+            ds.iter().fold(Decimal::one(), |acc, d| acc.mul(d)),
+        )),
+        Numbers::Complexes(cs) => Ok(Value::Complex(
+            cs.into_iter()
+                .fold(Complex::new(1.0, 0.0), |acc, z| acc * z),
+        )),
+    }
+}
+
+fn sub(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match numbers(&args)? {
+        Numbers::Floats(ns) => match ns.as_slice() {
+            [] => Ok(Value::Number(0.0)),
+            [n] => Ok(Value::Number(-n)),
+            [first, rest @ ..] => rest
+                .iter()
+                .try_fold(*first, |acc, n| checked("-", acc, *n, |a, b| a - b))
+                .map(Value::Number),
+        },
+        Numbers::Decimals(ds) => Ok(Value::Decimal(match ds.as_slice() {
+            [n] => n.neg(),
+            [first, rest @ ..] => rest.iter().fold(first.clone(), |acc, d| acc.sub(d)),
+            [] => unreachable!("decimals come from at least one argument"),
+        })),
+        Numbers::Complexes(cs) => Ok(Value::Complex(match cs.as_slice() {
+            [z] => -*z,
+            [first, rest @ ..] => rest.iter().fold(*first, |acc, z| acc - *z),
+            [] => unreachable!("complex numbers come from at least one argument"),
+        })),
+    }
+}
+
+fn div(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    if args.is_empty() {
+        return Err(EvalError::ArityMismatch {
+            name: "/".into(),
+            expected: "at least 1".into(),
+            found: 0,
+        });
+    }
+    match numbers(&args)? {
+        Numbers::Floats(ns) => match ns.as_slice() {
+            [n] => checked("/", 1.0, *n, |a, b| a / b),
+            [first, rest @ ..] => rest
+                .iter()
+                .try_fold(*first, |acc, n| checked("/", acc, *n, |a, b| a / b)),
+            [] => unreachable!(),
+        }
+        .map(Value::Number),
+        Numbers::Decimals(ds) => {
+            let (first, rest) = match ds.as_slice() {
+                [n] => (Decimal::one(), core::slice::from_ref(n)),
+                [first, rest @ ..] => (first.clone(), rest),
+                [] => unreachable!(),
+            };
+            rest.iter()
+                .try_fold(first, |acc, d| acc.div(d))
+                .map(Value::Decimal)
+                .ok_or_else(|| EvalError::Host("decimal division by zero".into()))
+        }
+        Numbers::Complexes(cs) => Ok(Value::Complex(match cs.as_slice() {
+            [z] => Complex::new(1.0, 0.0) / *z,
+            [first, rest @ ..] => rest.iter().fold(*first, |acc, z| acc / *z),
+            [] => unreachable!(),
+        })),
+    }
+}
+
+/// Whether `a` and `b` are equal, comparing numbers of different kinds
+/// by value.
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(n), Value::Decimal(d)) | (Value::Decimal(d), Value::Number(n)) => {
+            Decimal::from_f32(*n).as_ref() == Some(d)
+        }
+        (Value::Complex(z), real @ (Value::Number(_) | Value::Decimal(_)))
+        | (real @ (Value::Number(_) | Value::Decimal(_)), Value::Complex(z)) => {
+            expect_complex(real).is_ok_and(|real| real == *z)
+        }
+        _ => a == b,
+    }
+}
+
+fn eq(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    Ok(Value::Bool(
+        args.windows(2).all(|pair| equal(&pair[0], &pair[1])),
+    ))
+}
+
+/// Whether `check` holds for the order of each number and the next. NaN
+/// is in no order, and complex numbers have none.
+fn compare(args: &[Value], check: fn(Ordering) -> bool) -> Result<Value, EvalError> {
+    let holds = |order: Option<Ordering>| order.is_some_and(check);
+    Ok(Value::Bool(match numbers(args)? {
+        Numbers::Floats(ns) => ns
+            .windows(2)
+            .all(|pair| holds(pair[0].partial_cmp(&pair[1]))),
+        Numbers::Decimals(ds) => ds.windows(2).all(|pair| holds(Some(pair[0].cmp(&pair[1])))),
+        Numbers::Complexes(_) => {
+            return Err(EvalError::TypeMismatch {
+                expected: "real number",
+                found: "complex",
+            })
+        }
+    }))
+}
+
+fn lt(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    compare(&args, Ordering::is_lt)
+}
+
+fn gt(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    compare(&args, Ordering::is_gt)
+}
+
+fn le(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    compare(&args, Ordering::is_le)
+}
+
+fn ge(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    compare(&args, Ordering::is_ge)
+}
+
+fn decimal(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("decimal", &args, 1)?;
+    match &args[0] {
+        Value::String(s) => Ok(Decimal::parse(s.trim()).map_or(Value::Nil, Value::Decimal)),
+        other => expect_decimal(other).map(Value::Decimal),
+    }
+}
+
+fn is_decimal(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("decimal?", &args, 1)?;
+    Ok(Value::Bool(matches!(args[0], Value::Decimal(_))))
+}
+
+fn complex(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("complex", &args, 2)?;
+    let re = expect_number(&args[0])?;
+    let im = expect_number(&args[1])?;
+    Ok(Value::Complex(Complex::new(re, im)))
+}
+
+fn is_complex(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("complex?", &args, 1)?;
+    Ok(Value::Bool(matches!(args[0], Value::Complex(_))))
+}
+
+fn real_part(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("real-part", &args, 1)?;
+    match &args[0] {
+        Value::Complex(z) => Ok(Value::Number(z.re)),
+        real => expect_complex(real).map(|_| real.clone()),
+    }
+}
+
+fn imag_part(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("imag-part", &args, 1)?;
+    expect_complex(&args[0]).map(|z| Value::Number(z.im))
+}
+
+#[cfg(feature = "std")]
+fn magnitude(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("magnitude", &args, 1)?;
+    match &args[0] {
+        Value::Decimal(d) if *d < Decimal::zero() => Ok(Value::Decimal(d.neg())),
+        Value::Decimal(d) => Ok(Value::Decimal(d.clone())),
+        z => expect_complex(z).map(|z| Value::Number(z.magnitude())),
+    }
+}
+
+fn decimal_round(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("decimal/round", &args, 2)?;
+    let d = expect_decimal(&args[0])?;
+    let places = expect_number(&args[1])?;
+    if places < 0.0 || places % 1.0 != 0.0 {
+        return Err(EvalError::Host(format!(
+            "decimal/round takes a whole number of places, not {}",
+            places
+        )));
+    }
+    Ok(Value::Decimal(d.round(places as u32)))
+}
+
+fn not(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("not", &args, 1)?;
+    Ok(Value::Bool(!args[0].is_truthy()))
+}
+
+fn list(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    Ok(Value::List(args))
+}
+
+fn cons(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("cons", &args, 2)?;
+    let mut args = args.into_iter();
+    let head = args.next().unwrap();
+    let mut list = expect_list(args.next().unwrap())?;
+    list.insert(0, head);
+    Ok(Value::List(list))
+}
+
+fn car(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("car", &args, 1)?;
+    let list = expect_list(args.into_iter().next().unwrap())?;
+    Ok(list.into_iter().next().unwrap_or(Value::Nil))
+}
+
+fn cdr(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("cdr", &args, 1)?;
+    let list = expect_list(args.into_iter().next().unwrap())?;
+    Ok(Value::List(list.into_iter().skip(1).collect()))
+}
+
+fn is_empty(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("empty?", &args, 1)?;
+    let list = expect_list(args.into_iter().next().unwrap())?;
+    Ok(Value::Bool(list.is_empty()))
+}
+
+fn is_list(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("list?", &args, 1)?;
+    Ok(Value::Bool(matches!(args[0], Value::List(_))))
+}
+
+/// The lazy sequence `range` makes. Each item is computed from `start`
+/// rather than by adding `step` to the one before, so that rounding does
+/// not build up, nor stop the range once adding `step` no longer changes
+/// an f32. The bounds are taken as the decimals they were written as, so
+/// that stepping by 0.1 gives 0.9 rather than the f32 after it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Range {
+    start: f64,
+    step: f64,
+    /// The index of the next item.
+    index: u64,
+    len: u64,
+}
+
+impl Range {
+    fn new(start: f32, end: f32, step: f32) -> Self {
+        let (start, step) = (as_written(start), as_written(step));
+        let steps = (as_written(end) - start) / step;
+        // the ceiling, by hand as core has no f64::ceil; the cast saturates,
+        // at 0 for a range stepping away from its end
+        let whole = steps as u64;
+        let len = match (whole as f64) < steps {
+            true => whole.saturating_add(1),
+            false => whole,
+        };
+        Self {
+            start,
+            step,
+            index: 0,
+            len,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.index >= self.len
+    }
+
+    fn first(&self) -> f32 {
+        (self.start + self.index as f64 * self.step) as f32
+    }
+}
+
+/// The f64 nearest the shortest decimal that reads as `n`, such as 0.1 for
+/// the f32 nearest 0.1.
+fn as_written(n: f32) -> f64 {
+    n.to_string().parse().unwrap_or(n.into())
+}
+
+fn range(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (start, end, step) = match args.as_slice() {
+        [end] => (0.0, expect_number(end)?, 1.0),
+        [start, end] => (expect_number(start)?, expect_number(end)?, 1.0),
+        [start, end, step] => (
+            expect_number(start)?,
+            expect_number(end)?,
+            expect_number(step)?,
+        ),
+        _ => {
+            return Err(EvalError::ArityMismatch {
+                name: "range".into(),
+                expected: "1 to 3".into(),
+                found: args.len(),
+            })
+        }
+    };
+    if step == 0.0 || step.is_nan() {
+        return Err(EvalError::Host(format!("range cannot step by {}", step)));
+    }
+    let range = Range::new(start, end, step);
+    Ok(Value::Host(HostValue::new("range", range)))
+}
+
+/// The first item of the iterable `coll` and the rest of it, or `None` if
+/// it has no items left.
+fn step(coll: Value) -> Result<Option<(Value, Value)>, EvalError> {
+    let not_iterable = |found: &Value| EvalError::TypeMismatch {
+        expected: "iterable",
+        found: found.type_name(),
+    };
+    Ok(match coll {
+        Value::Nil => None,
+        Value::List(items) => {
+            let mut items = items.into_iter();
+            items
+                .next()
+                .map(|first| (first, Value::List(items.collect())))
+        }
+        Value::String(s) => s.chars().next().map(|c| {
+            let rest = s[c.len_utf8()..].into();
+            (Value::String(c.into()), Value::String(rest))
+        }),
+        Value::Bytes(bytes) => bytes
+            .split_first()
+            .map(|(first, rest)| (Value::Number((*first).into()), Value::Bytes(rest.to_vec()))),
+        Value::Host(ref host) => {
+            let range = host
+                .downcast_ref::<Range>()
+                .ok_or_else(|| not_iterable(&coll))?;
+            (!range.is_empty()).then(|| {
+                let rest = Range {
+                    index: range.index + 1,
+                    ..*range
+                };
+                let rest = Value::Host(HostValue::new("range", rest));
+                (Value::Number(range.first()), rest)
+            })
+        }
+        other => return Err(not_iterable(&other)),
+    })
+}
+
+/// The items of the iterable `coll`, for builtins taking any iterable.
+fn items(mut coll: Value) -> Result<Vec<Value>, EvalError> {
+    if let Value::List(items) = coll {
+        return Ok(items);
+    }
+    let mut items = Vec::new();
+    while let Some((first, rest)) = step(coll)? {
+        items.push(first);
+        coll = rest;
+    }
+    Ok(items)
+}
+
+fn next(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("next", &args, 1)?;
+    let step = step(args.into_iter().next().unwrap())?;
+    Ok(step.map_or(Value::Nil, |(first, rest)| Value::List(vec![first, rest])))
+}
+
+fn type_of(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("type-of", &args, 1)?;
+    // a record is an alist starting with (:type name)
+    if let Value::List(items) = &args[0] {
+        if let Some(Value::List(pair)) = items.first() {
+            if let [Value::Symbol(key), name @ Value::Symbol(_)] = pair.as_slice() {
+                if key == ":type" {
+                    return Ok(name.clone());
+                }
+            }
+        }
+    }
+    Ok(Value::Symbol(args[0].type_name().into()))
+}
+
+/// The pairs of an association list with their keys, which are the first
+/// element of each.
+fn pairs(value: Value) -> Result<Vec<(Value, Value)>, EvalError> {
+    expect_list(value)?
+        .into_iter()
+        .map(|pair| match pair {
+            Value::List(ref items) if !items.is_empty() => Ok((items[0].clone(), pair)),
+            other => Err(EvalError::TypeMismatch {
+                expected: "pair",
+                found: other.type_name(),
+            }),
+        })
+        .collect()
+}
+
+/// The name of a map key, which may be a string or a symbol.
+fn key_name(key: Value) -> Result<String, EvalError> {
+    match key {
+        Value::String(name) | Value::Symbol(name) => Ok(name),
+        other => Err(EvalError::TypeMismatch {
+            expected: "string",
+            found: other.type_name(),
+        }),
+    }
+}
+
+/// `pair` with its key replaced by `key`.
+fn rekey(pair: Value, key: Value) -> Value {
+    let Value::List(mut items) = pair else {
+        unreachable!("pairs are lists");
+    };
+    items[0] = key;
+    Value::List(items)
+}
+
+fn assoc(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("assoc", &args, 2)?;
+    let mut args = args.into_iter();
+    let key = args.next().unwrap();
+    let found = pairs(args.next().unwrap())?
+        .into_iter()
+        .find(|(other, _)| *other == key);
+    Ok(found.map_or(Value::Nil, |(_, pair)| pair))
+}
+
+fn assq(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("assq", &args, 2)?;
+    let mut args = args.into_iter();
+    let name = match args.next().unwrap() {
+        Value::Symbol(name) => name,
+        other => {
+            return Err(EvalError::TypeMismatch {
+                expected: "symbol",
+                found: other.type_name(),
+            })
+        }
+    };
+    let found = pairs(args.next().unwrap())?
+        .into_iter()
+        .find(|(key, _)| matches!(key, Value::Symbol(key) if *key == name));
+    Ok(found.map_or(Value::Nil, |(_, pair)| pair))
+}
+
+fn alist_to_map(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("alist->map", &args, 1)?;
+    let mut map: Vec<(String, Value)> = Vec::new();
+    for (key, pair) in pairs(args.into_iter().next().unwrap())? {
+        let name = key_name(key)?;
+        if let Err(at) = map.binary_search_by(|(other, _)| other.as_str().cmp(&name)) {
+            let pair = rekey(pair, Value::String(name.clone()));
+            map.insert(at, (name, pair));
+        }
+    }
+    Ok(Value::List(map.into_iter().map(|(_, pair)| pair).collect()))
+}
+
+fn map_to_alist(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("map->alist", &args, 1)?;
+    pairs(args.into_iter().next().unwrap())?
+        .into_iter()
+        .map(|(key, pair)| Ok(rekey(pair, Value::Symbol(key_name(key)?))))
+        .collect::<Result<_, _>>()
+        .map(Value::List)
+}
+
+/// Calls the method of a multimethod. Its key is `(dispatch args...)`, or
+/// when `dispatch` is a keyword, the value paired with that keyword in the
+/// first argument, an alist. `methods` pairs keys with methods, the latest
+/// first, and the method for `:default` is the fallback.
+fn multi_dispatch(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("multi-dispatch", &args, 4)?;
+    let mut args = args.into_iter();
+    let name = args.next().unwrap();
+    let dispatch = args.next().unwrap();
+    let methods = pairs(args.next().unwrap())?;
+    let args = expect_list(args.next().unwrap())?;
+    let key = match &dispatch {
+        Value::Symbol(keyword) if is_keyword(keyword) => {
+            let fields = args.first().cloned().map_or(Ok(Vec::new()), pairs)?;
+            fields
+                .into_iter()
+                .find(|(field, _)| *field == dispatch)
+                .map_or(Value::Nil, |(_, pair)| second(pair))
+        }
+        _ => interpreter.apply(&dispatch, args.clone())?,
+    };
+    let default = Value::Symbol(":default".into());
+    let method = methods
+        .iter()
+        .find(|(other, _)| *other == key)
+        .or_else(|| methods.iter().find(|(other, _)| *other == default));
+    match method {
+        Some((_, pair)) => interpreter.apply(&second(pair.clone()), args),
+        None => Err(EvalError::Host(format!(
+            "{} has no method for {}",
+            name, key
+        ))),
+    }
+}
+
+/// The second element of an alist pair, nil if it has none.
+fn second(pair: Value) -> Value {
+    match pair {
+        Value::List(items) => items.into_iter().nth(1).unwrap_or(Value::Nil),
+        _ => Value::Nil,
+    }
+}
+
+fn raise(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("raise", &args, 2)?;
+    let mut args = args.into_iter();
+    let tag = match args.next().unwrap() {
+        Value::Symbol(tag) => tag,
+        other => {
+            return Err(EvalError::TypeMismatch {
+                expected: "symbol",
+                found: other.type_name(),
+            })
+        }
+    };
+    Err(interpreter.raise(&tag, args.next().unwrap()))
+}
+
+fn apply(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("apply", &args, 2)?;
+    let mut args = args.into_iter();
+    let callee = args.next().unwrap();
+    let list = expect_list(args.next().unwrap())?;
+    interpreter.apply(&callee, list)
+}
+
+fn macroexpand_1(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("macroexpand-1", &args, 1)?;
+    interpreter.macroexpand_1(&args[0]).map(|(form, _)| form)
+}
+
+fn macroexpand(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("macroexpand", &args, 1)?;
+    interpreter.macroexpand(&args[0])
+}
+
+fn arity(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("arity", &args, 1)?;
+    match &args[0] {
+        Value::Lambda(lambda) => Ok(Value::Number(lambda.params.len() as f32)),
+        Value::Builtin(builtin) => {
+            Ok(fixed_arity(builtin.name).map_or(Value::Nil, |n| Value::Number(n as f32)))
+        }
+        other => Err(EvalError::TypeMismatch {
+            expected: "function",
+            found: other.type_name(),
+        }),
+    }
+}
+
+fn stack_depth(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("stack-depth", &args, 0)?;
+    Ok(Value::Number(interpreter.stack_depth() as f32))
+}
+
+fn function_params(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("function-params", &args, 1)?;
+    match &args[0] {
+        Value::Lambda(lambda) | Value::Macro(lambda) => Ok(Value::List(lambda.param_list())),
+        Value::Builtin(builtin) => {
+            let params = doc(builtin.name).map_or("", |(params, _)| params);
+            let params = params
+                .split_whitespace()
+                .map(|param| Value::Symbol(param.into()));
+            Ok(Value::List(params.collect()))
+        }
+        other => Err(EvalError::TypeMismatch {
+            expected: "function",
+            found: other.type_name(),
+        }),
+    }
+}
+
+fn source(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("source", &args, 1)?;
+    let (head, lambda) = match &args[0] {
+        Value::Lambda(lambda) => ("def", lambda),
+        Value::Macro(lambda) => ("defmacro", lambda),
+        Value::Builtin(_) => return Ok(Value::Nil),
+        other => {
+            return Err(EvalError::TypeMismatch {
+                expected: "function",
+                found: other.type_name(),
+            })
+        }
+    };
+    let mut form = match &lambda.name {
+        Some(name) => {
+            let mut signature = vec![Value::Symbol(name.clone())];
+            signature.extend(lambda.param_list());
+            vec![Value::Symbol(head.into()), Value::List(signature)]
+        }
+        None => vec![
+            Value::Symbol("lambda".into()),
+            Value::List(lambda.param_list()),
+        ],
+    };
+    form.extend(lambda.body.iter().cloned());
+    Ok(Value::List(form))
+}
+
+fn pmap(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("pmap", &args, 2)?;
+    let mut args = args.into_iter();
+    let callee = args.next().unwrap();
+    let list = items(args.next().unwrap())?;
+    #[cfg(feature = "std")]
+    if let Some(results) = crate::parallel::map(interpreter, &callee, &list) {
+        return results.map(Value::List);
+    }
+    list.into_iter()
+        .map(|item| interpreter.apply(&callee, vec![item]))
+        .collect::<Result<_, _>>()
+        .map(Value::List)
+}
+
+fn string_append(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let mut result = String::new();
+    for arg in args {
+        match arg {
+            Value::String(s) => result.push_str(&s),
+            other => {
+                return Err(EvalError::TypeMismatch {
+                    expected: "string",
+                    found: other.type_name(),
+                })
+            }
+        }
+    }
+    Ok(Value::String(result))
+}
+
+fn string_to_list(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("string->list", &args, 1)?;
+    let chars = expect_string(&args[0])?.chars();
+    Ok(Value::List(
+        chars.map(|c| Value::String(c.into())).collect(),
+    ))
+}
+
+fn list_to_string(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("list->string", &args, 1)?;
+    let chars = expect_list(args.into_iter().next().unwrap())?;
+    chars
+        .iter()
+        .map(expect_char)
+        .collect::<Result<_, _>>()
+        .map(Value::String)
+}
+
+fn string_ref(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("string/ref", &args, 2)?;
+    let s = expect_string(&args[0])?;
+    let i = expect_number(&args[1])?;
+    if i < 0.0 || i % 1.0 != 0.0 {
+        return Ok(Value::Nil);
+    }
+    Ok(s.chars()
+        .nth(i as usize)
+        .map_or(Value::Nil, |c| Value::String(c.into())))
+}
+
+fn string_length(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("string/length", &args, 1)?;
+    Ok(Value::Number(
+        expect_string(&args[0])?.chars().count() as f32
+    ))
+}
+
+fn string_slice(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (s, start, end) = match args.as_slice() {
+        [s, start] => (expect_string(s)?, index(start)?, None),
+        [s, start, end] => (expect_string(s)?, index(start)?, Some(index(end)?)),
+        _ => {
+            return Err(EvalError::ArityMismatch {
+                name: "string/slice".into(),
+                expected: "2 or 3".into(),
+                found: args.len(),
+            })
+        }
+    };
+    // byte offsets of the code points, and of the end of s
+    let offsets: Vec<usize> = s.char_indices().map(|(i, _)| i).chain([s.len()]).collect();
+    let end = end.unwrap_or(Some(offsets.len() - 1));
+    let slice = start
+        .zip(end)
+        .filter(|(start, end)| start <= end)
+        .and_then(|(start, end)| Some(&s[*offsets.get(start)?..*offsets.get(end)?]));
+    Ok(slice.map_or(Value::Nil, |slice| Value::String(slice.into())))
+}
+
+/// `value` as an index, or `None` if it is negative or fractional.
+fn index(value: &Value) -> Result<Option<usize>, EvalError> {
+    let n = expect_number(value)?;
+    Ok((n >= 0.0 && n % 1.0 == 0.0).then_some(n as usize))
+}
+
+fn string_reverse(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("string/reverse", &args, 1)?;
+    Ok(Value::String(
+        expect_string(&args[0])?.chars().rev().collect(),
+    ))
+}
+
+fn char_code(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("char-code", &args, 1)?;
+    Ok(Value::Number(u32::from(expect_char(&args[0])?) as f32))
+}
+
+fn code_char(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("code-char", &args, 1)?;
+    let n = expect_number(&args[0])?;
+    let c = (n >= 0.0 && n % 1.0 == 0.0)
+        .then(|| char::from_u32(n as u32))
+        .flatten();
+    Ok(c.map_or(Value::Nil, |c| Value::String(c.into())))
+}
+
+/// The bytes `value` holds, or a type mismatch if it is not bytes.
+fn expect_bytes(value: &Value) -> Result<&[u8], EvalError> {
+    match value {
+        Value::Bytes(bytes) => Ok(bytes),
+        other => Err(EvalError::TypeMismatch {
+            expected: "bytes",
+            found: other.type_name(),
+        }),
+    }
+}
+
+fn bytes(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    args.iter()
+        .map(|arg| match index(arg)? {
+            Some(byte @ 0..=255) => Ok(byte as u8),
+            _ => Err(EvalError::Host(format!("{} is not a byte", arg))),
+        })
+        .collect::<Result<_, _>>()
+        .map(Value::Bytes)
+}
+
+fn is_bytes(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("bytes?", &args, 1)?;
+    Ok(Value::Bool(matches!(args[0], Value::Bytes(_))))
+}
+
+fn bytes_length(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("bytes-length", &args, 1)?;
+    Ok(Value::Number(expect_bytes(&args[0])?.len() as f32))
+}
+
+fn bytes_ref(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("bytes-ref", &args, 2)?;
+    let bytes = expect_bytes(&args[0])?;
+    let byte = index(&args[1])?.and_then(|i| bytes.get(i));
+    Ok(byte.map_or(Value::Nil, |&byte| Value::Number(byte.into())))
+}
+
+fn bytes_slice(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (bytes, start, end) = match args.as_slice() {
+        [bytes, start] => (expect_bytes(bytes)?, index(start)?, None),
+        [bytes, start, end] => (expect_bytes(bytes)?, index(start)?, Some(index(end)?)),
+        _ => {
+            return Err(EvalError::ArityMismatch {
+                name: "bytes-slice".into(),
+                expected: "2 or 3".into(),
+                found: args.len(),
+            })
+        }
+    };
+    let end = end.unwrap_or(Some(bytes.len()));
+    let slice = start
+        .zip(end)
+        .and_then(|(start, end)| bytes.get(start..end));
+    Ok(slice.map_or(Value::Nil, |slice| Value::Bytes(slice.to_vec())))
+}
+
+fn bytes_append(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let mut appended = Vec::new();
+    for arg in &args {
+        appended.extend_from_slice(expect_bytes(arg)?);
+    }
+    Ok(Value::Bytes(appended))
+}
+
+/// Splits the arguments of `name` into the first and the encoding given
+/// after it, UTF-8 by default.
+fn with_encoding<'a>(name: &str, args: &'a [Value]) -> Result<(&'a Value, Encoding), EvalError> {
+    match args {
+        [first] => Ok((first, Encoding::Utf8)),
+        [first, Value::Symbol(encoding)] => match Encoding::from_name(encoding) {
+            Some(encoding) => Ok((first, encoding)),
+            None => Err(EvalError::Host(format!(
+                "unknown encoding {}: expected :utf-8, :latin-1, :ascii, :utf-16le, or :utf-16be",
+                encoding
+            ))),
+        },
+        _ => Err(EvalError::ArityMismatch {
+            name: name.into(),
+            expected: "1 or 2".into(),
+            found: args.len(),
+        }),
+    }
+}
+
+fn string_to_bytes(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (s, encoding) = with_encoding("string->bytes", &args)?;
+    let bytes = encoding.encode(expect_string(s)?);
+    Ok(bytes.map_or(Value::Nil, Value::Bytes))
+}
+
+fn bytes_to_string(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (bytes, encoding) = with_encoding("bytes->string", &args)?;
+    let s = encoding.decode(expect_bytes(bytes)?);
+    Ok(s.map_or(Value::Nil, Value::String))
+}
+
+/// Splits the arguments of `name` into the first and the base its options
+/// give, as in `(name x :base 16)`.
+fn with_base<'a>(name: &str, args: &'a [Value]) -> Result<(&'a Value, u32), EvalError> {
+    let Some((first, options)) = args.split_first() else {
+        return Err(EvalError::ArityMismatch {
+            name: name.into(),
+            expected: "1 or 3".into(),
+            found: 0,
+        });
+    };
+    match options {
+        [] => Ok((first, 10)),
+        [Value::Symbol(key), Value::Number(base)]
+            if key == ":base" && base % 1.0 == 0.0 && (2.0..=36.0).contains(base) =>
+        {
+            Ok((first, *base as u32))
+        }
+        _ => Err(EvalError::Host(format!(
+            "{} takes one option, :base, a whole number from 2 to 36",
+            name
+        ))),
+    }
+}
+
+/// The token `text` reads as when it is one token and nothing more.
+fn single_token(text: &str) -> Option<Token<'_>> {
+    let mut scanner = Scanner::new(text);
+    let tok = scanner.next_token().ok()?;
+    (scanner.position() == text.len()).then_some(tok.token)
+}
+
+fn pretty(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (x, width) = match args.as_slice() {
+        [x] => (x, crate::pretty::DEFAULT_WIDTH),
+        [x, Value::Symbol(key), width] if key == ":width" => match index(width)? {
+            Some(width) => (x, width),
+            None => return Err(EvalError::Host("pretty takes a whole number :width".into())),
+        },
+        [_, _, _] => return Err(EvalError::Host("pretty takes one option, :width".into())),
+        _ => {
+            return Err(EvalError::ArityMismatch {
+                name: "pretty".into(),
+                expected: "1 or 3".into(),
+                found: args.len(),
+            })
+        }
+    };
+    let floats = interpreter.float_format()?;
+    let limits = crate::pretty::Limits::default();
+    Ok(Value::String(crate::pretty::pretty_limited(
+        x, width, &limits, &floats,
+    )))
+}
+
+fn number_to_string(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (n, base) = with_base("number->string", &args)?;
+    let n = match n {
+        Value::Decimal(d) if base == 10 => return Ok(Value::String(d.to_string())),
+        Value::Decimal(d) => d.to_f32(),
+        Value::Complex(z) if base == 10 => return Ok(Value::String(z.to_string())),
+        Value::Complex(_) => return Ok(Value::Nil),
+        n => expect_number(n)?,
+    };
+    if base == 10 {
+        return Ok(Value::String(n.to_string()));
+    }
+    if n % 1.0 != 0.0 || n.abs() >= i64::MAX as f32 {
+        return Ok(Value::Nil);
+    }
+    let n = n as i64;
+    let mut digits = Vec::new();
+    let mut rest = n.unsigned_abs();
+    loop {
+        let digit = (rest % u64::from(base)) as u32;
+        digits.push(char::from_digit(digit, base).unwrap());
+        rest /= u64::from(base);
+        if rest == 0 {
+            break;
+        }
+    }
+    if n < 0 {
+        digits.push('-');
+    }
+    Ok(Value::String(digits.into_iter().rev().collect()))
+}
+
+fn string_to_number(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (s, base) = with_base("string->number", &args)?;
+    let s = expect_string(s)?;
+    let n = match single_token(s) {
+        Some(Token::Number(n)) if base == 10 => match n.strip_suffix('m') {
+            Some(d) => return Ok(Decimal::parse(d).map_or(Value::Nil, Value::Decimal)),
+            None if n.ends_with('i') => {
+                return Ok(Complex::parse(n).map_or(Value::Nil, Value::Complex))
+            }
+            None => n.parse().ok(),
+        },
+        _ if base == 10 => None,
+        _ => i64::from_str_radix(s, base).ok().map(|n| n as f32),
+    };
+    Ok(n.map_or(Value::Nil, Value::Number))
+}
+
+fn symbol_to_string(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("symbol->string", &args, 1)?;
+    match &args[0] {
+        Value::Symbol(s) => Ok(Value::String(s.clone())),
+        other => Err(EvalError::TypeMismatch {
+            expected: "symbol",
+            found: other.type_name(),
+        }),
+    }
+}
+
+fn string_to_symbol(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("string->symbol", &args, 1)?;
+    Ok(match single_token(expect_string(&args[0])?) {
+        Some(Token::Symbol(s)) => Value::Symbol(s.into()),
+        _ => Value::Nil,
+    })
+}
+
+fn print(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let floats = interpreter.float_format()?;
+    let line: Vec<String> = args
+        .iter()
+        .map(|arg| match arg {
+            Value::String(s) => s.clone(),
+            other => floats.value(other),
+        })
+        .collect();
+    interpreter.write_line(&line.join(" "))?;
+    Ok(Value::Nil)
+}
+
+fn read_line(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let line = match args.as_slice() {
+        [] => interpreter.read_line()?,
+        [file] => read_file_line(file)?,
+        _ => {
+            return Err(EvalError::ArityMismatch {
+                name: "read-line".into(),
+                expected: "0 or 1".into(),
+                found: args.len(),
+            })
+        }
+    };
+    Ok(line.map_or(Value::Nil, Value::String))
+}
+
+#[cfg(feature = "std")]
+fn load(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("load", &args, 1)?;
+    interpreter.load_file(expect_string(&args[0])?)
+}
+
+#[cfg(feature = "std")]
+fn log_debug(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    log("log/debug", Level::Debug, interpreter, args)
+}
+
+#[cfg(feature = "std")]
+fn log_info(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    log("log/info", Level::Info, interpreter, args)
+}
+
+#[cfg(feature = "std")]
+fn log_warn(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    log("log/warn", Level::Warn, interpreter, args)
+}
+
+#[cfg(feature = "std")]
+fn log(
+    name: &str,
+    level: Level,
+    interpreter: &mut Interpreter,
+    args: Vec<Value>,
+) -> Result<Value, EvalError> {
+    let (message, data) = match args.as_slice() {
+        [message] => (message, Value::Nil),
+        [message, data] => (message, data.clone()),
+        _ => {
+            return Err(EvalError::ArityMismatch {
+                name: name.into(),
+                expected: "1 or 2".into(),
+                found: args.len(),
+            })
+        }
+    };
+    let message = match message {
+        Value::String(message) => message.clone(),
+        other => other.to_string(),
+    };
+    interpreter.log(&Record {
+        level,
+        message,
+        data,
+    })?;
+    Ok(Value::Nil)
+}
+
+#[cfg(feature = "std")]
+fn log_set_level(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("log/set-level", &args, 1)?;
+    let level = match &args[0] {
+        Value::Symbol(name) | Value::String(name) => Level::from_name(name),
+        _ => None,
+    };
+    let level = level.ok_or_else(|| {
+        EvalError::Host(format!(
+            "log/set-level takes :debug, :info, or :warn, not {}",
+            args[0]
+        ))
+    })?;
+    interpreter.set_log_level(level);
+    Ok(Value::Nil)
+}
+
+#[cfg(feature = "toml")]
+fn toml_parse(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("toml/parse", &args, 1)?;
+    crate::config::parse_toml(expect_string(&args[0])?)
+        .map_err(|message| interpreter.raise("parse-error", Value::String(message)))
+}
+
+#[cfg(feature = "toml")]
+fn toml_write(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("toml/write", &args, 1)?;
+    crate::config::write_toml(&args[0]).map(Value::String)
+}
+
+#[cfg(feature = "yaml")]
+fn yaml_parse(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("yaml/parse", &args, 1)?;
+    crate::config::parse_yaml(expect_string(&args[0])?)
+        .map_err(|message| interpreter.raise("parse-error", Value::String(message)))
+}
+
+#[cfg(feature = "yaml")]
+fn yaml_write(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("yaml/write", &args, 1)?;
+    crate::config::write_yaml(&args[0]).map(Value::String)
+}
+
+/// A file from `open-file`, empty once closed.
+#[cfg(feature = "std")]
+type File = std::sync::Mutex<Option<std::io::BufReader<std::fs::File>>>;
+
+#[cfg(feature = "std")]
+fn open_file(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("open-file", &args, 1)?;
+    let path = expect_string(&args[0])?;
+    let file = std::fs::File::open(path)
+        .map_err(|err| EvalError::Io(format!("cannot open {}: {}", path, err)))?;
+    let file: File = std::sync::Mutex::new(Some(std::io::BufReader::new(file)));
+    let close = |file: &File| {
+        file.lock().map_err(|_| EvalError::Internal)?.take();
+        Ok(())
+    };
+    Ok(Value::Host(crate::value::HostValue::closeable(
+        "file", file, close,
+    )))
+}
+
+/// `read` applied to the open file `value`.
+#[cfg(feature = "std")]
+fn with_file<T>(
+    value: &Value,
+    read: impl FnOnce(&mut std::io::BufReader<std::fs::File>) -> Result<T, EvalError>,
+) -> Result<T, EvalError> {
+    let Some(file) = (match value {
+        Value::Host(host) => host.downcast_ref::<File>(),
+        _ => None,
+    }) else {
+        return Err(EvalError::TypeMismatch {
+            expected: "file",
+            found: value.type_name(),
+        });
+    };
+    let mut file = file.lock().map_err(|_| EvalError::Internal)?;
+    match file.as_mut() {
+        Some(file) => read(file),
+        None => Err(EvalError::Io("the file is closed".into())),
+    }
+}
+
+#[cfg(feature = "std")]
+fn read_file_line(value: &Value) -> Result<Option<String>, EvalError> {
+    with_file(value, crate::eval::read_line_from)
+}
+
+#[cfg(feature = "std")]
+fn read_bytes(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    use std::io::Read;
+
+    expect_arity("read-bytes", &args, 2)?;
+    let n = index(&args[1])?.ok_or_else(|| {
+        EvalError::Host("read-bytes takes a whole number of bytes to read".into())
+    })?;
+    let bytes = with_file(&args[0], |file| {
+        let mut bytes = Vec::new();
+        file.take(n as u64)
+            .read_to_end(&mut bytes)
+            .map_err(|err| EvalError::Io(err.to_string()))?;
+        Ok(bytes)
+    })?;
+    Ok(if bytes.is_empty() && n > 0 {
+        Value::Nil
+    } else {
+        Value::Bytes(bytes)
+    })
+}
+
+/// Without std there are no files to read.
+#[cfg(not(feature = "std"))]
+fn read_file_line(value: &Value) -> Result<Option<String>, EvalError> {
+    Err(EvalError::TypeMismatch {
+        expected: "file",
+        found: value.type_name(),
+    })
+}
+
+fn host_close(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("host/close", &args, 1)?;
+    match &args[0] {
+        Value::Host(host) => host.close().map(|()| Value::Nil),
+        other => Err(EvalError::TypeMismatch {
+            expected: "host value",
+            found: other.type_name(),
+        }),
+    }
+}
+
+/// Does nothing itself: the debugger stops before evaluating a call to it.
+fn breakpoint(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("breakpoint", &args, 0)?;
+    Ok(Value::Nil)
+}
+
+#[cfg(feature = "std")]
+fn actor(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("actor", &args, 2)?;
+    let mut args = args.into_iter();
+    let init = args.next().unwrap();
+    let handler = args.next().unwrap();
+    crate::actor::Actor::spawn(interpreter, init, handler).map(Value::Actor)
+}
+
+#[cfg(feature = "std")]
+fn expect_actor(value: &Value) -> Result<&crate::actor::Actor, EvalError> {
+    match value {
+        Value::Actor(actor) => Ok(actor),
+        other => Err(EvalError::TypeMismatch {
+            expected: "actor",
+            found: other.type_name(),
+        }),
+    }
+}
+
+#[cfg(feature = "std")]
+fn send(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("send!", &args, 2)?;
+    expect_actor(&args[0])?.send(args[1].clone())?;
+    Ok(Value::Nil)
+}
+
+#[cfg(feature = "std")]
+fn ask(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("ask", &args, 3)?;
+    let timeout = std::time::Duration::try_from_secs_f32(expect_number(&args[2])?)
+        .map_err(|_| EvalError::Host("ask needs a timeout of zero or more seconds".into()))?;
+    expect_actor(&args[0])?.ask(args[1].clone(), timeout)
+}
+
+/// The name calls to a function are traced under.
+pub fn function_name(value: &Value) -> Result<String, EvalError> {
+    match value {
+        Value::Builtin(builtin) => Ok(builtin.name.into()),
+        Value::Lambda(lambda) => Ok(lambda.name.clone().unwrap_or_else(|| "lambda".into())),
+        other => Err(EvalError::TypeMismatch {
+            expected: "function",
+            found: other.type_name(),
+        }),
+    }
+}
+
+fn trace(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("trace", &args, 1)?;
+    interpreter.set_traced(&function_name(&args[0])?, true);
+    Ok(Value::Nil)
+}
+
+fn untrace(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    expect_arity("untrace", &args, 1)?;
+    interpreter.set_traced(&function_name(&args[0])?, false);
+    Ok(Value::Nil)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn test_sub_negates_single_argument() {
+        let mut x = Interpreter::bare();
+        assert_eq!(
+            sub(&mut x, vec![Value::Number(2.0)]),
+            Ok(Value::Number(-2.0))
+        );
+        assert_eq!(
+            sub(&mut x, vec![Value::Number(5.0), Value::Number(2.0)]),
+            Ok(Value::Number(3.0))
+        );
+    }
+
+    #[test]
+    fn test_decimals() {
+        let mut x = Interpreter::bare();
+        let d = |text: &str| Value::Decimal(Decimal::parse(text).unwrap());
+        let cases = [
+            ("(= (+ 0.1m 0.2m) 0.3m)", Value::Bool(true)),
+            ("(* (decimal \"19.99\") 3)", d("59.97")),
+            ("(- 10 0.01m)", d("9.99")),
+            ("(/ 1m 8)", d("0.125")),
+            ("(decimal/round (/ 10m 3) 2)", d("3.33")),
+            ("(< 0.1m 0.2 0.3m)", Value::Bool(true)),
+            ("(= 0.5 0.5m)", Value::Bool(true)),
+            ("(decimal \"1.2.3\")", Value::Nil),
+            ("(number->string -0.05m)", Value::String("-0.05".into())),
+            ("(string->number \"2.50m\")", d("2.5")),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(x.eval_str(source), Ok(expected), "{}", source);
+        }
+        assert!(x.eval_str("(/ 1m 0)").is_err());
+    }
+
+    #[test]
+    fn test_complex() {
+        let mut x = Interpreter::new();
+        let z = |re, im| Value::Complex(Complex::new(re, im));
+        let cases = [
+            ("(+ 3+4i 1-2i)", z(4.0, 2.0)),
+            ("(* 3+4i 2)", z(6.0, 8.0)),
+            ("(- 4i)", z(-0.0, -4.0)),
+            ("(/ 11-2i 1-2i)", z(3.0, 4.0)),
+            ("(* 1i 1i)", z(-1.0, 0.0)),
+            ("(= (complex 3 0) 3)", Value::Bool(true)),
+            ("(real-part 3+4i)", Value::Number(3.0)),
+            ("(imag-part 2.5)", Value::Number(0.0)),
+            ("(magnitude 3+4i)", Value::Number(5.0)),
+            (
+                "(magnitude -1.5m)",
+                Value::Decimal(Decimal::parse("1.5").unwrap()),
+            ),
+            ("(number->string 1.5-2i)", Value::String("1.5-2i".into())),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(x.eval_str(source), Ok(expected), "{}", source);
+        }
+        assert!(x.eval_str("(< 1i 2)").is_err());
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut x = Interpreter::new();
+        let cases = [
+            ("#u8(1 2 255)", Value::Bytes(vec![1, 2, 255])),
+            ("(bytes-ref #u8(7 8) 1)", Value::Number(8.0)),
+            ("(bytes-ref #u8(7 8) 2)", Value::Nil),
+            ("(bytes-slice #u8(1 2 3 4) 1 3)", Value::Bytes(vec![2, 3])),
+            ("(bytes-slice #u8(1 2) 3)", Value::Nil),
+            ("(bytes-append (bytes 1) #u8(2))", Value::Bytes(vec![1, 2])),
+            ("(string->bytes \"é\")", Value::Bytes(vec![0xc3, 0xa9])),
+            ("(string->bytes \"é\" :latin-1)", Value::Bytes(vec![0xe9])),
+            (
+                "(bytes->string #u8(104 105) :ascii)",
+                Value::String("hi".into()),
+            ),
+            ("(bytes->string #u8(255))", Value::Nil),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(x.eval_str(source), Ok(expected), "{}", source);
+        }
+        assert!(x.eval_str("(bytes 256)").is_err());
+        assert!(x.eval_str("#u8(1 300)").is_err());
+
+        let path = std::env::temp_dir().join(format!("jon-read-bytes-{}", std::process::id()));
+        std::fs::write(&path, [0, 159, 146, 150]).unwrap();
+        x.set_global("path", path.to_str().unwrap());
+        assert_eq!(
+            x.eval_str("(with-open (f (open-file path)) (list (read-bytes f 3) (read-bytes f 3) (read-bytes f 3)))"),
+            Ok(Value::List(vec![
+                Value::Bytes(vec![0, 159, 146]),
+                Value::Bytes(vec![150]),
+                Value::Nil
+            ]))
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_cons_car_cdr() {
+        let mut x = Interpreter::bare();
+        let list = cons(&mut x, vec![Value::Number(1.0), Value::List(vec![])]).unwrap();
+        assert_eq!(car(&mut x, vec![list.clone()]), Ok(Value::Number(1.0)));
+        assert_eq!(cdr(&mut x, vec![list]), Ok(Value::List(vec![])));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_print_and_read_line_use_ports() {
+        #[derive(Clone, Default)]
+        struct Shared(Rc<RefCell<Vec<u8>>>);
+
+        impl io::Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let output = Shared::default();
+        let mut x = Interpreter::new();
+        x.set_output(output.clone());
+        x.set_input(io::Cursor::new("Ada\r\n"));
+        assert_eq!(
+            x.eval_str("(print \"name?\") (print \"hi\" (read-line) 1) (read-line)"),
+            Ok(Value::Nil)
+        );
+        assert_eq!(output.0.borrow().as_slice(), b"name?\nhi Ada 1\n");
+    }
+
+    #[test]
+    fn test_conversions() {
+        let mut x = Interpreter::bare();
+        let cases = [
+            ("(number->string 255 :base 16)", Value::String("ff".into())),
+            ("(number->string -5 :base 2)", Value::String("-101".into())),
+            ("(number->string 1.5 :base 2)", Value::Nil),
+            ("(number->string 1.5)", Value::String("1.5".into())),
+            ("(string->number \"-12.5\")", Value::Number(-12.5)),
+            ("(string->number \"ff\" :base 16)", Value::Number(255.0)),
+            ("(string->number \"12abc\")", Value::Nil),
+            ("(string->symbol \"a-b\")", Value::Symbol("a-b".into())),
+            ("(string->symbol \"a b\")", Value::Nil),
+            ("(symbol->string 'ab)", Value::String("ab".into())),
+            (
+                "(list->string (string->list \"añb\"))",
+                Value::String("añb".into()),
+            ),
+            ("(string/ref \"añb\" 1)", Value::String("ñ".into())),
+            ("(string-ref \"añb\" 3)", Value::Nil),
+            ("(string/length \"añb\")", Value::Number(3.0)),
+            ("(string/slice \"añbç\" 1 3)", Value::String("ñb".into())),
+            ("(string/slice \"añb\" 2)", Value::String("b".into())),
+            ("(string/slice \"añb\" 2 4)", Value::Nil),
+            ("(string/reverse \"añb\")", Value::String("bña".into())),
+            ("(string/bytes \"ñ\")", Value::Bytes(vec![0xc3, 0xb1])),
+            ("(char-code \"ñ\")", Value::Number(241.0)),
+            ("(code-char 241)", Value::String("ñ".into())),
+            ("(code-char 55296)", Value::Nil),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(x.eval_str(source), Ok(expected), "{}", source);
+        }
+        assert!(x.eval_str("(number->string 1 :base 1)").is_err());
+    }
+
+    #[test]
+    fn test_alists() {
+        let mut x = Interpreter::bare();
+        x.eval_str("(def pets '((dog 1) (\"cat\" 2) (dog 3)))")
+            .unwrap();
+        assert_eq!(x.eval_str("(assoc 'dog pets)"), x.eval_str("'(dog 1)"));
+        assert_eq!(
+            x.eval_str("(assoc \"cat\" pets)"),
+            x.eval_str("'(\"cat\" 2)")
+        );
+        assert_eq!(x.eval_str("(assq 'cat pets)"), Ok(Value::Nil));
+        assert_eq!(
+            x.eval_str("(alist->map pets)"),
+            x.eval_str("'((\"cat\" 2) (\"dog\" 1))")
+        );
+        assert_eq!(
+            x.eval_str("(map->alist (alist->map pets))"),
+            x.eval_str("'((cat 2) (dog 1))")
+        );
+        assert!(x.eval_str("(assoc 1 '(2))").is_err());
+    }
+
+    #[test]
+    fn test_iteration() {
+        let mut x = Interpreter::new();
+        assert_eq!(x.eval_str("(collect (range 3))"), x.eval_str("'(0 1 2)"));
+        assert_eq!(
+            x.eval_str("(collect (range 10 0 -4))"),
+            x.eval_str("'(10 6 2)")
+        );
+        assert_eq!(
+            x.eval_str("(map string/append \"hé!\")"),
+            x.eval_str("'(\"h\" \"é\" \"!\")")
+        );
+        assert_eq!(
+            x.eval_str("(filter (lambda (b) (> b 1)) #u8(1 2 3))"),
+            x.eval_str("'(2 3)")
+        );
+        assert_eq!(
+            x.eval_str("(length (range 0 20000 0.5))"),
+            Ok(Value::Number(40000.0))
+        );
+        assert_eq!(
+            x.eval_str("(length (range 16777215 16777218))"),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(
+            x.eval_str("(collect (range 0 1 0.1))"),
+            x.eval_str("'(0 0.1 0.2 0.3 0.4 0.5 0.6 0.7 0.8 0.9)")
+        );
+        assert_eq!(x.eval_str("(collect (range 5 0))"), Ok(Value::List(vec![])));
+        assert_eq!(x.eval_str("(next '())"), Ok(Value::Nil));
+        assert_eq!(
+            x.eval_str("(next (second (next (range 1 2))))"),
+            Ok(Value::Nil)
+        );
+        assert!(x.eval_str("(range 0 1 0)").is_err());
+        assert!(x.eval_str("(next 1)").is_err());
+    }
+
+    #[test]
+    fn test_reflection() {
+        let mut x = Interpreter::new();
+        x.eval_str("(def (greet name & more) \"Says hello.\" (list 'hello name))")
+            .unwrap();
+        assert_eq!(
+            x.eval_str("(function-params greet)"),
+            x.eval_str("'(name & more)")
+        );
+        assert_eq!(x.eval_str("(function-params cons)"), x.eval_str("'(x xs)"));
+        assert_eq!(
+            x.eval_str("(source greet)"),
+            x.eval_str("'(def (greet name & more) \"Says hello.\" (list 'hello name))")
+        );
+        assert_eq!(
+            x.eval_str("(source (lambda (x) x))"),
+            x.eval_str("'(lambda (x) x)")
+        );
+        assert_eq!(x.eval_str("(source car)"), Ok(Value::Nil));
+        assert_eq!(
+            x.eval_str("(let ((local 1)) (filter (lambda (name) (= name 'local)) (bindings)))"),
+            x.eval_str("'(local)")
+        );
+        assert_eq!(
+            x.eval_str("(length (filter (lambda (name) (= name 'greet)) (bindings)))"),
+            Ok(Value::Number(1.0))
+        );
+        assert!(x.eval_str("(source 1)").is_err());
+    }
+
+    #[test]
+    fn test_multimethods() {
+        let mut x = Interpreter::new();
+        x.eval_str(
+            "(defmulti area :shape)
+             (defmethod area :square (s) (* (second (assoc :side s)) 2))
+             (defmethod area :square (s) (* (second (assoc :side s)) (second (assoc :side s))))
+             (defmulti size length)
+             (defmethod size 0 (xs) 'empty)
+             (defmethod size :default (xs) 'some)",
+        )
+        .unwrap();
+        assert_eq!(
+            x.eval_str("(area '((:shape :square) (:side 3)))"),
+            Ok(Value::Number(9.0))
+        );
+        assert_eq!(
+            x.eval_str("(list (size '()) (size '(1)))"),
+            x.eval_str("'(empty some)")
+        );
+        assert_eq!(
+            x.eval_str("(area '((:shape :circle)))"),
+            Err(EvalError::Host("area has no method for :circle".into()).into())
+        );
+    }
+
+    #[test]
+    fn test_macroexpand() {
+        let mut x = Interpreter::new();
+        x.eval_str("(defmacro (my-when test & body) (cons 'when (cons test body)))")
+            .unwrap();
+        assert_eq!(
+            x.eval_str("(macroexpand-1 '(my-when c (unless d x)))"),
+            x.eval_str("'(when c (unless d x))")
+        );
+        assert_eq!(
+            x.eval_str("(macroexpand '(my-when c (unless d x)))"),
+            x.eval_str("'(if c (do (unless d x)))")
+        );
+        assert_eq!(x.eval_str("(macroexpand '(car x))"), x.eval_str("'(car x)"));
+
+        let form = x
+            .eval_str("'(let ((when (my-when a b))) (lambda (unless) '(when c)))")
+            .unwrap();
+        assert_eq!(
+            x.macroexpand_all(&form).map(|form| form.to_string()),
+            Ok("(let ((when (if a (do b)))) (lambda (unless) (quote (when c))))".into())
+        );
+    }
+
+    #[test]
+    fn test_protocols() {
+        let mut x = Interpreter::new();
+        x.register_fn("open", |_, _| {
+            Ok(Value::Host(HostValue::new("file", vec![1u8, 2, 3])))
+        });
+        x.register_fn("file-size", |_, args| {
+            let file = args.first().and_then(|arg| match arg {
+                Value::Host(host) => host.downcast_ref::<Vec<u8>>(),
+                _ => None,
+            });
+            file.map(|bytes| Value::Number(bytes.len() as f32))
+                .ok_or_else(|| EvalError::Host("not a file".into()))
+        });
+        x.eval_str(
+            "(defprotocol Collection (count c) (get c key))
+             (defrecord point x y)
+             (extend point Collection
+               (count (p) 2)
+               (get (p key) (second (assoc key p))))
+             (extend file Collection
+               (count (f) (file-size f)))
+             (extend :default Collection
+               (count (xs) (length xs)))",
+        )
+        .unwrap();
+        assert_eq!(
+            x.eval_str("(list (type-of (point 1 2)) (type-of (open)) (type-of 1))"),
+            x.eval_str("'(point file number)")
+        );
+        assert_eq!(
+            x.eval_str(
+                "(list (count (point 1 2)) (get (point 1 2) :y) (count (open)) (count '(1)))"
+            ),
+            x.eval_str("'(2 2 3 1)")
+        );
+        assert_eq!(x.eval_str("Collection"), x.eval_str("'(count get)"));
+        assert_eq!(x.eval_str("(= (open) (open))"), Ok(Value::Bool(false)));
+        assert_eq!(
+            x.eval_str("(open)").map(|file| file.to_string()),
+            Ok("#<host file>".into())
+        );
+    }
+
+    #[test]
+    fn test_with_open() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let mut x = Interpreter::new();
+        let resource = HostValue::closeable("socket", closed.clone(), |closed| {
+            closed.store(true, Ordering::Relaxed);
+            Ok(())
+        });
+        x.set_global("socket", Value::Host(resource));
+        assert!(x
+            .eval_str("(with-open (s socket) (raise 'oops s))")
+            .is_err());
+        assert!(closed.load(Ordering::Relaxed));
+
+        let path = std::env::temp_dir().join(format!("jon-with-open-{}", std::process::id()));
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        x.set_global("path", path.to_str().unwrap());
+        assert_eq!(
+            x.eval_str("(def f (open-file path)) (with-open (g f) (read-line g) (read-line g))"),
+            Ok(Value::String("two".into()))
+        );
+        assert_eq!(
+            x.eval_str("(read-line f)"),
+            Err(EvalError::Io("the file is closed".into()).into())
+        );
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(
+            x.eval_str("(close 1)"),
+            Err(EvalError::TypeMismatch {
+                expected: "host value",
+                found: "number"
+            }
+            .into())
+        );
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let mut x = Interpreter::new();
+        assert_eq!(
+            x.eval_str("(list (+ 16777215 1) (* 4096 4096) (* 0.5 3) (/ 33554432 2))"),
+            x.eval_str("(list 16777216 16777216 1.5 16777216)")
+        );
+        // the reader has no exponents, so 1e7 is written out
+        assert_eq!(
+            x.eval_str("(list (* 2.5 10000000) (/ 50000000 0.5))"),
+            Ok(Value::List(vec![
+                Value::Number(25_000_000.0),
+                Value::Number(100_000_000.0),
+            ]))
+        );
+        for expr in [
+            "(+ 16777216 1)",
+            "(* 4097 4097)",
+            "(- -16777216 1)",
+            "(+ (* 8192 4096) 1)",
+            "(* 100000000 2)",
+            "(/ 67108864 2)",
+        ] {
+            assert!(
+                matches!(
+                    x.eval_str(expr),
+                    Err(crate::Error::Eval(EvalError::Overflow(_)))
+                ),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            x.eval_str("(handler-case (+ 16777216 1) ((overflow-error e) 'caught))"),
+            Ok(Value::Symbol("caught".into()))
+        );
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        let mut x = Interpreter::bare();
+        assert_eq!(
+            add(&mut x, vec![Value::String("a".into())]),
+            Err(EvalError::TypeMismatch {
+                expected: "number",
+                found: "string"
+            })
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Parsed forms kept on disk, keyed by a hash of the source text, so that
+//! loading an unchanged file again skips scanning and parsing.
+//!
+//! Each entry is a file named after the hash, holding the forms with their
+//! spans in a compact binary encoding. An entry that cannot be read or does
+//! not decode is treated as missing and written again; a source that does
+//! not parse is not cached.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::complex::Complex;
+use crate::decimal::Decimal;
+use crate::parser::{Atom, Expr, ParseError, Parser, Span};
+use crate::scanner::Scanner;
+
+/// Starts every entry, so that entries from another encoding are missed.
+const MAGIC: &[u8] = b"jon forms 1\n";
+
+const SYMBOL: u8 = 0;
+const NUMBER: u8 = 1;
+const STRING: u8 = 2;
+const LIST: u8 = 3;
+const DECIMAL: u8 = 4;
+const COMPLEX: u8 = 5;
+const BYTES: u8 = 6;
+
+/// A cache directory of parsed forms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseCache {
+    dir: PathBuf,
+}
+
+impl ParseCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The user's cache directory: `$XDG_CACHE_HOME/jon`, by default
+    /// `~/.cache/jon`.
+    pub fn default_dir() -> Option<PathBuf> {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .map(|cache| cache.join("jon"))
+    }
+
+    /// The forms of `source` with their spans, as [`Parser::parse_forms`]
+    /// gives them, read from the cache when it has them. Failing to write
+    /// the cache is not an error.
+    pub fn parse(&self, source: &str) -> Result<Vec<(Span, Expr)>, ParseError> {
+        let path = self.dir.join(format!("{:016x}.forms", hash(source)));
+        if let Some(forms) = fs::read(&path)
+            .ok()
+            .and_then(|bytes| decode(&bytes, source))
+        {
+            return Ok(forms);
+        }
+        let forms = Parser::new(&mut Scanner::new(source)).parse_forms()?;
+        // written aside and renamed, so that a reader never sees half an entry
+        let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(&partial, encode(&forms, source)))
+            .and_then(|()| fs::rename(&partial, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        Ok(forms)
+    }
+}
+
+/// The 64-bit FNV-1a hash of `source`, which is stable across builds.
+pub fn hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// An entry for `forms`, which also records the length of `source` to
+/// catch most hash collisions.
+fn encode(forms: &[(Span, Expr)], source: &str) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    push_len(&mut bytes, source.len());
+    push_len(&mut bytes, forms.len());
+    for (span, expr) in forms {
+        push_len(&mut bytes, span.start);
+        push_len(&mut bytes, span.end);
+        push_expr(&mut bytes, expr);
+    }
+    bytes
+}
+
+pub(crate) fn push_len(bytes: &mut Vec<u8>, len: usize) {
+    bytes.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+pub(crate) fn push_str(bytes: &mut Vec<u8>, s: &str) {
+    push_len(bytes, s.len());
+    bytes.extend_from_slice(s.as_bytes());
+}
+
+fn push_expr(bytes: &mut Vec<u8>, expr: &Expr) {
+    match expr {
+        Expr::Atom(Atom::Symbol(s)) => {
+            bytes.push(SYMBOL);
+            push_str(bytes, s);
+        }
+        Expr::Atom(Atom::Number(n)) => {
+            bytes.push(NUMBER);
+            bytes.extend_from_slice(&n.to_le_bytes());
+        }
+        Expr::Atom(Atom::Decimal(d)) => {
+            bytes.push(DECIMAL);
+            push_str(bytes, &d.to_string());
+        }
+        Expr::Atom(Atom::Complex(c)) => {
+            bytes.push(COMPLEX);
+            bytes.extend_from_slice(&c.re.to_le_bytes());
+            bytes.extend_from_slice(&c.im.to_le_bytes());
+        }
+        Expr::Atom(Atom::String(s)) => {
+            bytes.push(STRING);
+            push_str(bytes, s);
+        }
+        Expr::Atom(Atom::Bytes(data)) => {
+            bytes.push(BYTES);
+            push_len(bytes, data.len());
+            bytes.extend_from_slice(data);
+        }
+        Expr::List(list) => {
+            bytes.push(LIST);
+            push_len(bytes, list.len());
+            for item in list {
+                push_expr(bytes, item);
+            }
+        }
+    }
+}
+
+/// The forms in an entry made by [`encode`] for `source`, or `None` if the
+/// entry is for another source or is damaged.
+fn decode(bytes: &[u8], source: &str) -> Option<Vec<(Span, Expr)>> {
+    let mut reader = Reader {
+        bytes: bytes.strip_prefix(MAGIC)?,
+    };
+    if reader.len()? != source.len() {
+        return None;
+    }
+    let count = reader.len()?;
+    let mut forms = Vec::new();
+    for _ in 0..count {
+        let span = reader.len()?..reader.len()?;
+        forms.push((span, reader.expr()?));
+    }
+    reader.bytes.is_empty().then_some(forms)
+}
+
+/// Reads what [`push_len`] and [`push_str`] wrote, giving `None` for
+/// anything cut short or malformed.
+pub(crate) struct Reader<'a> {
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.bytes.len() {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    pub fn len(&mut self) -> Option<usize> {
+        let bytes = self.take(8)?.try_into().ok()?;
+        usize::try_from(u64::from_le_bytes(bytes)).ok()
+    }
+
+    pub fn string(&mut self) -> Option<String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn expr(&mut self) -> Option<Expr> {
+        let tag = self.take(1)?[0];
+        Some(match tag {
+            SYMBOL => Expr::Atom(Atom::Symbol(self.string()?)),
+            NUMBER => {
+                let bytes = self.take(4)?.try_into().ok()?;
+                Expr::Atom(Atom::Number(f32::from_le_bytes(bytes)))
+            }
+            DECIMAL => Expr::Atom(Atom::Decimal(Decimal::parse(&self.string()?)?)),
+            COMPLEX => {
+                let re = f32::from_le_bytes(self.take(4)?.try_into().ok()?);
+                let im = f32::from_le_bytes(self.take(4)?.try_into().ok()?);
+                Expr::Atom(Atom::Complex(Complex::new(re, im)))
+            }
+            STRING => Expr::Atom(Atom::String(self.string()?)),
+            BYTES => {
+                let len = self.len()?;
+                Expr::Atom(Atom::Bytes(self.take(len)?.to_vec()))
+            }
+            LIST => {
+                let len = self.len()?;
+                // a damaged length must not reserve memory up front
+                let mut list = Vec::new();
+                for _ in 0..len {
+                    list.push(self.expr()?);
+                }
+                Expr::List(list)
+            }
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cache() {
+        let dir = std::env::temp_dir().join(format!("jon-cache-{}", std::process::id()));
+        let cache = ParseCache::new(&dir);
+        let source = "(def x '(1 \"two\" three)) ; done\n(+ x 2.5)";
+        let parsed = Parser::new(&mut Scanner::new(source)).parse_forms();
+        assert_eq!(cache.parse(source), parsed);
+        // the second time comes from the entry written the first
+        let entry = dir.join(format!("{:016x}.forms", hash(source)));
+        assert!(entry.is_file());
+        assert_eq!(cache.parse(source), parsed);
+
+        fs::write(&entry, &MAGIC[..4]).unwrap();
+        assert_eq!(cache.parse(source), parsed);
+        assert_eq!(
+            decode(&fs::read(&entry).unwrap(), source).map(|forms| forms.len()),
+            Some(2)
+        );
+        assert!(cache.parse("(").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,55 @@
+//! What a script may reach outside the interpreter. A host evaluating
+//! untrusted code, such as config snippets, denies capabilities with
+//! [`Interpreter::deny`](crate::Interpreter::deny), and the builtins
+//! needing them fail with a `permission-error` instead, leaving pure
+//! computation.
+
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Capability {
+    /// Files: `load`, `open-file`, and `read-bytes`.
+    Fs,
+    /// The network, which no builtin reaches yet.
+    Net,
+    /// Environment variables, which no builtin reads yet.
+    Env,
+    /// Other processes, which no builtin starts yet.
+    Proc,
+}
+
+impl Capability {
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Fs => "fs",
+            Capability::Net => "net",
+            Capability::Env => "env",
+            Capability::Proc => "proc",
+        }
+    }
+
+    /// The capability called `name`, which may be a keyword such as `:fs`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.strip_prefix(':').unwrap_or(name) {
+            "fs" => Some(Capability::Fs),
+            "net" => Some(Capability::Net),
+            "env" => Some(Capability::Env),
+            "proc" => Some(Capability::Proc),
+            _ => None,
+        }
+    }
+
+    /// The capability the builtin `name` needs, if any.
+    pub fn of_builtin(name: &str) -> Option<Self> {
+        match name {
+            "load" | "open-file" | "read-bytes" => Some(Capability::Fs),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -0,0 +1,241 @@
+//! A C API for embedding jon, declared in `include/jon.h`. The shared
+//! library is built with:
+//!
+//! ```text
+//! cargo rustc --lib --release --features capi --crate-type cdylib
+//! ```
+//!
+//! Values cross the boundary as jon source text: a result is the printed
+//! value, a registered function receives its arguments as a printed list
+//! and returns the text of its result.
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr;
+
+use crate::eval::{EvalError, Interpreter};
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::value::Value;
+
+/// An interpreter with the prelude loaded.
+pub struct JonInterpreter(Interpreter);
+
+/// The outcome of `jon_eval`: the printed value, or a rendered diagnostic.
+pub struct JonResult {
+    error: bool,
+    text: CString,
+}
+
+/// A host function. `args` is the printed list of arguments, such as
+/// `(1 "a")`. It returns the jon text of its result, which must stay valid
+/// until the next call, or null to fail the call.
+pub type JonCallback =
+    unsafe extern "C" fn(user_data: *mut c_void, args: *const c_char) -> *const c_char;
+
+struct Callback {
+    func: JonCallback,
+    user_data: *mut c_void,
+}
+
+// SAFETY: `jon_register_fn` requires the callback and its user data to be
+// usable from whichever threads the host runs interpreters on.
+unsafe impl Send for Callback {}
+unsafe impl Sync for Callback {}
+
+impl Callback {
+    fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, EvalError> {
+        let args = CString::new(Value::List(args).to_string())
+            .map_err(|_| EvalError::Host(format!("{}: arguments contain a NUL byte", name)))?;
+        // SAFETY: the host promised a valid callback when registering it
+        let text = unsafe { (self.func)(self.user_data, args.as_ptr()) };
+        if text.is_null() {
+            return Err(EvalError::Host(format!("{} failed", name)));
+        }
+        // SAFETY: a non-null result is a NUL-terminated string
+        let text = unsafe { CStr::from_ptr(text) }.to_string_lossy();
+        let mut scanner = Scanner::new(&text);
+        let mut parser = Parser::new(&mut scanner);
+        match parser.parse_program().as_deref() {
+            Ok([expr]) => Ok(Value::from(expr)),
+            _ => Err(EvalError::Host(format!(
+                "{} returned {:?}, which is not a single jon value",
+                name, text
+            ))),
+        }
+    }
+}
+
+/// Creates an interpreter, to be freed with `jon_free`.
+#[no_mangle]
+pub extern "C" fn jon_new() -> *mut JonInterpreter {
+    Box::into_raw(Box::new(JonInterpreter(Interpreter::new())))
+}
+
+/// # Safety
+///
+/// `jon` must come from `jon_new` and not be used afterwards; null is
+/// ignored.
+#[no_mangle]
+pub unsafe extern "C" fn jon_free(jon: *mut JonInterpreter) {
+    if !jon.is_null() {
+        drop(Box::from_raw(jon));
+    }
+}
+
+/// Evaluates the UTF-8 `source`, returning a result to be freed with
+/// `jon_result_free`, or null if an argument is null.
+///
+/// # Safety
+///
+/// `jon` must be a live interpreter and `source` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn jon_eval(
+    jon: *mut JonInterpreter,
+    source: *const c_char,
+) -> *mut JonResult {
+    if jon.is_null() || source.is_null() {
+        return ptr::null_mut();
+    }
+    let interpreter = &mut (*jon).0;
+    let (error, text) = match CStr::from_ptr(source).to_str() {
+        Ok(source) => match interpreter.eval_source(source) {
+            Ok(value) => (false, value.to_string()),
+            Err(diagnostic) => (true, diagnostic.render("<input>", source, false)),
+        },
+        Err(_) => (true, "error: source is not valid UTF-8\n".into()),
+    };
+    // printed values and source text cannot contain NUL
+    let text = CString::new(text).unwrap_or_default();
+    Box::into_raw(Box::new(JonResult { error, text }))
+}
+
+/// Whether the evaluation failed.
+///
+/// # Safety
+///
+/// `result` must be a live result from `jon_eval`.
+#[no_mangle]
+pub unsafe extern "C" fn jon_result_is_error(result: *const JonResult) -> bool {
+    !result.is_null() && (*result).error
+}
+
+/// A copy of the result's text, to be freed with `jon_string_free`.
+///
+/// # Safety
+///
+/// `result` must be a live result from `jon_eval`.
+#[no_mangle]
+pub unsafe extern "C" fn jon_result_to_string(result: *const JonResult) -> *mut c_char {
+    if result.is_null() {
+        return ptr::null_mut();
+    }
+    (*result).text.clone().into_raw()
+}
+
+/// # Safety
+///
+/// `result` must come from `jon_eval` and not be used afterwards; null is
+/// ignored.
+#[no_mangle]
+pub unsafe extern "C" fn jon_result_free(result: *mut JonResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+/// # Safety
+///
+/// `text` must come from `jon_result_to_string` and not be used
+/// afterwards; null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn jon_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+/// Binds `name` to `callback`, called with `user_data`. Returns false if
+/// `name` is null or not UTF-8. The name is kept for the life of the
+/// process.
+///
+/// # Safety
+///
+/// `jon` must be a live interpreter and `name` a NUL-terminated string.
+/// `callback` is called with `user_data` whenever a script calls the
+/// function, possibly from another thread if the host moves interpreters
+/// between threads, until the interpreter is freed.
+#[no_mangle]
+pub unsafe extern "C" fn jon_register_fn(
+    jon: *mut JonInterpreter,
+    name: *const c_char,
+    callback: JonCallback,
+    user_data: *mut c_void,
+) -> bool {
+    if jon.is_null() || name.is_null() {
+        return false;
+    }
+    let Ok(name) = CStr::from_ptr(name).to_str() else {
+        return false;
+    };
+    let name: &'static str = Box::leak(name.into());
+    let callback = Callback {
+        func: callback,
+        user_data,
+    };
+    (*jon)
+        .0
+        .register_fn(name, move |_, args| callback.call(name, args));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sums its numeric arguments into the `String` behind `user_data`.
+    unsafe extern "C" fn sum(user_data: *mut c_void, args: *const c_char) -> *const c_char {
+        let out = &mut *(user_data as *mut CString);
+        let args = CStr::from_ptr(args).to_str().unwrap();
+        let numbers: Result<Vec<f32>, _> = args
+            .trim_matches(['(', ')'])
+            .split_whitespace()
+            .map(str::parse)
+            .collect();
+        let Ok(numbers) = numbers else {
+            return ptr::null();
+        };
+        *out = CString::new(numbers.iter().sum::<f32>().to_string()).unwrap();
+        out.as_ptr()
+    }
+
+    unsafe fn eval(jon: *mut JonInterpreter, source: &str) -> (bool, String) {
+        let source = CString::new(source).unwrap();
+        let result = jon_eval(jon, source.as_ptr());
+        let text = jon_result_to_string(result);
+        let outcome = (
+            jon_result_is_error(result),
+            CStr::from_ptr(text).to_str().unwrap().to_string(),
+        );
+        jon_string_free(text);
+        jon_result_free(result);
+        outcome
+    }
+
+    #[test]
+    fn test_eval_and_callbacks() {
+        unsafe {
+            let jon = jon_new();
+            let mut buffer = CString::default();
+            let name = CString::new("host-sum").unwrap();
+            let user_data = &mut buffer as *mut CString as *mut c_void;
+            assert!(jon_register_fn(jon, name.as_ptr(), sum, user_data));
+
+            assert_eq!(eval(jon, "(inc (host-sum 1 2 3))"), (false, "7".into()));
+            let (error, text) = eval(jon, "(host-sum \"a\")");
+            assert!(error);
+            assert!(text.starts_with("error[E0107]: host-sum failed\n"));
+            assert!(eval(jon, "(car").0);
+            jon_free(jon);
+        }
+    }
+}
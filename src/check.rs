@@ -0,0 +1,314 @@
+use std::collections::HashSet;
+
+use crate::builtins;
+use crate::diagnostic::Diagnostic;
+use crate::eval::{is_keyword, EvalError, Interpreter};
+use crate::parser::{ParseError, Parser, Span};
+use crate::scanner::{Scanner, Token};
+use crate::value::Value;
+
+/// Checks `source` without running it: macros are expanded, then every
+/// symbol must be bound and calls to known functions must pass a fitting
+/// number of arguments. Macro and function definitions are loaded into
+/// `interpreter`, so that later macros can use them; nothing else is
+/// evaluated.
+pub fn check_source(
+    interpreter: &mut Interpreter,
+    source: &str,
+) -> Result<Vec<Diagnostic>, ParseError> {
+    let mut scanner = Scanner::new(source);
+    let mut parser = Parser::new(&mut scanner);
+    let forms: Vec<_> = parser
+        .parse_borrowed_forms()?
+        .into_iter()
+        .map(|(span, expr)| (span, Value::from(expr)))
+        .collect();
+
+    let mut checker = Checker {
+        interpreter,
+        defined: HashSet::new(),
+        values: HashSet::new(),
+        problems: Vec::new(),
+    };
+    let mut diagnostics = Vec::new();
+    for (span, form) in &forms {
+        if let Err(err) = checker.declare(form) {
+            diagnostics.push(Diagnostic::eval(&err, span.clone()));
+        }
+    }
+    for (span, form) in &forms {
+        checker.form(form, &mut Vec::new());
+        for err in checker.problems.drain(..) {
+            let diagnostic = Diagnostic::eval(&err, locate(source, span, &err));
+            if !diagnostics.contains(&diagnostic) {
+                diagnostics.push(diagnostic);
+            }
+        }
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    Ok(diagnostics)
+}
+
+struct Checker<'i> {
+    interpreter: &'i mut Interpreter,
+    /// Every name defined at the top level of the file, so that forms may
+    /// refer to definitions further down.
+    defined: HashSet<String>,
+    /// Top-level names bound with `(def name expr)`, whose values are
+    /// unknown until the program runs.
+    values: HashSet<String>,
+    problems: Vec<EvalError>,
+}
+
+impl Checker<'_> {
+    /// Records a top-level definition, including those in a `do` or made
+    /// by a macro. Function and macro definitions are evaluated, which only
+    /// builds them.
+    fn declare(&mut self, form: &Value) -> Result<(), EvalError> {
+        let Value::List(list) = form else {
+            return Ok(());
+        };
+        match list.as_slice() {
+            [Value::Symbol(head), Value::List(signature), ..]
+                if head == "def" || head == "defmacro" =>
+            {
+                if let Some(Value::Symbol(name)) = signature.first() {
+                    self.defined.insert(name.clone());
+                }
+                self.interpreter.eval_form(form).map(|_| ())
+            }
+            [Value::Symbol(head), Value::Symbol(name), _] if head == "def" => {
+                self.defined.insert(name.clone());
+                self.values.insert(name.clone());
+                Ok(())
+            }
+            [Value::Symbol(head), forms @ ..] if head == "do" => {
+                forms.iter().try_for_each(|form| self.declare(form))
+            }
+            [Value::Symbol(head), args @ ..] => match self.interpreter.get_global(head) {
+                Some(Value::Macro(lambda)) if !self.values.contains(head) => {
+                    let expansion = self.interpreter.expand_macro(&lambda, args.to_vec())?;
+                    self.declare(&expansion)
+                }
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn is_bound(&self, name: &str, locals: &[String]) -> bool {
+        is_keyword(name)
+            || locals.iter().any(|local| local == name)
+            || self.defined.contains(name)
+            || self.interpreter.get_global(name).is_some()
+    }
+
+    fn form(&mut self, form: &Value, locals: &mut Vec<String>) {
+        match form {
+            Value::Symbol(name) if !self.is_bound(name, locals) => {
+                self.problems.push(EvalError::UnboundSymbol(name.clone()))
+            }
+            Value::List(list) => self.list(list, locals),
+            _ => {}
+        }
+    }
+
+    fn forms(&mut self, forms: &[Value], locals: &mut Vec<String>) {
+        for form in forms {
+            self.form(form, locals);
+        }
+    }
+
+    fn list(&mut self, list: &[Value], locals: &mut Vec<String>) {
+        let Some(Value::Symbol(head)) = list.first() else {
+            return self.forms(list, locals);
+        };
+        let args = &list[1..];
+        if locals.contains(head) {
+            return self.forms(list, locals);
+        }
+        match (head.as_str(), args) {
+            ("quote" | "bindings", _) => {}
+            ("def" | "defmacro", [Value::List(signature), body @ ..]) => {
+                if let Some(Value::Symbol(name)) = signature.first() {
+                    locals.push(name.clone());
+                }
+                self.lambda(signature.get(1..).unwrap_or_default(), body, locals);
+            }
+            ("def", [Value::Symbol(name), expr]) => {
+                self.form(expr, locals);
+                locals.push(name.clone());
+            }
+            ("lambda", [Value::List(params), body @ ..]) => self.lambda(params, body, locals),
+            ("let" | "loop", [Value::List(bindings), body @ ..]) => {
+                if head == "loop" {
+                    if let Err(err) = self.interpreter.check_loop_body(body) {
+                        self.problems.push(err);
+                    }
+                }
+                let scope = locals.len();
+                for binding in bindings {
+                    if let Value::List(pair) = binding {
+                        if let [Value::Symbol(name), expr] = pair.as_slice() {
+                            self.form(expr, locals);
+                            locals.push(name.clone());
+                        }
+                    }
+                }
+                self.forms(body, locals);
+                locals.truncate(scope);
+            }
+            ("handler-case", [expr, clauses @ ..]) => {
+                self.form(expr, locals);
+                for clause in clauses {
+                    if let Value::List(clause) = clause {
+                        if let [Value::List(head), body @ ..] = clause.as_slice() {
+                            self.lambda(head.get(1..).unwrap_or_default(), body, locals);
+                        }
+                    }
+                }
+            }
+            ("if" | "do" | "and" | "or" | "recur" | "unwind-protect", _) => {
+                self.forms(args, locals)
+            }
+            _ => match self.interpreter.get_global(head) {
+                Some(Value::Macro(lambda)) if !self.values.contains(head) => {
+                    match self.interpreter.expand_macro(&lambda, args.to_vec()) {
+                        Ok(expansion) => self.form(&expansion, locals),
+                        Err(err) => self.problems.push(err),
+                    }
+                }
+                _ => {
+                    self.arity(head, args.len());
+                    self.forms(list, locals);
+                }
+            },
+        }
+    }
+
+    fn lambda(&mut self, params: &[Value], body: &[Value], locals: &mut Vec<String>) {
+        let scope = locals.len();
+        for param in params {
+            if let Value::Symbol(name) = param {
+                locals.push(name.clone());
+            }
+        }
+        self.forms(body, locals);
+        locals.truncate(scope);
+    }
+
+    /// Reports a call to a known function with the wrong number of
+    /// arguments.
+    fn arity(&mut self, name: &str, found: usize) {
+        if self.values.contains(name) {
+            return;
+        }
+        let expected = match self.interpreter.get_global(name) {
+            Some(Value::Lambda(lambda)) => match lambda.rest {
+                Some(_) if found < lambda.params.len() => {
+                    format!("at least {}", lambda.params.len())
+                }
+                None if found != lambda.params.len() => lambda.params.len().to_string(),
+                _ => return,
+            },
+            Some(Value::Builtin(builtin)) => match builtins::fixed_arity(builtin.name) {
+                Some(expected) if expected != found => expected.to_string(),
+                _ => return,
+            },
+            _ => return,
+        };
+        self.problems.push(EvalError::ArityMismatch {
+            name: name.into(),
+            expected,
+            found,
+        });
+    }
+}
+
+/// The span of the symbol `err` is about within the top-level form at
+/// `span`: the called name for an arity mismatch, the symbol itself when it
+/// is unbound. Falls back to the whole form.
+fn locate(source: &str, span: &Span, err: &EvalError) -> Span {
+    let found = match err {
+        EvalError::UnboundSymbol(name) => find_symbol(source, span, name, false),
+        EvalError::ArityMismatch { name, .. } => find_symbol(source, span, name, true),
+        _ => None,
+    };
+    found.unwrap_or_else(|| span.clone())
+}
+
+/// The span of the first occurrence of the symbol `name` within `span` of
+/// `source`, only in the head of a list when `called`.
+pub fn find_symbol(source: &str, span: &Span, name: &str, called: bool) -> Option<Span> {
+    let mut scanner = Scanner::new(&source[span.clone()]);
+    let mut after_paren = false;
+    while let Ok(tok) = scanner.next_token() {
+        match tok.token {
+            Token::Symbol(symbol) if symbol == name && (after_paren || !called) => {
+                let start = span.start + tok.position;
+                return Some(start..start + symbol.len());
+            }
+            Token::WhiteSpace(_) | Token::Comment(_) => continue,
+            _ => {}
+        }
+        after_paren = tok.token == Token::LParen;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(source: &str) -> Vec<(String, Span)> {
+        check_source(&mut Interpreter::new(), source)
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, diagnostic.span))
+            .collect()
+    }
+
+    #[test]
+    fn test_check_reports_unbound_symbols() {
+        assert_eq!(
+            check("(def (f x) (+ x y))\n(print (g 1))"),
+            vec![
+                ("Unbound symbol y".to_string(), 16..17),
+                ("Unbound symbol g".to_string(), 28..29),
+            ]
+        );
+        assert_eq!(
+            check("(def (f x) (let ((y x)) (when y (g y))))\n(def (g x) x)\n(map f '(a b))"),
+            vec![]
+        );
+        assert_eq!(check("(h 1)\n(defmulti h :k)"), vec![]);
+    }
+
+    #[test]
+    fn test_check_reports_arity_mismatches() {
+        assert_eq!(
+            check("(def (f x) (car x x))\n(f)\n(map inc)"),
+            vec![
+                ("car expects 1 arguments, got 2".to_string(), 12..15),
+                ("f expects 1 arguments, got 0".to_string(), 23..24),
+                ("map expects 2 arguments, got 1".to_string(), 27..30),
+            ]
+        );
+        assert_eq!(check("(def car 1) (list car)"), vec![]);
+    }
+
+    #[test]
+    fn test_check_reports_misplaced_recur() {
+        assert_eq!(
+            check("(loop ((i 0)) (when (< i 3) (recur (inc i))))"),
+            vec![]
+        );
+        assert_eq!(
+            check("(loop ((i 0)) (print (recur i)))"),
+            vec![(
+                "recur outside the tail position of a loop".to_string(),
+                0..32
+            )]
+        );
+    }
+}
@@ -0,0 +1,718 @@
+//! An experimental compiler from jon to Rust source, for `jon compile`.
+//!
+//! It takes the subset of jon whose meaning is fixed before running:
+//! top-level `def`s of functions and values, macros (expanded while
+//! compiling), and the special forms other than `handler-case` and
+//! `unwind-protect`. Functions defined at top level become Rust functions
+//! called directly, with their arities checked while compiling; loops
+//! become Rust loops. Everything else, such as builtins and the prelude,
+//! is called through the jon runtime, which the generated program uses as
+//! a library.
+//!
+//! A `lambda` is compiled only if it refers to no local variable, as it is
+//! then made by the runtime in the global environment.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use thiserror::Error;
+
+use crate::builtins;
+use crate::eval::{is_keyword, EvalError, Interpreter};
+use crate::parser::{ParseError, Parser};
+use crate::scanner::{line_column, Scanner};
+use crate::value::Value;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CompileError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("line {line}: {form} is not supported by the compiler")]
+    Unsupported { line: usize, form: String },
+    #[error("line {line}: {name} takes {expected} arguments, not {found}")]
+    Arity {
+        line: usize,
+        name: String,
+        expected: String,
+        found: usize,
+    },
+    #[error("line {line}: {name} is not defined")]
+    Unbound { line: usize, name: String },
+    #[error("line {line}: {source}")]
+    Expand { line: usize, source: EvalError },
+}
+
+/// The parameters of a function defined at top level.
+#[derive(Clone)]
+struct Signature {
+    params: Vec<String>,
+    rest: Option<String>,
+}
+
+impl Signature {
+    fn parse(params: &[Value]) -> Option<Self> {
+        let mut signature = Signature {
+            params: Vec::new(),
+            rest: None,
+        };
+        let mut symbols = params.iter();
+        while let Some(param) = symbols.next() {
+            match param {
+                Value::Symbol(s) if s == "&" => match (symbols.next(), symbols.next()) {
+                    (Some(Value::Symbol(rest)), None) => signature.rest = Some(rest.clone()),
+                    _ => return None,
+                },
+                Value::Symbol(s) => signature.params.push(s.clone()),
+                _ => return None,
+            }
+        }
+        Some(signature)
+    }
+
+    fn accepts(&self, count: usize) -> bool {
+        match self.rest {
+            Some(_) => count >= self.params.len(),
+            None => count == self.params.len(),
+        }
+    }
+
+    fn expected(&self) -> String {
+        match self.rest {
+            Some(_) => format!("at least {}", self.params.len()),
+            None => self.params.len().to_string(),
+        }
+    }
+}
+
+/// A top-level form once macros are expanded.
+enum Item {
+    Function {
+        name: String,
+        signature: Signature,
+        body: Vec<Value>,
+    },
+    Global {
+        name: String,
+        expr: Value,
+    },
+    Expr(Value),
+}
+
+/// The loop a `recur` in tail position continues.
+struct Loop {
+    label: String,
+    vars: Vec<String>,
+}
+
+struct Compiler {
+    /// Expands macros, and knows the globals of the runtime.
+    interpreter: Interpreter,
+    functions: HashMap<String, Signature>,
+    globals: HashSet<String>,
+    /// The line of the top-level form being compiled.
+    line: usize,
+    /// Numbers the Rust variables and loop labels.
+    next_id: usize,
+}
+
+/// A Rust program that runs `source` with the jon runtime.
+pub fn to_rust(source: &str) -> Result<String, CompileError> {
+    let forms = Parser::new(&mut Scanner::new(source)).parse_forms()?;
+    let mut compiler = Compiler {
+        interpreter: Interpreter::new(),
+        functions: HashMap::new(),
+        globals: HashSet::new(),
+        line: 0,
+        next_id: 0,
+    };
+    // bound by the generated main
+    compiler
+        .interpreter
+        .set_global("*args*", Value::List(Vec::new()));
+    let mut items = Vec::new();
+    for (span, expr) in &forms {
+        compiler.line = line_column(source, span.start).0;
+        compiler.items(Value::from(expr), &mut items)?;
+    }
+    for item in &items {
+        match item {
+            Item::Function {
+                name, signature, ..
+            } => {
+                if compiler
+                    .functions
+                    .insert(name.clone(), signature.clone())
+                    .is_some()
+                {
+                    return Err(compiler.unsupported(format!("defining {} again", name)));
+                }
+            }
+            Item::Global { name, .. } => {
+                compiler.globals.insert(name.clone());
+            }
+            Item::Expr(_) => {}
+        }
+    }
+
+    let mut out = String::from(HEADER);
+    let mut run = String::new();
+    for (name, signature) in sorted(&compiler.functions) {
+        let _ = writeln!(
+            run,
+            "    interpreter.register_fn({:?}, |interpreter, args| {{\n        \
+             let args = arguments({:?}, args, {}, {})?;\n        \
+             let mut args = args.into_iter();\n        \
+             {}(interpreter{})\n    }});",
+            name,
+            name,
+            signature.params.len(),
+            signature.rest.is_some(),
+            function_name(name),
+            ", args.next().unwrap()"
+                .repeat(signature.params.len() + signature.rest.is_some() as usize)
+        );
+    }
+    for item in &items {
+        match item {
+            Item::Function {
+                name,
+                signature,
+                body,
+            } => out.push_str(&compiler.function(name, signature, body)?),
+            Item::Global { name, expr } => {
+                let value = compiler.expr(expr, &[], None)?;
+                let _ = writeln!(run, "    let value = {};", value);
+                let _ = writeln!(run, "    interpreter.set_global({:?}, value);", name);
+            }
+            Item::Expr(expr) => {
+                let _ = writeln!(run, "    let _ = {};", compiler.expr(expr, &[], None)?);
+            }
+        }
+    }
+    let _ = write!(
+        out,
+        "\nfn run(interpreter: &mut Interpreter) -> Result<(), EvalError> {{\n{}    Ok(())\n}}\n",
+        run
+    );
+    Ok(out)
+}
+
+/// The start of every program: its imports, `main`, and the helpers the
+/// compiled code calls.
+const HEADER: &str = "\
+// Generated by jon compile.
+#![allow(unused_braces, unused_mut, unused_variables)]
+
+use std::process::ExitCode;
+
+use jon::eval::{EvalError, Interpreter};
+use jon::value::Value;
+
+fn main() -> ExitCode {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global(\"*args*\", std::env::args().skip(1).collect::<Vec<String>>());
+    match run(&mut interpreter) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!(\"error: {}\", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn global(interpreter: &Interpreter, name: &str) -> Result<Value, EvalError> {
+    interpreter
+        .get_global(name)
+        .ok_or_else(|| EvalError::UnboundSymbol(name.into()))
+}
+
+/// `args` for a function of `count` parameters, with the rest gathered
+/// into a list if it has a rest parameter.
+fn arguments(name: &str, mut args: Vec<Value>, count: usize, rest: bool) -> Result<Vec<Value>, EvalError> {
+    if args.len() < count || (!rest && args.len() > count) {
+        return Err(EvalError::ArityMismatch {
+            name: name.into(),
+            expected: if rest { format!(\"at least {}\", count) } else { count.to_string() },
+            found: args.len(),
+        });
+    }
+    if rest {
+        let rest = args.split_off(count);
+        args.push(Value::List(rest));
+    }
+    Ok(args)
+}
+";
+
+impl Compiler {
+    /// Adds the items `form` amounts to, expanding its macro call and
+    /// taking apart `do`.
+    fn items(&mut self, form: Value, items: &mut Vec<Item>) -> Result<(), CompileError> {
+        let line = self.line;
+        let form = self
+            .interpreter
+            .macroexpand(&form)
+            .map_err(|source| CompileError::Expand { line, source })?;
+        let Value::List(list) = &form else {
+            items.push(Item::Expr(form));
+            return Ok(());
+        };
+        match list.as_slice() {
+            [Value::Symbol(head), forms @ ..] if head == "do" => {
+                for form in forms {
+                    self.items(form.clone(), items)?;
+                }
+            }
+            [Value::Symbol(head), Value::List(signature), body @ ..] if head == "def" => {
+                let (Some(Value::Symbol(name)), Some(parsed)) =
+                    (signature.first(), Signature::parse(&signature[1..]))
+                else {
+                    return Err(self.unsupported(form.preview(40)));
+                };
+                // macros defined later may call it while expanding
+                self.interpreter
+                    .eval_form(&form)
+                    .map_err(|source| CompileError::Expand { line, source })?;
+                let body = body
+                    .iter()
+                    .map(|form| self.interpreter.macroexpand_all(form))
+                    .collect::<Result<_, _>>()
+                    .map_err(|source| CompileError::Expand { line, source })?;
+                items.push(Item::Function {
+                    name: name.clone(),
+                    signature: parsed,
+                    body,
+                });
+            }
+            [Value::Symbol(head), Value::Symbol(name), expr] if head == "def" => {
+                let expr = self
+                    .interpreter
+                    .macroexpand_all(expr)
+                    .map_err(|source| CompileError::Expand { line, source })?;
+                items.push(Item::Global {
+                    name: name.clone(),
+                    expr,
+                });
+            }
+            [Value::Symbol(head), ..] if head == "defmacro" => {
+                self.interpreter
+                    .eval_form(&form)
+                    .map_err(|source| CompileError::Expand { line, source })?;
+            }
+            _ => {
+                let expr = self
+                    .interpreter
+                    .macroexpand_all(&form)
+                    .map_err(|source| CompileError::Expand { line, source })?;
+                items.push(Item::Expr(expr));
+            }
+        }
+        Ok(())
+    }
+
+    fn function(
+        &mut self,
+        name: &str,
+        signature: &Signature,
+        body: &[Value],
+    ) -> Result<String, CompileError> {
+        let mut scope = Vec::new();
+        let mut params = String::new();
+        for param in signature.params.iter().chain(&signature.rest) {
+            let var = self.var(param);
+            let _ = write!(params, ", {}: Value", var);
+            scope.push((param.clone(), var));
+        }
+        let body = self.body(body, &scope, None)?;
+        Ok(format!(
+            "\nfn {}(interpreter: &mut Interpreter{}) -> Result<Value, EvalError> {{\n    Ok({})\n}}\n",
+            function_name(name),
+            params,
+            body
+        ))
+    }
+
+    /// A fresh Rust variable for the jon variable `name`.
+    fn var(&mut self, name: &str) -> String {
+        self.next_id += 1;
+        format!("v{}_{}", self.next_id, mangle(name))
+    }
+
+    /// Forms evaluated in order for the value of the last, or nil.
+    fn body(
+        &mut self,
+        forms: &[Value],
+        scope: &[(String, String)],
+        tail: Option<&Loop>,
+    ) -> Result<String, CompileError> {
+        let Some((last, init)) = forms.split_last() else {
+            return Ok("Value::Nil".into());
+        };
+        let mut out = String::from("{ ");
+        for form in init {
+            let _ = write!(out, "let _ = {}; ", self.expr(form, scope, None)?);
+        }
+        let _ = write!(out, "{} }}", self.expr(last, scope, tail)?);
+        Ok(out)
+    }
+
+    /// A Rust expression for the value of `form`, which may return early
+    /// with an error. `tail` is the loop a `recur` here would continue.
+    fn expr(
+        &mut self,
+        form: &Value,
+        scope: &[(String, String)],
+        tail: Option<&Loop>,
+    ) -> Result<String, CompileError> {
+        let list = match form {
+            Value::Symbol(name) => return self.symbol(name, scope),
+            Value::List(list) if !list.is_empty() => list,
+            other => return Ok(literal(other)),
+        };
+        let head = match &list[0] {
+            Value::Symbol(head) if !scope.iter().any(|(name, _)| name == head) => head.as_str(),
+            _ => "",
+        };
+        let args = &list[1..];
+        match head {
+            "quote" => match args {
+                [quoted] => Ok(literal(quoted)),
+                _ => Err(self.unsupported(form.preview(40))),
+            },
+            "if" => match args {
+                [test, then, rest @ ..] if rest.len() <= 1 => {
+                    let test = self.expr(test, scope, None)?;
+                    let then = self.expr(then, scope, tail)?;
+                    let otherwise = match rest {
+                        [otherwise] => self.expr(otherwise, scope, tail)?,
+                        _ => "Value::Nil".into(),
+                    };
+                    Ok(format!(
+                        "if {}.is_truthy() {{ {} }} else {{ {} }}",
+                        test, then, otherwise
+                    ))
+                }
+                _ => Err(self.unsupported(form.preview(40))),
+            },
+            "do" => self.body(args, scope, tail),
+            "and" | "or" => {
+                let Some((last, init)) = args.split_last() else {
+                    return Ok(if head == "and" {
+                        "Value::Bool(true)".into()
+                    } else {
+                        "Value::Nil".into()
+                    });
+                };
+                // each value but the last decides whether to go on
+                let stop = if head == "and" { "!" } else { "" };
+                let mut out = self.expr(last, scope, None)?;
+                for arg in init.iter().rev() {
+                    out = format!(
+                        "{{ let value = {}; if {}value.is_truthy() {{ value }} else {{ {} }} }}",
+                        self.expr(arg, scope, None)?,
+                        stop,
+                        out
+                    );
+                }
+                Ok(out)
+            }
+            "let" | "loop" => {
+                let [Value::List(bindings), body @ ..] = args else {
+                    return Err(self.unsupported(form.preview(40)));
+                };
+                let mut scope = scope.to_vec();
+                let mut out = String::from("{ ");
+                let mut vars = Vec::new();
+                for binding in bindings {
+                    let Value::List(pair) = binding else {
+                        return Err(self.unsupported(form.preview(40)));
+                    };
+                    let [Value::Symbol(name), init] = pair.as_slice() else {
+                        return Err(self.unsupported(form.preview(40)));
+                    };
+                    let value = self.expr(init, &scope, None)?;
+                    let var = self.var(name);
+                    let mutable = if head == "loop" { "mut " } else { "" };
+                    let _ = write!(out, "let {}{} = {}; ", mutable, var, value);
+                    scope.push((name.clone(), var.clone()));
+                    vars.push(var);
+                }
+                if head == "let" {
+                    let _ = write!(out, "{} }}", self.body(body, &scope, tail)?);
+                } else {
+                    self.next_id += 1;
+                    let this = Loop {
+                        label: format!("'loop{}", self.next_id),
+                        vars,
+                    };
+                    let body = self.body(body, &scope, Some(&this))?;
+                    let _ = write!(
+                        out,
+                        "{}: loop {{ break {} ({}); }} }}",
+                        this.label, this.label, body
+                    );
+                }
+                Ok(out)
+            }
+            "recur" => {
+                let Some(target) = tail else {
+                    return Err(CompileError::Expand {
+                        line: self.line,
+                        source: EvalError::MisplacedRecur,
+                    });
+                };
+                if args.len() != target.vars.len() {
+                    return Err(self.arity("recur", target.vars.len().to_string(), args.len()));
+                }
+                let mut out = String::from("{ ");
+                let values = args
+                    .iter()
+                    .map(|arg| self.expr(arg, scope, None))
+                    .collect::<Result<Vec<_>, _>>()?;
+                for (i, value) in values.iter().enumerate() {
+                    let _ = write!(out, "let next{} = {}; ", i, value);
+                }
+                for (i, var) in target.vars.iter().enumerate() {
+                    let _ = write!(out, "{} = next{}; ", var, i);
+                }
+                let _ = write!(out, "continue {} }}", target.label);
+                Ok(out)
+            }
+            "lambda" => {
+                if let Some(local) = symbols(form)
+                    .into_iter()
+                    .find(|symbol| scope.iter().any(|(name, _)| name == symbol))
+                {
+                    return Err(
+                        self.unsupported(format!("a lambda using the local variable {}", local))
+                    );
+                }
+                Ok(format!("interpreter.eval_form(&{})?", literal(form)))
+            }
+            "def" | "defmacro" | "handler-case" | "unwind-protect" | "bindings" => {
+                Err(self.unsupported(head.into()))
+            }
+            _ => self.call(head, list, scope),
+        }
+    }
+
+    fn symbol(&mut self, name: &str, scope: &[(String, String)]) -> Result<String, CompileError> {
+        if let Some((_, var)) = scope.iter().rev().find(|(local, _)| local == name) {
+            return Ok(format!("{}.clone()", var));
+        }
+        if is_keyword(name) {
+            return Ok(literal(&Value::Symbol(name.into())));
+        }
+        if name == "nil" {
+            return Ok("Value::Nil".into());
+        }
+        if self.functions.contains_key(name)
+            || self.globals.contains(name)
+            || self.interpreter.get_global(name).is_some()
+        {
+            return Ok(format!("global(interpreter, {:?})?", name));
+        }
+        Err(CompileError::Unbound {
+            line: self.line,
+            name: name.into(),
+        })
+    }
+
+    /// A call of `list[0]`, whose name is `head` if it is a global.
+    fn call(
+        &mut self,
+        head: &str,
+        list: &[Value],
+        scope: &[(String, String)],
+    ) -> Result<String, CompileError> {
+        let args = &list[1..];
+        let mut out = String::from("{ ");
+        let callee = if head.is_empty() || !self.functions.contains_key(head) {
+            if !head.is_empty() {
+                self.check_arity(head, args.len())?;
+            }
+            let callee = self.expr(&list[0], scope, None)?;
+            let _ = write!(out, "let callee = {}; ", callee);
+            None
+        } else {
+            Some(head)
+        };
+        let mut values = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            let _ = write!(out, "let arg{} = {}; ", i, self.expr(arg, scope, None)?);
+            values.push(format!("arg{}", i));
+        }
+        match callee {
+            Some(name) => {
+                let signature = &self.functions[name];
+                if !signature.accepts(args.len()) {
+                    return Err(self.arity(name, signature.expected(), args.len()));
+                }
+                let mut params = values[..signature.params.len()].to_vec();
+                if signature.rest.is_some() {
+                    params.push(format!(
+                        "Value::List(vec![{}])",
+                        values[signature.params.len()..].join(", ")
+                    ));
+                }
+                let _ = write!(
+                    out,
+                    "{}(interpreter, {})? }}",
+                    function_name(name),
+                    params.join(", ")
+                );
+            }
+            None => {
+                let _ = write!(
+                    out,
+                    "interpreter.apply(&callee, vec![{}])? }}",
+                    values.join(", ")
+                );
+            }
+        }
+        Ok(out)
+    }
+
+    /// Fails if the global `name` is a builtin or prelude function that
+    /// does not take `count` arguments.
+    fn check_arity(&self, name: &str, count: usize) -> Result<(), CompileError> {
+        if self.globals.contains(name) {
+            return Ok(());
+        }
+        match self.interpreter.get_global(name) {
+            Some(Value::Builtin(builtin)) => match builtins::fixed_arity(builtin.name) {
+                Some(expected) if expected != count => {
+                    Err(self.arity(name, expected.to_string(), count))
+                }
+                _ => Ok(()),
+            },
+            Some(Value::Lambda(lambda)) => {
+                let signature = Signature {
+                    params: lambda.params.clone(),
+                    rest: lambda.rest.clone(),
+                };
+                if signature.accepts(count) {
+                    Ok(())
+                } else {
+                    Err(self.arity(name, signature.expected(), count))
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn arity(&self, name: &str, expected: String, found: usize) -> CompileError {
+        CompileError::Arity {
+            line: self.line,
+            name: name.into(),
+            expected,
+            found,
+        }
+    }
+
+    fn unsupported(&self, form: String) -> CompileError {
+        CompileError::Unsupported {
+            line: self.line,
+            form,
+        }
+    }
+}
+
+/// A Rust expression building `value`, which holds only data.
+fn literal(value: &Value) -> String {
+    match value {
+        Value::Number(n) => format!("Value::Number({:?})", n),
+        Value::Complex(c) => format!(
+            "Value::Complex(jon::complex::Complex::new({:?}, {:?}))",
+            c.re, c.im
+        ),
+        Value::Decimal(d) => format!(
+            "Value::Decimal(jon::decimal::Decimal::parse({:?}).unwrap())",
+            d.to_string()
+        ),
+        Value::String(s) => format!("Value::String({:?}.into())", s),
+        Value::Bytes(bytes) => format!("Value::Bytes(vec!{:?})", bytes),
+        Value::Symbol(s) => format!("Value::Symbol({:?}.into())", s),
+        Value::Bool(b) => format!("Value::Bool({})", b),
+        Value::List(items) => format!(
+            "Value::List(vec![{}])",
+            items.iter().map(literal).collect::<Vec<_>>().join(", ")
+        ),
+        _ => "Value::Nil".into(),
+    }
+}
+
+/// Every symbol in `form`.
+fn symbols(form: &Value) -> Vec<String> {
+    match form {
+        Value::Symbol(name) => vec![name.clone()],
+        Value::List(items) => items.iter().flat_map(symbols).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The Rust function the jon function `name` compiles to.
+fn function_name(name: &str) -> String {
+    format!("jon_{}", mangle(name))
+}
+
+/// `name` with the characters Rust does not allow in identifiers spelled
+/// out, keeping distinct names distinct.
+fn mangle(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => out.push(c),
+            '-' => out.push('_'),
+            _ => {
+                let _ = write!(out, "_{:x}_", c as u32);
+            }
+        }
+    }
+    out
+}
+
+fn sorted(functions: &HashMap<String, Signature>) -> Vec<(&String, &Signature)> {
+    let mut functions: Vec<_> = functions.iter().collect();
+    functions.sort_by_key(|(name, _)| *name);
+    functions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_rust() {
+        let program = to_rust(
+            "(def (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+             (def (sum-to n)
+               (loop ((i 0) (total 0))
+                 (if (> i n) total (recur (inc i) (+ total i)))))
+             (when (> (fib 10) 50) (print (sum-to 10)))",
+        )
+        .unwrap();
+        assert!(program.contains(
+            "fn jon_fib(interpreter: &mut Interpreter, v1_n: Value) -> Result<Value, EvalError>"
+        ));
+        assert!(program.contains("jon_fib(interpreter, arg0)?"));
+        assert!(program.contains("continue 'loop"));
+        assert!(program.contains("interpreter.register_fn(\"sum-to\""));
+
+        assert_eq!(
+            to_rust("(def (f x) (lambda () x))"),
+            Err(CompileError::Unsupported {
+                line: 1,
+                form: "a lambda using the local variable x".into()
+            })
+        );
+        assert!(matches!(
+            to_rust("(def (f x) x)\n(f 1 2)"),
+            Err(CompileError::Arity { line: 2, .. })
+        ));
+        assert!(matches!(
+            to_rust("(car '(1) '(2))"),
+            Err(CompileError::Arity { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,116 @@
+//! Complex numbers, such as `3+4i`, with `f32` parts like the rest of the
+//! numbers. Arithmetic mixing them with other numbers is complex.
+
+use core::fmt;
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A complex number `re + im·i`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+impl Complex {
+    pub fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    /// The complex number in `text`, such as `3+4i`, `-1.5-2i`, or `4i`,
+    /// or `None` if it is not one.
+    pub fn parse(text: &str) -> Option<Self> {
+        let body = text.strip_suffix('i')?;
+        // the sign between the parts, which is not a leading sign
+        match body.rfind(['+', '-']).filter(|&i| i > 0) {
+            Some(i) => Some(Self::new(body[..i].parse().ok()?, body[i..].parse().ok()?)),
+            None => Some(Self::new(0.0, body.parse().ok()?)),
+        }
+    }
+
+    /// The distance from zero.
+    #[cfg(feature = "std")]
+    pub fn magnitude(self) -> f32 {
+        self.re.hypot(self.im)
+    }
+}
+
+impl Neg for Complex {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.re, -self.im)
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+/// The quotient, whose parts are infinite or NaN when dividing by zero, as
+/// with floats.
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        let denominator = other.re * other.re + other.im * other.im;
+        Self::new(
+            (self.re * other.re + self.im * other.im) / denominator,
+            (self.im * other.re - self.re * other.im) / denominator,
+        )
+    }
+}
+
+/// The number as it is written, such as `3-4i` or `0+1i`.
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.im.is_sign_negative() {
+            write!(f, "{}{}i", self.re, self.im)
+        } else {
+            write!(f, "{}+{}i", self.re, self.im)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic() {
+        let (a, b) = (Complex::new(3.0, 4.0), Complex::new(1.0, -2.0));
+        assert_eq!(Complex::parse("3+4i"), Some(a));
+        assert_eq!(Complex::parse("1-2i"), Some(b));
+        assert_eq!(Complex::parse("-2.5i"), Some(Complex::new(0.0, -2.5)));
+        assert_eq!(Complex::parse("3+4"), None);
+        assert_eq!(a + b, Complex::new(4.0, 2.0));
+        assert_eq!(a - b, Complex::new(2.0, 6.0));
+        assert_eq!(a * b, Complex::new(11.0, -2.0));
+        assert_eq!(a * b / b, a);
+        assert_eq!(a.magnitude(), 5.0);
+        assert_eq!(b.to_string(), "1-2i");
+        assert_eq!(Complex::new(0.0, 1.0).to_string(), "0+1i");
+    }
+}
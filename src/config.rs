@@ -0,0 +1,245 @@
+//! The config formats TOML and YAML as jon data, for the `toml/` builtins
+//! of the `toml` feature and the `yaml/` builtins of the `yaml` feature.
+//!
+//! Tables and mappings read as maps: lists of `(key value)` pairs with
+//! string keys sorted by key, as `alist->map` makes. Arrays read as lists
+//! and whole numbers as numbers, exact up to 16777216. Writing takes any
+//! list of `(key value)` pairs with string or symbol keys as a table, so
+//! an array of such pairs cannot be written. Decimals are written as
+//! floats.
+
+use crate::decimal::Decimal;
+use crate::eval::EvalError;
+#[cfg(feature = "toml")]
+use crate::scanner::line_column;
+use crate::value::Value;
+
+/// `entries` as a map, sorted by key.
+fn map(mut entries: Vec<(String, Value)>) -> Value {
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Value::List(
+        entries
+            .into_iter()
+            .map(|(key, value)| Value::List(vec![Value::String(key), value]))
+            .collect(),
+    )
+}
+
+/// The entries of `list` if it is a non-empty list of `(key value)` pairs
+/// with string or symbol keys.
+fn entries(list: &[Value]) -> Option<Vec<(&str, &Value)>> {
+    if list.is_empty() {
+        return None;
+    }
+    list.iter()
+        .map(|entry| match entry {
+            Value::List(pair) => match pair.as_slice() {
+                [Value::String(key) | Value::Symbol(key), value] => Some((key.as_str(), value)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// `n` as a whole number if it is one.
+fn whole(n: f32) -> Option<i64> {
+    (n.fract() == 0.0 && n.abs() < i64::MAX as f32).then_some(n as i64)
+}
+
+/// The nearest float to `d`, which is as exact as the formats store
+/// numbers.
+fn float(d: &Decimal) -> f64 {
+    d.to_string().parse().unwrap_or(f64::NAN)
+}
+
+fn unwritable(format: &'static str, value: &Value) -> EvalError {
+    EvalError::TypeMismatch {
+        expected: format,
+        found: value.type_name(),
+    }
+}
+
+/// The document `source` as jon data, or the parser's message with the
+/// line and column it failed at.
+#[cfg(feature = "toml")]
+pub fn parse_toml(source: &str) -> Result<Value, String> {
+    let table: toml::Table = source.parse().map_err(|err: toml::de::Error| {
+        let position = err.span().map_or(source.len(), |span| span.start);
+        let (line, column) = line_column(source, position);
+        let message = match err.message().trim() {
+            "" => "invalid TOML".to_string(),
+            message => message.replace('\n', "; "),
+        };
+        format!("line {}, column {}: {}", line, column, message)
+    })?;
+    Ok(from_toml(toml::Value::Table(table)))
+}
+
+#[cfg(feature = "toml")]
+fn from_toml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(n) => Value::Number(n as f32),
+        toml::Value::Float(n) => Value::Number(n as f32),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(items) => Value::List(items.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => map(table
+            .into_iter()
+            .map(|(key, value)| (key, from_toml(value)))
+            .collect()),
+    }
+}
+
+/// `value`, a table, as a TOML document. nil has no TOML form.
+#[cfg(feature = "toml")]
+pub fn write_toml(value: &Value) -> Result<String, EvalError> {
+    let table = match value {
+        Value::List(list) if list.is_empty() => toml::Table::new(),
+        Value::Nil => toml::Table::new(),
+        value => match to_toml(value)? {
+            toml::Value::Table(table) => table,
+            _ => return Err(unwritable("TOML table", value)),
+        },
+    };
+    toml::to_string(&table).map_err(|err| EvalError::Host(err.to_string()))
+}
+
+#[cfg(feature = "toml")]
+fn to_toml(value: &Value) -> Result<toml::Value, EvalError> {
+    Ok(match value {
+        Value::String(s) => toml::Value::String(s.clone()),
+        Value::Number(n) => match whole(*n) {
+            Some(n) => toml::Value::Integer(n),
+            None => toml::Value::Float(f64::from(*n)),
+        },
+        Value::Decimal(d) => toml::Value::Float(float(d)),
+        Value::Bool(b) => toml::Value::Boolean(*b),
+        Value::List(list) => match entries(list) {
+            Some(entries) => toml::Value::Table(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| Ok((key.to_string(), to_toml(value)?)))
+                    .collect::<Result<_, EvalError>>()?,
+            ),
+            None => toml::Value::Array(list.iter().map(to_toml).collect::<Result<_, _>>()?),
+        },
+        other => return Err(unwritable("TOML value", other)),
+    })
+}
+
+/// The document `source` as jon data, or the parser's message. Null is
+/// nil, and keys that are not strings are written out as strings.
+#[cfg(feature = "yaml")]
+pub fn parse_yaml(source: &str) -> Result<Value, String> {
+    let value: serde_yaml::Value = serde_yaml::from_str(source).map_err(|err| err.to_string())?;
+    Ok(from_yaml(value))
+}
+
+#[cfg(feature = "yaml")]
+fn from_yaml(value: serde_yaml::Value) -> Value {
+    match value {
+        serde_yaml::Value::Null => Value::Nil,
+        serde_yaml::Value::Bool(b) => Value::Bool(b),
+        serde_yaml::Value::Number(n) => Value::Number(n.as_f64().unwrap_or(f64::NAN) as f32),
+        serde_yaml::Value::String(s) => Value::String(s),
+        serde_yaml::Value::Sequence(items) => {
+            Value::List(items.into_iter().map(from_yaml).collect())
+        }
+        serde_yaml::Value::Mapping(mapping) => map(mapping
+            .into_iter()
+            .map(|(key, value)| {
+                let key = match from_yaml(key) {
+                    Value::String(key) => key,
+                    other => other.to_string(),
+                };
+                (key, from_yaml(value))
+            })
+            .collect()),
+        serde_yaml::Value::Tagged(tagged) => from_yaml(tagged.value),
+    }
+}
+
+/// `value` as a YAML document.
+#[cfg(feature = "yaml")]
+pub fn write_yaml(value: &Value) -> Result<String, EvalError> {
+    serde_yaml::to_string(&to_yaml(value)?).map_err(|err| EvalError::Host(err.to_string()))
+}
+
+#[cfg(feature = "yaml")]
+fn to_yaml(value: &Value) -> Result<serde_yaml::Value, EvalError> {
+    Ok(match value {
+        Value::Nil => serde_yaml::Value::Null,
+        Value::String(s) => serde_yaml::Value::String(s.clone()),
+        Value::Number(n) => match whole(*n) {
+            Some(n) => serde_yaml::Value::Number(n.into()),
+            None => serde_yaml::Value::Number(f64::from(*n).into()),
+        },
+        Value::Decimal(d) => serde_yaml::Value::Number(float(d).into()),
+        Value::Bool(b) => serde_yaml::Value::Bool(*b),
+        Value::List(list) => match entries(list) {
+            Some(entries) => serde_yaml::Value::Mapping(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| Ok((key.into(), to_yaml(value)?)))
+                    .collect::<Result<_, EvalError>>()?,
+            ),
+            None => {
+                serde_yaml::Value::Sequence(list.iter().map(to_yaml).collect::<Result<_, _>>()?)
+            }
+        },
+        other => return Err(unwritable("YAML value", other)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(key: &str, value: Value) -> Value {
+        Value::List(vec![Value::String(key.into()), value])
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml() {
+        let value =
+            parse_toml("name = \"jon\"\n[server]\nport = 8080\nhosts = [\"a\", \"b\"]\n").unwrap();
+        let server = Value::List(vec![
+            pair(
+                "hosts",
+                Value::List(vec![Value::String("a".into()), Value::String("b".into())]),
+            ),
+            pair("port", Value::Number(8080.0)),
+        ]);
+        assert_eq!(
+            value,
+            Value::List(vec![
+                pair("name", Value::String("jon".into())),
+                pair("server", server),
+            ])
+        );
+        assert_eq!(parse_toml(&write_toml(&value).unwrap()), Ok(value));
+        assert!(parse_toml("name = ").is_err());
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml() {
+        let value = parse_yaml("name: jon\nretries: 3\nratio: 0.5\nbackup: ~\n").unwrap();
+        assert_eq!(
+            value,
+            Value::List(vec![
+                pair("backup", Value::Nil),
+                pair("name", Value::String("jon".into())),
+                pair("ratio", Value::Number(0.5)),
+                pair("retries", Value::Number(3.0)),
+            ])
+        );
+        assert_eq!(
+            write_yaml(&value).unwrap(),
+            "backup: null\nname: jon\nratio: 0.5\nretries: 3\n"
+        );
+    }
+}
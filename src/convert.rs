@@ -0,0 +1,355 @@
+//! Conversions between [`Value`]s and Rust types, for host code passing data
+//! in and out of jon.
+//!
+//! Maps have no jon type of their own: a `HashMap<String, T>` becomes an
+//! association list of `(key value)` pairs, sorted by key.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+use crate::eval::EvalError;
+use crate::value::{HostValue, Value};
+
+/// Conversion of a Rust value into a jon value.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+/// Conversion of a jon value into a Rust value, failing with a type
+/// mismatch when the value has the wrong shape.
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, EvalError>;
+}
+
+/// Conversion of Rust values into the arguments of a call, for
+/// [`Interpreter::call`](crate::Interpreter::call): a tuple of up to eight
+/// [`IntoValue`] types, `()` for none, or a vector of one type.
+pub trait IntoValueList {
+    fn into_value_list(self) -> Vec<Value>;
+}
+
+impl<T: IntoValue> IntoValueList for Vec<T> {
+    fn into_value_list(self) -> Vec<Value> {
+        self.into_iter().map(IntoValue::into_value).collect()
+    }
+}
+
+macro_rules! tuple_value_list {
+    ($($name:ident),*) => {
+        impl<$($name: IntoValue),*> IntoValueList for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn into_value_list(self) -> Vec<Value> {
+                let ($($name,)*) = self;
+                vec![$($name.into_value()),*]
+            }
+        }
+    };
+}
+
+tuple_value_list!();
+tuple_value_list!(A);
+tuple_value_list!(A, B);
+tuple_value_list!(A, B, C);
+tuple_value_list!(A, B, C, D);
+tuple_value_list!(A, B, C, D, E);
+tuple_value_list!(A, B, C, D, E, F);
+tuple_value_list!(A, B, C, D, E, F, G);
+tuple_value_list!(A, B, C, D, E, F, G, H);
+
+fn mismatch(expected: &'static str, found: &Value) -> EvalError {
+    EvalError::TypeMismatch {
+        expected,
+        found: found.type_name(),
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, EvalError> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Number(self as f32)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, EvalError> {
+        match value {
+            Value::Number(n) => Ok(n.into()),
+            other => Err(mismatch("number", &other)),
+        }
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Number(self as f32)
+    }
+}
+
+impl FromValue for i64 {
+    /// Only numbers without a fractional part convert.
+    fn from_value(value: Value) -> Result<Self, EvalError> {
+        match value {
+            Value::Number(n) if n as i64 as f32 == n && n < i64::MAX as f32 => Ok(n as i64),
+            Value::Number(_) => Err(EvalError::TypeMismatch {
+                expected: "integer",
+                found: "number",
+            }),
+            other => Err(mismatch("integer", &other)),
+        }
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, EvalError> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            other => Err(mismatch("bool", &other)),
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, EvalError> {
+        match value {
+            Value::String(s) => Ok(s),
+            other => Err(mismatch("string", &other)),
+        }
+    }
+}
+
+impl IntoValue for HostValue {
+    fn into_value(self) -> Value {
+        Value::Host(self)
+    }
+}
+
+/// Any host value converts; its type is checked by
+/// [`HostValue::downcast_ref`].
+impl FromValue for HostValue {
+    fn from_value(value: Value) -> Result<Self, EvalError> {
+        match value {
+            Value::Host(host) => Ok(host),
+            other => Err(mismatch("host value", &other)),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::List(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    /// nil converts to an empty vector, like an empty list.
+    fn from_value(value: Value) -> Result<Self, EvalError> {
+        match value {
+            Value::List(list) => list.into_iter().map(T::from_value).collect(),
+            Value::Nil => Ok(Vec::new()),
+            other => Err(mismatch("list", &other)),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> Value {
+        let mut entries: Vec<(String, T)> = self.into_iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Value::List(
+            entries
+                .into_iter()
+                .map(|(key, value)| Value::List(vec![Value::String(key), value.into_value()]))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    /// Keys may be strings or symbols.
+    fn from_value(value: Value) -> Result<Self, EvalError> {
+        Vec::<Value>::from_value(value)?
+            .into_iter()
+            .map(|entry| match entry {
+                Value::List(pair) => match <[Value; 2]>::try_from(pair) {
+                    Ok([Value::String(key) | Value::Symbol(key), value]) => {
+                        Ok((key, T::from_value(value)?))
+                    }
+                    Ok([key, _]) => Err(mismatch("string", &key)),
+                    Err(pair) => Err(mismatch("pair", &Value::List(pair))),
+                },
+                other => Err(mismatch("pair", &other)),
+            })
+            .collect()
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        n.into_value()
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        n.into_value()
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        b.into_value()
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        s.into_value()
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        s.into_value()
+    }
+}
+
+impl<T: IntoValue> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        items.into_value()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: IntoValue> From<HashMap<String, T>> for Value {
+    fn from(map: HashMap<String, T>) -> Self {
+        map.into_value()
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = EvalError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        f64::from_value(value)
+    }
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = EvalError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        i64::from_value(value)
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = EvalError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        bool::from_value(value)
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = EvalError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        String::from_value(value)
+    }
+}
+
+impl<T: FromValue> TryFrom<Value> for Vec<T> {
+    type Error = EvalError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Vec::from_value(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: FromValue> TryFrom<Value> for HashMap<String, T> {
+    type Error = EvalError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        HashMap::from_value(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(Value::from(2.5), Value::Number(2.5));
+        assert_eq!(i64::try_from(Value::from(7_i64)), Ok(7));
+        assert_eq!(
+            i64::try_from(Value::Number(1.5)),
+            Err(EvalError::TypeMismatch {
+                expected: "integer",
+                found: "number"
+            })
+        );
+        assert_eq!(bool::try_from(Value::from(true)), Ok(true));
+        assert_eq!(String::try_from(Value::from("hi")), Ok("hi".to_string()));
+        assert!(f64::try_from(Value::from("hi")).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_collections() {
+        let value = Value::from(vec![vec![1_i64, 2], vec![]]);
+        assert_eq!(value.to_string(), "((1 2) ())");
+        assert_eq!(
+            Vec::<Vec<i64>>::try_from(value),
+            Ok(vec![vec![1, 2], vec![]])
+        );
+
+        let map = HashMap::from([("b".to_string(), 2_i64), ("a".to_string(), 1)]);
+        let value = Value::from(map.clone());
+        assert_eq!(value.to_string(), "((\"a\" 1) (\"b\" 2))");
+        assert_eq!(HashMap::<String, i64>::try_from(value), Ok(map));
+        let parsed = crate::eval_str("'((x 1) (y 2))").unwrap();
+        assert_eq!(
+            HashMap::<String, f64>::try_from(parsed),
+            Ok(HashMap::from([
+                ("x".to_string(), 1.0),
+                ("y".to_string(), 2.0)
+            ]))
+        );
+    }
+}
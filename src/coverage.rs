@@ -0,0 +1,378 @@
+//! Which forms of jon files ran, for `jon test --coverage`.
+//!
+//! Forms carry no source positions once they are values, so a
+//! [`CoverageHook`] records each list form it sees by its printed text,
+//! with the name of the function defined by `(def (name ...) ...)` that
+//! was running it. A form of a file counts as run if a form printing the
+//! same ran inside the function it is written in, or outside any named
+//! one; identical forms in one function are covered together.
+//!
+//! Parameter lists, bindings, and quoted data are not forms. Arguments of
+//! a macro call count only once run, as the macro decides which are code.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::iter::Peekable;
+use std::rc::Rc;
+
+use crate::env::Env;
+use crate::eval::{EvalError, Hook, Interpreter};
+use crate::parser::{ParseError, Parser};
+use crate::scanner::{line_column, Scanner, TokItem, Token};
+use crate::value::Value;
+
+/// The forms run under any number of [`CoverageHook`]s, shared with them.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// How many times each form ran, by its text, and in which function.
+    hits: HashMap<String, HashMap<Option<String>, usize>>,
+    /// The macros seen being called.
+    macros: HashSet<String>,
+    /// The files loaded, in the order they were first loaded.
+    files: Vec<String>,
+}
+
+/// A hook recording what an interpreter evaluates into a [`Coverage`].
+pub struct CoverageHook {
+    coverage: Rc<RefCell<Coverage>>,
+    /// For each call being evaluated, the name of the function defined by
+    /// `def` that it runs, if any.
+    calls: Vec<Option<String>>,
+}
+
+impl CoverageHook {
+    pub fn new(coverage: Rc<RefCell<Coverage>>) -> Self {
+        Self {
+            coverage,
+            calls: Vec::new(),
+        }
+    }
+}
+
+impl Hook for CoverageHook {
+    fn before_form(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        form: &Value,
+        env: &Rc<RefCell<Env>>,
+    ) {
+        let mut coverage = self.coverage.borrow_mut();
+        if let Value::List(list) = form {
+            if let Some(Value::Symbol(head)) = list.first() {
+                if matches!(env.borrow().get(head), Some(Value::Macro(_))) {
+                    coverage.macros.insert(head.clone());
+                }
+            }
+        }
+        let function = self.calls.last().cloned().flatten();
+        *coverage
+            .hits
+            .entry(form.to_string())
+            .or_default()
+            .entry(function)
+            .or_default() += 1;
+    }
+
+    fn enter_call(&mut self, _interpreter: &mut Interpreter, callee: &Value, _args: &[Value]) {
+        let function = match callee {
+            Value::Lambda(lambda) => lambda.name.clone(),
+            // a builtin calling back into jon runs code of whoever called it
+            _ => self.calls.last().cloned().flatten(),
+        };
+        self.calls.push(function);
+    }
+
+    fn exit_call(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _callee: &Value,
+        _result: &Result<Value, EvalError>,
+    ) {
+        self.calls.pop();
+    }
+
+    fn loaded(&mut self, _interpreter: &mut Interpreter, path: &str) {
+        let mut coverage = self.coverage.borrow_mut();
+        if !coverage.files.iter().any(|file| file == path) {
+            coverage.files.push(path.to_string());
+        }
+    }
+}
+
+/// A form of a file and how many times it ran.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Form {
+    pub line: usize,
+    pub text: String,
+    pub hits: usize,
+}
+
+/// The coverage of one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileCoverage {
+    pub path: String,
+    /// The forms of the file in source order.
+    pub forms: Vec<Form>,
+}
+
+impl FileCoverage {
+    /// Each line a form starts on, in order, with how many times the forms
+    /// starting there ran at most.
+    pub fn lines(&self) -> Vec<(usize, usize)> {
+        let mut lines: Vec<(usize, usize)> = Vec::new();
+        for form in &self.forms {
+            match lines.iter_mut().find(|(line, _)| *line == form.line) {
+                Some((_, hits)) => *hits = (*hits).max(form.hits),
+                None => lines.push((form.line, form.hits)),
+            }
+        }
+        lines.sort();
+        lines
+    }
+
+    /// The forms that never ran.
+    pub fn missed(&self) -> impl Iterator<Item = &Form> {
+        self.forms.iter().filter(|form| form.hits == 0)
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The files loaded under the hooks, in the order they were first
+    /// loaded.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// The coverage of the file at `path` holding `source`.
+    pub fn file(&self, path: &str, source: &str) -> Result<FileCoverage, ParseError> {
+        Parser::new(&mut Scanner::new(source)).parse_forms()?;
+        let trees = trees(source);
+        let mut sites = Vec::new();
+        for tree in &trees {
+            self.sites(tree, None, false, source, &mut sites);
+        }
+        let defined: HashSet<&str> = sites.iter().filter_map(|site| site.function).collect();
+        let forms = sites
+            .into_iter()
+            .filter_map(|site| {
+                let hits: usize = self.hits.get(&site.text).map_or(0, |runs| {
+                    runs.iter()
+                        .filter(|(function, _)| match function.as_deref() {
+                            None => true,
+                            Some(name) => Some(name) == site.function || !defined.contains(name),
+                        })
+                        .map(|(_, hits)| hits)
+                        .sum()
+                });
+                (hits > 0 || !site.optional).then_some(Form {
+                    line: site.line,
+                    text: site.text,
+                    hits,
+                })
+            })
+            .collect();
+        Ok(FileCoverage {
+            path: path.to_string(),
+            forms,
+        })
+    }
+
+    /// Adds the forms of `tree` to `sites`, found inside the function
+    /// `function`; `optional` for arguments of macro calls.
+    fn sites<'a>(
+        &self,
+        tree: &'a Tree,
+        function: Option<&'a str>,
+        optional: bool,
+        source: &str,
+        sites: &mut Vec<Site<'a>>,
+    ) {
+        let Tree::List { span, items } = tree else {
+            return;
+        };
+        let Some(first) = items.first() else {
+            return;
+        };
+        let Ok(expr) = Parser::new(&mut Scanner::new(&source[span.clone()])).parse_expr() else {
+            return;
+        };
+        sites.push(Site {
+            line: line_column(source, span.start).0,
+            text: Value::from(&expr).to_string(),
+            function,
+            optional,
+        });
+        let mut walk = |trees: &'a [Tree], function: Option<&'a str>, optional: bool| {
+            for tree in trees {
+                self.sites(tree, function, optional, source, sites);
+            }
+        };
+        let head = match first {
+            Tree::Symbol(head) => head.as_str(),
+            _ => return walk(items, function, optional),
+        };
+        match (head, items.get(1)) {
+            ("quote", _) => {}
+            (
+                "def",
+                Some(Tree::List {
+                    items: signature, ..
+                }),
+            ) => {
+                let name = match signature.first() {
+                    Some(Tree::Symbol(name)) => Some(name.as_str()),
+                    _ => function,
+                };
+                walk(&items[2..], name, optional);
+            }
+            ("defmacro" | "lambda", Some(_)) => walk(&items[2..], function, optional),
+            (
+                "let" | "loop",
+                Some(Tree::List {
+                    items: bindings, ..
+                }),
+            ) => {
+                for binding in bindings {
+                    if let Tree::List { items: binding, .. } = binding {
+                        walk(binding.get(1..).unwrap_or_default(), function, optional);
+                    }
+                }
+                walk(&items[2..], function, optional);
+            }
+            ("handler-case", Some(expr)) => {
+                walk(core::slice::from_ref(expr), function, optional);
+                for clause in &items[2..] {
+                    if let Tree::List { items: clause, .. } = clause {
+                        walk(clause.get(1..).unwrap_or_default(), function, optional);
+                    }
+                }
+            }
+            _ if self.macros.contains(head) => walk(&items[1..], function, true),
+            _ => walk(&items[1..], function, optional),
+        }
+    }
+}
+
+/// A list form of a file before it is matched against what ran.
+struct Site<'a> {
+    line: usize,
+    text: String,
+    function: Option<&'a str>,
+    optional: bool,
+}
+
+/// The shape of a file with the span of each list, enough to tell forms
+/// from parameter lists and data.
+enum Tree {
+    Symbol(String),
+    List {
+        span: core::ops::Range<usize>,
+        items: Vec<Tree>,
+    },
+    /// A number, string, or quoted form.
+    Other,
+}
+
+/// The top-level trees of `source`, which parses.
+fn trees(source: &str) -> Vec<Tree> {
+    let mut scanner = Scanner::new(source);
+    let mut tokens = Vec::new();
+    while let Ok(tok) = scanner.next_token() {
+        if !matches!(tok.token, Token::WhiteSpace(_) | Token::Comment(_)) {
+            tokens.push(tok);
+        }
+    }
+    let mut tokens = tokens.into_iter().peekable();
+    let mut trees = Vec::new();
+    while tokens.peek().is_some() {
+        trees.push(tree(&mut tokens));
+    }
+    trees
+}
+
+fn tree<'a>(tokens: &mut Peekable<impl Iterator<Item = TokItem<'a>>>) -> Tree {
+    let Some(tok) = tokens.next() else {
+        return Tree::Other;
+    };
+    match tok.token {
+        Token::LParen => {
+            let mut items = Vec::new();
+            loop {
+                match tokens.peek() {
+                    None => return Tree::Other,
+                    Some(next) if next.token == Token::RParen => {
+                        let end = next.position + 1;
+                        tokens.next();
+                        return Tree::List {
+                            span: tok.position..end,
+                            items,
+                        };
+                    }
+                    Some(_) => items.push(tree(tokens)),
+                }
+            }
+        }
+        Token::Quote => {
+            tree(tokens);
+            Tree::Other
+        }
+        Token::Symbol(name) => Tree::Symbol(name.to_string()),
+        _ => Tree::Other,
+    }
+}
+
+/// `files` in the lcov tracefile format, with a line record for each line
+/// a form starts on.
+pub fn lcov(files: &[FileCoverage]) -> String {
+    let mut out = String::new();
+    for file in files {
+        let lines = file.lines();
+        let _ = writeln!(out, "TN:\nSF:{}", file.path);
+        for (line, hits) in &lines {
+            let _ = writeln!(out, "DA:{},{}", line, hits);
+        }
+        let hit = lines.iter().filter(|(_, hits)| *hits > 0).count();
+        let _ = writeln!(out, "LF:{}\nLH:{}\nend_of_record", lines.len(), hit);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let source = "(def (sign n)
+  (if (< n 0)
+      (- 0 1)
+      (let ((m (* n 1)))
+        (when (> m 0) (print 'positive))
+        1)))
+(def (unused xs) (car xs))
+(sign 5)";
+        let coverage = Rc::new(RefCell::new(Coverage::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.add_hook(Box::new(CoverageHook::new(coverage.clone())));
+        interpreter.set_output(Vec::new());
+        interpreter.eval_str(source).unwrap();
+
+        let file = coverage.borrow().file("sign.jon", source).unwrap();
+        let missed: Vec<_> = file
+            .missed()
+            .map(|form| (form.line, form.text.as_str()))
+            .collect();
+        assert_eq!(missed, [(3, "(- 0 1)"), (7, "(car xs)")]);
+        assert_eq!(file.forms.iter().filter(|form| form.hits > 0).count(), 10);
+        assert_eq!(
+            lcov(&[file]),
+            "TN:\nSF:sign.jon\nDA:1,1\nDA:2,1\nDA:3,0\nDA:4,1\nDA:5,1\nDA:7,1\nDA:8,1\n\
+             LF:7\nLH:6\nend_of_record\n"
+        );
+    }
+}
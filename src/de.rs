@@ -0,0 +1,405 @@
+//! Deserializing Rust data from jon s-expressions, for use with serde.
+//!
+//! The input is data, not code: it is read but never evaluated, so it may
+//! not contain quotes. It is the format [`crate::ser`] writes, with
+//! symbols and strings accepted alike for struct fields and variant names:
+//!
+//! ```
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, Debug, PartialEq)]
+//! struct Server {
+//!     host: String,
+//!     port: u16,
+//!     tags: Vec<String>,
+//! }
+//!
+//! let config = r#"
+//!     ; where to listen
+//!     ((host "localhost")
+//!      (port 8080)
+//!      (tags ("web" "dev")))
+//! "#;
+//! assert_eq!(
+//!     jon::from_str::<Server>(config).unwrap(),
+//!     Server { host: "localhost".into(), port: 8080, tags: vec!["web".into(), "dev".into()] }
+//! );
+//! ```
+
+use alloc::{borrow::Cow, format, string::ToString, vec::Vec};
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Unexpected, Visitor};
+use serde::Deserialize;
+
+use crate::error::DataError;
+use crate::scanner::{unescape, ScanError, Scanner, TokItem, Token};
+
+/// Reads a `T` from `source`, which must hold exactly one form.
+pub fn from_str<'de, T: Deserialize<'de>>(source: &'de str) -> Result<T, DataError> {
+    let mut deserializer = Deserializer::new(source)?;
+    let value = T::deserialize(&mut deserializer)?;
+    match deserializer.peek() {
+        Ok(tok) => Err(DataError::Syntax(tok.position, "Trailing input".into())),
+        Err(_) => Ok(value),
+    }
+}
+
+pub struct Deserializer<'de> {
+    tokens: Vec<TokItem<'de>>,
+    current_pos: usize,
+}
+
+impl<'de> Deserializer<'de> {
+    /// Scans all of `source`, failing on text that is not jon syntax.
+    pub fn new(source: &'de str) -> Result<Self, DataError> {
+        let mut scanner = Scanner::new(source);
+        let mut tokens = Vec::new();
+        loop {
+            let position = scanner.position();
+            match scanner.next_token() {
+                Ok(TokItem {
+                    token: Token::WhiteSpace(_) | Token::Comment(_),
+                    position: _,
+                }) => {}
+                Ok(tok) => tokens.push(tok),
+                Err(ScanError::Eof) => break,
+                Err(err) => return Err(DataError::Syntax(position, err.to_string())),
+            }
+        }
+        Ok(Self {
+            tokens,
+            current_pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Result<TokItem<'de>, DataError> {
+        self.tokens
+            .get(self.current_pos)
+            .copied()
+            .ok_or(DataError::Eof)
+    }
+
+    fn next(&mut self) -> Result<TokItem<'de>, DataError> {
+        let tok = self.peek()?;
+        self.current_pos += 1;
+        Ok(tok)
+    }
+
+    fn unexpected(tok: TokItem<'de>) -> DataError {
+        DataError::Syntax(tok.position, format!("Unexpected token {:?}", tok.token))
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), DataError> {
+        match self.next()? {
+            tok if tok.token == token => Ok(()),
+            tok => Err(Self::unexpected(tok)),
+        }
+    }
+
+    /// Whether the next token closes the current list.
+    fn at_close(&self) -> Result<bool, DataError> {
+        Ok(self.peek()?.token == Token::RParen)
+    }
+
+    fn at_nil(&self) -> bool {
+        matches!(self.peek(), Ok(tok) if tok.token == Token::Symbol("nil"))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = DataError;
+
+    /// Numbers are read as unsigned, signed, or floating point, whichever
+    /// fits their text; symbols other than nil, true, and false as
+    /// strings.
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DataError> {
+        let tok = self.next()?;
+        match tok.token {
+            Token::LParen => {
+                let value = visitor.visit_seq(Elements { de: self })?;
+                self.expect(Token::RParen)?;
+                Ok(value)
+            }
+            Token::Number(n) => {
+                let invalid = || DataError::Syntax(tok.position, format!("Invalid number {}", n));
+                if let Ok(n) = n.parse() {
+                    visitor.visit_u64(n)
+                } else if let Ok(n) = n.parse() {
+                    visitor.visit_i64(n)
+                } else {
+                    visitor.visit_f64(n.parse().map_err(|_| invalid())?)
+                }
+            }
+            Token::String(s) => match unescape(s) {
+                Ok(Cow::Borrowed(s)) => visitor.visit_borrowed_str(s),
+                Ok(Cow::Owned(s)) => visitor.visit_string(s),
+                Err(err) => Err(DataError::Syntax(
+                    tok.position + 1 + err.offset,
+                    err.to_string(),
+                )),
+            },
+            Token::Symbol("nil") => visitor.visit_unit(),
+            Token::Symbol("true") => visitor.visit_bool(true),
+            Token::Symbol("false") => visitor.visit_bool(false),
+            Token::Symbol(s) => visitor.visit_borrowed_str(s),
+            _ => Err(Deserializer::unexpected(tok)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DataError> {
+        if self.at_nil() {
+            self.next()?;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DataError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Maps are association lists of `(key value)` pairs.
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DataError> {
+        self.expect(Token::LParen)?;
+        let value = visitor.visit_map(Entries { de: self })?;
+        self.expect(Token::RParen)?;
+        Ok(value)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DataError> {
+        self.deserialize_map(visitor)
+    }
+
+    /// A unit variant is a bare name, any other variant a list headed by
+    /// its name.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DataError> {
+        let wrapped = self.peek()?.token == Token::LParen;
+        if wrapped {
+            self.next()?;
+        }
+        visitor.visit_enum(Variant { de: self, wrapped })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct identifier
+        ignored_any
+    }
+}
+
+/// The remaining elements of a list, up to its closing parenthesis.
+struct Elements<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de> de::SeqAccess<'de> for Elements<'_, 'de> {
+    type Error = DataError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, DataError> {
+        if self.de.at_close()? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+/// The remaining `(key value)` pairs of a list.
+struct Entries<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de> de::MapAccess<'de> for Entries<'_, 'de> {
+    type Error = DataError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, DataError> {
+        if self.de.at_close()? {
+            return Ok(None);
+        }
+        self.de.expect(Token::LParen)?;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, DataError> {
+        let value = seed.deserialize(&mut *self.de)?;
+        self.de.expect(Token::RParen)?;
+        Ok(value)
+    }
+}
+
+/// An enum variant; `wrapped` once the list around it has been opened.
+struct Variant<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    wrapped: bool,
+}
+
+impl<'de> de::EnumAccess<'de> for Variant<'_, 'de> {
+    type Error = DataError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), DataError> {
+        let name = match self.de.next()? {
+            TokItem {
+                token: Token::Symbol(name) | Token::String(name),
+                position: _,
+            } => name,
+            tok => return Err(Deserializer::unexpected(tok)),
+        };
+        let value = seed.deserialize(name.into_deserializer())?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Variant<'_, 'de> {
+    type Error = DataError;
+
+    fn unit_variant(self) -> Result<(), DataError> {
+        if self.wrapped {
+            self.de.expect(Token::RParen)?;
+        }
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, DataError> {
+        if !self.wrapped {
+            return Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"newtype variant",
+            ));
+        }
+        let value = seed.deserialize(&mut *self.de)?;
+        self.de.expect(Token::RParen)?;
+        Ok(value)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DataError> {
+        if !self.wrapped {
+            return Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"tuple variant",
+            ));
+        }
+        let value = visitor.visit_seq(Elements { de: self.de })?;
+        self.de.expect(Token::RParen)?;
+        Ok(value)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DataError> {
+        if !self.wrapped {
+            return Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"struct variant",
+            ));
+        }
+        let value = visitor.visit_map(Entries { de: self.de })?;
+        self.de.expect(Token::RParen)?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::ser::to_string;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Event {
+        Quit,
+        Move(i32, i32),
+        Say(String),
+        Set { key: String, value: Option<f64> },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Log {
+        id: u64,
+        events: Vec<Event>,
+        counts: HashMap<String, i8>,
+        raw: (bool, char),
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let log = Log {
+            id: u64::MAX,
+            events: vec![
+                Event::Quit,
+                Event::Move(-1, 2),
+                Event::Say("hi (there)".into()),
+                Event::Say("say \"hi\"\n\\".into()),
+                Event::Set {
+                    key: "x".into(),
+                    value: Some(0.5),
+                },
+            ],
+            counts: HashMap::from([("a".into(), -3)]),
+            raw: (false, 'λ'),
+        };
+        let text = to_string(&log).unwrap();
+        assert_eq!(from_str::<Log>(&text), Ok(log));
+        assert_eq!(
+            from_str::<Event>("(\"Set\" (value nil) (key k))"),
+            Ok(Event::Set {
+                key: "k".into(),
+                value: None
+            })
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            from_str::<Vec<u8>>("(1 2) 3"),
+            Err(DataError::Syntax(6, "Trailing input".into()))
+        );
+        assert_eq!(from_str::<Vec<u8>>("(1 2"), Err(DataError::Eof));
+        assert_eq!(
+            from_str::<Vec<u8>>("'(1)"),
+            Err(DataError::Syntax(0, "Unexpected token Quote".into()))
+        );
+        assert_eq!(
+            from_str::<u8>("300"),
+            Err(DataError::Message(
+                "invalid value: integer `300`, expected u8".into()
+            ))
+        );
+        assert_eq!(
+            from_str::<Event>("Move"),
+            Err(DataError::Message(
+                "invalid type: unit variant, expected tuple variant".into()
+            ))
+        );
+    }
+}
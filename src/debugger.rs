@@ -0,0 +1,269 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, BufReader, Write};
+use std::rc::Rc;
+
+use crate::env::Env;
+use crate::eval::{EvalError, Hook, Interpreter, Origin};
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::value::Value;
+
+/// How many characters of a form or value the debugger prints.
+const PREVIEW_WIDTH: usize = 72;
+
+const HELP: &str = "\
+step, s         evaluate up to the next form
+next, n         evaluate up to the next form, stepping over calls
+continue, c     run until the next (breakpoint)
+locals, l       list the local bindings
+backtrace, bt   show the call stack and the macro calls being expanded
+help, h         show this message
+Anything else is evaluated in the current environment.";
+
+/// When to stop next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    /// Only at a `(breakpoint)`.
+    Continue,
+    /// At the next form.
+    Step,
+    /// At the next form no deeper in the call stack than the given depth.
+    Next(usize),
+}
+
+/// A hook that stops at `(breakpoint)`, or at every form while stepping,
+/// and reads debugger commands until told to go on.
+pub struct Debugger<R, W> {
+    mode: Mode,
+    /// The calls being evaluated, outermost first.
+    stack: Vec<String>,
+    input: R,
+    output: W,
+}
+
+impl Debugger<BufReader<io::Stdin>, io::Stdout> {
+    /// A debugger on the terminal. With `step`, it stops at the first form
+    /// rather than waiting for a breakpoint. Standard input is only locked
+    /// while reading a command.
+    pub fn stdio(step: bool) -> Self {
+        Self::new(BufReader::new(io::stdin()), io::stdout(), step)
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    pub fn new(input: R, output: W, step: bool) -> Self {
+        Self {
+            mode: if step { Mode::Step } else { Mode::Continue },
+            stack: Vec::new(),
+            input,
+            output,
+        }
+    }
+
+    /// Reads and runs commands until one resumes evaluation. The end of
+    /// the input resumes without stopping again.
+    fn stop(&mut self, interpreter: &mut Interpreter, form: &Value, env: &Rc<RefCell<Env>>) {
+        let _ = writeln!(
+            self.output,
+            "-> {} [depth {}]",
+            form.preview(PREVIEW_WIDTH),
+            self.stack.len()
+        );
+        loop {
+            let _ = write!(self.output, "debug> ");
+            let _ = self.output.flush();
+            let mut line = String::new();
+            if !matches!(self.input.read_line(&mut line), Ok(n) if n > 0) {
+                self.mode = Mode::Continue;
+                return;
+            }
+            let resume = match line.trim() {
+                "step" | "s" => Mode::Step,
+                "next" | "n" => Mode::Next(self.stack.len()),
+                "continue" | "c" => Mode::Continue,
+                "" => continue,
+                "locals" | "l" => {
+                    self.locals(interpreter, env);
+                    continue;
+                }
+                "backtrace" | "bt" => {
+                    for (depth, call) in self.stack.iter().enumerate().rev() {
+                        let _ = writeln!(self.output, "{:>3}: {}", depth, call);
+                    }
+                    for expansion in interpreter.expansions().iter().rev() {
+                        let _ = write!(self.output, "     expanded from `{}`", expansion.name);
+                        if let Some(Origin::Source { name, line }) = &expansion.origin {
+                            let _ = write!(self.output, " at {}:{}", name, line);
+                        }
+                        let _ =
+                            writeln!(self.output, ": {}", expansion.call.preview(PREVIEW_WIDTH));
+                    }
+                    continue;
+                }
+                "help" | "h" => {
+                    let _ = writeln!(self.output, "{}", HELP);
+                    continue;
+                }
+                text => {
+                    self.eval(interpreter, text, env);
+                    continue;
+                }
+            };
+            self.mode = resume;
+            return;
+        }
+    }
+
+    /// Prints the bindings visible in `env` apart from the globals, inner
+    /// ones hiding outer ones of the same name.
+    fn locals(&mut self, interpreter: &Interpreter, env: &Rc<RefCell<Env>>) {
+        let mut seen = Vec::new();
+        let mut frame = Some(env.clone());
+        while let Some(current) = frame.filter(|frame| !interpreter.is_global(frame)) {
+            let current = current.borrow();
+            for name in current.names() {
+                if !seen.contains(&name) {
+                    let value = current.get(&name).unwrap_or(Value::Nil);
+                    let _ = writeln!(self.output, "{} = {}", name, value.preview(PREVIEW_WIDTH));
+                    seen.push(name);
+                }
+            }
+            frame = current.parent();
+        }
+        if seen.is_empty() {
+            let _ = writeln!(self.output, "no locals");
+        }
+    }
+
+    fn eval(&mut self, interpreter: &mut Interpreter, text: &str, env: &Rc<RefCell<Env>>) {
+        let mut scanner = Scanner::new(text);
+        let mut parser = Parser::new(&mut scanner);
+        let forms = match parser.parse_program() {
+            Ok(forms) => forms,
+            Err(err) => {
+                let _ = writeln!(self.output, "error: {}", err);
+                return;
+            }
+        };
+        for expr in &forms {
+            match interpreter.eval_value(&Value::from(expr), env) {
+                Ok(value) => {
+                    let _ = writeln!(self.output, "{}", value);
+                }
+                Err(err) => {
+                    let _ = writeln!(self.output, "error: {}", err);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Hook for Debugger<R, W> {
+    fn before_form(&mut self, interpreter: &mut Interpreter, form: &Value, env: &Rc<RefCell<Env>>) {
+        let breakpoint = matches!(form, Value::List(list)
+            if matches!(list.as_slice(), [Value::Symbol(head)] if head == "breakpoint"));
+        let stop = match self.mode {
+            Mode::Continue => breakpoint,
+            Mode::Step => true,
+            Mode::Next(depth) => breakpoint || self.stack.len() <= depth,
+        };
+        if stop {
+            self.stop(interpreter, form, env);
+        }
+    }
+
+    fn enter_call(&mut self, _interpreter: &mut Interpreter, callee: &Value, args: &[Value]) {
+        if let Value::Lambda(lambda) = callee {
+            let mut call = vec![lambda.name.clone().unwrap_or_else(|| "lambda".into())];
+            call.extend(args.iter().map(|arg| arg.preview(PREVIEW_WIDTH)));
+            self.stack.push(format!("({})", call.join(" ")));
+        }
+    }
+
+    fn exit_call(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        callee: &Value,
+        _result: &Result<Value, EvalError>,
+    ) {
+        if let Value::Lambda(_) = callee {
+            self.stack.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output shared with the test after the debugger is handed to the
+    /// interpreter.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn debug(program: &str, commands: &str) -> String {
+        let output = Shared::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.add_hook(Box::new(Debugger::new(
+            io::Cursor::new(commands.to_string()),
+            output.clone(),
+            false,
+        )));
+        let mut scanner = Scanner::new(program);
+        let mut parser = Parser::new(&mut scanner);
+        for expr in parser.parse_program().unwrap() {
+            interpreter.eval(&expr).unwrap();
+        }
+        let text = String::from_utf8(output.0.borrow().clone()).unwrap();
+        text
+    }
+
+    #[test]
+    fn test_breakpoint_inspects_locals_and_stack() {
+        let output = debug(
+            "(def (f x) (let ((y 2)) (breakpoint) (+ x y))) (f 1)",
+            "locals\nbt\n(* x 10)\nc\n",
+        );
+        assert_eq!(
+            output,
+            "-> (breakpoint) [depth 1]
+debug> y = 2
+x = 1
+debug>   0: (f 1)
+debug> 10
+debug> "
+        );
+    }
+
+    #[test]
+    fn test_step_and_next() {
+        let output = debug(
+            "(def (f x) (+ x 1)) (breakpoint) (f 1) (f 2)",
+            "n\nn\ns\ns\nc\n",
+        );
+        let stops: Vec<&str> = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("debug> -> ").or(line.strip_prefix("-> ")))
+            .collect();
+        assert_eq!(
+            stops,
+            [
+                "(breakpoint) [depth 0]",
+                "(f 1) [depth 0]",
+                "(f 2) [depth 0]",
+                "(+ x 1) [depth 1]",
+            ]
+        );
+    }
+}
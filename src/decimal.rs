@@ -0,0 +1,307 @@
+//! Exact decimal numbers, such as `19.99m`, for money and other amounts
+//! where `(+ 0.1m 0.2m)` must be `0.3m`. They have as many digits as they
+//! need; only division rounds, to [`DIVISION_PLACES`] places.
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+
+/// How many places after the point a quotient keeps, at least, rounding
+/// the rest half away from zero.
+pub const DIVISION_PLACES: u32 = 28;
+
+/// A decimal number: a whole number of any size shifted `scale` places
+/// to the right.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    negative: bool,
+    /// The decimal digits of the whole number, most significant first,
+    /// without leading zeros, so empty for zero.
+    digits: Vec<u8>,
+    /// How many of the digits are after the point, none of them trailing
+    /// zeros.
+    scale: u32,
+}
+
+impl Decimal {
+    pub fn zero() -> Self {
+        Self::new(false, Vec::new(), 0)
+    }
+
+    pub fn one() -> Self {
+        Self::new(false, vec![1], 0)
+    }
+
+    /// The decimal in `text`, such as `-12.50`, or `None` if it is not one.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        let digits = whole
+            .bytes()
+            .chain(fraction.bytes())
+            .map(|b| b.is_ascii_digit().then(|| b - b'0'))
+            .collect::<Option<Vec<u8>>>()?;
+        Some(Self::new(negative, digits, fraction.len() as u32))
+    }
+
+    /// The decimal `n` prints as, which is exactly the number it was
+    /// written as, or `None` if `n` is not finite.
+    pub fn from_f32(n: f32) -> Option<Self> {
+        if n.is_finite() {
+            Self::parse(&n.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// The nearest float.
+    pub fn to_f32(&self) -> f32 {
+        self.to_string().parse().unwrap_or(f32::NAN)
+    }
+
+    /// Normalizes the parts of a decimal.
+    fn new(negative: bool, mut digits: Vec<u8>, mut scale: u32) -> Self {
+        while scale > 0 && digits.last() == Some(&0) {
+            digits.pop();
+            scale -= 1;
+        }
+        let leading = digits.iter().take_while(|&&d| d == 0).count();
+        digits.drain(..leading);
+        if digits.is_empty() {
+            scale = 0;
+        }
+        Self {
+            negative: negative && !digits.is_empty(),
+            digits,
+            scale,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    /// The digits shifted left to have `scale` places after the point.
+    fn digits_at(&self, scale: u32) -> Vec<u8> {
+        let mut digits = self.digits.clone();
+        digits.resize(digits.len() + (scale - self.scale) as usize, 0);
+        digits
+    }
+
+    pub fn neg(&self) -> Self {
+        Self::new(!self.negative, self.digits.clone(), self.scale)
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        let scale = self.scale.max(other.scale);
+        let (a, b) = (self.digits_at(scale), other.digits_at(scale));
+        if self.negative == other.negative {
+            return Self::new(self.negative, add_digits(&a, &b), scale);
+        }
+        match compare_digits(&a, &b) {
+            Ordering::Less => Self::new(other.negative, sub_digits(&b, &a), scale),
+            _ => Self::new(self.negative, sub_digits(&a, &b), scale),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        Self::new(
+            self.negative != other.negative,
+            mul_digits(&self.digits, &other.digits),
+            self.scale + other.scale,
+        )
+    }
+
+    /// The quotient to at least [`DIVISION_PLACES`] places, or `None` when
+    /// dividing by zero.
+    pub fn div(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        // one place more than kept, to round by
+        let places = DIVISION_PLACES.max(self.scale) + 1;
+        let mut dividend = self.digits.clone();
+        dividend.resize(
+            dividend.len() + (places + other.scale - self.scale) as usize,
+            0,
+        );
+        let (quotient, _) = div_digits(&dividend, &other.digits);
+        Some(Self::new(self.negative != other.negative, quotient, places).round(places - 1))
+    }
+
+    /// The decimal rounded to `places` places after the point, halves away
+    /// from zero.
+    pub fn round(&self, places: u32) -> Self {
+        if self.scale <= places {
+            return self.clone();
+        }
+        let mut digits = self.digits.clone();
+        let dropped = (self.scale - places) as usize;
+        let kept = digits.len().saturating_sub(dropped);
+        let round_up = digits.get(kept).is_some_and(|&d| d >= 5) && digits.len() >= dropped;
+        digits.truncate(kept);
+        if round_up {
+            digits = add_digits(&digits, &[1]);
+        }
+        Self::new(self.negative, digits, places)
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (negative, _) => {
+                let scale = self.scale.max(other.scale);
+                let order = compare_digits(&self.digits_at(scale), &other.digits_at(scale));
+                if negative {
+                    order.reverse()
+                } else {
+                    order
+                }
+            }
+        }
+    }
+}
+
+/// The number written out in full, such as `-0.05`, without a suffix.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scale = self.scale as usize;
+        let mut text: String = self.digits.iter().map(|d| char::from(b'0' + d)).collect();
+        if text.len() <= scale {
+            text.insert_str(0, &"0".repeat(scale + 1 - text.len()));
+        }
+        if scale > 0 {
+            text.insert(text.len() - scale, '.');
+        }
+        if self.negative {
+            text.insert(0, '-');
+        }
+        f.write_str(&text)
+    }
+}
+
+fn compare_digits(a: &[u8], b: &[u8]) -> Ordering {
+    let (a, b) = (trim(a), trim(b));
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+fn trim(digits: &[u8]) -> &[u8] {
+    let leading = digits.iter().take_while(|&&d| d == 0).count();
+    &digits[leading..]
+}
+
+fn add_digits(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    let (mut a, mut b) = (a.iter().rev(), b.iter().rev());
+    loop {
+        let (x, y) = (a.next(), b.next());
+        if x.is_none() && y.is_none() && carry == 0 {
+            break;
+        }
+        let digit = x.unwrap_or(&0) + y.unwrap_or(&0) + carry;
+        sum.push(digit % 10);
+        carry = digit / 10;
+    }
+    sum.reverse();
+    sum
+}
+
+/// `a - b`, where `a` is at least `b`.
+fn sub_digits(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    let mut b = b.iter().rev();
+    for &x in a.iter().rev() {
+        let y = b.next().unwrap_or(&0) + borrow;
+        if x >= y {
+            difference.push(x - y);
+            borrow = 0;
+        } else {
+            difference.push(x + 10 - y);
+            borrow = 1;
+        }
+    }
+    difference.reverse();
+    difference
+}
+
+fn mul_digits(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate().rev() {
+        for (j, &y) in b.iter().enumerate().rev() {
+            product[i + j + 1] += u32::from(x) * u32::from(y);
+        }
+    }
+    for i in (1..product.len()).rev() {
+        product[i - 1] += product[i] / 10;
+        product[i] %= 10;
+    }
+    product.into_iter().map(|d| d as u8).collect()
+}
+
+/// The quotient and remainder of `a / b`, by long division.
+fn div_digits(a: &[u8], b: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut quotient = Vec::with_capacity(a.len());
+    let mut remainder: Vec<u8> = Vec::new();
+    for &digit in a {
+        remainder.push(digit);
+        let mut times = 0;
+        while compare_digits(&remainder, b) != Ordering::Less {
+            remainder = sub_digits(&remainder, b);
+            times += 1;
+        }
+        quotient.push(times);
+    }
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(text: &str) -> Decimal {
+        Decimal::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(d("0.1").add(&d("0.2")), d("0.3"));
+        assert_eq!(d("19.99").mul(&d("3")).to_string(), "59.97");
+        assert_eq!(d("1.50").sub(&d("2.25")).to_string(), "-0.75");
+        assert_eq!(d("-0.05").to_string(), "-0.05");
+        assert_eq!(d("1").div(&d("4")), Some(d("0.25")));
+        assert_eq!(
+            d("2").div(&d("3")).unwrap().to_string(),
+            "0.6666666666666666666666666667"
+        );
+        assert_eq!(d("1").div(&d("0")), None);
+        assert_eq!(d("2.345").round(2).to_string(), "2.35");
+        assert_eq!(d("-2.344").round(2).to_string(), "-2.34");
+        assert_eq!(d("0.004").round(2), Decimal::zero());
+        assert!(d("-1.5") < d("-1.25") && d("10") > d("9.99"));
+        assert_eq!(Decimal::from_f32(0.1), Some(d("0.1")));
+        assert_eq!(Decimal::parse("1.2.3"), None);
+    }
+}
@@ -0,0 +1,261 @@
+use alloc::string::{String, ToString};
+use core::fmt::Write;
+#[cfg(feature = "std")]
+use std::io::{self, IsTerminal};
+
+use alloc::vec::Vec;
+
+use crate::eval::{EvalError, Expansion, Origin};
+use crate::parser::{ParseError, Parser, Span};
+use crate::scanner::{line_column, Scanner, Token};
+use crate::value::Value;
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+/// An error or warning located in a piece of source text, rendered with the
+/// offending line and a caret underline.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub hint: Option<&'static str>,
+    /// The macro calls a runtime error was raised in the expansions of,
+    /// innermost first.
+    pub expanded_from: Vec<ExpandedFrom>,
+}
+
+/// A macro call, located for a runtime error raised in its expansion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpandedFrom {
+    /// The name of the macro.
+    pub name: String,
+    /// Where the call starts in the diagnostic's source, if it is there.
+    pub position: Option<usize>,
+    /// Where the top-level form evaluating the call comes from, for a
+    /// call written elsewhere.
+    pub origin: Option<Origin>,
+}
+
+impl Diagnostic {
+    pub fn parse(err: &ParseError, source: &str) -> Self {
+        Self {
+            level: Level::Error,
+            code: err.code(),
+            message: err.to_string(),
+            span: err.span().unwrap_or(source.len()..source.len()),
+            hint: err.hint(),
+            expanded_from: Vec::new(),
+        }
+    }
+
+    /// A runtime error, pointing at the top-level form `span` being
+    /// evaluated when it was raised.
+    pub fn eval(err: &EvalError, span: Span) -> Self {
+        Self {
+            level: Level::Error,
+            code: err.code(),
+            message: err.to_string(),
+            span,
+            hint: err.hint(),
+            expanded_from: Vec::new(),
+        }
+    }
+
+    /// Notes that the error was raised in code expanded from
+    /// `expansions`, as given by `Interpreter::error_expansions`, each
+    /// located in `source` when written there.
+    pub fn with_expansions(mut self, expansions: &[Expansion], source: &str) -> Self {
+        self.expanded_from = expansions
+            .iter()
+            .rev()
+            .map(|expansion| ExpandedFrom {
+                name: expansion.name.clone(),
+                position: find_call(source, &self.span, &expansion.call),
+                origin: expansion.origin.clone(),
+            })
+            .collect();
+        self
+    }
+
+    /// Prints the diagnostic to stderr, in color when stderr is a terminal.
+    #[cfg(feature = "std")]
+    pub fn emit(&self, name: &str, source: &str) {
+        eprint!("{}", self.render(name, source, io::stderr().is_terminal()));
+    }
+
+    pub fn render(&self, name: &str, source: &str, color: bool) -> String {
+        let paint = |style: &'static str| if color { style } else { "" };
+        let (red, blue, cyan, bold, reset) = (
+            paint(RED),
+            paint(BLUE),
+            paint(CYAN),
+            paint(BOLD),
+            paint(RESET),
+        );
+
+        let (label, accent) = match self.level {
+            Level::Error => ("error", red),
+            Level::Warning => ("warning", paint(YELLOW)),
+        };
+        let (line, column) = line_column(source, self.span.start);
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let text = &source[line_start..line_end];
+        let underline = source[start..self.span.end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+        let gutter = " ".repeat(line.to_string().len());
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{accent}{label}[{}]{reset}{bold}: {}{reset}",
+            self.code, self.message
+        );
+        let _ = writeln!(out, "{gutter}{blue}-->{reset} {}:{}:{}", name, line, column);
+        let _ = writeln!(out, "{gutter} {blue}|{reset}");
+        let _ = writeln!(out, "{blue}{line} |{reset} {}", text);
+        let _ = writeln!(
+            out,
+            "{gutter} {blue}|{reset} {}{accent}{}{reset}",
+            " ".repeat(column - 1),
+            "^".repeat(underline)
+        );
+        for expanded in &self.expanded_from {
+            let _ = write!(
+                out,
+                "{gutter} {blue}={reset} {cyan}note{reset}: expanded from `{}`",
+                expanded.name
+            );
+            match (expanded.position, &expanded.origin) {
+                (Some(position), _) => {
+                    let _ = write!(out, " at {}:{}", name, line_column(source, position).0);
+                }
+                (None, Some(Origin::Source { name, line })) => {
+                    let _ = write!(out, " at {}:{}", name, line);
+                }
+                (None, _) => {}
+            }
+            out.push('\n');
+        }
+        if let Some(hint) = self.hint {
+            let _ = writeln!(out, "{gutter} {blue}={reset} {cyan}hint{reset}: {}", hint);
+        }
+        out
+    }
+}
+
+/// Where `call` is written in `source`: preferably within `span`, the
+/// form being evaluated, else anywhere, as in the body of a function.
+fn find_call(source: &str, span: &Span, call: &Value) -> Option<usize> {
+    let Value::List(list) = call else {
+        return None;
+    };
+    let Some(Value::Symbol(head)) = list.first() else {
+        return None;
+    };
+    let mut scanner = Scanner::new(source);
+    let mut found = None;
+    let mut paren = None;
+    while let Ok(tok) = scanner.next_token() {
+        match tok.token {
+            Token::Symbol(symbol) if symbol == head => {
+                let start = paren.filter(|&start| {
+                    Parser::new(&mut Scanner::new(&source[start..]))
+                        .parse_expr()
+                        .is_ok_and(|expr| Value::from(&expr) == *call)
+                });
+                match start {
+                    Some(start) if span.contains(&start) => return Some(start),
+                    Some(_) => found = found.or(start),
+                    None => {}
+                }
+            }
+            Token::WhiteSpace(_) | Token::Comment(_) => continue,
+            _ => {}
+        }
+        paren = (tok.token == Token::LParen).then_some(tok.position);
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_points_at_span() {
+        let source = "(print 1)\n  (car 5)\n";
+        let diagnostic = Diagnostic::eval(
+            &EvalError::TypeMismatch {
+                expected: "list",
+                found: "number",
+            },
+            12..19,
+        );
+        assert_eq!(
+            diagnostic.render("a.jon", source, false),
+            "error[E0102]: Expected list, found number
+ --> a.jon:2:3
+  |
+2 |   (car 5)
+  |   ^^^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn test_render_end_of_input_with_hint() {
+        let source = "(print 1";
+        let diagnostic = Diagnostic::parse(&ParseError::Eof, source);
+        assert_eq!(
+            diagnostic.render("<eval>", source, false),
+            "error[E0001]: End of Input
+ --> <eval>:1:9
+  |
+1 | (print 1
+  |         ^
+  = hint: a closing parenthesis or quote may be missing
+"
+        );
+    }
+
+    #[test]
+    fn test_render_notes_macro_call_sites() {
+        let source = "(defmacro (check x) (list 'when x '(car 5)))
+(def (f n)
+  (check (> n 0)))
+(f 1)
+";
+        let mut interpreter = crate::eval::Interpreter::new();
+        let diagnostic = interpreter.eval_source(source).unwrap_err();
+        assert_eq!(
+            diagnostic.render("a.jon", source, false),
+            "error[E0102]: Expected list, found number
+ --> a.jon:4:1
+  |
+4 | (f 1)
+  | ^^^^^
+  = note: expanded from `when`
+  = note: expanded from `check` at a.jon:3
+"
+        );
+    }
+}
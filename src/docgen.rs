@@ -0,0 +1,207 @@
+//! Reference documentation for jon modules, collected from the docstrings
+//! of their top-level `def` and `defmacro` forms without running them.
+//!
+//! A definition is documented by its docstring, the leading string of a
+//! body that has more forms after it, or else by the `;` comments directly
+//! above it. Definitions with neither are listed with their signature only.
+
+use crate::formatter::{flat, parse_nodes, Node};
+use crate::parser::ParseError;
+
+/// What a documented definition defines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Function,
+    Macro,
+    Value,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Function => "function",
+            Kind::Macro => "macro",
+            Kind::Value => "value",
+        }
+    }
+}
+
+/// One top-level definition of a module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub kind: Kind,
+    /// The parameter list as written, such as `(map f xs)`, or the name of
+    /// a value.
+    pub signature: String,
+    pub doc: Option<String>,
+}
+
+/// The definitions of `source`, in the order they appear.
+pub fn entries(source: &str) -> Result<Vec<Entry>, ParseError> {
+    let mut entries = Vec::new();
+    let mut comments: Vec<&str> = Vec::new();
+    for node in parse_nodes(source)? {
+        match node {
+            Node::Comment {
+                text,
+                trailing: false,
+            } => {
+                comments.push(text.trim_start_matches(';').trim());
+                continue;
+            }
+            Node::Comment { trailing: true, .. } => continue,
+            Node::List(items) => {
+                if let Some(mut entry) = entry(&items) {
+                    if entry.doc.is_none() && !comments.is_empty() {
+                        entry.doc = Some(comments.join("\n"));
+                    }
+                    entries.push(entry);
+                }
+            }
+            _ => {}
+        }
+        comments.clear();
+    }
+    Ok(entries)
+}
+
+/// The entry for a `def` or `defmacro` form, or `None` for anything else.
+fn entry(items: &[Node]) -> Option<Entry> {
+    let head = match items.first()? {
+        Node::Atom(head) => *head,
+        _ => return None,
+    };
+    let (name, kind, signature) = match (head, items.get(1)?) {
+        ("def" | "defmacro", Node::List(params)) => {
+            let name = match params.first()? {
+                Node::Atom(name) => name.to_string(),
+                _ => return None,
+            };
+            let kind = if head == "def" {
+                Kind::Function
+            } else {
+                Kind::Macro
+            };
+            (name, kind, flat(&items[1])?)
+        }
+        ("def", Node::Atom(name)) => (name.to_string(), Kind::Value, name.to_string()),
+        _ => return None,
+    };
+    let body: Vec<&Node> = items[2..]
+        .iter()
+        .filter(|node| !matches!(node, Node::Comment { .. }))
+        .collect();
+    let doc = match body.as_slice() {
+        [Node::Atom(doc), _, ..] if doc.starts_with('"') => Some(doc[1..doc.len() - 1].to_string()),
+        _ => None,
+    };
+    Some(Entry {
+        name,
+        kind,
+        signature,
+        doc,
+    })
+}
+
+/// A Markdown page for `module`, with a section per entry.
+pub fn markdown(module: &str, entries: &[Entry]) -> String {
+    let mut out = format!("# {}\n", module);
+    for entry in entries {
+        out.push_str(&format!(
+            "\n## `{}`\n\n*{}* `{}`\n",
+            entry.name,
+            entry.kind.name(),
+            entry.signature
+        ));
+        if let Some(doc) = &entry.doc {
+            out.push_str(&format!("\n{}\n", doc));
+        }
+    }
+    out
+}
+
+/// A standalone HTML page for `module`, with the same content as
+/// [`markdown`].
+pub fn html(module: &str, entries: &[Entry]) -> String {
+    let module = escape(module);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body>\n<h1>{0}</h1>\n",
+        module
+    );
+    for entry in entries {
+        out.push_str(&format!(
+            "<h2 id=\"{0}\"><code>{0}</code></h2>\n<p><em>{1}</em> <code>{2}</code></p>\n",
+            escape(&entry.name),
+            entry.kind.name(),
+            escape(&entry.signature)
+        ));
+        if let Some(doc) = &entry.doc {
+            out.push_str(&format!("<p>{}</p>\n", escape(doc)));
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// A Markdown page linking each `(module, page)` in `links`.
+pub fn markdown_index(links: &[(String, String)]) -> String {
+    let mut out = String::from("# Modules\n\n");
+    for (module, page) in links {
+        out.push_str(&format!("- [{}]({})\n", module, page));
+    }
+    out
+}
+
+/// An HTML page linking each `(module, page)` in `links`.
+pub fn html_index(links: &[(String, String)]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Modules</title></head>\n<body>\n<h1>Modules</h1>\n<ul>\n",
+    );
+    for (module, page) in links {
+        out.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            escape(page),
+            escape(module)
+        ));
+    }
+    out.push_str("</ul>\n</body>\n</html>\n");
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries() {
+        let source = "; Adds one.\n; Twice, even.\n(def (add-two n) (+ n 2))\n\n\
+                      (def (square x) \"x times x.\" (* x x)) ; trailing\n\
+                      (def pi 3.14)\n\
+                      (defmacro (when test & body) \"Runs body when test holds.\" (list 'if test))\n\
+                      (print \"not a definition\")";
+        let entries = entries(source).unwrap();
+        let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["add-two", "square", "pi", "when"]);
+        assert_eq!(entries[0].doc.as_deref(), Some("Adds one.\nTwice, even."));
+        assert_eq!(entries[1].doc.as_deref(), Some("x times x."));
+        assert_eq!(entries[2].kind, Kind::Value);
+        assert_eq!(entries[2].doc, None);
+        assert_eq!(entries[3].kind, Kind::Macro);
+        assert_eq!(entries[3].signature, "(when test & body)");
+
+        let page = markdown("math", &entries[1..2]);
+        assert_eq!(
+            page,
+            "# math\n\n## `square`\n\n*function* `(square x)`\n\nx times x.\n"
+        );
+        assert!(html("math", &entries).contains("<code>(when test &amp; body)</code>"));
+    }
+}
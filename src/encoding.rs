@@ -0,0 +1,84 @@
+//! The text encodings `string->bytes` and `bytes->string` convert between
+//! strings and bytes with.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    /// ISO 8859-1, whose bytes are the first 256 code points.
+    Latin1,
+    Ascii,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    /// The encoding called `name`, which may be a keyword such as
+    /// `:latin-1`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.strip_prefix(':').unwrap_or(name) {
+            "utf-8" => Some(Encoding::Utf8),
+            "latin-1" => Some(Encoding::Latin1),
+            "ascii" => Some(Encoding::Ascii),
+            "utf-16le" => Some(Encoding::Utf16Le),
+            "utf-16be" => Some(Encoding::Utf16Be),
+            _ => None,
+        }
+    }
+
+    /// `s` in this encoding, or `None` if it has a character the encoding
+    /// lacks.
+    pub fn encode(self, s: &str) -> Option<Vec<u8>> {
+        match self {
+            Encoding::Utf8 => Some(s.as_bytes().to_vec()),
+            Encoding::Latin1 => s.chars().map(|c| u8::try_from(c).ok()).collect(),
+            Encoding::Ascii => s.is_ascii().then(|| s.as_bytes().to_vec()),
+            Encoding::Utf16Le => Some(s.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Some(s.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+        }
+    }
+
+    /// The string `bytes` encode, or `None` if they are not valid in this
+    /// encoding.
+    pub fn decode(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            Encoding::Latin1 => Some(bytes.iter().map(|&b| char::from(b)).collect()),
+            Encoding::Ascii => bytes
+                .is_ascii()
+                .then(|| bytes.iter().map(|&b| char::from(b)).collect()),
+            Encoding::Utf16Le => utf16(bytes, u16::from_le_bytes),
+            Encoding::Utf16Be => utf16(bytes, u16::from_be_bytes),
+        }
+    }
+}
+
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    char::decode_utf16(units).collect::<Result<_, _>>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips() {
+        let text = "naïve €";
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+            let bytes = encoding.encode(text).unwrap();
+            assert_eq!(encoding.decode(&bytes).as_deref(), Some(text));
+        }
+        assert_eq!(Encoding::Latin1.encode("ï"), Some(vec![0xef]));
+        assert_eq!(Encoding::Latin1.encode("€"), None);
+        assert_eq!(Encoding::Ascii.decode(&[0x80]), None);
+        assert_eq!(Encoding::Utf16Be.encode("a"), Some(vec![0, 0x61]));
+        assert_eq!(Encoding::Utf8.decode(&[0xff]), None);
+        assert_eq!(Encoding::from_name(":latin-1"), Some(Encoding::Latin1));
+    }
+}
@@ -0,0 +1,120 @@
+use alloc::{borrow::ToOwned, collections::BTreeMap, rc::Rc, string::String, vec::Vec};
+use core::cell::RefCell;
+
+use crate::value::Value;
+
+/// The names bound directly in an environment with their values, shared
+/// by copies until one of them changes.
+pub type Bindings = Rc<BTreeMap<String, Value>>;
+
+#[derive(Debug, Default)]
+pub struct Env {
+    vars: Bindings,
+    parent: Option<Rc<RefCell<Env>>>,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_parent(parent: Rc<RefCell<Env>>) -> Self {
+        Self {
+            vars: Bindings::default(),
+            parent: Some(parent),
+        }
+    }
+
+    /// The value bound to `name` here or in a parent. A qualified name
+    /// `ns/name` that is not bound itself names the member `name` of the
+    /// namespace bound to `ns`: an association list of exports such as
+    /// `((sqrt f) (pi 3.14))`.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.lookup(name).or_else(|| {
+            let (namespace, member) = split_qualified(name)?;
+            namespace_member(self.lookup(namespace)?, member)
+        })
+    }
+
+    fn lookup(&self, name: &str) -> Option<Value> {
+        match self.vars.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref().and_then(|p| p.borrow().lookup(name)),
+        }
+    }
+
+    pub fn define(&mut self, name: &str, value: Value) {
+        Rc::make_mut(&mut self.vars).insert(name.to_owned(), value);
+    }
+
+    /// The bindings made here, which stay as they are when the environment
+    /// changes.
+    pub fn bindings(&self) -> Bindings {
+        self.vars.clone()
+    }
+
+    /// Replaces the bindings made here with `bindings`.
+    pub fn set_bindings(&mut self, bindings: Bindings) {
+        self.vars = bindings;
+    }
+
+    pub fn parent(&self) -> Option<Rc<RefCell<Env>>> {
+        self.parent.clone()
+    }
+
+    /// The names bound directly in this environment, sorted.
+    pub fn names(&self) -> Vec<String> {
+        self.vars.keys().cloned().collect()
+    }
+}
+
+/// The namespace and member of a qualified name `ns/name`. Names starting
+/// or ending in `/`, such as `/` itself, are not qualified.
+pub fn split_qualified(name: &str) -> Option<(&str, &str)> {
+    let (namespace, member) = name.split_once('/')?;
+    (!namespace.is_empty() && !member.is_empty()).then_some((namespace, member))
+}
+
+/// The value exported as `member` by `namespace`, the second element of
+/// its pair whose first is the symbol or string `member`.
+fn namespace_member(namespace: Value, member: &str) -> Option<Value> {
+    let Value::List(exports) = namespace else {
+        return None;
+    };
+    exports.into_iter().find_map(|export| match export {
+        Value::List(pair) => match <[Value; 2]>::try_from(pair) {
+            Ok([Value::Symbol(key) | Value::String(key), value]) if key == member => Some(value),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_lookup_falls_back_to_parent() {
+        let parent = Rc::new(RefCell::new(Env::new()));
+        parent.borrow_mut().define("x", Value::Number(1.0));
+        let mut child = Env::with_parent(parent);
+        assert_eq!(child.get("x"), Some(Value::Number(1.0)));
+        child.define("x", Value::Number(2.0));
+        assert_eq!(child.get("x"), Some(Value::Number(2.0)));
+        assert_eq!(child.get("y"), None);
+    }
+
+    #[test]
+    fn test_qualified_names_look_in_namespaces() {
+        let mut env = Env::new();
+        let pi = Value::List(vec![Value::Symbol("pi".into()), Value::Number(3.0)]);
+        env.define("math", Value::List(vec![pi]));
+        env.define("math/e", Value::Number(2.0));
+        assert_eq!(env.get("math/pi"), Some(Value::Number(3.0)));
+        assert_eq!(env.get("math/e"), Some(Value::Number(2.0)));
+        assert_eq!(env.get("math/tau"), None);
+        assert_eq!(split_qualified("/"), None);
+        assert_eq!(split_qualified("a/b/c"), Some(("a", "b/c")));
+    }
+}
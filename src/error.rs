@@ -0,0 +1,73 @@
+#[cfg(feature = "serde")]
+use alloc::string::{String, ToString};
+
+use thiserror::Error;
+
+use crate::eval::EvalError;
+use crate::parser::ParseError;
+use crate::scanner::ScanError;
+
+/// Any failure of the pipeline from source text to a value. None of them
+/// borrow the source, and each keeps its cause as
+/// [`Error::source`](core::error::Error::source).
+#[derive(Error, Debug, PartialEq)]
+pub enum Error {
+    #[error(transparent)]
+    Scan(#[from] ScanError),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+}
+
+/// A failure of [`crate::to_string`] or [`crate::from_str`].
+#[cfg(feature = "serde")]
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum DataError {
+    #[error("{0}")]
+    Message(String),
+    #[error("End of Input")]
+    Eof,
+    /// Malformed input at a byte offset.
+    #[error("{1}")]
+    Syntax(usize, String),
+    #[error("jon cannot represent {0}")]
+    Unsupported(&'static str),
+}
+
+#[cfg(feature = "serde")]
+impl serde::ser::Error for DataError {
+    fn custom<T: core::fmt::Display>(msg: T) -> Self {
+        DataError::Message(msg.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for DataError {
+    fn custom<T: core::fmt::Display>(msg: T) -> Self {
+        DataError::Message(msg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::error::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn test_source_chain() {
+        let err = crate::eval_str("(1 [)").unwrap_err();
+        assert_eq!(
+            err,
+            Error::Parse(ParseError::Scan {
+                position: 3,
+                source: ScanError::UnexpectedChar('[')
+            })
+        );
+        assert_eq!(err.to_string(), "Unexpected character '['");
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), "Unexpected character '['");
+        assert!(source.source().is_none());
+    }
+}
@@ -1,85 +1,2209 @@
-pub struct Evaluator;
-use crate::parser::{Atom, Expr};
-
-fn is_operator(expr: &Expr) -> bool {
-    if let Expr::Atom(Atom::Symbol(y)) = expr {
-        y == "+" || y == "-" || y == "*" || y == "/"
-    } else {
-        false
-    }
-}
-
-impl Evaluator {
-    pub fn eval(&self, expr: &Expr) -> Atom {
-        match expr {
-            Expr::Atom(Atom::Symbol(x)) => Atom::Symbol(x.to_owned()),
-            Expr::Atom(Atom::Number(x)) => Atom::Number(*x),
-            Expr::Atom(Atom::String(x)) => Atom::String(x.to_owned()),
-            Expr::List(list) => {
-                if list.len() <= 1 {
-                    unimplemented!("later")
-                };
-                match &list[0] {
-                    Expr::List(_) => unimplemented!("later"),
-                    atom if is_operator(atom) => {
-                        let Expr::Atom(Atom::Symbol(op)) = atom else {
-                            unreachable!("")
-                        };
-                        match op.as_str() {
-                            "+" => Atom::Number(list.iter().skip(1).map(|e| self.eval(e)).fold(
-                                0.0,
-                                |result, expr| {
-                                    if let Atom::Number(n) = expr {
-                                        result + n
-                                    } else {
-                                        result
-                                    }
-                                },
-                            )),
-
-                            "-" => Atom::Number(list.iter().skip(1).map(|e| self.eval(e)).fold(
-                                0.0,
-                                |result, expr| {
-                                    if let Atom::Number(n) = expr {
-                                        result - n
-                                    } else {
-                                        result
-                                    }
-                                },
-                            )),
-
-                            _ => todo!("later"),
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::cell::RefCell;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use std::io::{self, BufRead, Write};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::builtins;
+#[cfg(feature = "std")]
+use crate::cache::ParseCache;
+use crate::capability::Capability;
+use crate::convert::{FromValue, IntoValue, IntoValueList};
+use crate::diagnostic::Diagnostic;
+use crate::env::{Bindings, Env};
+use crate::error::Error;
+use crate::float_format::FloatFormat;
+#[cfg(feature = "std")]
+use crate::image::{self, ImageError};
+use crate::isolated::{Copier, Isolated};
+#[cfg(feature = "std")]
+use crate::logging::{self, Logger, Record};
+use crate::parser::{Expr, Parser};
+use crate::scanner::Scanner;
+use crate::value::{Builtin, Lambda, Value};
+
+const PRELUDE: &str = include_str!("prelude.jon");
+
+/// How deeply function calls may nest unless the host sets another limit
+/// with [`Interpreter::set_max_depth`].
+pub const DEFAULT_MAX_DEPTH: usize = 1_000;
+
+/// The stack a thread needs for calls nested [`DEFAULT_MAX_DEPTH`] deep,
+/// with room for the forms inside each. A debug build takes several times
+/// the stack per call that a release build does, more than the 8 MiB of a
+/// main thread.
+pub const STACK_SIZE: usize = if cfg!(debug_assertions) {
+    64 << 20
+} else {
+    16 << 20
+};
+
+/// How many of the innermost calls a stack overflow names.
+const TRACE_LENGTH: usize = 8;
+
+/// Forms handled by the evaluator itself rather than bound in the environment.
+pub const SPECIAL_FORMS: &[&str] = &[
+    "quote",
+    "if",
+    "def",
+    "lambda",
+    "defmacro",
+    "do",
+    "let",
+    "and",
+    "or",
+    "loop",
+    "recur",
+    "handler-case",
+    "unwind-protect",
+    "bindings",
+];
+
+/// Whether the symbol `name` is a keyword such as `:base`, which evaluates
+/// to itself and names an option.
+pub fn is_keyword(name: &str) -> bool {
+    name.len() > 1 && name.starts_with(':')
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum EvalError {
+    #[error("Unbound symbol {0}")]
+    UnboundSymbol(String),
+    #[error("Expected {expected}, found {found}")]
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[error("{name} expects {expected} arguments, got {found}")]
+    ArityMismatch {
+        name: String,
+        expected: String,
+        found: usize,
+    },
+    #[error("{0} is not callable")]
+    NotCallable(String),
+    #[error("Malformed {0} form")]
+    MalformedForm(&'static str),
+    #[error("recur outside the tail position of a loop")]
+    MisplacedRecur,
+    /// A condition signalled with `raise`, with its type tag and its data
+    /// as printed.
+    #[error("{tag}: {message}")]
+    Condition { tag: String, message: String },
+    /// Whole numbers whose sum, difference, or product is too large to
+    /// hold exactly.
+    #[error("{0} overflowed: the result is not an exact whole number")]
+    Overflow(&'static str),
+    #[error("I/O error: {0}")]
+    Io(String),
+    /// A failure reported by a function the host registered.
+    #[error("{0}")]
+    Host(String),
+    /// The host set the interrupt flag, as the REPL does on Ctrl-C.
+    #[error("Interrupted")]
+    Interrupted,
+    /// A builtin needing a capability the host denied.
+    #[error("{name} needs the {capability} capability, which is denied")]
+    Denied {
+        name: String,
+        capability: Capability,
+    },
+    /// More calls were nested than the interpreter allows, with the names
+    /// of the innermost ones, innermost first.
+    #[error("Stack overflow: calls nested deeper than {limit}, in {}", .trace.join(" <- "))]
+    StackOverflow { limit: usize, trace: Vec<String> },
+    #[error("Evaluation aborted by an internal error")]
+    Internal,
+}
+
+impl EvalError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            EvalError::UnboundSymbol(_) => "E0101",
+            EvalError::TypeMismatch { .. } => "E0102",
+            EvalError::ArityMismatch { .. } => "E0103",
+            EvalError::NotCallable(_) => "E0104",
+            EvalError::MalformedForm(_) => "E0105",
+            EvalError::MisplacedRecur => "E0108",
+            EvalError::Condition { .. } => "E0109",
+            EvalError::Overflow(_) => "E0110",
+            EvalError::Io(_) => "E0106",
+            EvalError::Host(_) => "E0107",
+            EvalError::Interrupted => "E0111",
+            EvalError::Denied { .. } => "E0112",
+            EvalError::StackOverflow { .. } => "E0113",
+            EvalError::Internal => "E0199",
+        }
+    }
+
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            EvalError::UnboundSymbol(_) => Some("define it with def before using it"),
+            EvalError::MisplacedRecur => {
+                Some("recur must be the last form a loop body evaluates, as in (if done result (recur ...))")
+            }
+            EvalError::NotCallable(_) => {
+                Some("the first element of a list is called as a function; quote the list to use it as data")
+            }
+            EvalError::Overflow(_) => {
+                Some("numbers are 32-bit floats, exact for whole numbers up to 16777216")
+            }
+            EvalError::StackOverflow { .. } => {
+                Some("recursion that is not needed can often be a loop with recur instead")
+            }
+            _ => None,
+        }
+    }
+
+    /// The condition type `handler-case` catches the error as, or `None`
+    /// for an internal error, which cannot be caught.
+    pub fn condition_type(&self) -> Option<&str> {
+        match self {
+            EvalError::UnboundSymbol(_) => Some("unbound-symbol"),
+            EvalError::TypeMismatch { .. } | EvalError::NotCallable(_) => Some("type-error"),
+            EvalError::ArityMismatch { .. } => Some("arity-error"),
+            EvalError::MalformedForm(_) | EvalError::MisplacedRecur => Some("syntax-error"),
+            EvalError::Io(_) => Some("io-error"),
+            EvalError::Overflow(_) => Some("overflow-error"),
+            EvalError::Host(_) => Some("host-error"),
+            EvalError::Denied { .. } => Some("permission-error"),
+            EvalError::StackOverflow { .. } => Some("stack-overflow"),
+            EvalError::Condition { tag, .. } => Some(tag),
+            EvalError::Interrupted | EvalError::Internal => None,
+        }
+    }
+}
+
+/// A condition of type `tag`: the record `((:type tag) (:data data))`.
+pub fn condition(tag: &str, data: Value) -> Value {
+    let field = |key: &str, value| Value::List(vec![Value::Symbol(key.into()), value]);
+    Value::List(vec![
+        field(":type", Value::Symbol(tag.into())),
+        field(":data", data),
+    ])
+}
+
+/// Where a global definition comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origin {
+    Builtin,
+    Prelude,
+    Source { name: String, line: usize },
+}
+
+/// A macro call whose expansion is being evaluated: the macro, the call
+/// as written, and where the top-level form evaluating it comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+    pub name: String,
+    pub call: Value,
+    pub origin: Option<Origin>,
+}
+
+/// What `:doc` knows about a global: its docstring, the leading string of a
+/// `def` or `defmacro` body, and where it was defined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Doc {
+    pub docstring: Option<String>,
+    pub origin: Option<Origin>,
+}
+
+/// The globals, their docs, and the traced functions as they were when
+/// [`Interpreter::snapshot`] was called.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    globals: Bindings,
+    docs: BTreeMap<String, Doc>,
+    traced: BTreeSet<String>,
+}
+
+/// The calls being evaluated when an error was raised, outermost first,
+/// as [`Interpreter::on_error`] handlers see them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CallStack {
+    pub frames: Vec<Frame>,
+}
+
+/// A call of a function: its name, or `lambda` for an anonymous one, and
+/// its evaluated arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub args: Vec<Value>,
+}
+
+impl fmt::Display for CallStack {
+    /// The calls innermost first, one per line, as they would be written.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for frame in self.frames.iter().rev() {
+            write!(f, "({}", frame.function)?;
+            for arg in &frame.args {
+                write!(f, " {}", arg)?;
+            }
+            writeln!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// What becomes of an error once the host's error handler has seen it.
+#[derive(Debug, PartialEq)]
+pub enum ErrorAction {
+    /// The error propagates as it is.
+    Propagate,
+    /// This error propagates instead.
+    Replace(EvalError),
+    /// This value is returned instead, by the innermost call to a function
+    /// the error was raised in or, for an error outside any call such as an
+    /// unbound symbol, by the whole top-level form.
+    Suppress(Value),
+}
+
+/// Sees each error before it propagates; installed with
+/// [`Interpreter::on_error`].
+type ErrorHandler = Box<dyn FnMut(&EvalError, &CallStack) -> ErrorAction>;
+
+/// Observes evaluation at form and call boundaries; installed with
+/// `Interpreter::add_hook`. Hooks may evaluate code themselves, which does
+/// not reach any hook.
+pub trait Hook {
+    /// Called before a list form is evaluated in `env`.
+    fn before_form(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _form: &Value,
+        _env: &Rc<RefCell<Env>>,
+    ) {
+    }
+
+    /// Called when `callee` is applied to evaluated `args`.
+    fn enter_call(&mut self, _interpreter: &mut Interpreter, _callee: &Value, _args: &[Value]) {}
+
+    /// Called when a call entered with `enter_call` finishes.
+    fn exit_call(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _callee: &Value,
+        _result: &Result<Value, EvalError>,
+    ) {
+    }
+
+    /// Called when `Interpreter::load_file` has read the file at `path`,
+    /// before evaluating it.
+    fn loaded(&mut self, _interpreter: &mut Interpreter, _path: &str) {}
+}
+
+/// Where `print` writes.
+#[cfg(feature = "std")]
+type Output = Box<dyn Write>;
+#[cfg(not(feature = "std"))]
+type Output = Box<dyn fmt::Write>;
+
+#[cfg(feature = "std")]
+fn default_output() -> Output {
+    Box::new(io::stdout())
+}
+
+/// Without std there is no standard output, so `print` output is dropped.
+#[cfg(not(feature = "std"))]
+fn default_output() -> Output {
+    struct Discard;
+
+    impl fmt::Write for Discard {
+        fn write_str(&mut self, _: &str) -> fmt::Result {
+            Ok(())
+        }
+    }
+
+    Box::new(Discard)
+}
+
+pub struct Interpreter {
+    global: Rc<RefCell<Env>>,
+    hooks: Vec<Box<dyn Hook>>,
+    /// Docs of the globals defined by `def` and `defmacro`.
+    docs: BTreeMap<String, Doc>,
+    /// Where the forms being evaluated come from, if known.
+    origin: Option<Origin>,
+    /// Names of the functions marked with `(trace f)`.
+    traced: BTreeSet<String>,
+    output: Output,
+    /// Where `read-line` reads, or standard input when `None`.
+    #[cfg(feature = "std")]
+    input: Option<Box<dyn BufRead>>,
+    /// Where `load` keeps the forms of the files it parses, if anywhere.
+    #[cfg(feature = "std")]
+    parse_cache: Option<ParseCache>,
+    /// The directories `load` looks in for a relative path that is not
+    /// found from the current directory.
+    #[cfg(feature = "std")]
+    load_path: Vec<PathBuf>,
+    /// Where `log/info` and the other logging builtins write.
+    #[cfg(feature = "std")]
+    logger: Logger,
+    /// The condition last signalled with `raise`, whose error carries only
+    /// its printed data, for `handler-case` to catch.
+    raised: Option<Value>,
+    /// The macro calls whose expansions are being evaluated, outermost
+    /// first.
+    expansions: Vec<Expansion>,
+    /// What `expansions` was when the last uncaught error was raised.
+    failed_expansions: Vec<Expansion>,
+    /// Set by the host to stop the evaluation, if it can.
+    interrupt: Option<Arc<AtomicBool>>,
+    /// The capabilities the builtins may not use.
+    denied: BTreeSet<Capability>,
+    error_handler: Option<ErrorHandler>,
+    /// The calls being evaluated, tracked only while there is an error
+    /// handler to show them to.
+    calls: CallStack,
+    /// Whether the error propagating now has been given to the handler.
+    error_handled: bool,
+    /// How many function calls are being evaluated.
+    depth: usize,
+    /// How many may be, before a call fails with
+    /// [`EvalError::StackOverflow`].
+    max_depth: usize,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// Creates an interpreter with the builtins and the prelude loaded.
+    pub fn new() -> Self {
+        let mut interpreter = Self::bare();
+        interpreter.load_prelude();
+        interpreter
+    }
+
+    /// Creates an interpreter with only the native builtins.
+    pub fn bare() -> Self {
+        let mut global = Env::new();
+        builtins::install(&mut global);
+        Self {
+            global: Rc::new(RefCell::new(global)),
+            hooks: Vec::new(),
+            docs: BTreeMap::new(),
+            origin: None,
+            traced: BTreeSet::new(),
+            output: default_output(),
+            #[cfg(feature = "std")]
+            input: None,
+            #[cfg(feature = "std")]
+            parse_cache: None,
+            #[cfg(feature = "std")]
+            load_path: Vec::new(),
+            #[cfg(feature = "std")]
+            logger: Logger::default(),
+            raised: None,
+            expansions: Vec::new(),
+            failed_expansions: Vec::new(),
+            interrupt: None,
+            denied: BTreeSet::new(),
+            error_handler: None,
+            calls: CallStack::default(),
+            error_handled: false,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// A deep copy of the interpreter's globals, docs, and traced functions
+    /// that can be moved to another thread. Hooks are left out, the ports
+    /// are standard input and output again, and logs go to standard error
+    /// at the same minimum level. The copy shares the interrupt flag and
+    /// is denied the same capabilities.
+    pub fn clone_isolated(&self) -> Isolated {
+        self.clone_isolated_with(&[])
+    }
+
+    /// Like [`Interpreter::clone_isolated`], also copying `values` into the
+    /// new interpreter's world, where they share its environments.
+    pub(crate) fn clone_isolated_with(&self, values: &[Value]) -> Isolated {
+        let mut copier = Copier::default();
+        let interpreter = Self {
+            global: copier.env(&self.global),
+            hooks: Vec::new(),
+            docs: self.docs.clone(),
+            origin: self.origin.clone(),
+            traced: self.traced.clone(),
+            output: default_output(),
+            #[cfg(feature = "std")]
+            input: None,
+            #[cfg(feature = "std")]
+            parse_cache: self.parse_cache.clone(),
+            #[cfg(feature = "std")]
+            load_path: self.load_path.clone(),
+            #[cfg(feature = "std")]
+            logger: Logger {
+                level: self.logger.level,
+                ..Logger::default()
+            },
+            raised: None,
+            expansions: Vec::new(),
+            failed_expansions: Vec::new(),
+            interrupt: self.interrupt.clone(),
+            denied: self.denied.clone(),
+            error_handler: None,
+            calls: CallStack::default(),
+            error_handled: false,
+            depth: 0,
+            max_depth: self.max_depth,
+        };
+        let values = values.iter().map(|value| copier.value(value)).collect();
+        Isolated::new(interpreter, values)
+    }
+
+    /// Evaluates `source` like [`Interpreter::eval_str`], in an isolated
+    /// copy on tokio's blocking thread pool, so that a long evaluation does
+    /// not hold up the executor. The future is `Send`, for use in request
+    /// handlers; definitions made by `source` are not kept. It must be
+    /// awaited within a tokio runtime.
+    #[cfg(feature = "async")]
+    pub fn eval_async<T>(
+        &self,
+        source: &str,
+    ) -> impl core::future::Future<Output = Result<T, Error>> + Send + 'static
+    where
+        T: FromValue + Send + 'static,
+    {
+        let isolated = self.clone_isolated();
+        let source = source.to_string();
+        async move {
+            let task = tokio::task::spawn_blocking(move || {
+                let value = isolated.into_inner().eval_str(&source)?;
+                Ok(T::from_value(value)?)
+            });
+            match task.await {
+                Ok(result) => result,
+                Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+                Err(_) => Err(EvalError::Host("evaluation was cancelled".into()).into()),
+            }
+        }
+    }
+
+    /// Sends what `print` writes to `output` instead of standard output.
+    #[cfg(feature = "std")]
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// Sends what `print` writes to `output` instead of discarding it.
+    #[cfg(not(feature = "std"))]
+    pub fn set_output(&mut self, output: impl fmt::Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// Makes `read-line` read from `input` instead of standard input.
+    #[cfg(feature = "std")]
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.input = Some(Box::new(input));
+    }
+
+    /// Makes evaluation fail with [`EvalError::Interrupted`] at the next
+    /// form it starts once `flag` is set, from a signal handler or another
+    /// thread. The flag is cleared when it takes effect, so that code
+    /// cleaning up in `unwind-protect` still runs.
+    pub fn set_interrupt(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+
+    /// Limits how deeply function calls may nest, so that runaway recursion
+    /// fails with [`EvalError::StackOverflow`] before the thread runs out
+    /// of stack. The default, [`DEFAULT_MAX_DEPTH`], fits in a thread of
+    /// [`STACK_SIZE`], as the `jon` binary and `pmap` and actor threads
+    /// evaluate on; a host evaluating on a thread with less stack should
+    /// lower it.
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    /// How many function calls are being evaluated, as `stack-depth`
+    /// returns.
+    pub fn stack_depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Makes the builtins needing `capability` fail with
+    /// [`EvalError::Denied`], for evaluating code that is not trusted.
+    /// There is no way to allow it again.
+    pub fn deny(&mut self, capability: Capability) {
+        self.denied.insert(capability);
+    }
+
+    pub fn is_denied(&self, capability: Capability) -> bool {
+        self.denied.contains(&capability)
+    }
+
+    /// Calls `handler` with each error where it is raised, before it
+    /// propagates, and with the calls it was raised in. What the handler
+    /// returns decides whether the error goes on, is replaced, or is
+    /// suppressed. An error `handler-case` catches is seen all the same.
+    /// Replaces any handler installed before.
+    pub fn on_error(
+        &mut self,
+        handler: impl FnMut(&EvalError, &CallStack) -> ErrorAction + 'static,
+    ) {
+        self.error_handler = Some(Box::new(handler));
+    }
+
+    /// Gives the error of `result`, if it has not been already, to the
+    /// error handler, and does what it decides.
+    fn handle_error(&mut self, result: Result<Value, EvalError>) -> Result<Value, EvalError> {
+        let Err(err) = result else {
+            return result;
+        };
+        if self.error_handled {
+            return Err(err);
+        }
+        let Some(mut handler) = self.error_handler.take() else {
+            return Err(err);
+        };
+        let action = handler(&err, &self.calls);
+        self.error_handler.get_or_insert(handler);
+        match action {
+            ErrorAction::Propagate => {
+                self.error_handled = true;
+                Err(err)
+            }
+            ErrorAction::Replace(err) => {
+                self.error_handled = true;
+                Err(err)
+            }
+            ErrorAction::Suppress(value) => Ok(value),
+        }
+    }
+
+    /// Fails with [`EvalError::Interrupted`] if the interrupt flag is set.
+    fn check_interrupt(&self) -> Result<(), EvalError> {
+        match &self.interrupt {
+            Some(flag) if flag.swap(false, Ordering::Relaxed) => Err(EvalError::Interrupted),
+            _ => Ok(()),
+        }
+    }
+
+    /// Drops log records below `level`, which is info at first.
+    #[cfg(feature = "std")]
+    pub fn set_log_level(&mut self, level: logging::Level) {
+        self.logger.level = level;
+    }
+
+    /// Sends log records to `target` instead of standard error.
+    #[cfg(feature = "std")]
+    pub fn set_log_target(&mut self, target: logging::Target) {
+        self.logger.target = target;
+    }
+
+    /// Logs `record` unless it is below the minimum level.
+    #[cfg(feature = "std")]
+    pub fn log(&mut self, record: &Record) -> Result<(), EvalError> {
+        self.logger
+            .log(record)
+            .map_err(|err| EvalError::Io(err.to_string()))
+    }
+
+    /// The interpreter's globals, with their docs and the traced
+    /// functions, as an image that [`Interpreter::load_image`] restores.
+    /// Fails if a global holds a host value or an actor.
+    #[cfg(feature = "std")]
+    pub fn save_image(&self) -> Result<Vec<u8>, ImageError> {
+        image::save(&image::Parts {
+            global: self.global.clone(),
+            docs: self.docs.clone(),
+            traced: self.traced.clone(),
+        })
+    }
+
+    /// Replaces the globals, docs, and traced functions with those of an
+    /// image made by [`Interpreter::save_image`]. Builtins in it are looked
+    /// up among the current globals, so host functions must be registered
+    /// first; those registered after come after the image's globals too.
+    #[cfg(feature = "std")]
+    pub fn load_image(&mut self, bytes: &[u8]) -> Result<(), ImageError> {
+        let parts = image::load(bytes, &self.global.borrow())?;
+        self.global = parts.global;
+        self.docs = parts.docs;
+        self.traced = parts.traced;
+        Ok(())
+    }
+
+    /// Makes `load` keep the forms it parses in `cache`, or parse every
+    /// time with `None`.
+    #[cfg(feature = "std")]
+    pub fn set_parse_cache(&mut self, cache: Option<ParseCache>) {
+        self.parse_cache = cache;
+    }
+
+    /// Makes `load` look for a relative path in each of `dirs` in turn
+    /// when it is not found from the current directory.
+    #[cfg(feature = "std")]
+    pub fn set_load_path(&mut self, dirs: Vec<PathBuf>) {
+        self.load_path = dirs;
+    }
+
+    /// Evaluates the forms of the file at `path` in the global environment
+    /// like `load`, returning the value of the last one. A file that does
+    /// not parse raises a `parse-error` condition.
+    #[cfg(feature = "std")]
+    pub fn load_file(&mut self, path: &str) -> Result<Value, EvalError> {
+        let found = match Path::new(path) {
+            file if file.is_relative() && !file.exists() => self
+                .load_path
+                .iter()
+                .map(|dir| dir.join(file))
+                .find(|file| file.exists()),
+            _ => None,
+        };
+        let found = found.map(|file| file.to_string_lossy().into_owned());
+        let path = found.as_deref().unwrap_or(path);
+        let source = std::fs::read_to_string(path)
+            .map_err(|err| EvalError::Io(format!("cannot read {}: {}", path, err)))?;
+        let parsed = match &self.parse_cache {
+            Some(cache) => cache.parse(&source),
+            None => Parser::new(&mut Scanner::new(&source)).parse_forms(),
+        };
+        let forms = match parsed {
+            Ok(forms) => forms,
+            Err(err) => {
+                let position = err.span().map_or(source.len(), |span| span.start);
+                let (line, column) = crate::scanner::line_column(&source, position);
+                let message = format!("{}:{}:{}: {}", path, line, column, err);
+                return Err(self.raise("parse-error", Value::String(message)));
+            }
+        };
+        self.run_hooks(|hook, interpreter| hook.loaded(interpreter, path));
+        let origin = self.origin.clone();
+        let mut result = Ok(Value::Nil);
+        for (span, expr) in forms {
+            self.set_origin(path, crate::scanner::line_column(&source, span.start).0);
+            result = self.eval(&expr);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.origin = origin;
+        result
+    }
+
+    /// Writes `line` and a newline to the output port.
+    pub fn write_line(&mut self, line: &str) -> Result<(), EvalError> {
+        let written = writeln!(self.output, "{}", line);
+        #[cfg(feature = "std")]
+        let written = written.and_then(|()| self.output.flush());
+        written.map_err(|err| EvalError::Io(err.to_string()))
+    }
+
+    /// Reads a line from the input port without its line ending, or `None`
+    /// at the end of the input.
+    #[cfg(feature = "std")]
+    pub fn read_line(&mut self) -> Result<Option<String>, EvalError> {
+        match &mut self.input {
+            Some(input) => read_line_from(input),
+            None => read_line_from(&mut io::stdin().lock()),
+        }
+    }
+
+    /// Without std there is no input, so this is always its end.
+    #[cfg(not(feature = "std"))]
+    pub fn read_line(&mut self) -> Result<Option<String>, EvalError> {
+        Ok(None)
+    }
+
+    /// The value bound to `name` in the global environment.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.global.borrow().get(name)
+    }
+
+    /// Binds `name` to `value`, a jon value or a host one converted with
+    /// [`IntoValue`], in the global environment.
+    pub fn set_global(&mut self, name: &str, value: impl IntoValue) {
+        self.global.borrow_mut().define(name, value.into_value());
+    }
+
+    /// The global `name` converted to a host type, for reading results
+    /// back after evaluation.
+    pub fn global<T: FromValue>(&self, name: &str) -> Result<T, EvalError> {
+        let value = self
+            .get_global(name)
+            .ok_or_else(|| EvalError::UnboundSymbol(name.into()))?;
+        T::from_value(value)
+    }
+
+    /// Calls the global function `name` with `args`, such as `(1, "two")`,
+    /// converted to jon values, so that the host can run callbacks that
+    /// scripts define.
+    pub fn call(&mut self, name: &str, args: impl IntoValueList) -> Result<Value, Error> {
+        self.error_handled = false;
+        let callee = self
+            .get_global(name)
+            .ok_or_else(|| EvalError::UnboundSymbol(name.into()));
+        let result = match callee {
+            Ok(callee) => self.apply(&callee, args.into_value_list()),
+            Err(err) => self.handle_error(Err(err)),
+        };
+        Ok(result?)
+    }
+
+    /// Like [`Interpreter::call`], converting the result to a host type.
+    pub fn call_as<T: FromValue>(
+        &mut self,
+        name: &str,
+        args: impl IntoValueList,
+    ) -> Result<T, Error> {
+        let result = self.call(name, args)?;
+        Ok(T::from_value(result)?)
+    }
+
+    /// Binds `name` to a native function or closure, which scripts call
+    /// like a builtin.
+    pub fn register_fn<F>(&mut self, name: &'static str, func: F)
+    where
+        F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, EvalError> + Send + Sync + 'static,
+    {
+        let func = Arc::new(func);
+        let builtin = Builtin {
+            name,
+            func,
+            pure: false,
+        };
+        self.set_global(name, Value::Builtin(builtin));
+    }
+
+    /// How floats print for people, as `*float-format*` says, or an error
+    /// if it does not hold a format.
+    pub fn float_format(&self) -> Result<FloatFormat, EvalError> {
+        let value = self.get_global("*float-format*").unwrap_or(Value::Nil);
+        FloatFormat::from_value(&value).ok_or_else(|| {
+            EvalError::Host(format!(
+                "*float-format* must be nil or options such as (:digits 6 :notation :fixed), not {}",
+                value
+            ))
+        })
+    }
+
+    /// Sets `*float-format*` to `format`.
+    pub fn set_float_format(&mut self, format: FloatFormat) {
+        self.set_global("*float-format*", format.to_value());
+    }
+
+    /// The globals as they are now, for [`Interpreter::restore`]. Taking
+    /// one is cheap: the bindings are shared until they change.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            globals: self.global.borrow().bindings(),
+            docs: self.docs.clone(),
+            traced: self.traced.clone(),
+        }
+    }
+
+    /// Puts the globals, their docs, and the traced functions back as they
+    /// were at `snapshot`. Effects outside the interpreter, such as output
+    /// and actors started, stay.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.global
+            .borrow_mut()
+            .set_bindings(snapshot.globals.clone());
+        self.docs = snapshot.docs.clone();
+        self.traced = snapshot.traced.clone();
+    }
+
+    /// The globals bound or rebound since `snapshot`, sorted.
+    pub fn changed_since(&self, snapshot: &Snapshot) -> Vec<String> {
+        let now = self.global.borrow().bindings();
+        if Rc::ptr_eq(&now, &snapshot.globals) {
+            return Vec::new();
+        }
+        now.iter()
+            .filter(|&(name, value)| snapshot.globals.get(name) != Some(value))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// The names bound in the global environment with their values, sorted
+    /// by name.
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        let global = self.global.borrow();
+        global
+            .names()
+            .into_iter()
+            .map(|name| {
+                let value = global.get(&name).unwrap();
+                (name, value)
+            })
+            .collect()
+    }
+
+    pub fn add_hook(&mut self, hook: Box<dyn Hook>) {
+        self.hooks.push(hook);
+    }
+
+    /// Runs `f` on every hook. Hooks are detached meanwhile, so that code
+    /// they evaluate is not observed.
+    fn run_hooks(&mut self, mut f: impl FnMut(&mut dyn Hook, &mut Interpreter)) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        let mut hooks = core::mem::take(&mut self.hooks);
+        for hook in hooks.iter_mut() {
+            f(hook.as_mut(), self);
+        }
+        hooks.append(&mut self.hooks);
+        self.hooks = hooks;
+    }
+
+    /// The macro calls whose expansions are being evaluated, outermost
+    /// first.
+    pub fn expansions(&self) -> &[Expansion] {
+        &self.expansions
+    }
+
+    /// The macro calls, outermost first, whose expansions the last error
+    /// not caught by `handler-case` was raised in, such as the `when` whose
+    /// body called `car` on a number.
+    pub fn error_expansions(&self) -> &[Expansion] {
+        &self.failed_expansions
+    }
+
+    /// Marks the function `name` for tracing, or unmarks it.
+    pub fn set_traced(&mut self, name: &str, traced: bool) {
+        if traced {
+            self.traced.insert(name.into());
+        } else {
+            self.traced.remove(name);
+        }
+    }
+
+    pub fn is_traced(&self, name: &str) -> bool {
+        self.traced.contains(name)
+    }
+
+    /// Whether `env` is the global environment.
+    pub fn is_global(&self, env: &Rc<RefCell<Env>>) -> bool {
+        Rc::ptr_eq(env, &self.global)
+    }
+
+    /// Records that the following forms come from line `line` of `name`,
+    /// for the docs of what they define.
+    pub fn set_origin(&mut self, name: &str, line: usize) {
+        self.origin = Some(Origin::Source {
+            name: name.into(),
+            line,
+        });
+    }
+
+    /// The docs of the global `name`, if it is a builtin or was defined
+    /// with `def` or `defmacro`.
+    pub fn doc(&self, name: &str) -> Option<Doc> {
+        if let Some(doc) = self.docs.get(name) {
+            return Some(doc.clone());
+        }
+        match self.get_global(name)? {
+            Value::Builtin(builtin) => Some(Doc {
+                docstring: builtins::doc(builtin.name).map(|(_, doc)| doc.into()),
+                origin: Some(Origin::Builtin),
+            }),
+            _ => None,
+        }
+    }
+
+    fn load_prelude(&mut self) {
+        let mut scanner = Scanner::new(PRELUDE);
+        let mut parser = Parser::new(&mut scanner);
+        let program = parser.parse_program().expect("prelude should parse");
+        self.origin = Some(Origin::Prelude);
+        for expr in &program {
+            self.eval(expr).expect("prelude should evaluate");
+        }
+        self.origin = None;
+    }
+
+    /// Stores the docs of `name` when it is defined in the global
+    /// environment.
+    fn record_doc(&mut self, name: &str, body: &[Value], env: &Rc<RefCell<Env>>) {
+        if !self.is_global(env) {
+            return;
+        }
+        let docstring = match body {
+            [Value::String(doc), _, ..] => Some(doc.clone()),
+            _ => None,
+        };
+        self.docs.insert(
+            name.into(),
+            Doc {
+                docstring,
+                origin: self.origin.clone(),
+            },
+        );
+    }
+
+    /// Scans, parses and evaluates every form of `source` in order,
+    /// returning the value of the last one, or nil if there is none. The
+    /// forms are parsed borrowing from `source`, so the text of each atom
+    /// is copied only into the value that is evaluated.
+    pub fn eval_str(&mut self, source: &str) -> Result<Value, Error> {
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(&mut scanner);
+        let forms = parser.parse_borrowed_forms()?;
+        let mut result = Value::Nil;
+        for (_, expr) in forms {
+            result = self.eval_top(&Value::from(expr))?;
+        }
+        Ok(result)
+    }
+
+    /// Like [`Interpreter::eval_str`], but a failure is a diagnostic that
+    /// points at the form raising it, ready to render against `source`.
+    pub fn eval_source(&mut self, source: &str) -> Result<Value, Diagnostic> {
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(&mut scanner);
+        let forms = parser
+            .parse_borrowed_forms()
+            .map_err(|err| Diagnostic::parse(&err, source))?;
+        let mut result = Value::Nil;
+        for (span, expr) in forms {
+            result = self.eval_top(&Value::from(expr)).map_err(|err| {
+                Diagnostic::eval(&err, span.clone())
+                    .with_expansions(&self.failed_expansions, source)
+            })?;
+        }
+        Ok(result)
+    }
+
+    pub fn eval(&mut self, expr: &Expr) -> Result<Value, EvalError> {
+        self.eval_top(&Value::from(expr))
+    }
+
+    /// Evaluates a top-level form, as [`Interpreter::eval`] does.
+    fn eval_top(&mut self, form: &Value) -> Result<Value, EvalError> {
+        self.failed_expansions.clear();
+        self.error_handled = false;
+        let result = self.eval_form(form);
+        self.handle_error(result)
+    }
+
+    /// Evaluates code held as a value, such as a macro expansion, in the
+    /// global environment.
+    pub fn eval_form(&mut self, form: &Value) -> Result<Value, EvalError> {
+        let env = self.global.clone();
+        self.eval_value(form, &env)
+    }
+
+    pub fn eval_value(
+        &mut self,
+        value: &Value,
+        env: &Rc<RefCell<Env>>,
+    ) -> Result<Value, EvalError> {
+        match value {
+            Value::Symbol(name) if is_keyword(name) => Ok(value.clone()),
+            Value::Symbol(name) => env
+                .borrow()
+                .get(name)
+                .ok_or_else(|| EvalError::UnboundSymbol(name.clone())),
+            Value::List(list) if list.is_empty() => Ok(Value::List(vec![])),
+            Value::List(list) => {
+                self.check_interrupt()?;
+                self.run_hooks(|hook, interpreter| hook.before_form(interpreter, value, env));
+                if let Value::Symbol(head) = &list[0] {
+                    match head.as_str() {
+                        "quote" => return eval_quote(&list[1..]),
+                        "if" => return self.eval_if(&list[1..], env),
+                        "def" => return self.eval_def(&list[1..], env),
+                        "lambda" => return make_lambda(None, &list[1..], env).map(Value::Lambda),
+                        "defmacro" => return self.eval_defmacro(&list[1..], env),
+                        "do" => return self.eval_body(&list[1..], env),
+                        "let" => return self.eval_let(&list[1..], env),
+                        "and" => return self.eval_and(&list[1..], env),
+                        "or" => return self.eval_or(&list[1..], env),
+                        "loop" => return self.eval_loop(&list[1..], env),
+                        "recur" => return Err(EvalError::MisplacedRecur),
+                        "handler-case" => return self.eval_handler_case(&list[1..], env),
+                        "unwind-protect" => return self.eval_unwind_protect(&list[1..], env),
+                        "bindings" => return eval_bindings(&list[1..], env),
+                        _ => {}
+                    }
+                }
+
+                let callee = self.eval_value(&list[0], env)?;
+                if let Value::Macro(lambda) = &callee {
+                    let expansion = self.call_lambda(lambda, list[1..].to_vec())?;
+                    self.expansions.push(Expansion {
+                        name: lambda.name.clone().unwrap_or_default(),
+                        call: value.clone(),
+                        origin: self.origin.clone(),
+                    });
+                    let result = self.eval_value(&expansion, env);
+                    // the innermost expansion the error passes through
+                    // records the whole stack
+                    if result.is_err() && self.failed_expansions.is_empty() {
+                        self.failed_expansions = self.expansions.clone();
+                    }
+                    self.expansions.pop();
+                    return result;
+                }
+
+                let args = list[1..]
+                    .iter()
+                    .map(|arg| self.eval_value(arg, env))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.apply(&callee, args)
+            }
+            other => Ok(other.clone()),
+        }
+    }
+
+    pub fn apply(&mut self, callee: &Value, args: Vec<Value>) -> Result<Value, EvalError> {
+        self.run_hooks(|hook, interpreter| hook.enter_call(interpreter, callee, &args));
+        let tracked = self.error_handler.is_some();
+        if tracked {
+            let function = match callee {
+                Value::Builtin(builtin) => builtin.name.into(),
+                Value::Lambda(lambda) => lambda.name.clone().unwrap_or("lambda".into()),
+                other => other.to_string(),
+            };
+            let args = args.clone();
+            self.calls.frames.push(Frame { function, args });
+        }
+        let result = match callee {
+            Value::Builtin(builtin) => match Capability::of_builtin(builtin.name) {
+                Some(capability) if self.is_denied(capability) => Err(EvalError::Denied {
+                    name: builtin.name.into(),
+                    capability,
+                }),
+                _ => (builtin.func)(self, args),
+            },
+            Value::Lambda(lambda) => self.call_lambda(lambda, args),
+            other => Err(EvalError::NotCallable(other.to_string())),
+        };
+        let result = match tracked {
+            true => {
+                let result = self.handle_error(result);
+                self.calls.frames.pop();
+                result
+            }
+            false => result,
+        };
+        self.run_hooks(|hook, interpreter| hook.exit_call(interpreter, callee, &result));
+        result
+    }
+
+    /// Applies the expander of a macro to its unevaluated arguments.
+    pub fn expand_macro(&mut self, lambda: &Lambda, args: Vec<Value>) -> Result<Value, EvalError> {
+        self.call_lambda(lambda, args)
+    }
+
+    /// `form` with its macro call, if it is one, expanded once, and
+    /// whether it was.
+    pub fn macroexpand_1(&mut self, form: &Value) -> Result<(Value, bool), EvalError> {
+        let Value::List(list) = form else {
+            return Ok((form.clone(), false));
+        };
+        let lambda = match list.first() {
+            Some(Value::Symbol(head)) => match self.get_global(head) {
+                Some(Value::Macro(lambda)) => lambda,
+                _ => return Ok((form.clone(), false)),
+            },
+            _ => return Ok((form.clone(), false)),
+        };
+        Ok((self.expand_macro(&lambda, list[1..].to_vec())?, true))
+    }
+
+    /// `form` expanded until it is no longer a macro call. Forms inside it
+    /// are left as they are.
+    pub fn macroexpand(&mut self, form: &Value) -> Result<Value, EvalError> {
+        let mut form = form.clone();
+        loop {
+            match self.macroexpand_1(&form)? {
+                (expansion, true) => form = expansion,
+                (form, false) => return Ok(form),
+            }
+        }
+    }
+
+    /// `form` with every macro call that evaluating it would expand
+    /// expanded, leaving quoted data, parameter lists, and binding names.
+    pub fn macroexpand_all(&mut self, form: &Value) -> Result<Value, EvalError> {
+        let form = self.macroexpand(form)?;
+        let Value::List(list) = &form else {
+            return Ok(form);
+        };
+        let head = match list.first() {
+            Some(Value::Symbol(head)) => head.as_str(),
+            _ => "",
+        };
+        // how many forms after the head are kept as they are
+        let kept = match (head, list.get(1)) {
+            ("quote", _) => list.len(),
+            ("def", Some(Value::List(_))) | ("defmacro" | "lambda", _) => 2,
+            ("let" | "loop", Some(Value::List(bindings))) => {
+                let bindings = bindings
+                    .iter()
+                    .map(|binding| match binding {
+                        Value::List(binding) if binding.len() == 2 => Ok(Value::List(vec![
+                            binding[0].clone(),
+                            self.macroexpand_all(&binding[1])?,
+                        ])),
+                        other => Ok(other.clone()),
+                    })
+                    .collect::<Result<_, EvalError>>()?;
+                let mut expanded = vec![list[0].clone(), Value::List(bindings)];
+                for form in &list[2..] {
+                    expanded.push(self.macroexpand_all(form)?);
+                }
+                return Ok(Value::List(expanded));
+            }
+            ("handler-case", _) => {
+                let mut expanded = list[..1].to_vec();
+                for (i, form) in list.iter().enumerate().skip(1) {
+                    expanded.push(match form {
+                        Value::List(clause) if i > 1 && !clause.is_empty() => {
+                            let mut clause = clause.clone();
+                            for form in &mut clause[1..] {
+                                *form = self.macroexpand_all(form)?;
+                            }
+                            Value::List(clause)
                         }
+                        form => self.macroexpand_all(form)?,
+                    });
+                }
+                return Ok(Value::List(expanded));
+            }
+            _ => 0,
+        };
+        let kept = kept.min(list.len());
+        let mut expanded = list[..kept].to_vec();
+        for form in &list[kept..] {
+            expanded.push(self.macroexpand_all(form)?);
+        }
+        Ok(Value::List(expanded))
+    }
+
+    fn call_lambda(&mut self, lambda: &Lambda, args: Vec<Value>) -> Result<Value, EvalError> {
+        if self.depth >= self.max_depth {
+            return Err(EvalError::StackOverflow {
+                limit: self.max_depth,
+                trace: Vec::new(),
+            });
+        }
+        self.depth += 1;
+        let mut result = self.enter_lambda(lambda, args);
+        self.depth -= 1;
+        if let Err(EvalError::StackOverflow { trace, .. }) = &mut result {
+            if trace.len() < TRACE_LENGTH {
+                trace.push(lambda.name.clone().unwrap_or_else(|| "lambda".into()));
+            }
+        }
+        result
+    }
+
+    fn enter_lambda(&mut self, lambda: &Lambda, args: Vec<Value>) -> Result<Value, EvalError> {
+        let arity_ok = match lambda.rest {
+            Some(_) => args.len() >= lambda.params.len(),
+            None => args.len() == lambda.params.len(),
+        };
+        if !arity_ok {
+            return Err(EvalError::ArityMismatch {
+                name: lambda.name.clone().unwrap_or_else(|| "lambda".into()),
+                expected: match lambda.rest {
+                    Some(_) => format!("at least {}", lambda.params.len()),
+                    None => lambda.params.len().to_string(),
+                },
+                found: args.len(),
+            });
+        }
+
+        let mut frame = Env::with_parent(lambda.env.clone());
+        let mut args = args.into_iter();
+        for param in &lambda.params {
+            frame.define(param, args.next().unwrap());
+        }
+        if let Some(rest) = &lambda.rest {
+            frame.define(rest, Value::List(args.collect()));
+        }
+
+        self.eval_body(&lambda.body, &Rc::new(RefCell::new(frame)))
+    }
+
+    fn eval_body(&mut self, body: &[Value], env: &Rc<RefCell<Env>>) -> Result<Value, EvalError> {
+        let mut result = Value::Nil;
+        for form in body {
+            result = self.eval_value(form, env)?;
+        }
+        Ok(result)
+    }
+
+    fn eval_if(&mut self, args: &[Value], env: &Rc<RefCell<Env>>) -> Result<Value, EvalError> {
+        let (test, then, otherwise) = match args {
+            [test, then] => (test, then, None),
+            [test, then, otherwise] => (test, then, Some(otherwise)),
+            _ => return Err(EvalError::MalformedForm("if")),
+        };
+        if self.eval_value(test, env)?.is_truthy() {
+            self.eval_value(then, env)
+        } else {
+            match otherwise {
+                Some(otherwise) => self.eval_value(otherwise, env),
+                None => Ok(Value::Nil),
+            }
+        }
+    }
+
+    fn eval_def(&mut self, args: &[Value], env: &Rc<RefCell<Env>>) -> Result<Value, EvalError> {
+        match args {
+            // (def (name params...) body...)
+            [Value::List(signature), body @ ..] => {
+                let Some(Value::Symbol(name)) = signature.first() else {
+                    return Err(EvalError::MalformedForm("def"));
+                };
+                let mut lambda_args = vec![Value::List(signature[1..].to_vec())];
+                lambda_args.extend_from_slice(body);
+                let lambda = make_lambda(Some(name.clone()), &lambda_args, env)?;
+                env.borrow_mut().define(name, Value::Lambda(lambda));
+                self.record_doc(name, body, env);
+                Ok(Value::Symbol(name.clone()))
+            }
+            // (def name expr)
+            [Value::Symbol(name), expr] => {
+                let value = self.eval_value(expr, env)?;
+                env.borrow_mut().define(name, value);
+                self.record_doc(name, &[], env);
+                Ok(Value::Symbol(name.clone()))
+            }
+            _ => Err(EvalError::MalformedForm("def")),
+        }
+    }
+
+    fn eval_defmacro(
+        &mut self,
+        args: &[Value],
+        env: &Rc<RefCell<Env>>,
+    ) -> Result<Value, EvalError> {
+        let [Value::List(signature), body @ ..] = args else {
+            return Err(EvalError::MalformedForm("defmacro"));
+        };
+        let Some(Value::Symbol(name)) = signature.first() else {
+            return Err(EvalError::MalformedForm("defmacro"));
+        };
+        let mut lambda_args = vec![Value::List(signature[1..].to_vec())];
+        lambda_args.extend_from_slice(body);
+        let lambda = make_lambda(Some(name.clone()), &lambda_args, env)?;
+        env.borrow_mut().define(name, Value::Macro(lambda));
+        self.record_doc(name, body, env);
+        Ok(Value::Symbol(name.clone()))
+    }
+
+    fn eval_let(&mut self, args: &[Value], env: &Rc<RefCell<Env>>) -> Result<Value, EvalError> {
+        let [Value::List(bindings), body @ ..] = args else {
+            return Err(EvalError::MalformedForm("let"));
+        };
+        let frame = self.bind("let", bindings, env)?;
+        self.eval_body(body, &frame)
+    }
+
+    /// Evaluates `((name expr)...)` bindings of the form `form` in order,
+    /// each seeing the ones before, in a new frame under `env`.
+    fn bind(
+        &mut self,
+        form: &'static str,
+        bindings: &[Value],
+        env: &Rc<RefCell<Env>>,
+    ) -> Result<Rc<RefCell<Env>>, EvalError> {
+        let frame = Rc::new(RefCell::new(Env::with_parent(env.clone())));
+        for binding in bindings {
+            let Value::List(pair) = binding else {
+                return Err(EvalError::MalformedForm(form));
+            };
+            let [Value::Symbol(name), expr] = pair.as_slice() else {
+                return Err(EvalError::MalformedForm(form));
+            };
+            let value = self.eval_value(expr, &frame)?;
+            frame.borrow_mut().define(name, value);
+        }
+        Ok(frame)
+    }
+
+    /// `(loop ((name init)...) body...)` binds like `let`, then evaluates
+    /// the body again with the names rebound whenever it ends in
+    /// `(recur values...)`, without growing the stack. The body is checked
+    /// for a `recur` anywhere else before it runs.
+    fn eval_loop(&mut self, args: &[Value], env: &Rc<RefCell<Env>>) -> Result<Value, EvalError> {
+        let [Value::List(bindings), body @ ..] = args else {
+            return Err(EvalError::MalformedForm("loop"));
+        };
+        let mut frame = self.bind("loop", bindings, env)?;
+        let names: Vec<&str> = bindings
+            .iter()
+            .map(|binding| match binding {
+                Value::List(pair) => match pair.first() {
+                    Some(Value::Symbol(name)) => name.as_str(),
+                    _ => unreachable!("bind checked the bindings"),
+                },
+                _ => unreachable!("bind checked the bindings"),
+            })
+            .collect();
+        let body = self.expand_loop_body(body, env)?;
+        loop {
+            self.check_interrupt()?;
+            let values = match self.eval_tail_body(&body, &frame)? {
+                Step::Done(value) => return Ok(value),
+                Step::Recur(values) => values,
+            };
+            if values.len() != names.len() {
+                return Err(EvalError::ArityMismatch {
+                    name: "recur".into(),
+                    expected: names.len().to_string(),
+                    found: values.len(),
+                });
+            }
+            // a fresh frame, as closures made by the last pass keep theirs
+            let mut next = Env::with_parent(env.clone());
+            for (name, value) in names.iter().zip(values) {
+                next.define(name, value);
+            }
+            frame = Rc::new(RefCell::new(next));
+        }
+    }
+
+    /// Fails with [`EvalError::MisplacedRecur`] if a `recur` in the loop
+    /// body `body` is not in tail position, expanding global macros.
+    #[cfg(feature = "std")]
+    pub(crate) fn check_loop_body(&mut self, body: &[Value]) -> Result<(), EvalError> {
+        let env = self.global.clone();
+        self.expand_loop_body(body, &env).map(|_| ())
+    }
+
+    /// The body of a loop with the macros in tail position expanded, so
+    /// that every `recur` is in sight, or [`EvalError::MisplacedRecur`]
+    /// if one is not in tail position. Macros are looked up in `env`.
+    fn expand_loop_body(
+        &mut self,
+        body: &[Value],
+        env: &Rc<RefCell<Env>>,
+    ) -> Result<Vec<Value>, EvalError> {
+        let last = body.len().saturating_sub(1);
+        body.iter()
+            .enumerate()
+            .map(|(i, form)| self.expand_tail(form, i == last, env))
+            .collect()
+    }
+
+    fn expand_tail(
+        &mut self,
+        form: &Value,
+        tail: bool,
+        env: &Rc<RefCell<Env>>,
+    ) -> Result<Value, EvalError> {
+        let Value::List(list) = form else {
+            return Ok(form.clone());
+        };
+        let Some(Value::Symbol(head)) = list.first() else {
+            return self.expand_each(list, 0, env);
+        };
+        match head.as_str() {
+            "quote" => Ok(form.clone()),
+            "recur" if !tail => Err(EvalError::MisplacedRecur),
+            // the branches of an if, and the last form of a do or let body
+            "if" => self.expand_each(list, 2, env),
+            "do" => self.expand_each(list, list.len() - 1, env),
+            "let" if list.len() > 2 => self.expand_each(list, list.len() - 1, env),
+            // an inner loop checks its own body, which may recur to it
+            "loop" => match list.get(1) {
+                Some(bindings) => self.expand_tail(bindings, false, env).map(|_| form.clone()),
+                None => Ok(form.clone()),
+            },
+            _ => {
+                let lambda = match env.borrow().get(head) {
+                    Some(Value::Macro(lambda)) if tail => lambda,
+                    _ => return self.expand_each(list, list.len(), env),
+                };
+                let expansion = self.expand_macro(&lambda, list[1..].to_vec())?;
+                self.expand_tail(&expansion, tail, env)
+            }
+        }
+    }
+
+    /// Checks the forms of `list`, those from index `tail` on being in
+    /// tail position, and returns the list of their expansions.
+    fn expand_each(
+        &mut self,
+        list: &[Value],
+        tail: usize,
+        env: &Rc<RefCell<Env>>,
+    ) -> Result<Value, EvalError> {
+        list.iter()
+            .enumerate()
+            .map(|(i, form)| self.expand_tail(form, i >= tail, env))
+            .collect::<Result<_, _>>()
+            .map(Value::List)
+    }
+
+    fn eval_tail_body(
+        &mut self,
+        body: &[Value],
+        env: &Rc<RefCell<Env>>,
+    ) -> Result<Step, EvalError> {
+        let Some((last, init)) = body.split_last() else {
+            return Ok(Step::Done(Value::Nil));
+        };
+        self.eval_body(init, env)?;
+        self.eval_tail(last, env)
+    }
+
+    /// Evaluates a form in tail position of a loop body, following the
+    /// forms [`Interpreter::expand_tail`] lets a `recur` through.
+    fn eval_tail(&mut self, form: &Value, env: &Rc<RefCell<Env>>) -> Result<Step, EvalError> {
+        let Value::List(list) = form else {
+            return self.eval_value(form, env).map(Step::Done);
+        };
+        let head = match list.first() {
+            Some(Value::Symbol(head)) => head.as_str(),
+            _ => "",
+        };
+        match (head, &list[1..]) {
+            ("recur", args) => {
+                self.run_hooks(|hook, interpreter| hook.before_form(interpreter, form, env));
+                args.iter()
+                    .map(|arg| self.eval_value(arg, env))
+                    .collect::<Result<_, _>>()
+                    .map(Step::Recur)
+            }
+            ("if", [test, then, otherwise @ ..]) if otherwise.len() <= 1 => {
+                self.run_hooks(|hook, interpreter| hook.before_form(interpreter, form, env));
+                if self.eval_value(test, env)?.is_truthy() {
+                    self.eval_tail(then, env)
+                } else {
+                    match otherwise.first() {
+                        Some(otherwise) => self.eval_tail(otherwise, env),
+                        None => Ok(Step::Done(Value::Nil)),
                     }
-                    _ => todo!("later"),
+                }
+            }
+            ("do", body) => {
+                self.run_hooks(|hook, interpreter| hook.before_form(interpreter, form, env));
+                self.eval_tail_body(body, env)
+            }
+            ("let", [Value::List(bindings), body @ ..]) => {
+                self.run_hooks(|hook, interpreter| hook.before_form(interpreter, form, env));
+                let frame = self.bind("let", bindings, env)?;
+                self.eval_tail_body(body, &frame)
+            }
+            _ => self.eval_value(form, env).map(Step::Done),
+        }
+    }
+
+    fn eval_and(&mut self, args: &[Value], env: &Rc<RefCell<Env>>) -> Result<Value, EvalError> {
+        let mut result = Value::Bool(true);
+        for arg in args {
+            result = self.eval_value(arg, env)?;
+            if !result.is_truthy() {
+                break;
+            }
+        }
+        Ok(result)
+    }
+
+    /// `(handler-case expr ((type name) body...)...)` evaluates `expr`,
+    /// and if it fails with a condition, the body of the first clause
+    /// whose type matches, with `name` bound to the condition. The type
+    /// `condition` matches any. Unmatched conditions are raised again.
+    fn eval_handler_case(
+        &mut self,
+        args: &[Value],
+        env: &Rc<RefCell<Env>>,
+    ) -> Result<Value, EvalError> {
+        let [expr, clauses @ ..] = args else {
+            return Err(EvalError::MalformedForm("handler-case"));
+        };
+        let clauses = clauses
+            .iter()
+            .map(|clause| match clause {
+                Value::List(clause) => match clause.as_slice() {
+                    [Value::List(head), body @ ..] => match head.as_slice() {
+                        [Value::Symbol(tag), Value::Symbol(name)] => Ok((tag, name, body)),
+                        _ => Err(EvalError::MalformedForm("handler-case")),
+                    },
+                    _ => Err(EvalError::MalformedForm("handler-case")),
+                },
+                _ => Err(EvalError::MalformedForm("handler-case")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let err = match self.eval_value(expr, env) {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        let Some(tag) = err.condition_type() else {
+            return Err(err);
+        };
+        let Some((_, name, body)) = clauses
+            .iter()
+            .find(|(clause, _, _)| *clause == tag || *clause == "condition")
+        else {
+            return Err(err);
+        };
+        let condition = self.caught(&err);
+        let mut frame = Env::with_parent(env.clone());
+        frame.define(name, condition);
+        self.eval_body(body, &Rc::new(RefCell::new(frame)))
+    }
+
+    /// `(unwind-protect expr cleanup...)` evaluates `expr`, then the
+    /// cleanup forms whether it returned or failed, and gives what `expr`
+    /// did unless a cleanup form fails.
+    fn eval_unwind_protect(
+        &mut self,
+        args: &[Value],
+        env: &Rc<RefCell<Env>>,
+    ) -> Result<Value, EvalError> {
+        let [expr, cleanup @ ..] = args else {
+            return Err(EvalError::MalformedForm("unwind-protect"));
+        };
+        let result = self.eval_value(expr, env);
+        // cleanup may raise and catch conditions of its own
+        let raised = self.raised.take();
+        let failed_expansions = core::mem::take(&mut self.failed_expansions);
+        self.eval_body(cleanup, env)?;
+        self.raised = raised;
+        self.failed_expansions = failed_expansions;
+        result
+    }
+
+    /// The condition `handler-case` would bind for `err`, such as the last
+    /// error a REPL keeps, or `None` for an internal error, which cannot be
+    /// caught.
+    pub fn condition_of(&mut self, err: &EvalError) -> Option<Value> {
+        err.condition_type()?;
+        Some(self.caught(err))
+    }
+
+    /// The condition value for `err`: what `raise` was given if it raised
+    /// `err`, otherwise its message as data.
+    pub(crate) fn caught(&mut self, err: &EvalError) -> Value {
+        self.failed_expansions.clear();
+        self.error_handled = false;
+        let tag = err.condition_type().unwrap_or_default();
+        if let EvalError::Condition { .. } = err {
+            if let Some(raised) = self.raised.take() {
+                if raised_error(&raised) == *err {
+                    return raised;
                 }
             }
         }
+        let message = match err {
+            EvalError::Condition { message, .. } => message.clone(),
+            other => other.to_string(),
+        };
+        condition(tag, Value::String(message))
     }
+
+    /// Fails with the condition of type `tag` holding `data`.
+    pub fn raise(&mut self, tag: &str, data: Value) -> EvalError {
+        let condition = condition(tag, data);
+        let err = raised_error(&condition);
+        self.raised = Some(condition);
+        err
+    }
+
+    fn eval_or(&mut self, args: &[Value], env: &Rc<RefCell<Env>>) -> Result<Value, EvalError> {
+        let mut result = Value::Nil;
+        for arg in args {
+            result = self.eval_value(arg, env)?;
+            if result.is_truthy() {
+                break;
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Reads a line from `input` without its line ending, or `None` at the end
+/// of the input.
+#[cfg(feature = "std")]
+pub(crate) fn read_line_from(input: &mut impl BufRead) -> Result<Option<String>, EvalError> {
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) => Ok(None),
+        Ok(_) => {
+            let end = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(end);
+            Ok(Some(line))
+        }
+        Err(err) => Err(EvalError::Io(err.to_string())),
+    }
+}
+
+/// The error a condition made by [`condition`] is raised as.
+fn raised_error(condition: &Value) -> EvalError {
+    let field = |i: usize| match condition {
+        Value::List(fields) => match fields.get(i) {
+            Some(Value::List(pair)) => pair.get(1).cloned().unwrap_or(Value::Nil),
+            _ => Value::Nil,
+        },
+        _ => Value::Nil,
+    };
+    EvalError::Condition {
+        tag: field(0).to_string(),
+        message: match field(1) {
+            Value::String(message) => message,
+            data => data.to_string(),
+        },
+    }
+}
+
+/// How a form in tail position of a loop body ended.
+enum Step {
+    Done(Value),
+    Recur(Vec<Value>),
+}
+
+fn eval_quote(args: &[Value]) -> Result<Value, EvalError> {
+    match args {
+        [quoted] => Ok(quoted.clone()),
+        _ => Err(EvalError::MalformedForm("quote")),
+    }
+}
+
+/// `(bindings)` lists the names bound where it is evaluated, locals and
+/// globals alike, as sorted symbols.
+fn eval_bindings(args: &[Value], env: &Rc<RefCell<Env>>) -> Result<Value, EvalError> {
+    if !args.is_empty() {
+        return Err(EvalError::MalformedForm("bindings"));
+    }
+    let mut names = BTreeSet::new();
+    let mut frame = Some(env.clone());
+    while let Some(env) = frame {
+        names.extend(env.borrow().names());
+        frame = env.borrow().parent();
+    }
+    Ok(Value::List(names.into_iter().map(Value::Symbol).collect()))
+}
+
+/// Builds a lambda from `(params...) body...`, where a `&` in the parameter
+/// list binds the remaining arguments to the following symbol as a list.
+fn make_lambda(
+    name: Option<String>,
+    args: &[Value],
+    env: &Rc<RefCell<Env>>,
+) -> Result<Rc<Lambda>, EvalError> {
+    let [Value::List(param_list), body @ ..] = args else {
+        return Err(EvalError::MalformedForm("lambda"));
+    };
+    let mut params = Vec::new();
+    let mut rest = None;
+    let mut symbols = param_list.iter();
+    while let Some(param) = symbols.next() {
+        match param {
+            Value::Symbol(s) if s == "&" => match (symbols.next(), symbols.next()) {
+                (Some(Value::Symbol(r)), None) => rest = Some(r.clone()),
+                _ => return Err(EvalError::MalformedForm("lambda")),
+            },
+            Value::Symbol(s) => params.push(s.clone()),
+            _ => return Err(EvalError::MalformedForm("lambda")),
+        }
+    }
+    Ok(Rc::new(Lambda {
+        name,
+        params,
+        rest,
+        body: body.to_vec(),
+        env: env.clone(),
+    }))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::{Atom, ParseError};
+
+    fn eval_str(interpreter: &mut Interpreter, text: &str) -> Result<Value, EvalError> {
+        let mut scanner = Scanner::new(text);
+        let mut parser = Parser::new(&mut scanner);
+        let mut result = Value::Nil;
+        for expr in parser.parse_program().unwrap() {
+            result = interpreter.eval(&expr)?;
+        }
+        Ok(result)
+    }
 
     #[test]
     fn test_eval() {
-        let x = Evaluator;
+        let mut x = Interpreter::bare();
         let result = x.eval(&Expr::List(vec![
             Expr::Atom(Atom::Symbol("+".to_owned())),
             Expr::Atom(Atom::Number(1.0)),
         ]));
-        assert_eq!(result, Atom::Number(1.0));
+        assert_eq!(result, Ok(Value::Number(1.0)));
     }
 
     #[test]
     fn test_add() {
-        let x = Evaluator;
+        let mut x = Interpreter::bare();
         let result = x.eval(&Expr::List(vec![
             Expr::Atom(Atom::Symbol("+".to_owned())),
             Expr::Atom(Atom::Number(1.0)),
             Expr::Atom(Atom::Number(2.0)),
         ]));
-        assert_eq!(result, Atom::Number(3.0));
+        assert_eq!(result, Ok(Value::Number(3.0)));
+    }
+
+    #[test]
+    fn test_def_and_call() {
+        let mut x = Interpreter::bare();
+        let result = eval_str(&mut x, "(def (add x y) (+ x y)) (add 2 3)");
+        assert_eq!(result, Ok(Value::Number(5.0)));
+    }
+
+    #[test]
+    fn test_rest_params() {
+        let mut x = Interpreter::bare();
+        let result = eval_str(&mut x, "((lambda (a & more) more) 1 2 3)");
+        assert_eq!(
+            result,
+            Ok(Value::List(vec![Value::Number(2.0), Value::Number(3.0)]))
+        );
+    }
+
+    #[test]
+    fn test_arity_mismatch() {
+        let mut x = Interpreter::bare();
+        let result = eval_str(&mut x, "(def (f x) x) (f 1 2)");
+        assert_eq!(
+            result,
+            Err(EvalError::ArityMismatch {
+                name: "f".into(),
+                expected: "1".into(),
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_loop_recur() {
+        let mut x = Interpreter::new();
+        let sum = "(loop ((i 0) (sum 0)) (if (= i 100000) sum (recur (inc i) (+ sum 1))))";
+        assert_eq!(eval_str(&mut x, sum), Ok(Value::Number(100000.0)));
+        assert_eq!(
+            eval_str(
+                &mut x,
+                "(loop ((xs '(1 2)) (n 0)) (unless (empty? xs) (recur (cdr xs) (inc n))))"
+            ),
+            Ok(Value::Nil)
+        );
+        // checked before the body first runs
+        assert_eq!(
+            eval_str(
+                &mut x,
+                "(def ran false) (loop () (def ran true) (+ 1 (recur)))"
+            ),
+            Err(EvalError::MisplacedRecur)
+        );
+        assert_eq!(eval_str(&mut x, "ran"), Ok(Value::Bool(false)));
+        assert_eq!(
+            eval_str(&mut x, "(loop ((i 0)) (if (< i 1) (recur 1 2) i))"),
+            Err(EvalError::ArityMismatch {
+                name: "recur".into(),
+                expected: "1".into(),
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_handler_case() {
+        let mut x = Interpreter::new();
+        let catch = |x: &mut Interpreter, expr: &str| {
+            let source = format!(
+                "(handler-case {}
+                   ((io-error e) (list 'io (condition-data e)))
+                   ((type-error e) (list 'type (condition-data e))))",
+                expr
+            );
+            eval_str(x, &source)
+        };
+        assert_eq!(catch(&mut x, "(+ 1 2)"), Ok(Value::Number(3.0)));
+        assert_eq!(
+            catch(&mut x, "(raise 'io-error '(disk full))"),
+            eval_str(&mut x, "'(io (disk full))")
+        );
+        assert_eq!(
+            catch(&mut x, "(car 1)"),
+            eval_str(&mut x, "'(type \"Expected list, found number\")")
+        );
+        assert_eq!(
+            catch(&mut x, "(raise 'parse-error \"bad\")"),
+            Err(EvalError::Condition {
+                tag: "parse-error".into(),
+                message: "bad".into()
+            })
+        );
+        assert_eq!(
+            eval_str(&mut x, "(type-of (handler-case (f) ((condition e) e)))"),
+            Ok(Value::Symbol("unbound-symbol".into()))
+        );
+    }
+
+    #[test]
+    fn test_unwind_protect() {
+        let mut x = Interpreter::new();
+        eval_str(
+            &mut x,
+            "(def log '()) (def (note x) (def log (cons x log)))",
+        )
+        .unwrap();
+        assert_eq!(
+            eval_str(&mut x, "(unwind-protect (+ 1 2) (def log (cons 'a log)))"),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(
+            eval_str(
+                &mut x,
+                "(handler-case (unwind-protect (raise 'oops 1) (def log (cons 'b log)))
+                   ((oops e) (condition-data e)))"
+            ),
+            Ok(Value::Number(1.0))
+        );
+        assert_eq!(eval_str(&mut x, "log"), eval_str(&mut x, "'(b a)"));
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join(format!("jon-load-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.jon");
+        std::fs::write(&lib, "(def (twice x) (* 2 x))\n(twice 2)").unwrap();
+        std::fs::write(dir.join("bad.jon"), "(def x").unwrap();
+        let mut x = Interpreter::new();
+        x.set_parse_cache(Some(ParseCache::new(dir.join("cache"))));
+        x.set_global("dir", dir.to_str().unwrap());
+        for _ in 0..2 {
+            assert_eq!(
+                eval_str(&mut x, "(load (string/append dir \"/lib.jon\"))"),
+                Ok(Value::Number(4.0))
+            );
+        }
+        assert_eq!(
+            x.doc("twice").and_then(|doc| doc.origin),
+            Some(Origin::Source {
+                name: lib.to_str().unwrap().into(),
+                line: 1
+            })
+        );
+        assert_eq!(
+            eval_str(
+                &mut x,
+                "(handler-case (load (string/append dir \"/bad.jon\")) ((parse-error e) 'bad))"
+            ),
+            Ok(Value::Symbol("bad".into()))
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_prelude_helpers() {
+        let mut x = Interpreter::new();
+        assert_eq!(
+            eval_str(&mut x, "(second '(1 2 3))"),
+            Ok(Value::Number(2.0))
+        );
+        assert_eq!(
+            eval_str(&mut x, "(last (range 0 4))"),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(eval_str(&mut x, "(when true 1 2)"), Ok(Value::Number(2.0)));
+        assert_eq!(eval_str(&mut x, "(unless true 1)"), Ok(Value::Nil));
+        assert_eq!(
+            eval_str(&mut x, "(map (partial - 10) '(1 2))"),
+            eval_str(&mut x, "'(9 8)")
+        );
+        assert_eq!(
+            eval_str(
+                &mut x,
+                "(def (add3 a b c) (+ a b c)) (((curry add3) 1) 2 3)"
+            ),
+            Ok(Value::Number(6.0))
+        );
+        assert_eq!(
+            eval_str(
+                &mut x,
+                "(list ((comp car cdr) '(1 2)) ((comp inc +) 1 2) ((comp) 4))"
+            ),
+            eval_str(&mut x, "'(2 4 4)")
+        );
+        assert_eq!(
+            eval_str(&mut x, "(map (constantly 0) '(1 2))"),
+            eval_str(&mut x, "'(0 0)")
+        );
+        assert_eq!(
+            eval_str(&mut x, "(-> '(1 2 3) (cdr) car (- 10))"),
+            Ok(Value::Number(-8.0))
+        );
+        assert_eq!(
+            eval_str(
+                &mut x,
+                "(->> (range 0 4) (map inc) (filter (lambda (n) (> n 2))))"
+            ),
+            eval_str(&mut x, "'(3 4)")
+        );
+    }
+
+    #[test]
+    fn test_iteration_forms() {
+        let mut x = Interpreter::new();
+        assert_eq!(
+            eval_str(
+                &mut x,
+                "(handler-case (for (c \"abc\") (when (= c \"b\") (raise 'found c)))
+                   ((found e) (condition-data e)))"
+            ),
+            Ok(Value::String("b".into()))
+        );
+        assert_eq!(
+            eval_str(
+                &mut x,
+                "(handler-case (dotimes (i 4) (raise 'last i)) ((last e) (condition-data e)))"
+            ),
+            Ok(Value::Number(0.0))
+        );
+        assert_eq!(eval_str(&mut x, "(dotimes (i 4) i)"), Ok(Value::Nil));
+        assert_eq!(
+            eval_str(&mut x, "(for/list (x (range 1 4)) (* x x))"),
+            eval_str(&mut x, "'(1 4 9)")
+        );
+        assert_eq!(eval_str(&mut x, "(doseq (x '(1)) x)"), Ok(Value::Nil));
+    }
+
+    #[test]
+    fn test_error_handler() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let mut x = Interpreter::new();
+        let log = seen.clone();
+        x.on_error(move |err, stack| {
+            log.borrow_mut().push(format!("{}\n{}", err, stack));
+            match err {
+                EvalError::UnboundSymbol(name) if name == "missing" => {
+                    ErrorAction::Suppress(Value::Number(0.0))
+                }
+                EvalError::TypeMismatch { .. } => {
+                    ErrorAction::Replace(EvalError::Host("bad input".into()))
+                }
+                EvalError::Condition { tag, .. } if tag == "soft" => {
+                    ErrorAction::Suppress(Value::Number(5.0))
+                }
+                _ => ErrorAction::Propagate,
+            }
+        });
+        eval_str(&mut x, "(def (half n) (/ n 2)) (def (f n) (+ 1 (half n)))").unwrap();
+        assert_eq!(
+            eval_str(&mut x, "(f \"two\")"),
+            Err(EvalError::Host("bad input".into()))
+        );
+        assert_eq!(
+            seen.borrow().as_slice(),
+            ["Expected number, found string\n(/ \"two\" 2)\n(half \"two\")\n(f \"two\")\n"]
+        );
+        assert_eq!(eval_str(&mut x, "(+ 1 missing)"), Ok(Value::Number(0.0)));
+        assert_eq!(
+            eval_str(&mut x, "(handler-case (raise 'oops 1) ((oops e) 2))"),
+            Ok(Value::Number(2.0))
+        );
+        assert_eq!(seen.borrow().len(), 3);
+        assert_eq!(seen.borrow()[2], "oops: 1\n(raise oops 1)\n");
+        // only the failing call is replaced when the error is raised in one
+        assert_eq!(
+            eval_str(&mut x, "(+ 1 (* 2 (raise 'soft 0)))"),
+            Ok(Value::Number(11.0))
+        );
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut x = Interpreter::new();
+        x.set_max_depth(50);
+        eval_str(&mut x, "(def (down n) (+ 1 (down (- n 1))))").unwrap();
+        match eval_str(&mut x, "(down 0)") {
+            Err(EvalError::StackOverflow { limit: 50, trace }) => {
+                assert_eq!(trace, ["down"; TRACE_LENGTH]);
+            }
+            other => panic!("expected a stack overflow, got {:?}", other),
+        }
+        assert_eq!(
+            eval_str(&mut x, "(handler-case (down 0) ((stack-overflow e) 'deep))"),
+            Ok(Value::Symbol("deep".into()))
+        );
+        assert_eq!(
+            eval_str(&mut x, "(list (stack-depth) ((lambda () (stack-depth))))"),
+            eval_str(&mut x, "'(0 1)")
+        );
+    }
+
+    #[test]
+    fn test_docs() {
+        let mut x = Interpreter::new();
+        x.set_origin("a.jon", 3);
+        eval_str(&mut x, "(def (f x) \"Returns x.\" x)").unwrap();
+        assert_eq!(
+            x.doc("f"),
+            Some(Doc {
+                docstring: Some("Returns x.".into()),
+                origin: Some(Origin::Source {
+                    name: "a.jon".into(),
+                    line: 3
+                }),
+            })
+        );
+        assert_eq!(x.doc("map").unwrap().origin, Some(Origin::Prelude));
+        assert_eq!(x.doc("car").unwrap().origin, Some(Origin::Builtin));
+        assert_eq!(x.doc("nil"), None);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_eval_async() {
+        let mut x = Interpreter::new();
+        x.eval_str("(def (double n) (* 2 n))").unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let handle = runtime.spawn(x.eval_async::<i64>("(def y 4) (double y)"));
+        assert_eq!(runtime.block_on(handle).unwrap(), Ok(8));
+        assert_eq!(x.get_global("y"), None);
+    }
+
+    #[test]
+    fn test_eval_str() {
+        let mut x = Interpreter::new();
+        assert_eq!(x.eval_str("(def a 2) (inc a)"), Ok(Value::Number(3.0)));
+        assert_eq!(x.eval_str(""), Ok(Value::Nil));
+        assert_eq!(
+            x.eval_str("(car 1)"),
+            Err(Error::Eval(EvalError::TypeMismatch {
+                expected: "list",
+                found: "number"
+            }))
+        );
+        assert_eq!(x.eval_str("(inc"), Err(Error::Parse(ParseError::Eof)));
+    }
+
+    #[test]
+    fn test_host_globals() {
+        fn twice(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+            let n = f64::from_value(args.into_iter().next().unwrap_or(Value::Nil))?;
+            Ok(Value::from(n * 2.0))
+        }
+
+        let mut x = Interpreter::new();
+        x.set_global("limits", vec![1_i64, 2, 3]);
+        x.register_fn("twice", twice);
+        x.eval_str("(def doubled (map twice limits))").unwrap();
+        assert_eq!(x.global::<Vec<i64>>("doubled"), Ok(vec![2, 4, 6]));
+        assert_eq!(
+            x.global::<bool>("missing"),
+            Err(EvalError::UnboundSymbol("missing".into()))
+        );
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut x = Interpreter::new();
+        let flag = Arc::new(AtomicBool::new(false));
+        x.set_interrupt(flag.clone());
+        // the loop never ends on its own; the host interrupts it midway
+        let host = flag.clone();
+        x.register_fn("tick", move |_, args| {
+            if args.first() == Some(&Value::Number(1000.0)) {
+                host.store(true, Ordering::Relaxed);
+            }
+            Ok(Value::Nil)
+        });
+        assert_eq!(
+            x.eval_str(
+                "(handler-case (loop ((i 0)) (tick i) (recur (+ i 1))) ((condition e) 'caught))"
+            ),
+            Err(EvalError::Interrupted.into())
+        );
+        assert!(!flag.load(Ordering::Relaxed));
+        assert_eq!(x.eval_str("(+ 1 2)"), Ok(Value::Number(3.0)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_deny() {
+        let mut x = Interpreter::new();
+        x.deny(Capability::Fs);
+        assert_eq!(
+            x.eval_str("(load \"config.jon\")"),
+            Err(EvalError::Denied {
+                name: "load".into(),
+                capability: Capability::Fs,
+            }
+            .into())
+        );
+        assert_eq!(
+            x.eval_str("(handler-case (open-file \"a\") ((permission-error e) 'denied))"),
+            Ok(Value::Symbol("denied".into()))
+        );
+        let isolated = x.clone_isolated().into_inner();
+        assert!(isolated.is_denied(Capability::Fs) && !isolated.is_denied(Capability::Net));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut x = Interpreter::new();
+        x.eval_str("(def limit 10) (def (over? n) (> n limit))")
+            .unwrap();
+        let snapshot = x.snapshot();
+        assert!(x.changed_since(&snapshot).is_empty());
+        x.eval_str("(def limit 1) (def extra 2)").unwrap();
+        assert_eq!(x.changed_since(&snapshot), ["extra", "limit"]);
+        assert_eq!(x.eval_str("(over? 5)"), Ok(Value::Bool(true)));
+        x.restore(&snapshot);
+        assert_eq!(x.eval_str("(over? 5)"), Ok(Value::Bool(false)));
+        assert_eq!(x.get_global("extra"), None);
+    }
+
+    #[test]
+    fn test_call() {
+        let mut x = Interpreter::new();
+        x.eval_str("(def (on-event name count) (string/append name \"!\" (number->string count)))")
+            .unwrap();
+        assert_eq!(
+            x.call("on-event", ("saved", 3_i64)),
+            Ok(Value::from("saved!3"))
+        );
+        assert_eq!(x.call_as::<f64>("+", vec![1.5, 2.0]), Ok(3.5));
+        assert_eq!(x.call_as::<Vec<i64>>("list", ()), Ok(vec![]));
+        assert_eq!(
+            x.call("missing", ()),
+            Err(Error::Eval(EvalError::UnboundSymbol("missing".into())))
+        );
+        assert!(matches!(
+            x.call_as::<bool>("on-event", ("a", 1_i64)),
+            Err(Error::Eval(EvalError::TypeMismatch { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_bare_has_no_prelude() {
+        let mut x = Interpreter::bare();
+        assert_eq!(
+            eval_str(&mut x, "(inc 1)"),
+            Err(EvalError::UnboundSymbol("inc".into()))
+        );
     }
 }
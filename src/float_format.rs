@@ -0,0 +1,157 @@
+//! How floats print for people: in REPL results and with `print` and
+//! `pretty`, as the `*float-format*` global says. nil, the default, prints
+//! the shortest form that reads back as the same float, such as `3` or
+//! `3.3000002`; options such as `(:digits 6 :notation :scientific)` round
+//! to that many significant digits and pick the notation. What is written
+//! to be read back, such as saved sessions and images, keeps the shortest
+//! form.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::value::Value;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Notation {
+    /// Digits with a point, such as `1500` or `0.25`.
+    #[default]
+    Fixed,
+    /// A mantissa and an exponent, such as `1.5e3` or `2.5e-1`.
+    Scientific,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FloatFormat {
+    /// How many significant digits to round to, or `None` for as many as
+    /// the float needs. Trailing zeros are left off either way.
+    pub digits: Option<usize>,
+    pub notation: Notation,
+}
+
+impl FloatFormat {
+    /// The format `value` describes: nil, or a list of the options
+    /// `:digits`, a whole number from 1, and `:notation`, one of `:fixed`
+    /// and `:scientific`. `None` if it is neither.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let options = match value {
+            Value::Nil => return Some(Self::default()),
+            Value::List(options) if options.len().is_multiple_of(2) => options,
+            _ => return None,
+        };
+        let mut format = Self::default();
+        for option in options.chunks_exact(2) {
+            match option {
+                [Value::Symbol(key), Value::Number(n)]
+                    if key == ":digits" && *n >= 1.0 && n % 1.0 == 0.0 =>
+                {
+                    format.digits = Some(*n as usize)
+                }
+                [Value::Symbol(key), Value::Symbol(notation)] if key == ":notation" => {
+                    format.notation = match notation.as_str() {
+                        ":fixed" => Notation::Fixed,
+                        ":scientific" => Notation::Scientific,
+                        _ => return None,
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(format)
+    }
+
+    /// The value `*float-format*` holds for this format.
+    pub fn to_value(&self) -> Value {
+        if *self == Self::default() {
+            return Value::Nil;
+        }
+        let mut options = Vec::new();
+        if let Some(digits) = self.digits {
+            options.push(Value::Symbol(":digits".into()));
+            options.push(Value::Number(digits as f32));
+        }
+        let notation = match self.notation {
+            Notation::Fixed => ":fixed",
+            Notation::Scientific => ":scientific",
+        };
+        options.push(Value::Symbol(":notation".into()));
+        options.push(Value::Symbol(notation.into()));
+        Value::List(options)
+    }
+
+    /// `n` written in this format. Infinities and NaN print as ever.
+    pub fn float(&self, n: f32) -> String {
+        if !n.is_finite() {
+            return n.to_string();
+        }
+        let Some(digits) = self.digits else {
+            return match self.notation {
+                Notation::Fixed => n.to_string(),
+                Notation::Scientific => format!("{:e}", n),
+            };
+        };
+        let rounded = format!("{:.*e}", digits.max(1) - 1, n);
+        match self.notation {
+            // as a double the rounded number prints without float noise
+            Notation::Fixed => rounded
+                .parse::<f64>()
+                .map_or(rounded, |rounded| rounded.to_string()),
+            Notation::Scientific => {
+                let (mantissa, exponent) = rounded.split_once('e').unwrap_or((&rounded, "0"));
+                let mantissa = if mantissa.contains('.') {
+                    mantissa.trim_end_matches('0').trim_end_matches('.')
+                } else {
+                    mantissa
+                };
+                format!("{}e{}", mantissa, exponent)
+            }
+        }
+    }
+
+    /// `value` as it prints, with its floats, and the parts of its complex
+    /// numbers, in this format.
+    pub fn value(&self, value: &Value) -> String {
+        match value {
+            Value::Number(n) => self.float(*n),
+            Value::Complex(z) => {
+                let sign = if z.im.is_sign_negative() { "" } else { "+" };
+                format!("{}{}{}i", self.float(z.re), sign, self.float(z.im))
+            }
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| self.value(item)).collect();
+                format!("({})", items.join(" "))
+            }
+            other => other.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_float_formats() {
+        let noisy = 2.2_f32 + 1.1;
+        assert_eq!(FloatFormat::default().float(noisy), "3.3000002");
+        let six = FloatFormat {
+            digits: Some(6),
+            notation: Notation::Fixed,
+        };
+        assert_eq!(six.float(noisy), "3.3");
+        assert_eq!(six.float(3.0), "3");
+        assert_eq!(six.float(1234567.0), "1234570");
+        let scientific = FloatFormat {
+            digits: Some(3),
+            notation: Notation::Scientific,
+        };
+        assert_eq!(scientific.float(1500.0), "1.5e3");
+        assert_eq!(scientific.float(-0.000123456), "-1.23e-4");
+        let list = Value::List(vec![Value::Number(noisy), Value::String("a".into())]);
+        assert_eq!(six.value(&list), "(3.3 \"a\")");
+        let options = scientific.to_value();
+        assert_eq!(options.to_string(), "(:digits 3 :notation :scientific)");
+        assert_eq!(FloatFormat::from_value(&options), Some(scientific));
+        assert_eq!(FloatFormat::from_value(&Value::Number(6.0)), None);
+    }
+}
@@ -0,0 +1,315 @@
+use crate::parser::ParseError;
+use crate::scanner::{ScanError, Scanner, TokItem, Token};
+
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// Forms whose first few arguments stay on the head line while the rest
+/// form an indented body, with the number of such arguments.
+const BODY_FORMS: &[(&str, usize)] = &[
+    ("def", 1),
+    ("defmacro", 1),
+    ("defmethod", 3),
+    ("defprotocol", 1),
+    ("deftest", 1),
+    ("extend", 2),
+    ("lambda", 1),
+    ("let", 1),
+    ("loop", 1),
+    ("when", 1),
+    ("unless", 1),
+    ("for", 1),
+    ("for/list", 1),
+    ("doseq", 1),
+    ("dotimes", 1),
+    ("do", 0),
+    ("handler-case", 1),
+    ("unwind-protect", 1),
+    ("with-open", 1),
+];
+
+/// A syntax tree that, unlike the parser's, keeps comments and the source
+/// text of every atom.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Node<'a> {
+    Atom(&'a str),
+    List(Vec<Node<'a>>),
+    Quote(Box<Node<'a>>),
+    /// A comment; `trailing` when it follows code on the same line.
+    Comment {
+        text: &'a str,
+        trailing: bool,
+    },
+    /// One or more empty lines between top-level forms.
+    BlankLine,
+}
+
+/// Reads the tokens of `source`, keeping comments and recording how many
+/// line breaks preceded each one.
+fn tokens(source: &str) -> Result<Vec<(usize, TokItem<'_>)>, ParseError> {
+    let mut scanner = Scanner::new(source);
+    let mut tokens = Vec::new();
+    let mut newlines = 0;
+    loop {
+        let position = scanner.position();
+        match scanner.next_token() {
+            Ok(TokItem {
+                token: Token::WhiteSpace(spaces),
+                position: _,
+            }) => newlines += spaces.matches('\n').count(),
+            Ok(tok) => {
+                tokens.push((newlines, tok));
+                newlines = 0;
+            }
+            Err(ScanError::Eof) => return Ok(tokens),
+            Err(err) => {
+                return Err(ParseError::Scan {
+                    position,
+                    source: err,
+                })
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    source: &'a str,
+    tokens: Vec<(usize, TokItem<'a>)>,
+    current_pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn node(&mut self) -> Result<Node<'a>, ParseError> {
+        let (newlines, tok) = *self.tokens.get(self.current_pos).ok_or(ParseError::Eof)?;
+        self.current_pos += 1;
+        match tok.token {
+            Token::LParen => {
+                let mut items = Vec::new();
+                loop {
+                    match self.tokens.get(self.current_pos) {
+                        None => return Err(ParseError::Eof),
+                        Some((
+                            _,
+                            TokItem {
+                                token: Token::RParen,
+                                position: _,
+                            },
+                        )) => {
+                            self.current_pos += 1;
+                            return Ok(Node::List(items));
+                        }
+                        Some(_) => items.push(self.node()?),
+                    }
+                }
+            }
+            Token::Quote => match self.node()? {
+                Node::Comment { .. } => {
+                    Err(ParseError::unexpected(&self.tokens[self.current_pos - 1].1))
+                }
+                quoted => Ok(Node::Quote(Box::new(quoted))),
+            },
+            Token::Comment(text) => Ok(Node::Comment {
+                text: text.trim_end(),
+                trailing: newlines == 0 && self.current_pos > 1,
+            }),
+            Token::RParen => Err(ParseError::unexpected(&tok)),
+            _ => Ok(Node::Atom(&self.source[tok.span()])),
+        }
+    }
+
+    fn program(&mut self) -> Result<Vec<Node<'a>>, ParseError> {
+        let mut nodes = Vec::new();
+        while let Some((newlines, _)) = self.tokens.get(self.current_pos) {
+            if *newlines > 1 && !nodes.is_empty() {
+                nodes.push(Node::BlankLine);
+            }
+            nodes.push(self.node()?);
+        }
+        Ok(nodes)
+    }
+}
+
+/// The top-level forms of `source` with its comments and blank lines.
+pub(crate) fn parse_nodes(source: &str) -> Result<Vec<Node<'_>>, ParseError> {
+    let mut reader = Reader {
+        source,
+        tokens: tokens(source)?,
+        current_pos: 0,
+    };
+    reader.program()
+}
+
+/// Re-emits `source` in canonical layout: two-space bodies, arguments
+/// aligned under the first one, lists kept on one line when they fit in
+/// `width` columns, and at most one blank line between top-level forms.
+pub fn format_source(source: &str, width: usize) -> Result<String, ParseError> {
+    let nodes = parse_nodes(source)?;
+
+    let mut out = String::new();
+    let mut nodes = nodes.iter().peekable();
+    while let Some(node) = nodes.next() {
+        match node {
+            Node::BlankLine => out.push('\n'),
+            node => {
+                out.push_str(&render(node, 0, width));
+                if let Some(Node::Comment {
+                    text,
+                    trailing: true,
+                }) = nodes.peek()
+                {
+                    out.push(' ');
+                    out.push_str(text);
+                    nodes.next();
+                }
+                out.push('\n');
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// The node on a single line, or `None` if it contains a comment.
+pub(crate) fn flat(node: &Node) -> Option<String> {
+    match node {
+        Node::Atom(text) => Some(text.to_string()),
+        Node::Quote(quoted) => Some(format!("'{}", flat(quoted)?)),
+        Node::List(items) => {
+            let items = items.iter().map(flat).collect::<Option<Vec<_>>>()?;
+            Some(format!("({})", items.join(" ")))
+        }
+        Node::Comment { .. } | Node::BlankLine => None,
+    }
+}
+
+/// Renders `node` starting at column `indent`.
+fn render(node: &Node, indent: usize, width: usize) -> String {
+    if let Some(line) = flat(node) {
+        if indent + line.len() <= width {
+            return line;
+        }
+    }
+    match node {
+        Node::Quote(quoted) => format!("'{}", render(quoted, indent + 1, width)),
+        Node::List(items) => render_list(items, indent, width),
+        Node::Comment { text, .. } => text.to_string(),
+        other => flat(other).unwrap_or_default(),
+    }
+}
+
+fn render_list(items: &[Node], indent: usize, width: usize) -> String {
+    let head = match items.first() {
+        Some(Node::Atom(head)) => *head,
+        _ => return render_items("(", items, indent + 1, width),
+    };
+
+    let header_args = BODY_FORMS
+        .iter()
+        .find(|(name, _)| *name == head)
+        .map(|(_, count)| *count);
+    match header_args {
+        Some(count) => {
+            let header = items
+                .iter()
+                .take(count + 1)
+                .take_while(|item| !matches!(item, Node::Comment { .. }))
+                .count();
+            let mut out = String::from("(");
+            let mut column = indent + 1;
+            for (i, item) in items[..header].iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                    column += 1;
+                }
+                let text = render(item, column, width);
+                column += text.lines().last().unwrap_or_default().len();
+                out.push_str(&text);
+            }
+            if header == items.len() {
+                out.push(')');
+                return out;
+            }
+            let body = render_items("", &items[header..], indent + 2, width);
+            format!("{}\n{}{}", out, " ".repeat(indent + 2), body)
+        }
+        None => {
+            let align = indent + 1 + head.len() + 1;
+            match items.get(1) {
+                Some(Node::Comment { .. }) | None => render_items("(", items, indent + 1, width),
+                Some(_) => {
+                    let rest = render_items("", &items[1..], align, width);
+                    format!("({} {}", head, rest)
+                }
+            }
+        }
+    }
+}
+
+/// Renders `items` one per line at column `indent`, the first right after
+/// `open`, and closes the list.
+fn render_items(open: &str, items: &[Node], indent: usize, width: usize) -> String {
+    let mut out = String::from(open);
+    let mut items = items.iter().peekable();
+    let mut first = true;
+    while let Some(item) = items.next() {
+        if !first {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+        }
+        first = false;
+        out.push_str(&render(item, indent, width));
+        if let Some(Node::Comment {
+            text,
+            trailing: true,
+        }) = items.peek()
+        {
+            out.push(' ');
+            out.push_str(text);
+            items.next();
+        }
+        if matches!(item, Node::Comment { .. }) && items.peek().is_none() {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+        }
+    }
+    if let Some(last_line) = out.lines().last() {
+        if last_line.trim_start().starts_with(';') || last_line.contains(" ;") {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+        }
+    }
+    out.push(')');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_normalizes_whitespace() {
+        assert_eq!(
+            format_source("(  +   1\n 2 )\n\n\n\n(f)", 80),
+            Ok("(+ 1 2)\n\n(f)\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_breaks_long_forms() {
+        let source = "(def (add-all x y z) (+ x y z)) ; adds";
+        assert_eq!(
+            format_source(source, 20),
+            Ok("(def (add-all x y z)\n  (+ x y z)) ; adds\n".to_string())
+        );
+        assert_eq!(
+            format_source("(list 100 200 300)", 12),
+            Ok("(list 100\n      200\n      300)\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let source = "; header\n(def (f x)\n  ; body\n  x)\n";
+        let formatted = format_source(source, 80).unwrap();
+        assert_eq!(formatted, source);
+        assert_eq!(format_source(&formatted, 80).unwrap(), formatted);
+    }
+}
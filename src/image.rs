@@ -0,0 +1,440 @@
+//! Images: an interpreter's globals, with their docs and the traced
+//! functions, written to bytes and read back, so that a tool built on a
+//! large jon codebase starts without loading it again.
+//!
+//! Environments and lambdas are written once each, numbered, and referred
+//! to by number, so that closures keep sharing what they shared. Builtins
+//! are written by name and looked up again when the image is loaded, so
+//! host functions must be registered before then. Host values and actors
+//! cannot be written.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use thiserror::Error;
+
+use crate::cache::{push_len, push_str, Reader};
+use crate::complex::Complex;
+use crate::decimal::Decimal;
+use crate::env::Env;
+use crate::eval::{Doc, Origin};
+use crate::value::{Lambda, Value};
+
+/// Starts every image, followed by the version of jon that wrote it.
+const MAGIC: &[u8] = b"jon image 1\n";
+
+const NIL: u8 = 0;
+const FALSE: u8 = 1;
+const TRUE: u8 = 2;
+const NUMBER: u8 = 3;
+const STRING: u8 = 4;
+const SYMBOL: u8 = 5;
+const LIST: u8 = 6;
+const BUILTIN: u8 = 7;
+const LAMBDA: u8 = 8;
+const MACRO: u8 = 9;
+const DECIMAL: u8 = 10;
+const COMPLEX: u8 = 11;
+const BYTES: u8 = 12;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ImageError {
+    #[error("{name} cannot be saved in an image: it holds {value}")]
+    Unsaveable { name: String, value: String },
+    #[error("not an image from this version of jon")]
+    Invalid,
+    #[error("the image needs the builtin {0}, which is not registered")]
+    MissingBuiltin(String),
+}
+
+/// What an image holds of an interpreter.
+pub(crate) struct Parts {
+    pub global: Rc<RefCell<Env>>,
+    pub docs: BTreeMap<String, Doc>,
+    pub traced: BTreeSet<String>,
+}
+
+pub(crate) fn save(parts: &Parts) -> Result<Vec<u8>, ImageError> {
+    let mut writer = Writer::default();
+    writer.env(&parts.global)?;
+
+    let mut bytes = MAGIC.to_vec();
+    push_str(&mut bytes, env!("CARGO_PKG_VERSION"));
+    push_len(&mut bytes, writer.envs.len());
+    for env in &writer.envs {
+        match env.borrow().parent() {
+            Some(parent) => push_len(&mut bytes, writer.env_ids[&Rc::as_ptr(&parent)] + 1),
+            None => push_len(&mut bytes, 0),
+        }
+    }
+    push_len(&mut bytes, writer.lambdas.len());
+    for lambda in &writer.lambdas {
+        push_option(&mut bytes, lambda.name.as_deref());
+        push_len(&mut bytes, lambda.params.len());
+        for param in &lambda.params {
+            push_str(&mut bytes, param);
+        }
+        push_option(&mut bytes, lambda.rest.as_deref());
+        push_len(&mut bytes, writer.env_ids[&Rc::as_ptr(&lambda.env)]);
+        push_len(&mut bytes, lambda.body.len());
+        for form in &lambda.body {
+            writer.push_value(&mut bytes, form);
+        }
+    }
+    for env in &writer.envs {
+        let env = env.borrow();
+        let names = env.names();
+        push_len(&mut bytes, names.len());
+        for name in names {
+            push_str(&mut bytes, &name);
+            writer.push_value(&mut bytes, &env.get(&name).unwrap_or(Value::Nil));
+        }
+    }
+
+    push_len(&mut bytes, parts.docs.len());
+    for (name, doc) in &parts.docs {
+        push_str(&mut bytes, name);
+        push_option(&mut bytes, doc.docstring.as_deref());
+        match &doc.origin {
+            None => bytes.push(0),
+            Some(Origin::Builtin) => bytes.push(1),
+            Some(Origin::Prelude) => bytes.push(2),
+            Some(Origin::Source { name, line }) => {
+                bytes.push(3);
+                push_str(&mut bytes, name);
+                push_len(&mut bytes, *line);
+            }
+        }
+    }
+    push_len(&mut bytes, parts.traced.len());
+    for name in &parts.traced {
+        push_str(&mut bytes, name);
+    }
+    Ok(bytes)
+}
+
+/// The parts written by [`save`], with builtins looked up in `builtins`.
+pub(crate) fn load(bytes: &[u8], builtins: &Env) -> Result<Parts, ImageError> {
+    let mut reader = Reader {
+        bytes: bytes.strip_prefix(MAGIC).ok_or(ImageError::Invalid)?,
+    };
+    if reader.string().as_deref() != Some(env!("CARGO_PKG_VERSION")) {
+        return Err(ImageError::Invalid);
+    }
+    let mut loader = Loader {
+        reader,
+        builtins,
+        envs: Vec::new(),
+        lambdas: Vec::new(),
+    };
+    loader.read().ok_or(ImageError::Invalid)?
+}
+
+/// Numbers the environments and lambdas reachable from the globals, each
+/// environment after its parent and each lambda after those in its body.
+#[derive(Default)]
+struct Writer {
+    envs: Vec<Rc<RefCell<Env>>>,
+    env_ids: BTreeMap<*const RefCell<Env>, usize>,
+    lambdas: Vec<Rc<Lambda>>,
+    lambda_ids: BTreeMap<*const Lambda, usize>,
+}
+
+impl Writer {
+    fn env(&mut self, env: &Rc<RefCell<Env>>) -> Result<(), ImageError> {
+        if self.env_ids.contains_key(&Rc::as_ptr(env)) {
+            return Ok(());
+        }
+        if let Some(parent) = env.borrow().parent() {
+            self.env(&parent)?;
+        }
+        self.env_ids.insert(Rc::as_ptr(env), self.envs.len());
+        self.envs.push(env.clone());
+        let names = env.borrow().names();
+        for name in names {
+            let value = env.borrow().get(&name).unwrap_or(Value::Nil);
+            // named after the outermost binding that reaches it
+            self.value(&value).map_err(|err| match err {
+                ImageError::Unsaveable { value, .. } => ImageError::Unsaveable {
+                    name: name.clone(),
+                    value,
+                },
+                other => other,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, value: &Value) -> Result<(), ImageError> {
+        let unsaveable = || ImageError::Unsaveable {
+            name: String::new(),
+            value: value.to_string(),
+        };
+        match value {
+            Value::List(items) => items.iter().try_for_each(|item| self.value(item)),
+            Value::Lambda(lambda) | Value::Macro(lambda) => self.lambda(lambda),
+            Value::Host(_) | Value::Actor(_) => Err(unsaveable()),
+            _ => Ok(()),
+        }
+    }
+
+    fn lambda(&mut self, lambda: &Rc<Lambda>) -> Result<(), ImageError> {
+        if self.lambda_ids.contains_key(&Rc::as_ptr(lambda)) {
+            return Ok(());
+        }
+        self.env(&lambda.env)?;
+        lambda.body.iter().try_for_each(|form| self.value(form))?;
+        // numbering the environment may have reached this lambda already
+        if !self.lambda_ids.contains_key(&Rc::as_ptr(lambda)) {
+            self.lambda_ids
+                .insert(Rc::as_ptr(lambda), self.lambdas.len());
+            self.lambdas.push(lambda.clone());
+        }
+        Ok(())
+    }
+
+    fn push_value(&self, bytes: &mut Vec<u8>, value: &Value) {
+        match value {
+            Value::Nil => bytes.push(NIL),
+            Value::Bool(false) => bytes.push(FALSE),
+            Value::Bool(true) => bytes.push(TRUE),
+            Value::Number(n) => {
+                bytes.push(NUMBER);
+                bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Decimal(d) => {
+                bytes.push(DECIMAL);
+                push_str(bytes, &d.to_string());
+            }
+            Value::Complex(c) => {
+                bytes.push(COMPLEX);
+                bytes.extend_from_slice(&c.re.to_le_bytes());
+                bytes.extend_from_slice(&c.im.to_le_bytes());
+            }
+            Value::String(s) => {
+                bytes.push(STRING);
+                push_str(bytes, s);
+            }
+            Value::Bytes(data) => {
+                bytes.push(BYTES);
+                push_len(bytes, data.len());
+                bytes.extend_from_slice(data);
+            }
+            Value::Symbol(s) => {
+                bytes.push(SYMBOL);
+                push_str(bytes, s);
+            }
+            Value::List(items) => {
+                bytes.push(LIST);
+                push_len(bytes, items.len());
+                for item in items {
+                    self.push_value(bytes, item);
+                }
+            }
+            Value::Builtin(builtin) => {
+                bytes.push(BUILTIN);
+                push_str(bytes, builtin.name);
+            }
+            Value::Lambda(lambda) | Value::Macro(lambda) => {
+                let tag = match value {
+                    Value::Macro(_) => MACRO,
+                    _ => LAMBDA,
+                };
+                bytes.push(tag);
+                push_len(bytes, self.lambda_ids[&Rc::as_ptr(lambda)]);
+            }
+            Value::Host(_) | Value::Actor(_) => unreachable!("checked by Writer::value"),
+        }
+    }
+}
+
+fn push_option(bytes: &mut Vec<u8>, s: Option<&str>) {
+    match s {
+        Some(s) => {
+            bytes.push(1);
+            push_str(bytes, s);
+        }
+        None => bytes.push(0),
+    }
+}
+
+struct Loader<'a> {
+    reader: Reader<'a>,
+    builtins: &'a Env,
+    envs: Vec<Rc<RefCell<Env>>>,
+    lambdas: Vec<Rc<Lambda>>,
+}
+
+impl Loader<'_> {
+    /// The parts of the image, `None` if it is damaged, or a missing
+    /// builtin.
+    fn read(&mut self) -> Option<Result<Parts, ImageError>> {
+        for _ in 0..self.reader.len()? {
+            let env = match self.reader.len()? {
+                0 => Env::new(),
+                parent => Env::with_parent(self.envs.get(parent - 1)?.clone()),
+            };
+            self.envs.push(Rc::new(RefCell::new(env)));
+        }
+        for _ in 0..self.reader.len()? {
+            let name = self.option()?;
+            let params = (0..self.reader.len()?)
+                .map(|_| self.reader.string())
+                .collect::<Option<_>>()?;
+            let rest = self.option()?;
+            let env = self.envs.get(self.reader.len()?)?.clone();
+            let mut body = Vec::new();
+            for _ in 0..self.reader.len()? {
+                match self.value()? {
+                    Ok(form) => body.push(form),
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+            self.lambdas.push(Rc::new(Lambda {
+                name,
+                params,
+                rest,
+                body,
+                env,
+            }));
+        }
+        for i in 0..self.envs.len() {
+            for _ in 0..self.reader.len()? {
+                let name = self.reader.string()?;
+                match self.value()? {
+                    Ok(value) => self.envs[i].borrow_mut().define(&name, value),
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+        }
+
+        let mut docs = BTreeMap::new();
+        for _ in 0..self.reader.len()? {
+            let name = self.reader.string()?;
+            let docstring = self.option()?;
+            let origin = match self.reader.take(1)?[0] {
+                0 => None,
+                1 => Some(Origin::Builtin),
+                2 => Some(Origin::Prelude),
+                3 => Some(Origin::Source {
+                    name: self.reader.string()?,
+                    line: self.reader.len()?,
+                }),
+                _ => return None,
+            };
+            docs.insert(name, Doc { docstring, origin });
+        }
+        let traced = (0..self.reader.len()?)
+            .map(|_| self.reader.string())
+            .collect::<Option<_>>()?;
+        if !self.reader.bytes.is_empty() {
+            return None;
+        }
+        Some(Ok(Parts {
+            global: self.envs.first()?.clone(),
+            docs,
+            traced,
+        }))
+    }
+
+    fn option(&mut self) -> Option<Option<String>> {
+        match self.reader.take(1)?[0] {
+            0 => Some(None),
+            1 => self.reader.string().map(Some),
+            _ => None,
+        }
+    }
+
+    fn value(&mut self) -> Option<Result<Value, ImageError>> {
+        let value = match self.reader.take(1)?[0] {
+            NIL => Value::Nil,
+            FALSE => Value::Bool(false),
+            TRUE => Value::Bool(true),
+            NUMBER => Value::Number(f32::from_le_bytes(self.reader.take(4)?.try_into().ok()?)),
+            DECIMAL => Value::Decimal(Decimal::parse(&self.reader.string()?)?),
+            COMPLEX => {
+                let re = f32::from_le_bytes(self.reader.take(4)?.try_into().ok()?);
+                let im = f32::from_le_bytes(self.reader.take(4)?.try_into().ok()?);
+                Value::Complex(Complex::new(re, im))
+            }
+            STRING => Value::String(self.reader.string()?),
+            BYTES => {
+                let len = self.reader.len()?;
+                Value::Bytes(self.reader.take(len)?.to_vec())
+            }
+            SYMBOL => Value::Symbol(self.reader.string()?),
+            LIST => {
+                let mut items = Vec::new();
+                for _ in 0..self.reader.len()? {
+                    match self.value()? {
+                        Ok(item) => items.push(item),
+                        err => return Some(err),
+                    }
+                }
+                Value::List(items)
+            }
+            BUILTIN => {
+                let name = self.reader.string()?;
+                match self.builtins.get(&name) {
+                    Some(Value::Builtin(builtin)) if builtin.name == name => {
+                        Value::Builtin(builtin)
+                    }
+                    _ => return Some(Err(ImageError::MissingBuiltin(name))),
+                }
+            }
+            LAMBDA => Value::Lambda(self.lambdas.get(self.reader.len()?)?.clone()),
+            MACRO => Value::Macro(self.lambdas.get(self.reader.len()?)?.clone()),
+            _ => return None,
+        };
+        Some(Ok(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+    use crate::value::{HostValue, Value};
+
+    use super::*;
+
+    #[test]
+    fn test_image_round_trips() {
+        let mut x = Interpreter::new();
+        x.register_fn("host-add", |_, args| Ok(args[0].clone()));
+        x.eval_str(
+            "(def (make n) (let ((m (* n 2))) (lambda () (list n m))))
+             (def pair (make 3))
+             (def (shout s) \"Loud.\" (string/append s \"!\"))
+             (defmulti area :shape)
+             (defmethod area :square (s) 4)
+             (trace shout)",
+        )
+        .unwrap();
+        let image = x.save_image().unwrap();
+
+        let mut y = Interpreter::bare();
+        assert_eq!(
+            y.load_image(&image),
+            Err(ImageError::MissingBuiltin("host-add".into()))
+        );
+        y.register_fn("host-add", |_, args| Ok(args[0].clone()));
+        y.load_image(&image).unwrap();
+        assert_eq!(
+            y.eval_str("(list (pair) (shout \"hi\") (area '((:shape :square))) (inc 1))"),
+            x.eval_str("'((3 6) \"hi!\" 4 2)")
+        );
+        assert_eq!(y.doc("shout"), x.doc("shout"));
+        assert!(y.is_traced("shout"));
+        assert_eq!(y.load_image(b"jon image 1\n"), Err(ImageError::Invalid));
+
+        x.set_global("socket", Value::Host(HostValue::new("socket", ())));
+        assert_eq!(
+            x.save_image(),
+            Err(ImageError::Unsaveable {
+                name: "socket".into(),
+                value: "#<host socket>".into()
+            })
+        );
+    }
+}
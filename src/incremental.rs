@@ -0,0 +1,220 @@
+//! Reparsing after edits, for editors that keep a parse of a file that
+//! changes on every keystroke.
+//!
+//! A [`Document`] keeps its top-level forms with their spans. After an edit
+//! it keeps the forms that end before the edit, then reparses from there
+//! until a new form ends exactly where an old one did, past the edit. From
+//! that point the text and the scanner's state are the same as before, so
+//! the remaining forms are reused with their spans shifted.
+
+use alloc::{string::String, vec::Vec};
+use core::ops::Range;
+
+use crate::parser::{Expr, ParseError, Parser, Span};
+use crate::scanner::{ScanError, Scanner, Token};
+
+/// What an edit leaves of the old parse past the edit, with offsets moved
+/// to the new text.
+#[derive(Default)]
+struct Tail {
+    /// Where old forms ended, and so where reparsing may stop.
+    ends: Vec<usize>,
+    forms: Vec<(Span, Expr)>,
+    error: Option<ParseError>,
+}
+
+/// Source text with its parsed top-level forms.
+pub struct Document {
+    source: String,
+    forms: Vec<(Span, Expr)>,
+    /// The first parse error. Only the forms before it are kept.
+    error: Option<ParseError>,
+}
+
+impl Document {
+    pub fn new(source: impl Into<String>) -> Self {
+        let mut document = Self {
+            source: source.into(),
+            forms: Vec::new(),
+            error: None,
+        };
+        document.reparse(0, Tail::default());
+        document
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The top-level forms up to the first parse error, each with its span.
+    pub fn forms(&self) -> &[(Span, Expr)] {
+        &self.forms
+    }
+
+    pub fn error(&self) -> Option<&ParseError> {
+        self.error.as_ref()
+    }
+
+    /// Replaces the bytes in `range` with `replacement` and reparses the
+    /// forms the edit can affect, returning the indices of the forms that
+    /// were parsed again. Like [`String::replace_range`], it panics if
+    /// `range` is out of bounds or not on character boundaries.
+    pub fn edit(&mut self, range: Span, replacement: &str) -> Range<usize> {
+        self.source.replace_range(range.clone(), replacement);
+        let added = replacement.len();
+        let removed = range.len();
+
+        // a form ending right at the edit may run on into the inserted text
+        let kept = self
+            .forms
+            .partition_point(|(span, _)| span.end < range.start);
+        let moved = |offset: usize| offset + added - removed;
+        let mut tail = Tail::default();
+        for (span, expr) in self.forms.split_off(kept) {
+            if span.end >= range.end {
+                tail.ends.push(moved(span.end));
+            }
+            if span.start >= range.end {
+                tail.forms.push((moved(span.start)..moved(span.end), expr));
+            }
+        }
+        // an error before the edit stops the new parse again before any end
+        tail.error = self
+            .error
+            .take()
+            .filter(|err| err.span().is_none_or(|span| span.start >= range.end))
+            .map(|err| shift(err, moved));
+
+        let start = self.forms.last().map_or(0, |(span, _)| span.end);
+        let reused = self.reparse(start, tail);
+        kept..self.forms.len() - reused
+    }
+
+    /// Parses forms from the byte offset `start` until one ends at one of
+    /// `tail.ends`, then takes the rest of the parse from `tail`, returning
+    /// how many forms were reused.
+    fn reparse(&mut self, start: usize, tail: Tail) -> usize {
+        self.error = None;
+        let mut ends = tail.ends.into_iter().peekable();
+        let mut scanner = Scanner::new(&self.source[start..]);
+        loop {
+            let span = match next_form(&mut scanner) {
+                Ok(Some(span)) => start + span.start..start + span.end,
+                Ok(None) => break,
+                Err(at) => {
+                    // the parser decides which error a full parse would report
+                    let at = start + at;
+                    let mut parser = Parser::new(&mut Scanner::new(&self.source[at..]));
+                    let err = parser.parse_expr().err().unwrap_or(ParseError::Eof);
+                    self.error = Some(shift(err, |offset| offset + at));
+                    break;
+                }
+            };
+            let mut parser = Parser::new(&mut Scanner::new(&self.source[span.clone()]));
+            match parser.parse_expr() {
+                Ok(expr) => self.forms.push((span.clone(), expr)),
+                Err(err) => {
+                    self.error = Some(shift(err, |offset| offset + span.start));
+                    break;
+                }
+            }
+            while ends.next_if(|&end| end < span.end).is_some() {}
+            if ends.next_if(|&end| end == span.end).is_some() {
+                let parsed = self.forms.len();
+                let forms = tail.forms.into_iter();
+                self.forms
+                    .extend(forms.filter(|(old, _)| old.start >= span.end));
+                self.error = tail.error;
+                return self.forms.len() - parsed;
+            }
+        }
+        0
+    }
+}
+
+/// The span of the next top-level form, found from the tokens alone, or
+/// `None` at the end of the text. A form that cannot be read fails with
+/// the offset it starts at.
+fn next_form(scanner: &mut Scanner) -> Result<Option<Span>, usize> {
+    let mut start = None;
+    let mut depth = 0;
+    loop {
+        let position = scanner.position();
+        let tok = match scanner.next_token() {
+            Ok(tok) => tok,
+            Err(ScanError::Eof) if start.is_none() => return Ok(None),
+            Err(_) => return Err(start.unwrap_or(position)),
+        };
+        match tok.token {
+            Token::WhiteSpace(_) | Token::Comment(_) => continue,
+            Token::Quote => {
+                start.get_or_insert(tok.position);
+                continue;
+            }
+            Token::LParen => depth += 1,
+            Token::RParen if depth == 0 => return Err(*start.get_or_insert(tok.position)),
+            Token::RParen => depth -= 1,
+            Token::Symbol(_) | Token::Number(_) | Token::String(_) | Token::Bytes(_) => {}
+        }
+        let start = *start.get_or_insert(tok.position);
+        if depth == 0 {
+            return Ok(Some(start..tok.span().end));
+        }
+    }
+}
+
+/// Moves the offsets of an error, such as one found in a slice of the text
+/// to where the slice starts.
+fn shift(err: ParseError, by: impl Fn(usize) -> usize) -> ParseError {
+    match err {
+        ParseError::Eof => ParseError::Eof,
+        ParseError::UnexpectedToken { found, span } => ParseError::UnexpectedToken {
+            found,
+            span: by(span.start)..by(span.end),
+        },
+        ParseError::Scan { position, source } => ParseError::Scan {
+            position: by(position),
+            source,
+        },
+        ParseError::InvalidInput(position, message) => {
+            ParseError::InvalidInput(by(position), message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_parse(source: &str) -> Result<Vec<(Span, Expr)>, ParseError> {
+        Parser::new(&mut Scanner::new(source)).parse_forms()
+    }
+
+    #[test]
+    fn test_edits_match_a_full_parse() {
+        let mut document = Document::new("(def a 1) ; one\n(def b 2)\n(def c 3)\n");
+        let edits: &[(Span, &str, Range<usize>)] = &[
+            // inside the second form: only it is reparsed
+            (23..24, "20", 1..2),
+            // right after the first form, which may have grown
+            (9..9, "x", 0..2),
+            // an unterminated string stops the parse until it is removed
+            (11..11, "\"", 2..2),
+            (11..12, "", 2..4),
+            // a new form, reparsed with the next one, where the old parse
+            // has no form ending
+            (11..11, "'(e) ", 2..4),
+        ];
+        for (range, replacement, reparsed) in edits {
+            assert_eq!(&document.edit(range.clone(), replacement), reparsed);
+            match full_parse(document.source()) {
+                Ok(forms) => assert_eq!(document.forms(), forms, "{}", document.source()),
+                Err(err) => assert_eq!(document.error(), Some(&err)),
+            }
+        }
+        assert_eq!(
+            document.source(),
+            "(def a 1)x '(e) ; one\n(def b 20)\n(def c 3)\n"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use alloc::{collections::BTreeMap, rc::Rc, vec::Vec};
+use core::cell::RefCell;
+
+use crate::env::Env;
+use crate::eval::Interpreter;
+use crate::value::{Lambda, Value};
+
+/// An interpreter made by [`Interpreter::clone_isolated`], which shares no
+/// `Rc` with any other and so can be moved to another thread.
+///
+/// A server evaluating jon on several worker threads sets up one
+/// interpreter, then hands each worker an isolated copy of it:
+///
+/// ```
+/// use std::thread;
+///
+/// let mut setup = jon::Interpreter::new();
+/// setup.eval_str("(def (greet name) (string/append \"hi \" name))").unwrap();
+///
+/// let workers: Vec<_> = ["ann", "bo"]
+///     .into_iter()
+///     .map(|name| {
+///         let isolated = setup.clone_isolated();
+///         thread::spawn(move || {
+///             let mut interpreter = isolated.into_inner();
+///             interpreter.set_global("name", name);
+///             interpreter.eval_str("(greet name)").unwrap().to_string()
+///         })
+///     })
+///     .collect();
+/// let greetings: Vec<String> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+/// assert_eq!(greetings, ["\"hi ann\"", "\"hi bo\""]);
+/// ```
+pub struct Isolated {
+    interpreter: Interpreter,
+    /// Values copied along with the interpreter, such as a function and
+    /// the arguments `pmap` calls it with.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    values: Vec<Value>,
+}
+
+// SAFETY: `clone_isolated` builds every `Rc` reachable from the interpreter
+// and the values afresh and drops hooks and ports, which may hold shared
+// state, so no reference count or `RefCell` is reachable from outside and
+// moving the whole graph to another thread is sound. Host functions are
+// shared, but they are `Send + Sync`.
+unsafe impl Send for Isolated {}
+
+impl Isolated {
+    pub(crate) fn new(interpreter: Interpreter, values: Vec<Value>) -> Self {
+        Self {
+            interpreter,
+            values,
+        }
+    }
+
+    pub fn into_inner(self) -> Interpreter {
+        self.interpreter
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn into_parts(self) -> (Interpreter, Vec<Value>) {
+        (self.interpreter, self.values)
+    }
+}
+
+/// Deep copies of environments and lambdas, keyed by the address of the
+/// original so that sharing and cycles are kept.
+#[derive(Default)]
+pub(crate) struct Copier {
+    envs: BTreeMap<*const RefCell<Env>, Rc<RefCell<Env>>>,
+    lambdas: BTreeMap<*const Lambda, Rc<Lambda>>,
+}
+
+impl Copier {
+    pub fn env(&mut self, env: &Rc<RefCell<Env>>) -> Rc<RefCell<Env>> {
+        if let Some(copy) = self.envs.get(&Rc::as_ptr(env)) {
+            return copy.clone();
+        }
+        let parent = env.borrow().parent().map(|parent| self.env(&parent));
+        let copy = Rc::new(RefCell::new(match parent {
+            Some(parent) => Env::with_parent(parent),
+            None => Env::new(),
+        }));
+        // registered before the values, which may close over `env` itself
+        self.envs.insert(Rc::as_ptr(env), copy.clone());
+        let names = env.borrow().names();
+        for name in names {
+            let value = env.borrow().get(&name).unwrap_or(Value::Nil);
+            let value = self.value(&value);
+            copy.borrow_mut().define(&name, value);
+        }
+        copy
+    }
+
+    pub fn value(&mut self, value: &Value) -> Value {
+        match value {
+            Value::List(list) => Value::List(list.iter().map(|item| self.value(item)).collect()),
+            Value::Lambda(lambda) => Value::Lambda(self.lambda(lambda)),
+            Value::Macro(lambda) => Value::Macro(self.lambda(lambda)),
+            other => other.clone(),
+        }
+    }
+
+    fn lambda(&mut self, lambda: &Rc<Lambda>) -> Rc<Lambda> {
+        if let Some(copy) = self.lambdas.get(&Rc::as_ptr(lambda)) {
+            return copy.clone();
+        }
+        let env = self.env(&lambda.env);
+        // copying the environment may have reached this lambda already
+        if let Some(copy) = self.lambdas.get(&Rc::as_ptr(lambda)) {
+            return copy.clone();
+        }
+        let copy = Rc::new(Lambda {
+            name: lambda.name.clone(),
+            params: lambda.params.clone(),
+            rest: lambda.rest.clone(),
+            body: lambda.body.iter().map(|form| self.value(form)).collect(),
+            env,
+        });
+        self.lambdas.insert(Rc::as_ptr(lambda), copy.clone());
+        copy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_is_independent() {
+        let mut original = Interpreter::new();
+        original
+            .eval_str("(def n 1) (def counter (let ((step 2)) (lambda (x) (+ x step n))))")
+            .unwrap();
+        let mut copy = original.clone_isolated().into_inner();
+        copy.eval_str("(def n 10)").unwrap();
+        assert_eq!(copy.eval_str("(counter 0)"), Ok(Value::Number(12.0)));
+        assert_eq!(original.eval_str("(counter 0)"), Ok(Value::Number(3.0)));
+        assert_eq!(copy.eval_str("(= counter counter)"), Ok(Value::Bool(true)));
+        assert_eq!(copy.doc("inc"), original.doc("inc"));
+    }
+}
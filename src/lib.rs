@@ -0,0 +1,147 @@
+//! jon, a small Lisp.
+//!
+//! The pipeline is [`scanner`] → [`parser`] → [`eval`]: source text is
+//! scanned into tokens, parsed into [`parser::Expr`] forms, and evaluated by
+//! an [`Interpreter`] into [`Value`]s. Failures are [`ScanError`]s,
+//! [`ParseError`]s, and [`EvalError`]s, all gathered in [`Error`], which
+//! [`diagnostic`] renders against the source. Numbers are `f32`s, exact
+//! [`decimal`]s when written with an `m` suffix, such as `19.99m`, or
+//! [`complex`] numbers such as `3+4i`.
+//!
+//! [`eval_str`] and [`Interpreter::eval_str`] run the whole pipeline in one
+//! call:
+//!
+//! ```
+//! use jon::{Interpreter, Value};
+//!
+//! assert_eq!(jon::eval_str("(+ 1 2)"), Ok(Value::Number(3.0)));
+//!
+//! let mut interpreter = Interpreter::new();
+//! interpreter.eval_str("(def (square x) (* x x))").unwrap();
+//! assert_eq!(interpreter.eval_str("(square 4)"), Ok(Value::Number(16.0)));
+//! ```
+//!
+//! Host data crosses over with the [`IntoValue`] and [`FromValue`] traits,
+//! or the `From`/`TryFrom` impls built on them: [`Interpreter::set_global`]
+//! hands a value to scripts, [`Interpreter::global`] reads one back,
+//! [`Interpreter::register_fn`] adds a native function, and
+//! [`Interpreter::call`] calls a jon function from Rust, and
+//! [`Interpreter::deny`] keeps untrusted scripts from files and the other
+//! [`capability`]s. [`Interpreter::on_error`] lets the host see, replace,
+//! or suppress each error before it propagates. An interpreter is
+//! single-threaded; [`Interpreter::clone_isolated`] copies one for use on
+//! another thread, and scripts reach other threads through `pmap` and
+//! [`actor`]s. With the default `serde` feature, jon is also a serde
+//! data format: `to_string` and `from_str` read and write derived Rust types
+//! as s-expressions, without evaluating anything. Hosts reading config
+//! files written in jon can check them against a [`schema`], with errors
+//! pointing into the file.
+//!
+//! The tooling behind the `jon` binary is public too: [`check`] and
+//! [`lint`] for static checks, [`formatter`], [`session`] saving, and the
+//! [`debugger`], [`trace`], and [`audit`] evaluation hooks. Editors can keep a parse up
+//! to date as the text changes with [`incremental`], and [`bench`] holds the
+//! workloads `jon bench` and the criterion benchmarks time.
+//!
+//! The `repl` feature, on by default, builds the binary's line editor and
+//! pulls in rustyline. A library user who only embeds the interpreter can
+//! turn off default features and pick `std` and, if wanted, `serde`. The
+//! `capi` feature adds the C API declared in `include/jon.h`, and `async`
+//! adds `Interpreter::eval_async` for tokio services. The `toml` and
+//! `yaml` features add builtins reading and writing those formats, in
+//! [`config`]. The `wasm`
+//! feature adds JavaScript bindings in `wasm` for a `wasm32-unknown-unknown`
+//! build, which should leave `repl` off.
+//!
+//! Without the default `std` feature the crate is `no_std` and needs only
+//! `alloc`: the scanner, parser, evaluator, conversions, and the serde format
+//! if enabled remain, `print` output is discarded unless [`Interpreter::set_output`]
+//! gives it somewhere to go, and `read-line` always sees the end of input.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod actor;
+#[cfg(feature = "std")]
+pub mod audit;
+pub mod bench;
+pub mod builtins;
+#[cfg(feature = "std")]
+pub mod cache;
+pub mod capability;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "std")]
+pub mod check;
+#[cfg(feature = "std")]
+pub mod compile;
+pub mod complex;
+#[cfg(any(feature = "toml", feature = "yaml"))]
+pub mod config;
+pub mod convert;
+#[cfg(feature = "std")]
+pub mod coverage;
+#[cfg(feature = "serde")]
+pub mod de;
+#[cfg(feature = "std")]
+pub mod debugger;
+pub mod decimal;
+pub mod diagnostic;
+#[cfg(feature = "std")]
+pub mod docgen;
+pub mod encoding;
+pub mod env;
+pub mod error;
+pub mod eval;
+pub mod float_format;
+#[cfg(feature = "std")]
+pub mod formatter;
+#[cfg(feature = "std")]
+pub mod image;
+pub mod incremental;
+pub mod isolated;
+#[cfg(feature = "std")]
+pub mod lint;
+#[cfg(feature = "std")]
+pub mod logging;
+#[cfg(feature = "std")]
+pub mod parallel;
+pub mod parser;
+pub mod pretty;
+pub mod scanner;
+pub mod schema;
+#[cfg(feature = "serde")]
+pub mod ser;
+#[cfg(feature = "std")]
+pub mod session;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod trace;
+pub mod value;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use convert::{FromValue, IntoValue, IntoValueList};
+#[cfg(feature = "serde")]
+pub use de::from_str;
+#[cfg(feature = "serde")]
+pub use error::DataError;
+pub use error::Error;
+pub use eval::{EvalError, Interpreter};
+pub use isolated::Isolated;
+pub use parser::ParseError;
+pub use scanner::ScanError;
+#[cfg(feature = "serde")]
+pub use ser::to_string;
+#[cfg(all(feature = "serde", feature = "std"))]
+pub use ser::to_string_pretty;
+pub use value::Value;
+
+/// Evaluates every form of `source` in a fresh interpreter with the
+/// prelude, returning the value of the last one, or nil if there is none.
+pub fn eval_str(source: &str) -> Result<Value, Error> {
+    Interpreter::new().eval_str(source)
+}
@@ -0,0 +1,379 @@
+use std::collections::HashSet;
+
+use crate::builtins;
+use crate::check::find_symbol;
+use crate::diagnostic::{Diagnostic, Level};
+use crate::eval::Interpreter;
+use crate::parser::{ParseError, Parser, Span};
+use crate::scanner::Scanner;
+use crate::value::Value;
+
+/// The project lint configuration, read from the current directory.
+pub const CONFIG_FILE: &str = ".jonlint";
+
+/// A warning reported by `jon check --lint`. Config files and `; lint:`
+/// comments refer to it by code or by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedBinding,
+    ShadowedDefinition,
+    EmptyBody,
+    LiteralCondition,
+    Deprecated,
+}
+
+impl Lint {
+    const ALL: [Lint; 5] = [
+        Lint::UnusedBinding,
+        Lint::ShadowedDefinition,
+        Lint::EmptyBody,
+        Lint::LiteralCondition,
+        Lint::Deprecated,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Lint::UnusedBinding => "W0001",
+            Lint::ShadowedDefinition => "W0002",
+            Lint::EmptyBody => "W0003",
+            Lint::LiteralCondition => "W0004",
+            Lint::Deprecated => "W0005",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedBinding => "unused-binding",
+            Lint::ShadowedDefinition => "shadowed-definition",
+            Lint::EmptyBody => "empty-body",
+            Lint::LiteralCondition => "literal-condition",
+            Lint::Deprecated => "deprecated",
+        }
+    }
+
+    fn hint(self) -> Option<&'static str> {
+        match self {
+            Lint::UnusedBinding => Some("remove the binding, or start its name with _"),
+            Lint::ShadowedDefinition => Some("rename one of the definitions"),
+            _ => None,
+        }
+    }
+
+    fn parse(word: &str) -> Option<Lint> {
+        Lint::ALL
+            .into_iter()
+            .find(|lint| lint.code() == word || lint.name() == word)
+    }
+}
+
+/// Which lints are reported. All of them are by default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LintConfig {
+    disabled: HashSet<Lint>,
+}
+
+impl LintConfig {
+    /// The configuration from `.jonlint` in the current directory, if any.
+    pub fn load() -> Result<Self, String> {
+        let mut config = Self::default();
+        if let Ok(text) = std::fs::read_to_string(CONFIG_FILE) {
+            for (number, line) in text.lines().enumerate() {
+                let line = line.split('#').next().unwrap_or_default();
+                config
+                    .apply(line)
+                    .map_err(|err| format!("{}:{}: {}", CONFIG_FILE, number + 1, err))?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Applies one directive: `allow` or `warn` followed by lint names or
+    /// codes. Blank directives are ignored.
+    pub fn apply(&mut self, directive: &str) -> Result<(), String> {
+        let mut words = directive.split_whitespace();
+        let allow = match words.next() {
+            None => return Ok(()),
+            Some("allow") => true,
+            Some("warn") => false,
+            Some(other) => return Err(format!("expected allow or warn, found {}", other)),
+        };
+        for word in words {
+            let lint = Lint::parse(word).ok_or_else(|| format!("unknown lint {}", word))?;
+            if allow {
+                self.disabled.insert(lint);
+            } else {
+                self.disabled.remove(&lint);
+            }
+        }
+        Ok(())
+    }
+
+    /// This configuration overridden by the `; lint: allow NAME...` comment
+    /// lines of `source`.
+    pub fn for_file(&self, source: &str) -> Result<Self, String> {
+        let mut config = self.clone();
+        for (number, line) in source.lines().enumerate() {
+            let Some(comment) = line.trim_start().strip_prefix(';') else {
+                continue;
+            };
+            let comment = comment.trim_start_matches(';').trim_start();
+            if let Some(directive) = comment.strip_prefix("lint:") {
+                config
+                    .apply(directive)
+                    .map_err(|err| format!("line {}: {}", number + 1, err))?;
+            }
+        }
+        Ok(config)
+    }
+
+    fn enabled(&self, lint: Lint) -> bool {
+        !self.disabled.contains(&lint)
+    }
+}
+
+/// Lints every form of `source`. `interpreter` supplies the builtin and
+/// prelude names that top-level definitions may shadow.
+pub fn lint_source(
+    interpreter: &Interpreter,
+    config: &LintConfig,
+    source: &str,
+) -> Result<Vec<Diagnostic>, ParseError> {
+    let mut scanner = Scanner::new(source);
+    let mut parser = Parser::new(&mut scanner);
+    let forms = parser.parse_forms()?;
+
+    let mut linter = Linter {
+        interpreter,
+        defined: HashSet::new(),
+        warnings: Vec::new(),
+    };
+    let mut diagnostics = Vec::new();
+    for (span, expr) in &forms {
+        let form = Value::from(expr);
+        linter.definition(&form);
+        linter.form(&form);
+        for warning in linter.warnings.drain(..) {
+            if config.enabled(warning.lint) {
+                diagnostics.push(warning.diagnostic(source, span));
+            }
+        }
+    }
+    Ok(diagnostics)
+}
+
+struct Warning {
+    lint: Lint,
+    message: String,
+    /// The symbol to point at, and whether it is the head of a list.
+    symbol: String,
+    called: bool,
+}
+
+impl Warning {
+    fn diagnostic(self, source: &str, span: &Span) -> Diagnostic {
+        Diagnostic {
+            level: Level::Warning,
+            code: self.lint.code(),
+            message: format!("{} ({})", self.message, self.lint.name()),
+            span: find_symbol(source, span, &self.symbol, self.called)
+                .unwrap_or_else(|| span.clone()),
+            hint: self.lint.hint(),
+            expanded_from: Vec::new(),
+        }
+    }
+}
+
+struct Linter<'i> {
+    interpreter: &'i Interpreter,
+    /// Names defined so far at the top level of the file.
+    defined: HashSet<String>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, lint: Lint, message: String, symbol: &str, called: bool) {
+        self.warnings.push(Warning {
+            lint,
+            message,
+            symbol: symbol.into(),
+            called,
+        });
+    }
+
+    /// Warns when a top-level definition replaces an existing one.
+    fn definition(&mut self, form: &Value) {
+        let name = match form {
+            Value::List(list) => match list.as_slice() {
+                [Value::Symbol(head), Value::List(signature), ..]
+                    if head == "def" || head == "defmacro" =>
+                {
+                    match signature.first() {
+                        Some(Value::Symbol(name)) => name,
+                        _ => return,
+                    }
+                }
+                [Value::Symbol(head), Value::Symbol(name), ..] if head == "def" => name,
+                _ => return,
+            },
+            _ => return,
+        };
+        if self.defined.contains(name) || self.interpreter.get_global(name).is_some() {
+            self.warn(
+                Lint::ShadowedDefinition,
+                format!("{} shadows an existing definition", name),
+                name,
+                false,
+            );
+        }
+        self.defined.insert(name.clone());
+    }
+
+    fn form(&mut self, form: &Value) {
+        let Value::List(list) = form else {
+            return;
+        };
+        let Some(Value::Symbol(head)) = list.first() else {
+            return list.iter().for_each(|item| self.form(item));
+        };
+        let args = &list[1..];
+        match (head.as_str(), args) {
+            ("quote", _) => return,
+            ("def" | "defmacro", [Value::List(_)]) | ("lambda", [Value::List(_)]) => self.warn(
+                Lint::EmptyBody,
+                format!("{} has an empty body", head),
+                head,
+                true,
+            ),
+            ("let", [Value::List(bindings), body @ ..]) => {
+                if body.is_empty() {
+                    self.warn(Lint::EmptyBody, "let has an empty body".into(), head, true);
+                }
+                self.let_bindings(bindings, body);
+            }
+            ("if" | "when" | "unless", [test, ..]) => {
+                if let Some(truthy) = literal_truth(test) {
+                    self.warn(
+                        Lint::LiteralCondition,
+                        format!("the condition of this {} is always {}", head, truthy),
+                        head,
+                        true,
+                    );
+                }
+            }
+            _ => {
+                if let Some((_, replacement)) =
+                    builtins::DEPRECATED.iter().find(|(name, _)| name == head)
+                {
+                    self.warn(
+                        Lint::Deprecated,
+                        format!("{} is deprecated, use {} instead", head, replacement),
+                        head,
+                        true,
+                    );
+                }
+            }
+        }
+        args.iter().for_each(|arg| self.form(arg));
+    }
+
+    /// Warns about `let` bindings that neither a later binding nor the body
+    /// refers to.
+    fn let_bindings(&mut self, bindings: &[Value], body: &[Value]) {
+        for (i, binding) in bindings.iter().enumerate() {
+            let Value::List(pair) = binding else {
+                continue;
+            };
+            let Some(Value::Symbol(name)) = pair.first() else {
+                continue;
+            };
+            let later = bindings[i + 1..].iter().chain(body);
+            if !name.starts_with('_') && !later.clone().any(|form| mentions(form, name)) {
+                self.warn(
+                    Lint::UnusedBinding,
+                    format!("unused let binding {}", name),
+                    name,
+                    false,
+                );
+            }
+        }
+    }
+}
+
+/// Whether `form` refers to the symbol `name` outside of quoted data.
+fn mentions(form: &Value, name: &str) -> bool {
+    match form {
+        Value::Symbol(symbol) => symbol == name,
+        Value::List(list) => match list.first() {
+            Some(Value::Symbol(head)) if head == "quote" => false,
+            _ => list.iter().any(|item| mentions(item, name)),
+        },
+        _ => false,
+    }
+}
+
+/// The truthiness of `test` if it is a literal, whose value cannot depend on
+/// the program.
+fn literal_truth(test: &Value) -> Option<bool> {
+    match test {
+        Value::Number(_) | Value::String(_) => Some(true),
+        Value::Symbol(symbol) => match symbol.as_str() {
+            "true" => Some(true),
+            "nil" | "false" => Some(false),
+            _ => None,
+        },
+        Value::List(list) => match list.first() {
+            None => Some(true),
+            Some(Value::Symbol(head)) if head == "quote" => {
+                list.get(1).map(|quoted| quoted.is_truthy())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(source: &str) -> Vec<(&'static str, Span)> {
+        let config = LintConfig::default().for_file(source).unwrap();
+        lint_source(&Interpreter::new(), &config, source)
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.span))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_warnings() {
+        assert_eq!(
+            lint("(def (f x) (let ((y 1) (z x)) z))"),
+            vec![("W0001", 18..19)]
+        );
+        assert_eq!(
+            lint("(def (g) 1)\n(def g 2)\n(def (map) 3)"),
+            vec![("W0002", 17..18), ("W0002", 28..31)]
+        );
+        assert_eq!(lint("(lambda (x))"), vec![("W0003", 1..7)]);
+        assert_eq!(
+            lint("(if 1 2 3) (when nil 4) (if x 5)"),
+            vec![("W0004", 1..3), ("W0004", 12..16)]
+        );
+    }
+
+    #[test]
+    fn test_lint_config() {
+        assert_eq!(
+            lint("; lint: allow unused-binding\n(let ((x 1)) 2)"),
+            vec![]
+        );
+        let mut config = LintConfig::default();
+        config.apply("allow W0004 empty-body").unwrap();
+        assert!(!config.enabled(Lint::LiteralCondition));
+        config.apply("warn literal-condition").unwrap();
+        assert!(config.enabled(Lint::LiteralCondition));
+        assert!(config.apply("allow bogus").is_err());
+        assert!(config.apply("deny empty-body").is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! Leveled logs for long-running scripts: `log/debug`, `log/info`, and
+//! `log/warn` send a [`Record`] of a message and optional data to the
+//! interpreter's [`Logger`], which writes those at or above its minimum
+//! level to its [`Target`].
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::value::Value;
+
+/// How important a record is, least first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+        }
+    }
+
+    /// The level called `name`, which may be a keyword such as `:warn`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.strip_prefix(':').unwrap_or(name) {
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" => Some(Level::Warn),
+            _ => None,
+        }
+    }
+}
+
+/// One logged message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub level: Level,
+    pub message: String,
+    /// What the message is about, or nil if the script gave nothing.
+    pub data: Value,
+}
+
+/// A line such as `[warn] disk almost full ((:free 12))`.
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.level.name(), self.message)?;
+        match &self.data {
+            Value::Nil => Ok(()),
+            data => write!(f, " {}", data),
+        }
+    }
+}
+
+/// Where a [`Logger`] sends the records it keeps.
+pub enum Target {
+    Stderr,
+    /// A file, written a line per record.
+    File(File),
+    /// A function of the host, given each record instead of a line.
+    Host(Box<dyn FnMut(&Record)>),
+}
+
+/// The minimum level an interpreter logs, and where to.
+pub struct Logger {
+    pub level: Level,
+    pub target: Target,
+}
+
+impl Default for Logger {
+    /// Info and above to standard error.
+    fn default() -> Self {
+        Self {
+            level: Level::Info,
+            target: Target::Stderr,
+        }
+    }
+}
+
+impl Logger {
+    /// Sends `record` to the target unless it is below the minimum level.
+    pub fn log(&mut self, record: &Record) -> io::Result<()> {
+        if record.level < self.level {
+            return Ok(());
+        }
+        match &mut self.target {
+            Target::Stderr => writeln!(io::stderr(), "{}", record),
+            Target::File(file) => writeln!(file, "{}", record),
+            Target::Host(callback) => {
+                callback(record);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::eval::Interpreter;
+
+    #[test]
+    fn test_log_to_host() {
+        let records = Rc::new(RefCell::new(Vec::new()));
+        let seen = records.clone();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_log_target(Target::Host(Box::new(move |record| {
+            seen.borrow_mut().push(record.to_string())
+        })));
+        interpreter
+            .eval_str(
+                "(log/debug \"hidden\")
+                 (log/info \"started\" '((:port 8080)))
+                 (log/set-level :debug)
+                 (log/debug 'tick)
+                 (log/set-level :warn)
+                 (log/info \"hidden\")
+                 (log/warn \"disk almost full\" 12)",
+            )
+            .unwrap();
+        assert_eq!(
+            *records.borrow(),
+            [
+                "[info] started ((:port 8080))",
+                "[debug] tick",
+                "[warn] disk almost full 12",
+            ]
+        );
+    }
+}
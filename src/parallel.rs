@@ -0,0 +1,280 @@
+//! The threads behind `pmap`. A pure function can be called on any thread
+//! in any order with the same results, so `pmap` splits the list into one
+//! chunk per core and maps each chunk in an isolated copy of the
+//! interpreter.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::num::NonZeroUsize;
+use std::panic;
+use std::rc::Rc;
+use std::thread;
+
+use crate::env::Env;
+use crate::eval::{is_keyword, EvalError, Interpreter, STACK_SIZE};
+use crate::isolated::Isolated;
+use crate::value::{Lambda, Value};
+
+/// Whether calling `value`, if it is a function, or any function inside it
+/// has no effect beyond computing a result: only pure builtins are called,
+/// and nothing is defined. Data is pure; macros are not.
+pub fn is_pure(value: &Value) -> bool {
+    Purity::default().value(value)
+}
+
+#[derive(Default)]
+struct Purity {
+    /// Lambdas seen so far, taken to be pure while their bodies are checked
+    /// so that recursion ends.
+    seen: BTreeSet<*const Lambda>,
+}
+
+impl Purity {
+    fn value(&mut self, value: &Value) -> bool {
+        match value {
+            Value::Builtin(builtin) => builtin.pure,
+            Value::Lambda(lambda) => self.lambda(lambda),
+            Value::Macro(_) => false,
+            Value::List(items) => items.iter().all(|item| self.value(item)),
+            _ => true,
+        }
+    }
+
+    fn lambda(&mut self, lambda: &Rc<Lambda>) -> bool {
+        if !self.seen.insert(Rc::as_ptr(lambda)) {
+            return true;
+        }
+        let mut locals: Vec<&str> = lambda.params.iter().map(String::as_str).collect();
+        locals.extend(lambda.rest.as_deref());
+        let env = &lambda.env;
+        lambda
+            .body
+            .iter()
+            .all(|form| self.form(form, env, &mut locals))
+    }
+
+    /// Whether evaluating `form` in a call of a lambda closing over `env`,
+    /// with the parameters and `let` bindings `locals`, is pure.
+    fn form<'a>(
+        &mut self,
+        form: &'a Value,
+        env: &Rc<RefCell<Env>>,
+        locals: &mut Vec<&'a str>,
+    ) -> bool {
+        let list = match form {
+            Value::Symbol(name) if locals.contains(&name.as_str()) || is_keyword(name) => {
+                return true
+            }
+            Value::Symbol(name) => {
+                let value = env.borrow().get(name);
+                return value.is_some_and(|value| self.value(&value));
+            }
+            Value::List(list) => list,
+            _ => return true,
+        };
+        let scope = locals.len();
+        let pure = match list.first() {
+            Some(Value::Symbol(head)) => match head.as_str() {
+                "quote" | "bindings" => true,
+                "def" | "defmacro" => false,
+                "if" | "do" | "and" | "or" | "recur" | "unwind-protect" => {
+                    list[1..].iter().all(|form| self.form(form, env, locals))
+                }
+                "lambda" => match list.get(1) {
+                    Some(Value::List(params)) => {
+                        locals.extend(params.iter().filter_map(|param| match param {
+                            Value::Symbol(name) if name != "&" => Some(name.as_str()),
+                            _ => None,
+                        }));
+                        list[2..].iter().all(|form| self.form(form, env, locals))
+                    }
+                    _ => false,
+                },
+                "handler-case" => list[1..].iter().enumerate().all(|(i, form)| match form {
+                    Value::List(clause) if i > 0 => match clause.split_first() {
+                        Some((Value::List(head), body)) => {
+                            locals.extend(head.get(1).and_then(|name| match name {
+                                Value::Symbol(name) => Some(name.as_str()),
+                                _ => None,
+                            }));
+                            body.iter().all(|form| self.form(form, env, locals))
+                        }
+                        _ => false,
+                    },
+                    form => self.form(form, env, locals),
+                }),
+                "let" | "loop" => match list.get(1) {
+                    Some(Value::List(bindings)) => {
+                        bindings.iter().all(|binding| match binding {
+                            Value::List(pair) => match pair.as_slice() {
+                                [Value::Symbol(name), expr] => {
+                                    let pure = self.form(expr, env, locals);
+                                    locals.push(name);
+                                    pure
+                                }
+                                _ => false,
+                            },
+                            _ => false,
+                        }) && list[2..].iter().all(|form| self.form(form, env, locals))
+                    }
+                    _ => false,
+                },
+                _ => list.iter().all(|form| self.form(form, env, locals)),
+            },
+            _ => list.iter().all(|form| self.form(form, env, locals)),
+        };
+        locals.truncate(scope);
+        pure
+    }
+}
+
+/// Whether `value` holds no lambda, and so nothing tied to the
+/// environments of the interpreter that made it.
+pub(crate) fn is_data(value: &Value) -> bool {
+    match value {
+        Value::Lambda(_) | Value::Macro(_) => false,
+        Value::List(items) => items.iter().all(is_data),
+        _ => true,
+    }
+}
+
+/// Calls `callee` on each of `items` across several threads, or returns
+/// `None` when that cannot be done: `callee` or an item is not pure, a
+/// result is a function, or there is only one core or item.
+pub(crate) fn map(
+    interpreter: &Interpreter,
+    callee: &Value,
+    items: &[Value],
+) -> Option<Result<Vec<Value>, EvalError>> {
+    let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    map_on(cores, interpreter, callee, items)
+}
+
+/// Like [`map`], on at most `threads` threads.
+fn map_on(
+    threads: usize,
+    interpreter: &Interpreter,
+    callee: &Value,
+    items: &[Value],
+) -> Option<Result<Vec<Value>, EvalError>> {
+    let threads = threads.min(items.len());
+    if threads < 2 || !is_pure(callee) || !items.iter().all(is_pure) {
+        return None;
+    }
+    let chunk_len = items.len().div_ceil(threads);
+    let jobs: Vec<Isolated> = items
+        .chunks(chunk_len)
+        .map(|chunk| {
+            let mut values = vec![callee.clone()];
+            values.extend_from_slice(chunk);
+            interpreter.clone_isolated_with(&values)
+        })
+        .collect();
+
+    let outcomes: Vec<Result<Isolated, EvalError>> = thread::scope(|scope| {
+        let workers: Vec<_> = jobs
+            .into_iter()
+            .map(|job| {
+                thread::Builder::new()
+                    .stack_size(STACK_SIZE)
+                    .spawn_scoped(scope, move || map_chunk(job))
+                    .expect("failed to spawn a pmap thread")
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|err| panic::resume_unwind(err))
+            })
+            .collect()
+    });
+
+    let mut results = Vec::with_capacity(items.len());
+    for outcome in outcomes {
+        // the first error in list order is the one a sequential map raises
+        let (_, chunk) = match outcome {
+            Ok(done) => done.into_parts(),
+            Err(err) => return Some(Err(err)),
+        };
+        if !chunk.iter().all(is_data) {
+            return None;
+        }
+        results.extend(chunk);
+    }
+    Some(Ok(results))
+}
+
+/// Maps the function at the front of the job's values over the rest,
+/// handing the results back with the interpreter they were made in.
+fn map_chunk(job: Isolated) -> Result<Isolated, EvalError> {
+    let (mut interpreter, values) = job.into_parts();
+    let mut values = values.into_iter();
+    let callee = values.next().unwrap();
+    let results = values
+        .map(|item| interpreter.apply(&callee, vec![item]))
+        .collect::<Result<_, _>>()?;
+    // Only pure builtins ran, without hooks, so nothing outside the
+    // interpreter and the results shares their `Rc`s.
+    Ok(Isolated::new(interpreter, results))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pmap() {
+        let mut x = Interpreter::new();
+        x.eval_str("(def (square n) (* n n)) (def (shout s) (print s) s)")
+            .unwrap();
+        let global = |x: &Interpreter, name| x.get_global(name).unwrap();
+        assert!(is_pure(&global(&x, "square")));
+        assert!(is_pure(&global(&x, "map")));
+        assert!(!is_pure(&global(&x, "shout")));
+        assert!(!is_pure(&global(&x, "when")));
+
+        assert_eq!(
+            x.eval_str("(pmap square (range 0 40))"),
+            x.eval_str("(map square (range 0 40))")
+        );
+        let Ok(Value::List(numbers)) = x.eval_str("(collect (range 0 10))") else {
+            unreachable!()
+        };
+        let Ok(Value::List(squares)) = x.eval_str("(map square (range 0 10))") else {
+            unreachable!()
+        };
+        let square = global(&x, "square");
+        assert_eq!(map_on(4, &x, &square, &numbers), Some(Ok(squares)));
+        let first = global(&x, "car");
+        assert_eq!(
+            map_on(4, &x, &first, &numbers),
+            Some(Err(EvalError::TypeMismatch {
+                expected: "list",
+                found: "number"
+            }))
+        );
+        let make = x.eval_str("(lambda (n) (lambda () n))").unwrap();
+        assert_eq!(map_on(4, &x, &make, &numbers), None);
+        let shout = global(&x, "shout");
+        assert_eq!(map_on(4, &x, &shout, &numbers), None);
+        assert_eq!(
+            x.eval_str("(pmap (lambda (n) (let ((m (inc n))) (list n m))) '(1 2))"),
+            x.eval_str("'((1 2) (2 3))")
+        );
+        assert_eq!(
+            x.eval_str("(pmap (lambda (n) (lambda () n)) '(1 2))")
+                .map(|value| value.to_string()),
+            Ok("(#<lambda> #<lambda>)".into())
+        );
+        assert_eq!(
+            x.eval_str("(pmap car '((1) 2 (3)))"),
+            Err(EvalError::TypeMismatch {
+                expected: "list",
+                found: "number"
+            }
+            .into())
+        );
+    }
+}
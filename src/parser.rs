@@ -1,20 +1,44 @@
+use alloc::{
+    borrow::Cow,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{fmt::Write, ops::Range};
+
 use thiserror::Error;
 
-use crate::scanner::{Scanner, TokItem, Token};
+use crate::complex::Complex;
+use crate::decimal::Decimal;
+use crate::scanner::{unescape, ScanError, Scanner, TokItem, Token};
 
-#[derive(Error, Debug, Eq, PartialEq)]
-pub enum ParseError<'input> {
+/// Why the parser stopped. It owns its data, so it outlives the parsed
+/// text.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ParseError {
     #[error("End of Input")]
-    EOF,
-    #[error("Unexpected token {0}")]
-    UnexpectedToken(TokItem<'input>),
+    Eof,
+    #[error("Unexpected token {found}")]
+    UnexpectedToken { found: String, span: Span },
+    /// The scanner failed at `position`.
+    #[error("{source}")]
+    Scan { position: usize, source: ScanError },
+    #[error("{1}")]
+    InvalidInput(usize, String),
 }
 
+/// A byte range in the parsed text.
+pub type Span = Range<usize>;
+
 #[derive(Debug, PartialEq)]
 pub enum Atom {
     Symbol(String),
     Number(f32),
+    Decimal(Decimal),
+    Complex(Complex),
     String(String),
+    Bytes(Vec<u8>),
 }
 
 #[derive(Debug, PartialEq)]
@@ -25,45 +49,177 @@ pub enum Expr {
 
 type List = Vec<Expr>;
 
+/// An atom whose symbol or string text borrows from the parsed input, so
+/// that reading a large file does not allocate for each of them. A string
+/// is only copied when it has escapes to replace.
+#[derive(Debug, PartialEq)]
+pub enum BorrowedAtom<'input> {
+    Symbol(&'input str),
+    Number(f32),
+    Decimal(Decimal),
+    Complex(Complex),
+    Bytes(Vec<u8>),
+    String(Cow<'input, str>),
+}
+
+/// An expression of [`BorrowedAtom`]s, as [`Parser::parse_borrowed_forms`]
+/// returns.
+#[derive(Debug, PartialEq)]
+pub enum BorrowedExpr<'input> {
+    Atom(BorrowedAtom<'input>),
+    List(Vec<BorrowedExpr<'input>>),
+}
+
+impl BorrowedAtom<'_> {
+    pub fn into_owned(self) -> Atom {
+        match self {
+            BorrowedAtom::Symbol(s) => Atom::Symbol(s.into()),
+            BorrowedAtom::Number(n) => Atom::Number(n),
+            BorrowedAtom::Decimal(d) => Atom::Decimal(d),
+            BorrowedAtom::Complex(c) => Atom::Complex(c),
+            BorrowedAtom::Bytes(bytes) => Atom::Bytes(bytes),
+            BorrowedAtom::String(s) => Atom::String(s.into_owned()),
+        }
+    }
+}
+
+impl BorrowedExpr<'_> {
+    pub fn into_owned(self) -> Expr {
+        match self {
+            BorrowedExpr::Atom(atom) => Expr::Atom(atom.into_owned()),
+            BorrowedExpr::List(list) => {
+                Expr::List(list.into_iter().map(BorrowedExpr::into_owned).collect())
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// The expression as JSON, for tools outside Rust: `{"number":1}`,
+    /// `{"decimal":"19.99"}`, `{"complex":[3,4]}`, `{"string":"a"}`,
+    /// `{"bytes":[1,2]}`, `{"symbol":"car"}`, or `{"list":[...]}`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        self.write_json(&mut json);
+        json
+    }
+
+    fn write_json(&self, json: &mut String) {
+        match self {
+            Expr::Atom(Atom::Number(n)) => {
+                let _ = write!(json, "{{\"number\":{}}}", n);
+            }
+            Expr::Atom(Atom::Decimal(d)) => {
+                let _ = write!(json, "{{\"decimal\":\"{}\"}}", d);
+            }
+            Expr::Atom(Atom::Complex(c)) => {
+                let _ = write!(json, "{{\"complex\":[{},{}]}}", c.re, c.im);
+            }
+            Expr::Atom(Atom::String(s)) => {
+                json.push_str("{\"string\":");
+                push_json_string(json, s);
+                json.push('}');
+            }
+            Expr::Atom(Atom::Bytes(bytes)) => {
+                json.push_str("{\"bytes\":[");
+                for (i, byte) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        json.push(',');
+                    }
+                    let _ = write!(json, "{}", byte);
+                }
+                json.push_str("]}");
+            }
+            Expr::Atom(Atom::Symbol(s)) => {
+                json.push_str("{\"symbol\":");
+                push_json_string(json, s);
+                json.push('}');
+            }
+            Expr::List(items) => {
+                json.push_str("{\"list\":[");
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        json.push(',');
+                    }
+                    item.write_json(json);
+                }
+                json.push_str("]}");
+            }
+        }
+    }
+}
+
+/// Appends `s` to `json` as a quoted JSON string.
+pub(crate) fn push_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", u32::from(c));
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
 pub struct Parser<'input> {
     tokens: Vec<TokItem<'input>>,
     current_pos: usize,
+    /// A scanner failure, reported once the parser reaches that point of
+    /// the input instead of a plain end of input.
+    scan_error: Option<ParseError>,
 }
 
 impl<'input> Parser<'input> {
     pub fn new(scanner: &mut Scanner<'input>) -> Self {
+        let mut tokens = Vec::new();
+        let mut scan_error = None;
+        loop {
+            let position = scanner.position();
+            match scanner.next_token() {
+                Ok(TokItem {
+                    token: Token::WhiteSpace(_) | Token::Comment(_),
+                    position: _,
+                }) => {}
+                Ok(tok) => tokens.push(tok),
+                Err(ScanError::Eof) => break,
+                Err(err) => {
+                    scan_error = Some(ParseError::Scan {
+                        position,
+                        source: err,
+                    });
+                    break;
+                }
+            }
+        }
         Self {
-            tokens: scanner
-                .scan_all()
-                .into_iter()
-                .filter(|x| {
-                    !matches!(
-                        x,
-                        TokItem {
-                            token: Token::WhiteSpace(_),
-                            position: _
-                        }
-                    )
-                })
-                .collect(),
+            tokens,
             current_pos: 0,
+            scan_error,
         }
     }
 
-    fn get_token(&self) -> Result<&TokItem<'input>, ParseError<'input>> {
-        self.tokens.get(self.current_pos).ok_or(ParseError::EOF)
+    fn get_token(&self) -> Result<&TokItem<'input>, ParseError> {
+        self.tokens
+            .get(self.current_pos)
+            .ok_or_else(|| self.scan_error.clone().unwrap_or(ParseError::Eof))
     }
 
     fn at_eof(&self) -> bool {
-        self.current_pos >= self.tokens.len()
+        self.current_pos >= self.tokens.len() && self.scan_error.is_none()
     }
 
     fn advance(&mut self) {
         self.current_pos += 1;
     }
 
-    fn match_token(&mut self, tok: &Token) -> Result<(), ParseError<'input>> {
-        eprintln!("{}:{:?}: matching {:?}", self.current_pos, self.tokens, tok);
+    fn match_token(&mut self, tok: &Token) -> Result<(), ParseError> {
         match self.get_token()? {
             TokItem {
                 token: t,
@@ -72,30 +228,57 @@ impl<'input> Parser<'input> {
                 self.advance();
                 Ok(())
             }
-            x => Err(ParseError::UnexpectedToken(*x)),
+            x => Err(ParseError::unexpected(x)),
         }
     }
 
-    pub fn parse_atom(&mut self) -> Result<Atom, ParseError<'input>> {
-        if self.at_eof() {
-            return Err(ParseError::EOF);
-        };
+    pub fn parse_atom(&mut self) -> Result<Atom, ParseError> {
+        self.borrowed_atom().map(BorrowedAtom::into_owned)
+    }
 
+    fn borrowed_atom(&mut self) -> Result<BorrowedAtom<'input>, ParseError> {
         let result = match self.get_token()? {
             TokItem {
                 token: Token::Number(n),
-                position: _,
-            } => Ok(Atom::Number(n.parse().unwrap())),
+                position,
+            } => {
+                let atom = match n.strip_suffix('m') {
+                    Some(decimal) => Decimal::parse(decimal).map(BorrowedAtom::Decimal),
+                    None if n.ends_with('i') => Complex::parse(n).map(BorrowedAtom::Complex),
+                    None => n.parse().ok().map(BorrowedAtom::Number),
+                };
+                atom.ok_or_else(|| {
+                    ParseError::InvalidInput(*position, format!("Invalid number {}", n))
+                })
+            }
             TokItem {
                 token: Token::String(s),
-                position: _,
-            } => Ok(Atom::String((*s).into())),
+                position,
+            } => match unescape(s) {
+                Ok(s) => Ok(BorrowedAtom::String(s)),
+                Err(err) => Err(ParseError::InvalidInput(
+                    position + 1 + err.offset,
+                    err.to_string(),
+                )),
+            },
+            TokItem {
+                token: Token::Bytes(text),
+                position,
+            } => text[4..text.len() - 1]
+                .split_whitespace()
+                .map(|byte| {
+                    byte.parse().map_err(|_| {
+                        ParseError::InvalidInput(*position, format!("Invalid byte {}", byte))
+                    })
+                })
+                .collect::<Result<_, _>>()
+                .map(BorrowedAtom::Bytes),
             TokItem {
                 token: Token::Symbol(s),
                 position: _,
-            } => Ok(Atom::Symbol((*s).into())),
+            } => Ok(BorrowedAtom::Symbol(s)),
 
-            x => Err(ParseError::UnexpectedToken(*x)),
+            x => Err(ParseError::unexpected(x)),
         };
 
         if result.is_ok() {
@@ -105,31 +288,130 @@ impl<'input> Parser<'input> {
         result
     }
 
-    pub fn parse_list(&mut self) -> Result<Expr, ParseError<'input>> {
-        eprintln!("{}:{:?}: parsing list", self.current_pos, self.tokens);
-        let mut list = List::new();
-        if self.at_eof() {
-            eprintln!("{}:{:?}: at eof", self.current_pos, self.tokens);
-            return Err(ParseError::EOF);
-        };
+    pub fn parse_list(&mut self) -> Result<Expr, ParseError> {
+        self.borrowed_list().map(BorrowedExpr::into_owned)
+    }
 
+    fn borrowed_list(&mut self) -> Result<BorrowedExpr<'input>, ParseError> {
+        let mut list = Vec::new();
         self.match_token(&Token::LParen)?;
 
-        while let Ok(expr) = self.parse_expr() {
-            list.push(expr);
+        loop {
+            match self.borrowed_expr() {
+                Ok(expr) => list.push(expr),
+                // a malformed atom, rather than the end of the list
+                Err(e @ ParseError::InvalidInput(..)) => return Err(e),
+                Err(_) => break,
+            }
         }
 
         self.match_token(&Token::RParen)?;
-        Ok(Expr::List(list))
+        Ok(BorrowedExpr::List(list))
     }
 
-    pub fn parse_expr(&mut self) -> Result<Expr, ParseError<'input>> {
-        match self.parse_atom() {
-            Ok(atom) => Ok(Expr::Atom(atom)),
-            Err(_) => match self.parse_list() {
-                Ok(list) => Ok(list),
-                Err(e) => Err(e),
-            },
+    pub fn parse_quote(&mut self) -> Result<Expr, ParseError> {
+        self.borrowed_quote().map(BorrowedExpr::into_owned)
+    }
+
+    fn borrowed_quote(&mut self) -> Result<BorrowedExpr<'input>, ParseError> {
+        self.match_token(&Token::Quote)?;
+        let quoted = self.borrowed_expr()?;
+        Ok(BorrowedExpr::List(vec![
+            BorrowedExpr::Atom(BorrowedAtom::Symbol("quote")),
+            quoted,
+        ]))
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        self.borrowed_expr().map(BorrowedExpr::into_owned)
+    }
+
+    fn borrowed_expr(&mut self) -> Result<BorrowedExpr<'input>, ParseError> {
+        if let Ok(TokItem {
+            token: Token::Quote,
+            position: _,
+        }) = self.get_token()
+        {
+            return self.borrowed_quote();
+        }
+        match self.borrowed_atom() {
+            Ok(atom) => Ok(BorrowedExpr::Atom(atom)),
+            Err(e @ ParseError::InvalidInput(..)) => Err(e),
+            Err(_) => self.borrowed_list(),
+        }
+    }
+
+    pub fn parse_program(&mut self) -> Result<Vec<Expr>, ParseError> {
+        Ok(self
+            .parse_borrowed_forms()?
+            .into_iter()
+            .map(|(_, expr)| expr.into_owned())
+            .collect())
+    }
+
+    /// Parses every top-level form, pairing each with its span.
+    pub fn parse_forms(&mut self) -> Result<Vec<(Span, Expr)>, ParseError> {
+        Ok(self
+            .parse_borrowed_forms()?
+            .into_iter()
+            .map(|(span, expr)| (span, expr.into_owned()))
+            .collect())
+    }
+
+    /// Like [`Parser::parse_forms`], with the forms borrowing their text
+    /// from the input rather than copying it. The evaluator works on owned
+    /// values, so this saves the copy into an [`Expr`] for callers that go
+    /// straight on to a `Value`, such as `Interpreter::eval_str` and
+    /// `check_source`: each atom's text is then copied once, not twice.
+    pub fn parse_borrowed_forms(
+        &mut self,
+    ) -> Result<Vec<(Span, BorrowedExpr<'input>)>, ParseError> {
+        let mut program = Vec::new();
+        while !self.at_eof() {
+            let start = self.get_token()?.position;
+            let expr = self.borrowed_expr()?;
+            let end = self.tokens[self.current_pos - 1].span().end;
+            program.push((start..end, expr));
+        }
+        Ok(program)
+    }
+}
+
+impl ParseError {
+    /// An error pointing at `tok`, which the parser did not expect.
+    pub fn unexpected(tok: &TokItem) -> Self {
+        ParseError::UnexpectedToken {
+            found: format!("{:?}", tok.token),
+            span: tok.span(),
+        }
+    }
+
+    /// The part of the parsed text the error points at, or `None` when the
+    /// input ended early.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ParseError::Eof => None,
+            ParseError::UnexpectedToken { span, .. } => Some(span.clone()),
+            ParseError::Scan { position, .. } => Some(*position..*position + 1),
+            ParseError::InvalidInput(position, _) => Some(*position..*position + 1),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::Eof => "E0001",
+            ParseError::UnexpectedToken { .. } => "E0002",
+            ParseError::Scan { .. } | ParseError::InvalidInput(..) => "E0003",
+        }
+    }
+
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            ParseError::Eof => Some("a closing parenthesis or quote may be missing"),
+            ParseError::UnexpectedToken { found, .. } if found == "RParen" => {
+                Some("this parenthesis has no matching opening one")
+            }
+            _ => None,
         }
     }
 }
@@ -145,7 +427,7 @@ mod tests {
         assert_eq!(parser.parse_atom(), Ok(Atom::Number(1.0)));
         assert_eq!(parser.parse_atom(), Ok(Atom::Symbol("sdf".into())));
         assert_eq!(parser.parse_atom(), Ok(Atom::String("sadf".into())));
-        assert_eq!(parser.parse_atom(), Err(ParseError::EOF))
+        assert_eq!(parser.parse_atom(), Err(ParseError::Eof))
     }
 
     #[test]
@@ -186,4 +468,89 @@ mod tests {
             ]))
         );
     }
+
+    #[test]
+    fn test_parser_quote_and_program() {
+        use Atom::*;
+        let mut scanner = Scanner::new("'x ; comment\n(f)");
+        let mut parser = Parser::new(&mut scanner);
+
+        assert_eq!(
+            parser.parse_program(),
+            Ok(vec![
+                Expr::List(vec![
+                    Expr::Atom(Symbol("quote".into())),
+                    Expr::Atom(Symbol("x".into()))
+                ]),
+                Expr::List(vec![Expr::Atom(Symbol("f".into()))]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_borrowed_forms() {
+        let source = "(f \"plain\" \"tab\\t\")";
+        let mut scanner = Scanner::new(source);
+        let forms = Parser::new(&mut scanner).parse_borrowed_forms().unwrap();
+        let [(span, BorrowedExpr::List(items))] = forms.as_slice() else {
+            panic!("expected one list, got {:?}", forms);
+        };
+        assert_eq!(*span, 0..source.len());
+        assert!(matches!(
+            items.as_slice(),
+            [
+                BorrowedExpr::Atom(BorrowedAtom::Symbol("f")),
+                BorrowedExpr::Atom(BorrowedAtom::String(Cow::Borrowed("plain"))),
+                BorrowedExpr::Atom(BorrowedAtom::String(Cow::Owned(_))),
+            ]
+        ));
+        let mut scanner = Scanner::new(source);
+        assert_eq!(
+            forms
+                .into_iter()
+                .map(|(_, expr)| expr.into_owned())
+                .collect::<Vec<_>>(),
+            Parser::new(&mut scanner).parse_program().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_expr_to_json() {
+        let mut scanner = Scanner::new("(print 'x 1.5 \"a\tb\")");
+        let mut parser = Parser::new(&mut scanner);
+        assert_eq!(
+            parser.parse_expr().unwrap().to_json(),
+            r#"{"list":[{"symbol":"print"},{"list":[{"symbol":"quote"},{"symbol":"x"}]},{"number":1.5},{"string":"a\tb"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_parser_reports_invalid_input() {
+        let mut scanner = Scanner::new("(1 [)");
+        let mut parser = Parser::new(&mut scanner);
+        assert_eq!(
+            parser.parse_program(),
+            Err(ParseError::Scan {
+                position: 3,
+                source: ScanError::UnexpectedChar('[')
+            })
+        );
+
+        let mut scanner = Scanner::new("1.2.3");
+        let mut parser = Parser::new(&mut scanner);
+        assert_eq!(
+            parser.parse_atom(),
+            Err(ParseError::InvalidInput(0, "Invalid number 1.2.3".into()))
+        );
+
+        let mut scanner = Scanner::new("(\"ok \\u{1F600}\" \"bad \\u{110000}\")");
+        let mut parser = Parser::new(&mut scanner);
+        assert_eq!(
+            parser.parse_program(),
+            Err(ParseError::InvalidInput(
+                21,
+                "Invalid escape \\u{110000}: not a Unicode scalar value".into()
+            ))
+        );
+    }
 }
@@ -0,0 +1,240 @@
+//! A pretty printer for data, after Wadler's "A prettier printer": a value
+//! becomes a [`Doc`] of text and places it may break, grouped so that each
+//! group stays on one line when it fits in the width and breaks at every
+//! one of its places otherwise. Lists break with their items one per line,
+//! indented to line up after the opening parenthesis, so a long map shows
+//! one entry per line. [`Limits`] cut huge values short, as the REPL does
+//! with `*print-length*` and `*print-depth*`, and floats print in a
+//! [`FloatFormat`].
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::float_format::FloatFormat;
+use crate::value::Value;
+
+/// The width `pretty` wraps to when not given one.
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// How much of a value to print: at most `length` items of each list and
+/// lists nested at most `depth` deep, with `...` standing for the rest.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub length: Option<usize>,
+    pub depth: Option<usize>,
+}
+
+impl Limits {
+    /// Whether the limits leave out any of `value`.
+    pub fn elide(&self, value: &Value) -> bool {
+        self.elide_at(value, 0)
+    }
+
+    fn elide_at(&self, value: &Value, depth: usize) -> bool {
+        let Value::List(items) = value else {
+            return false;
+        };
+        self.depth
+            .is_some_and(|max| depth >= max && !items.is_empty())
+            || self.length.is_some_and(|max| items.len() > max)
+            || items.iter().any(|item| self.elide_at(item, depth + 1))
+    }
+}
+
+/// A document to lay out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Doc {
+    Text(String),
+    /// A space, or a new line at the current indentation if its group
+    /// breaks.
+    Line,
+    /// The document with new lines indented by that many more columns.
+    Nest(usize, Box<Doc>),
+    Concat(Vec<Doc>),
+    /// The document on one line if it fits, broken at each of its lines
+    /// otherwise.
+    Group(Box<Doc>),
+}
+
+impl Doc {
+    /// `value` as it prints, with a list able to break between its items.
+    pub fn value(value: &Value) -> Self {
+        Doc::limited(value, &Limits::default(), &FloatFormat::default(), 0)
+    }
+
+    /// `value` as it prints within `limits` with floats in `floats`, being
+    /// `depth` lists deep.
+    fn limited(value: &Value, limits: &Limits, floats: &FloatFormat, depth: usize) -> Self {
+        match value {
+            Value::List(items)
+                if limits.depth.is_some_and(|max| depth >= max) && !items.is_empty() =>
+            {
+                Doc::Text("(...)".into())
+            }
+            Value::List(items) if !items.is_empty() => {
+                let shown = limits
+                    .length
+                    .map_or(items.len(), |max| max.min(items.len()));
+                let mut body = Vec::with_capacity(shown * 2 + 2);
+                for (i, item) in items[..shown].iter().enumerate() {
+                    if i > 0 {
+                        body.push(Doc::Line);
+                    }
+                    body.push(Doc::limited(item, limits, floats, depth + 1));
+                }
+                if shown < items.len() {
+                    if shown > 0 {
+                        body.push(Doc::Line);
+                    }
+                    body.push(Doc::Text("...".into()));
+                }
+                Doc::Group(Box::new(Doc::Concat(vec![
+                    Doc::Text("(".into()),
+                    Doc::Nest(1, Box::new(Doc::Concat(body))),
+                    Doc::Text(")".into()),
+                ])))
+            }
+            other => Doc::Text(floats.value(other)),
+        }
+    }
+
+    /// The document laid out to `width` columns where it can be.
+    pub fn render(&self, width: usize) -> String {
+        let mut out = String::new();
+        let mut column = 0;
+        let mut stack = vec![(0, Mode::Break, self)];
+        while let Some((indent, mode, doc)) = stack.pop() {
+            match doc {
+                Doc::Text(text) => {
+                    out.push_str(text);
+                    column += text.chars().count();
+                }
+                Doc::Line if mode == Mode::Flat => {
+                    out.push(' ');
+                    column += 1;
+                }
+                Doc::Line => {
+                    out.push('\n');
+                    out.extend(core::iter::repeat_n(' ', indent));
+                    column = indent;
+                }
+                Doc::Nest(more, doc) => stack.push((indent + more, mode, doc)),
+                Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc))),
+                Doc::Group(doc) => {
+                    let room = width.saturating_sub(column);
+                    let mode = if fits(room, doc, &stack) {
+                        Mode::Flat
+                    } else {
+                        Mode::Break
+                    };
+                    stack.push((indent, mode, doc));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+/// Whether `doc` on one line, and what follows it up to the next line
+/// break, takes at most `room` columns.
+fn fits(room: usize, doc: &Doc, rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut room = room as isize;
+    let mut stack = vec![(Mode::Flat, doc)];
+    let mut rest = rest.iter().rev();
+    while room >= 0 {
+        let (mode, doc) = match stack.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, mode, doc)) => (mode, doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(text) => room -= text.chars().count() as isize,
+            Doc::Line if mode == Mode::Flat => room -= 1,
+            Doc::Line => return true,
+            Doc::Nest(_, doc) | Doc::Group(doc) => stack.push((mode, doc)),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (mode, doc))),
+        }
+    }
+    false
+}
+
+/// `value` as it prints, broken across lines to fit in `width` columns
+/// where it can be.
+pub fn pretty(value: &Value, width: usize) -> String {
+    Doc::value(value).render(width)
+}
+
+/// [`pretty`] with the parts of `value` beyond `limits` left out and its
+/// floats in `floats`.
+pub fn pretty_limited(
+    value: &Value,
+    width: usize,
+    limits: &Limits,
+    floats: &FloatFormat,
+) -> String {
+    Doc::limited(value, limits, floats, 0).render(width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pretty_breaks_long_lists() {
+        let entry =
+            |key: &str, n: f32| Value::List(vec![Value::String(key.into()), Value::Number(n)]);
+        let map = Value::List(vec![
+            entry("alpha", 1.0),
+            entry("beta", 2.0),
+            entry("gamma", 3.0),
+        ]);
+        assert_eq!(pretty(&map, 80), map.to_string());
+        assert_eq!(
+            pretty(&map, 20),
+            "((\"alpha\" 1)\n (\"beta\" 2)\n (\"gamma\" 3))"
+        );
+        let nested = Value::List(vec![Value::Symbol("config".into()), map]);
+        assert_eq!(
+            pretty(&nested, 24),
+            "(config\n ((\"alpha\" 1)\n  (\"beta\" 2)\n  (\"gamma\" 3)))"
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let numbers = Value::List((0..5).map(|n| Value::Number(n as f32)).collect());
+        let nested = Value::List(vec![Value::Number(1.0), Value::List(vec![numbers.clone()])]);
+        let limits = Limits {
+            length: Some(3),
+            depth: None,
+        };
+        assert_eq!(
+            pretty_limited(&numbers, 80, &limits, &FloatFormat::default()),
+            "(0 1 2 ...)"
+        );
+        assert!(limits.elide(&numbers));
+        let limits = Limits {
+            length: None,
+            depth: Some(2),
+        };
+        assert_eq!(
+            pretty_limited(&nested, 80, &limits, &FloatFormat::default()),
+            "(1 ((...)))"
+        );
+        assert!(limits.elide(&nested) && !limits.elide(&Value::Number(1.0)));
+        assert_eq!(
+            pretty_limited(&nested, 80, &Limits::default(), &FloatFormat::default()),
+            nested.to_string()
+        );
+    }
+}
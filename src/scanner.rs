@@ -1,7 +1,129 @@
-use std::{fmt::Display, io};
+use alloc::{borrow::Cow, string::String, vec::Vec};
+use core::{fmt::Display, ops::Range};
 
-fn is_identifier(c: char) -> bool {
-    c.is_alphabetic() || "-_@#$+=*&^%!".contains(c)
+use thiserror::Error;
+
+/// Why the scanner stopped.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanError {
+    /// The end of the text, where scanning stops normally.
+    #[error("Unexpected EOF")]
+    Eof,
+    #[error("Unterminated string")]
+    UnterminatedString,
+    #[error("Unterminated bytes")]
+    UnterminatedBytes,
+    #[error("Unexpected character {0:?}")]
+    UnexpectedChar(char),
+}
+
+/// A malformed escape sequence in a string literal, found `offset` bytes
+/// into the text between its quotes.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid escape {sequence}: {reason}")]
+pub struct EscapeError {
+    pub offset: usize,
+    pub sequence: String,
+    pub reason: &'static str,
+}
+
+/// The text between the quotes of a string literal with its escape
+/// sequences replaced: `\n`, `\t`, `\r`, `\0`, `\\`, and `\"`, `\xNN` for
+/// a code point below 128 in two hex digits, and `\u{N}` for any Unicode
+/// scalar value in one to six.
+pub fn unescape(content: &str) -> Result<Cow<'_, str>, EscapeError> {
+    let Some(first) = content.find('\\') else {
+        return Ok(Cow::Borrowed(content));
+    };
+    let mut text = String::from(&content[..first]);
+    let mut rest = &content[first..];
+    while let Some(i) = rest.find('\\') {
+        text.push_str(&rest[..i]);
+        let offset = content.len() - rest.len() + i;
+        let escape = &rest[i + 1..];
+        let error = |len: usize, reason| EscapeError {
+            offset,
+            sequence: content[offset..(offset + 1 + len).min(content.len())].into(),
+            reason,
+        };
+        let Some(kind) = escape.chars().next() else {
+            return Err(error(0, "nothing follows the backslash"));
+        };
+        let (c, len) = match kind {
+            'n' => ('\n', 1),
+            't' => ('\t', 1),
+            'r' => ('\r', 1),
+            '0' => ('\0', 1),
+            '\\' => ('\\', 1),
+            '"' => ('"', 1),
+            'x' => {
+                let hex = escape
+                    .get(1..3)
+                    .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()));
+                let Some(hex) = hex else {
+                    return Err(error(chars_len(escape, 3), "\\x takes two hex digits"));
+                };
+                match u8::from_str_radix(hex, 16) {
+                    Ok(code) if code < 0x80 => (char::from(code), 3),
+                    _ => return Err(error(3, "\\x only goes up to 7f; use \\u{...} above")),
+                }
+            }
+            'u' => {
+                let end = escape.find('}').map_or(chars_len(escape, 2), |end| end + 1);
+                let digits = escape[..end]
+                    .strip_prefix("u{")
+                    .and_then(|digits| digits.strip_suffix('}'))
+                    .filter(|digits| {
+                        (1..=6).contains(&digits.len())
+                            && digits.bytes().all(|b| b.is_ascii_hexdigit())
+                    });
+                let Some(digits) = digits else {
+                    return Err(error(end, "\\u takes one to six hex digits in braces"));
+                };
+                match u32::from_str_radix(digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                {
+                    Some(c) => (c, end),
+                    None => return Err(error(end, "not a Unicode scalar value")),
+                }
+            }
+            other => return Err(error(other.len_utf8(), "unknown escape")),
+        };
+        text.push(c);
+        rest = &escape[len..];
+    }
+    text.push_str(rest);
+    Ok(Cow::Owned(text))
+}
+
+/// The length in bytes of the first `n` characters of `text`, or of all
+/// of it if it is shorter.
+fn chars_len(text: &str, n: usize) -> usize {
+    text.char_indices().nth(n).map_or(text.len(), |(i, _)| i)
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || "-_@#$+=*&^%!?<>/:".contains(c)
+}
+
+pub fn is_identifier(c: char) -> bool {
+    is_identifier_start(c) || c.is_ascii_digit()
+}
+
+/// The length of the imaginary part at the start of `rest`, what follows
+/// the digits of a number: `+4i` in `3+4i`, or the `i` of `4i`.
+fn imaginary_len(rest: &str) -> Option<usize> {
+    let sign = usize::from(rest.starts_with(['+', '-']));
+    let digits = rest[sign..]
+        .bytes()
+        .take_while(|b| b.is_ascii_digit() || *b == b'.')
+        .count();
+    let end = sign + digits;
+    let imaginary = (sign == 0 || digits > 0)
+        && rest[end..].starts_with('i')
+        && !rest[end + 1..].chars().next().is_some_and(is_identifier);
+    imaginary.then_some(end + 1)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -12,17 +134,43 @@ pub enum Token<'input> {
     Symbol(&'input str),
     Number(&'input str),
     String(&'input str),
+    /// A bytes literal such as `#u8(1 2 255)`, parentheses and all.
+    Bytes(&'input str),
     WhiteSpace(&'input str),
+    Comment(&'input str),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TokItem<'input> {
     pub token: Token<'input>,
+    /// Byte offset of the token's first character in the scanned text.
     pub position: usize,
 }
 
+impl Token<'_> {
+    /// Length in bytes of the source text the token was scanned from.
+    pub fn text_len(&self) -> usize {
+        match self {
+            Token::LParen | Token::RParen | Token::Quote => 1,
+            Token::String(s) => s.len() + 2,
+            Token::Symbol(s)
+            | Token::Number(s)
+            | Token::Bytes(s)
+            | Token::WhiteSpace(s)
+            | Token::Comment(s) => s.len(),
+        }
+    }
+}
+
+impl TokItem<'_> {
+    /// The byte range of the token in the scanned text.
+    pub fn span(&self) -> Range<usize> {
+        self.position..self.position + self.token.text_len()
+    }
+}
+
 impl Display for TokItem<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{:#?},{:#?}", self.token, self.position)
     }
 }
@@ -39,27 +187,29 @@ impl<'input> Scanner<'input> {
             text,
         }
     }
-    fn peek(&self) -> Result<char, io::Error> {
-        self
-            .text
+    fn peek(&self) -> Result<char, ScanError> {
+        self.text[self.current_pos..]
             .chars()
-            .nth(self.current_pos)
-            .ok_or(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "Unexpected EOF",
-            ))
+            .next()
+            .ok_or(ScanError::Eof)
+    }
+    pub fn position(&self) -> usize {
+        self.current_pos
     }
-    fn advance(&mut self) -> Result<char, io::Error> {
+    fn peek_next(&self) -> Option<char> {
+        self.text[self.current_pos..].chars().nth(1)
+    }
+    fn advance(&mut self) -> Result<char, ScanError> {
         match self.peek() {
             Ok(ch) => {
-                self.current_pos += 1;
+                self.current_pos += ch.len_utf8();
                 Ok(ch)
             }
             Err(err) => Err(err),
         }
     }
 
-    pub fn next(&mut self) -> Result<TokItem<'input>, io::Error> {
+    pub fn next_token(&mut self) -> Result<TokItem<'input>, ScanError> {
         let ch = self.peek()?;
         match ch {
             '\'' => {
@@ -72,9 +222,16 @@ impl<'input> Scanner<'input> {
             '\"' => {
                 let start = self.current_pos;
                 self.advance().unwrap();
-                let string_content = self.advance_while(|ch| ch != '"').unwrap().unwrap();
-                assert_eq!(self.peek().unwrap(), '"');
-                self.advance().unwrap();
+                loop {
+                    match self.advance() {
+                        Ok('"') => break,
+                        // the escaped character, which unescape checks
+                        Ok('\\') => _ = self.advance(),
+                        Ok(_) => {}
+                        Err(_) => return Err(ScanError::UnterminatedString),
+                    }
+                }
+                let string_content = &self.text[start + 1..self.current_pos - 1];
                 Ok(TokItem {
                     token: Token::String(string_content),
                     position: start,
@@ -98,18 +255,49 @@ impl<'input> Scanner<'input> {
                 })
             }
 
-            x if x.is_ascii_digit() => {
+            ';' => {
+                let start = self.current_pos;
+                let comment = self.advance_while(|ch| ch != '\n').unwrap().unwrap();
+                Ok(TokItem {
+                    token: Token::Comment(comment),
+                    position: start,
+                })
+            }
+
+            x if x.is_ascii_digit()
+                || (x == '-' && self.peek_next().is_some_and(|c| c.is_ascii_digit())) =>
+            {
                 let start = self.current_pos;
-                let number = self
-                    .advance_while(|c| c.is_ascii_digit() || c == '.')
-                    .unwrap()
-                    .unwrap();
+                if x == '-' {
+                    self.advance().unwrap();
+                }
+                self.advance_while(|c| c.is_ascii_digit() || c == '.');
+                // a decimal such as 19.99m
+                if self.peek() == Ok('m') && !self.peek_next().is_some_and(is_identifier) {
+                    self.advance().unwrap();
+                } else if let Some(len) = imaginary_len(&self.text[self.current_pos..]) {
+                    // a complex number such as 3+4i or 4i
+                    self.current_pos += len;
+                }
+                let number = &self.text[start..self.current_pos];
                 Ok(TokItem {
                     token: Token::Number(number),
                     position: start,
                 })
             }
-            x if is_identifier(x) => {
+            '#' if self.text[self.current_pos..].starts_with("#u8(") => {
+                let start = self.current_pos;
+                let Some(len) = self.text[start..].find(')') else {
+                    self.current_pos = self.text.len();
+                    return Err(ScanError::UnterminatedBytes);
+                };
+                self.current_pos += len + 1;
+                Ok(TokItem {
+                    token: Token::Bytes(&self.text[start..self.current_pos]),
+                    position: start,
+                })
+            }
+            x if is_identifier_start(x) => {
                 let start = self.current_pos;
                 let identifer = self.advance_while(is_identifier).unwrap().unwrap();
                 Ok(TokItem {
@@ -132,14 +320,14 @@ impl<'input> Scanner<'input> {
                 })
             }
 
-            _ => unreachable!(),
+            x => Err(ScanError::UnexpectedChar(x)),
         }
     }
 
     fn advance_while<F: Fn(char) -> bool>(
         &mut self,
         check: F,
-    ) -> Option<Result<&'input str, io::Error>> {
+    ) -> Option<Result<&'input str, ScanError>> {
         let start = self.current_pos;
         match self.peek() {
             Ok(ch) if check(ch) => {
@@ -165,13 +353,46 @@ impl<'input> Scanner<'input> {
 
     pub fn scan_all(&mut self) -> Vec<TokItem<'input>> {
         let mut result = Vec::new();
-        while let Ok(tok) = self.next() {
+        while let Ok(tok) = self.next_token() {
             result.push(tok)
         }
         result
     }
 }
 
+/// The 1-based line and column of the byte offset `position` in `text`.
+pub fn line_column(text: &str, position: usize) -> (usize, usize) {
+    let before = &text[..position.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// How many parentheses are still open at the end of `text`. An unterminated
+/// string literal counts as one more open level, so callers reading input
+/// line by line know to keep going.
+pub fn open_depth(text: &str) -> usize {
+    let mut depth: usize = 0;
+    let mut in_string = false;
+    let mut in_comment = false;
+    let mut escaped = false;
+    for ch in text.chars() {
+        match ch {
+            '\n' if in_comment => in_comment = false,
+            _ if in_comment => {}
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            ';' => in_comment = true,
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    depth + usize::from(in_string)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -186,13 +407,13 @@ mod tests {
     #[should_panic]
     fn empty_text_gives_eof() {
         let mut scanner = Scanner::new("");
-        let _result = scanner.next().unwrap();
+        let _result = scanner.next_token().unwrap();
     }
 
     #[test]
     fn test_scanner_accepts_whitespace() {
         let mut scanner = Scanner::new("     ");
-        let result = scanner.next().unwrap();
+        let result = scanner.next_token().unwrap();
         assert_eq!(
             result,
             TokItem {
@@ -206,14 +427,14 @@ mod tests {
     #[should_panic]
     fn test_scanner_eof() {
         let mut scanner = Scanner::new("     ");
-        scanner.next().unwrap();
-        scanner.next().unwrap();
+        scanner.next_token().unwrap();
+        scanner.next_token().unwrap();
     }
 
     #[test]
     fn test_scanner_accepts_symbol() {
         let mut scanner = Scanner::new("abcde");
-        let result = scanner.next().unwrap();
+        let result = scanner.next_token().unwrap();
         assert_eq!(
             result,
             TokItem {
@@ -322,4 +543,129 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_scanner_accepts_comment_and_negative_number() {
+        let mut scanner = Scanner::new("; hi\n-12 x2");
+        let result = scanner.scan_all();
+        assert_eq!(
+            result,
+            vec![
+                TokItem {
+                    token: Token::Comment("; hi"),
+                    position: 0
+                },
+                TokItem {
+                    token: Token::WhiteSpace("\n"),
+                    position: 4
+                },
+                TokItem {
+                    token: Token::Number("-12"),
+                    position: 5
+                },
+                TokItem {
+                    token: Token::WhiteSpace(" "),
+                    position: 8
+                },
+                TokItem {
+                    token: Token::Symbol("x2"),
+                    position: 9
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_open_depth() {
+        assert_eq!(open_depth("(def (f x)"), 1);
+        assert_eq!(open_depth("(def (f x) x)"), 0);
+        assert_eq!(open_depth("(print \"(\" ; )"), 1);
+        assert_eq!(open_depth("(print \"abc"), 2);
+    }
+
+    #[test]
+    fn test_scanner_rejects_bad_input() {
+        let mut scanner = Scanner::new("\"abc");
+        assert_eq!(scanner.next_token(), Err(ScanError::UnterminatedString));
+
+        let mut scanner = Scanner::new("#u8(1 2");
+        assert_eq!(scanner.next_token(), Err(ScanError::UnterminatedBytes));
+
+        let mut scanner = Scanner::new("[");
+        assert_eq!(scanner.next_token(), Err(ScanError::UnexpectedChar('[')));
+    }
+
+    #[test]
+    fn test_strings_with_escapes() {
+        let mut scanner = Scanner::new(r#""a\"b\\" x"#);
+        assert_eq!(
+            scanner.next_token(),
+            Ok(TokItem {
+                token: Token::String(r#"a\"b\\"#),
+                position: 0
+            })
+        );
+        assert_eq!(open_depth(r#"("a\")"#), 2);
+        assert_eq!(
+            unescape(r#"tab\t \"q\" \x41\u{e9}\u{1F600}\\"#).unwrap(),
+            "tab\t \"q\" A\u{e9}\u{1F600}\\"
+        );
+        assert!(matches!(unescape("plain"), Ok(Cow::Borrowed("plain"))));
+        let error = |sequence: &str, reason| {
+            Err(EscapeError {
+                offset: 2,
+                sequence: sequence.into(),
+                reason,
+            })
+        };
+        assert_eq!(unescape(r"ab\q"), error(r"\q", "unknown escape"));
+        assert_eq!(
+            unescape(r"ab\x4"),
+            error(r"\x4", "\\x takes two hex digits")
+        );
+        assert_eq!(
+            unescape(r"ab\xff"),
+            error(r"\xff", "\\x only goes up to 7f; use \\u{...} above")
+        );
+        assert_eq!(
+            unescape(r"ab\u{}"),
+            error(r"\u{}", "\\u takes one to six hex digits in braces")
+        );
+        assert_eq!(
+            unescape(r"ab\u{D800}"),
+            error(r"\u{D800}", "not a Unicode scalar value")
+        );
+        assert_eq!(
+            unescape(r"ab\"),
+            error(r"\", "nothing follows the backslash")
+        );
+    }
+
+    #[test]
+    fn test_scanner_accepts_empty_string() {
+        let mut scanner = Scanner::new("\"\"");
+        assert_eq!(
+            scanner.scan_all(),
+            vec![TokItem {
+                token: Token::String(""),
+                position: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_scanner_positions_are_byte_offsets() {
+        let mut scanner = Scanner::new("\"héllo\" x");
+        let result = scanner.scan_all();
+        assert_eq!(result[0].token, Token::String("héllo"));
+        assert_eq!(result[2].position, 9);
+    }
+
+    #[test]
+    fn test_line_column() {
+        let text = "(a\n  b)";
+        assert_eq!(line_column(text, 0), (1, 1));
+        assert_eq!(line_column(text, 5), (2, 3));
+        assert_eq!(line_column(text, 100), (2, 5));
+    }
 }
@@ -0,0 +1,489 @@
+//! Checking jon config files against what the host expects, with errors
+//! pointing into the file. A config is data, read but never evaluated: a
+//! table of `(key value)` entries, whose values may be tables themselves.
+//! The host declares each key's type and, for optional keys, its default,
+//! in Rust:
+//!
+//! ```
+//! use jon::schema::{Schema, Type};
+//!
+//! let schema = Schema::new()
+//!     .required("host", Type::String)
+//!     .optional("port", Type::Integer, 8080.0)
+//!     .optional("tags", Type::List(Box::new(Type::String)), Vec::<String>::new());
+//! let config = schema.validate(r#"((host "localhost") (tags ("web")))"#).unwrap();
+//! assert_eq!(config.to_string(), r#"(("host" "localhost") ("port" 8080) ("tags" ("web")))"#);
+//!
+//! let errors = schema.validate(r#"((host "localhost") (port "80"))"#).unwrap_err();
+//! assert_eq!(errors[0].error.to_string(), "port should be integer, found string");
+//! assert_eq!(errors[0].span, 26..30);
+//! ```
+//!
+//! or in jon itself, with [`Schema::parse`]:
+//!
+//! ```text
+//! ((host string)
+//!  (port integer 8080)
+//!  (tags (list string) ())
+//!  (log (table ((level (one-of debug info warn) info)))))
+//! ```
+//!
+//! Each entry is a key, a type, and a default if the key may be left out.
+//! The types are `any`, `bool`, `number`, `integer`, `string`, `symbol`,
+//! `(list TYPE)`, `(table ENTRIES)`, and `(one-of CHOICE...)`. A validated
+//! config is a map, sorted by key as `alist->map` makes, with the defaults
+//! filled in and the symbols `true`, `false`, and `nil` read as what they
+//! name.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use thiserror::Error;
+
+use crate::diagnostic::{Diagnostic, Level};
+use crate::parser::{Atom, Expr, ParseError, Parser, Span};
+use crate::scanner::{ScanError, Scanner, Token};
+use crate::value::Value;
+
+/// What a key's value must be.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Any,
+    /// `true` or `false`.
+    Bool,
+    Number,
+    /// A number without a fractional part.
+    Integer,
+    String,
+    Symbol,
+    List(Box<Type>),
+    Table(Schema),
+    /// One of the symbols or strings given.
+    OneOf(Vec<String>),
+}
+
+impl fmt::Display for Type {
+    /// The type as it is declared in jon, such as `(list string)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Bool => write!(f, "bool"),
+            Type::Number => write!(f, "number"),
+            Type::Integer => write!(f, "integer"),
+            Type::String => write!(f, "string"),
+            Type::Symbol => write!(f, "symbol"),
+            Type::List(item) => write!(f, "(list {})", item),
+            Type::Table(_) => write!(f, "table"),
+            Type::OneOf(choices) => write!(f, "(one-of {})", choices.join(" ")),
+        }
+    }
+}
+
+/// A key of a table.
+#[derive(Debug, Clone, PartialEq)]
+struct Field {
+    name: String,
+    ty: Type,
+    /// The value it takes when left out, or `None` if it is required.
+    default: Option<Value>,
+}
+
+/// The keys a table may have.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    fields: Vec<Field>,
+}
+
+/// Why a config does not fit its schema. Keys are written as paths, such
+/// as `log.level` or `tags[2]`.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum SchemaError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("Quoting is not needed in a config, which is not evaluated")]
+    Quote,
+    #[error("Expected one table of (key value) entries")]
+    NotTable,
+    #[error("Expected a (key value) entry")]
+    NotEntry,
+    #[error("missing key {0}")]
+    Missing(String),
+    #[error("unknown key {0}")]
+    Unknown(String),
+    #[error("duplicate key {0}")]
+    Duplicate(String),
+    #[error("{key} should be {expected}, found {found}")]
+    Mismatch {
+        key: String,
+        expected: String,
+        found: &'static str,
+    },
+}
+
+impl SchemaError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SchemaError::Parse(err) => err.code(),
+            SchemaError::Quote => "E0201",
+            SchemaError::NotTable => "E0202",
+            SchemaError::NotEntry => "E0203",
+            SchemaError::Missing(_) => "E0204",
+            SchemaError::Unknown(_) => "E0205",
+            SchemaError::Duplicate(_) => "E0206",
+            SchemaError::Mismatch { .. } => "E0207",
+        }
+    }
+}
+
+/// A [`SchemaError`] and the span of the config it is about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub error: SchemaError,
+    pub span: Span,
+}
+
+impl Violation {
+    /// The violation as a diagnostic to render against the config.
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            level: Level::Error,
+            code: self.error.code(),
+            message: self.error.to_string(),
+            span: self.span.clone(),
+            hint: None,
+            expanded_from: Vec::new(),
+        }
+    }
+}
+
+/// A form of the config with the span of it and of each of its items.
+struct Node<'a> {
+    expr: &'a Expr,
+    span: Span,
+    items: Vec<Node<'a>>,
+}
+
+impl Schema {
+    /// A schema with no keys.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the key `name`, which must be given.
+    pub fn required(mut self, name: &str, ty: Type) -> Self {
+        self.fields.push(Field {
+            name: name.into(),
+            ty,
+            default: None,
+        });
+        self
+    }
+
+    /// Adds the key `name`, which is `default` when left out.
+    pub fn optional(mut self, name: &str, ty: Type, default: impl Into<Value>) -> Self {
+        self.fields.push(Field {
+            name: name.into(),
+            ty,
+            default: Some(default.into()),
+        });
+        self
+    }
+
+    /// The schema declared in jon by `source`, as the module docs show.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let forms = Parser::new(&mut Scanner::new(source))
+            .parse_forms()
+            .map_err(|err| err.to_string())?;
+        match forms.as_slice() {
+            [(_, expr)] => Self::from_value(&Value::from(expr)),
+            _ => Err("a schema is one table of (key type [default]) entries".into()),
+        }
+    }
+
+    /// The schema declared by `value`, a list of `(key type [default])`
+    /// entries.
+    pub fn from_value(value: &Value) -> Result<Self, String> {
+        let Value::List(entries) = value else {
+            return Err(format!("expected a list of entries, found {}", value));
+        };
+        let mut schema = Schema::new();
+        for entry in entries {
+            let (name, ty, default) = match entry {
+                Value::List(entry) => match entry.as_slice() {
+                    [Value::Symbol(name) | Value::String(name), ty] => (name, ty, None),
+                    [Value::Symbol(name) | Value::String(name), ty, default] => {
+                        (name, ty, Some(default))
+                    }
+                    _ => return Err(format!("malformed entry {}", Value::List(entry.clone()))),
+                },
+                other => return Err(format!("malformed entry {}", other)),
+            };
+            let ty = parse_type(ty)?;
+            schema = match default {
+                Some(default) => schema.optional(name, ty, data(default)),
+                None => schema.required(name, ty),
+            };
+        }
+        Ok(schema)
+    }
+
+    /// The config `source` as a map with the defaults filled in, or every
+    /// way it does not fit the schema.
+    pub fn validate(&self, source: &str) -> Result<Value, Vec<Violation>> {
+        let violation = |error, span| vec![Violation { error, span }];
+        let forms = Parser::new(&mut Scanner::new(source))
+            .parse_forms()
+            .map_err(|err| {
+                let span = err.span().unwrap_or(source.len()..source.len());
+                violation(SchemaError::Parse(err), span)
+            })?;
+        let [(span, expr)] = forms.as_slice() else {
+            return Err(violation(SchemaError::NotTable, 0..source.len()));
+        };
+        let mut tokens = Vec::new();
+        let mut scanner = Scanner::new(source);
+        loop {
+            match scanner.next_token() {
+                Ok(tok) if tok.token == Token::Quote => {
+                    return Err(violation(
+                        SchemaError::Quote,
+                        tok.position..tok.position + 1,
+                    ))
+                }
+                Ok(tok) if matches!(tok.token, Token::WhiteSpace(_) | Token::Comment(_)) => {}
+                Ok(tok) => tokens.push(tok.span()),
+                Err(ScanError::Eof) => break,
+                Err(_) => return Err(violation(SchemaError::NotTable, span.clone())),
+            }
+        }
+        let node = locate(expr, &mut tokens.into_iter());
+        let mut violations = Vec::new();
+        let config = self.check_table(&node, "", &mut violations);
+        match violations.is_empty() {
+            true => Ok(config),
+            false => Err(violations),
+        }
+    }
+
+    /// The table `node` as a map, noting where it does not fit in
+    /// `violations`. `path` is the key of the table, empty at the top.
+    fn check_table(&self, node: &Node, path: &str, violations: &mut Vec<Violation>) -> Value {
+        let key_path = |key: &str| match path {
+            "" => key.to_string(),
+            _ => format!("{}.{}", path, key),
+        };
+        if !matches!(node.expr, Expr::List(_)) {
+            violations.push(Violation {
+                error: SchemaError::NotTable,
+                span: node.span.clone(),
+            });
+        }
+        let mut entries: Vec<(&str, &Node)> = Vec::new();
+        for entry in &node.items {
+            let [key_node, value] = entry.items.as_slice() else {
+                violations.push(Violation {
+                    error: SchemaError::NotEntry,
+                    span: entry.span.clone(),
+                });
+                continue;
+            };
+            let Expr::Atom(Atom::Symbol(key) | Atom::String(key)) = key_node.expr else {
+                violations.push(Violation {
+                    error: SchemaError::NotEntry,
+                    span: key_node.span.clone(),
+                });
+                continue;
+            };
+            let key = key.as_str();
+            let error = match key {
+                _ if entries.iter().any(|(seen, _)| *seen == key) => {
+                    Some(SchemaError::Duplicate(key_path(key)))
+                }
+                _ if !self.fields.iter().any(|field| field.name == key) => {
+                    Some(SchemaError::Unknown(key_path(key)))
+                }
+                _ => None,
+            };
+            match error {
+                Some(error) => violations.push(Violation {
+                    error,
+                    span: key_node.span.clone(),
+                }),
+                None => entries.push((key, value)),
+            }
+        }
+        let mut map = Vec::new();
+        for field in &self.fields {
+            let value = match entries.iter().find(|(key, _)| *key == field.name) {
+                Some((_, value)) => check(&field.ty, value, &key_path(&field.name), violations),
+                None => match &field.default {
+                    Some(default) => default.clone(),
+                    None => {
+                        violations.push(Violation {
+                            error: SchemaError::Missing(key_path(&field.name)),
+                            span: node.span.clone(),
+                        });
+                        continue;
+                    }
+                },
+            };
+            map.push((field.name.clone(), value));
+        }
+        map.sort_by(|a, b| a.0.cmp(&b.0));
+        Value::List(
+            map.into_iter()
+                .map(|(key, value)| Value::List(vec![Value::String(key), value]))
+                .collect(),
+        )
+    }
+}
+
+/// The value of `node`, which must be a `ty` at `path`, noting where it is
+/// not in `violations`.
+fn check(ty: &Type, node: &Node, path: &str, violations: &mut Vec<Violation>) -> Value {
+    let value = data(&Value::from(node.expr));
+    let fits = match (ty, &value) {
+        (Type::Any, _) => true,
+        (Type::Bool, Value::Bool(_)) => true,
+        (Type::Number, Value::Number(_) | Value::Decimal(_)) => true,
+        (Type::Integer, Value::Number(n)) => *n as i64 as f32 == *n,
+        (Type::String, Value::String(_)) => true,
+        (Type::Symbol, Value::Symbol(_)) => true,
+        (Type::OneOf(choices), Value::Symbol(s) | Value::String(s)) => choices.contains(s),
+        (Type::List(item), Value::List(_)) => {
+            let items =
+                node.items.iter().enumerate().map(|(i, node)| {
+                    check(item, node, &format!("{}[{}]", path, i + 1), violations)
+                });
+            return Value::List(items.collect());
+        }
+        (Type::Table(schema), Value::List(_)) => return schema.check_table(node, path, violations),
+        _ => false,
+    };
+    if !fits {
+        violations.push(Violation {
+            error: SchemaError::Mismatch {
+                key: path.into(),
+                expected: ty.to_string(),
+                found: value.type_name(),
+            },
+            span: node.span.clone(),
+        });
+    }
+    value
+}
+
+/// `value` as data: with the symbols `true`, `false`, and `nil` read as
+/// what they name, as they would be evaluated.
+fn data(value: &Value) -> Value {
+    match value {
+        Value::Symbol(s) if s == "true" => Value::Bool(true),
+        Value::Symbol(s) if s == "false" => Value::Bool(false),
+        Value::Symbol(s) if s == "nil" => Value::Nil,
+        Value::List(items) => Value::List(items.iter().map(data).collect()),
+        other => other.clone(),
+    }
+}
+
+/// The type declared by `value`.
+fn parse_type(value: &Value) -> Result<Type, String> {
+    match value {
+        Value::Symbol(name) => match name.as_str() {
+            "any" => Ok(Type::Any),
+            "bool" => Ok(Type::Bool),
+            "number" => Ok(Type::Number),
+            "integer" => Ok(Type::Integer),
+            "string" => Ok(Type::String),
+            "symbol" => Ok(Type::Symbol),
+            _ => Err(format!("unknown type {}", name)),
+        },
+        Value::List(items) => match items.as_slice() {
+            [Value::Symbol(head), item] if head == "list" => {
+                Ok(Type::List(Box::new(parse_type(item)?)))
+            }
+            [Value::Symbol(head), entries] if head == "table" => {
+                Ok(Type::Table(Schema::from_value(entries)?))
+            }
+            [Value::Symbol(head), choices @ ..] if head == "one-of" => choices
+                .iter()
+                .map(|choice| match choice {
+                    Value::Symbol(s) | Value::String(s) => Ok(s.clone()),
+                    other => Err(format!("one-of takes symbols and strings, not {}", other)),
+                })
+                .collect::<Result<_, _>>()
+                .map(Type::OneOf),
+            _ => Err(format!("unknown type {}", value)),
+        },
+        other => Err(format!("unknown type {}", other)),
+    }
+}
+
+/// `expr` with its spans, taken in order from `tokens`, the spans of the
+/// tokens it was parsed from.
+fn locate<'a>(expr: &'a Expr, tokens: &mut impl Iterator<Item = Span>) -> Node<'a> {
+    let first = tokens.next().unwrap_or_default();
+    match expr {
+        Expr::Atom(_) => Node {
+            expr,
+            span: first,
+            items: Vec::new(),
+        },
+        Expr::List(list) => {
+            let items: Vec<Node> = list.iter().map(|item| locate(item, tokens)).collect();
+            let last = tokens.next().unwrap_or_default();
+            Node {
+                expr,
+                span: first.start..last.end,
+                items,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_against_jon_schema() {
+        let schema = Schema::parse(
+            "((host string) (port integer 8080) (debug bool false)
+              (log (table ((level (one-of debug info warn) info))) ()))",
+        )
+        .unwrap();
+        let config = schema
+            .validate("((host \"a\") (debug true) (log ((level warn))))")
+            .unwrap();
+        assert_eq!(
+            config.to_string(),
+            "((\"debug\" true) (\"host\" \"a\") (\"log\" ((\"level\" warn))) (\"port\" 8080))"
+        );
+        let source = "((port 80.5) (log ((level loud) (color 1))) (port 1))";
+        let errors: Vec<(String, &str)> = schema
+            .validate(source)
+            .unwrap_err()
+            .into_iter()
+            .map(|violation| (violation.error.to_string(), &source[violation.span]))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("duplicate key port".to_string(), "port"),
+                ("missing key host".into(), source),
+                ("port should be integer, found number".into(), "80.5"),
+                ("unknown key log.color".into(), "color"),
+                (
+                    "log.level should be (one-of debug info warn), found symbol".into(),
+                    "loud"
+                ),
+            ]
+        );
+        let quoted = schema.validate("'((host \"a\"))").unwrap_err();
+        assert_eq!(quoted[0].diagnostic().code, "E0201");
+        assert!(Schema::parse("((port int))").is_err());
+    }
+}
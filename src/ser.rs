@@ -0,0 +1,468 @@
+//! Serializing Rust data as jon s-expressions, for use with serde.
+//!
+//! Sequences and tuples become lists, structs and maps association lists of
+//! `(key value)` pairs, and enum variants a symbol or a list headed by one:
+//!
+//! ```
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! enum Shape {
+//!     Point,
+//!     Circle { radius: f32 },
+//! }
+//!
+//! #[derive(Serialize)]
+//! struct Scene {
+//!     name: String,
+//!     shapes: Vec<Shape>,
+//!     parent: Option<String>,
+//! }
+//!
+//! let scene = Scene {
+//!     name: "demo".into(),
+//!     shapes: vec![Shape::Point, Shape::Circle { radius: 1.5 }],
+//!     parent: None,
+//! };
+//! assert_eq!(
+//!     jon::to_string(&scene).unwrap(),
+//!     r#"((name "demo") (shapes (Point (Circle (radius 1.5)))) (parent nil))"#
+//! );
+//! ```
+
+use alloc::string::{String, ToString};
+use core::fmt::Display;
+
+use serde::ser::{self, Serialize};
+
+use crate::error::DataError;
+#[cfg(feature = "std")]
+use crate::formatter;
+use crate::scanner::is_identifier;
+
+/// Writes `value` as a single jon form on one line.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, DataError> {
+    let mut serializer = Serializer::default();
+    value.serialize(&mut serializer)?;
+    Ok(serializer.output)
+}
+
+/// Writes `value` laid out by [`formatter`], as `jon fmt` would.
+#[cfg(feature = "std")]
+pub fn to_string_pretty<T: Serialize + ?Sized>(value: &T) -> Result<String, DataError> {
+    let text = to_string(value)?;
+    formatter::format_source(&text, formatter::DEFAULT_WIDTH)
+        .map_err(|err| DataError::Message(err.to_string()))
+}
+
+#[derive(Default)]
+pub struct Serializer {
+    output: String,
+}
+
+impl Serializer {
+    /// The output so far.
+    pub fn into_inner(self) -> String {
+        self.output
+    }
+
+    fn write(&mut self, text: impl Display) {
+        self.output.push_str(&text.to_string());
+    }
+
+    /// Writes a field or variant name as a symbol, or as a string when it
+    /// would not read back as that symbol.
+    fn write_name(&mut self, name: &str) -> Result<(), DataError> {
+        let symbol = name.starts_with(|c: char| !c.is_ascii_digit() && c != '-')
+            && name.chars().all(is_identifier)
+            && !matches!(name, "nil" | "true" | "false");
+        if symbol {
+            self.write(name);
+            Ok(())
+        } else {
+            ser::Serializer::serialize_str(self, name)
+        }
+    }
+
+    /// Writes a float in its own shortest form that reads back the same.
+    fn write_float(&mut self, n: impl Display, finite: bool) -> Result<(), DataError> {
+        if !finite {
+            return Err(DataError::Unsupported("infinite or NaN numbers"));
+        }
+        self.write(n);
+        Ok(())
+    }
+
+    /// Opens a list, headed by `variant` when it is one.
+    fn open(&mut self, variant: Option<&str>) -> Result<Compound<'_>, DataError> {
+        self.output.push('(');
+        if let Some(variant) = variant {
+            self.write_name(variant)?;
+        }
+        Ok(Compound {
+            first: variant.is_none(),
+            serializer: self,
+        })
+    }
+}
+
+/// A list being written, element by element.
+pub struct Compound<'a> {
+    serializer: &'a mut Serializer,
+    first: bool,
+}
+
+impl Compound<'_> {
+    fn separate(&mut self) {
+        if !self.first {
+            self.serializer.output.push(' ');
+        }
+        self.first = false;
+    }
+
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.separate();
+        value.serialize(&mut *self.serializer)
+    }
+
+    /// Writes a `(name value)` pair.
+    fn field<T: Serialize + ?Sized>(&mut self, name: &str, value: &T) -> Result<(), DataError> {
+        self.separate();
+        self.serializer.output.push('(');
+        self.serializer.write_name(name)?;
+        self.serializer.output.push(' ');
+        value.serialize(&mut *self.serializer)?;
+        self.serializer.output.push(')');
+        Ok(())
+    }
+
+    fn close(self) -> Result<(), DataError> {
+        self.serializer.output.push(')');
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = DataError;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), DataError> {
+        self.write(v);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), DataError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), DataError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), DataError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), DataError> {
+        self.write(v);
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), DataError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), DataError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), DataError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), DataError> {
+        self.write(v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), DataError> {
+        self.write_float(v, v.is_finite())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), DataError> {
+        self.write_float(v, v.is_finite())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), DataError> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Quotes and backslashes are escaped, as are control characters.
+    fn serialize_str(self, v: &str) -> Result<(), DataError> {
+        self.write(format_args!("{:?}", v));
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), DataError> {
+        let mut list = self.open(None)?;
+        for byte in v {
+            list.element(byte)?;
+        }
+        list.close()
+    }
+
+    fn serialize_none(self) -> Result<(), DataError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), DataError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), DataError> {
+        self.write("nil");
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), DataError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<(), DataError> {
+        self.write_name(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), DataError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), DataError> {
+        let mut list = self.open(Some(variant))?;
+        list.element(value)?;
+        list.close()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>, DataError> {
+        self.open(None)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Compound<'a>, DataError> {
+        self.open(None)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, DataError> {
+        self.open(None)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, DataError> {
+        self.open(Some(variant))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a>, DataError> {
+        self.open(None)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound<'a>, DataError> {
+        self.open(None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, DataError> {
+        self.open(Some(variant))
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = DataError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), DataError> {
+        self.close()
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = DataError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), DataError> {
+        self.close()
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), DataError> {
+        self.close()
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), DataError> {
+        self.close()
+    }
+}
+
+/// Each entry is written as a `(key value)` list.
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = ();
+    type Error = DataError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), DataError> {
+        self.separate();
+        self.serializer.output.push('(');
+        key.serialize(&mut *self.serializer)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.serializer.output.push(' ');
+        value.serialize(&mut *self.serializer)?;
+        self.serializer.output.push(')');
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), DataError> {
+        self.close()
+    }
+}
+
+impl ser::SerializeStruct for Compound<'_> {
+    type Ok = ();
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), DataError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), DataError> {
+        self.close()
+    }
+}
+
+impl ser::SerializeStructVariant for Compound<'_> {
+    type Ok = ();
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), DataError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), DataError> {
+        self.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Point(i32, i32);
+
+    #[derive(Serialize)]
+    enum Event {
+        Quit,
+        Move(Point),
+        Key(char, bool),
+        #[serde(rename = "set value")]
+        Set {
+            key: String,
+        },
+    }
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(to_string(&Some(-0.25)).unwrap(), "-0.25");
+        assert_eq!(to_string(&(1_u8, "a", ())).unwrap(), "(1 \"a\" nil)");
+        assert_eq!(
+            to_string(&[
+                Event::Quit,
+                Event::Move(Point(1, 2)),
+                Event::Key('x', true),
+                Event::Set { key: "k".into() },
+            ])
+            .unwrap(),
+            "(Quit (Move (1 2)) (Key \"x\" true) (\"set value\" (key \"k\")))"
+        );
+        let map = BTreeMap::from([("b", vec![1]), ("a", vec![])]);
+        assert_eq!(to_string(&map).unwrap(), "((\"a\" ()) (\"b\" (1)))");
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert_eq!(
+            to_string(&f64::NAN),
+            Err(DataError::Unsupported("infinite or NaN numbers"))
+        );
+    }
+}
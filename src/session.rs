@@ -0,0 +1,128 @@
+use std::fmt::Write;
+
+use crate::eval::{Interpreter, Origin};
+use crate::value::{Lambda, Value};
+
+/// The REPL result history, which belongs to the session that made it.
+const SKIPPED: &[&str] = &["*1", "*2", "*3", "*e"];
+
+/// The globals of `interpreter` that a fresh interpreter would not have,
+/// written as jon source that redefines them when loaded. Closures and
+/// values that cannot be read back are listed in comments instead.
+pub fn save(interpreter: &Interpreter) -> String {
+    let fresh = Interpreter::bare();
+    let mut out = String::from("; jon session\n");
+    for (name, value) in interpreter.globals() {
+        let origin = interpreter.doc(&name).and_then(|doc| doc.origin);
+        if SKIPPED.contains(&name.as_str())
+            || origin == Some(Origin::Prelude)
+            || fresh.get_global(&name).as_ref() == Some(&value)
+        {
+            continue;
+        }
+        match &value {
+            Value::Lambda(lambda) | Value::Macro(lambda) => {
+                if !interpreter.is_global(&lambda.env) || !lambda.body.iter().all(readable) {
+                    let _ = writeln!(out, "; skipped {}: a closure cannot be saved", name);
+                    continue;
+                }
+                let keyword = match value {
+                    Value::Macro(_) => "defmacro",
+                    _ => "def",
+                };
+                let _ = writeln!(out, "({} {})", keyword, definition(&name, lambda));
+            }
+            value if readable(value) => {
+                let _ = writeln!(out, "(def {} {})", name, literal(value));
+            }
+            _ => {
+                let _ = writeln!(out, "; skipped {}: {} cannot be saved", name, value);
+            }
+        }
+    }
+    out
+}
+
+/// `(name params...) body...` for a `def` or `defmacro` of `lambda`.
+fn definition(name: &str, lambda: &Lambda) -> String {
+    let mut signature = vec![name.to_string()];
+    signature.extend(lambda.params.iter().cloned());
+    if let Some(rest) = &lambda.rest {
+        signature.extend(["&".to_string(), rest.clone()]);
+    }
+    let mut text = format!("({})", signature.join(" "));
+    for form in &lambda.body {
+        let _ = write!(text, " {}", form);
+    }
+    text
+}
+
+/// Source text that evaluates to `value`.
+fn literal(value: &Value) -> String {
+    match value {
+        Value::Symbol(_) | Value::List(_) => format!("'{}", value),
+        other => other.to_string(),
+    }
+}
+
+/// Whether the printed form of `value` reads back as the same value.
+fn readable(value: &Value) -> bool {
+    match value {
+        Value::Nil | Value::Bool(_) | Value::Symbol(_) | Value::Decimal(_) | Value::Bytes(_) => {
+            true
+        }
+        Value::Number(n) => n.is_finite(),
+        Value::Complex(c) => c.re.is_finite() && c.im.is_finite(),
+        // strings have no escapes
+        Value::String(s) => !s.contains(['"', '\\']) && !s.contains(char::is_control),
+        Value::List(list) => list.iter().all(readable),
+        Value::Builtin(_)
+        | Value::Lambda(_)
+        | Value::Macro(_)
+        | Value::Host(_)
+        | Value::Actor(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn eval_str(interpreter: &mut Interpreter, text: &str) {
+        let mut scanner = Scanner::new(text);
+        let mut parser = Parser::new(&mut scanner);
+        for expr in parser.parse_program().unwrap() {
+            interpreter.eval(&expr).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_save_round_trips() {
+        let mut interpreter = Interpreter::new();
+        eval_str(
+            &mut interpreter,
+            "(def xs '(1 \"a\" b)) (def (f x & more) \"Doc.\" (cons x more))
+             (defmacro (twice e) (list 'do e e)) (def g (let ((n 1)) (lambda () n)))",
+        );
+        let saved = save(&interpreter);
+        assert_eq!(
+            saved,
+            "; jon session
+(def (f x & more) \"Doc.\" (cons x more))
+; skipped g: a closure cannot be saved
+(defmacro (twice e) (list (quote do) e e))
+(def xs '(1 \"a\" b))
+"
+        );
+
+        let mut restored = Interpreter::new();
+        eval_str(&mut restored, &saved);
+        assert_eq!(restored.get_global("xs"), interpreter.get_global("xs"));
+        assert_eq!(
+            save(&restored),
+            saved.replace("; skipped g: a closure cannot be saved\n", "")
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Running the tests a jon file defines, for `jon test`.
+//!
+//! `deftest` adds a name and a function of no arguments to the global
+//! `*tests*`, newest first. A test passes if calling its function returns
+//! and fails if it raises; `is` and `assert-equal` raise
+//! `assertion-failed` with what they checked, so the failure can show it.
+
+use std::fmt;
+
+use crate::eval::{EvalError, Interpreter};
+use crate::value::Value;
+
+/// How one test went.
+#[derive(Debug, PartialEq)]
+pub struct Outcome {
+    pub name: String,
+    /// `None` if the test passed.
+    pub failure: Option<Failure>,
+}
+
+/// Why a test failed.
+#[derive(Debug, PartialEq)]
+pub enum Failure {
+    /// `assert-equal`, or `is` on an `=` form, was given different values.
+    NotEqual { expected: Value, actual: Value },
+    /// `is` found `form` false.
+    False { form: Value },
+    /// The test raised some other condition.
+    Error(EvalError),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::NotEqual { expected, actual } => {
+                write!(f, "expected: {}\n  actual: {}", expected, actual)?;
+                if let Some((path, expected, actual)) = difference(expected, actual, Vec::new()) {
+                    if !path.is_empty() {
+                        let path: Vec<String> = path.iter().map(ToString::to_string).collect();
+                        write!(
+                            f,
+                            "\n  first difference at index {}: {} != {}",
+                            path.join("."),
+                            expected,
+                            actual
+                        )?;
+                    }
+                }
+                Ok(())
+            }
+            Failure::False { form } => write!(f, "{} is false", form),
+            Failure::Error(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// The indices into nested lists of the first place `expected` and
+/// `actual` differ, with the values there, or `None` if they are equal.
+fn difference<'a>(
+    expected: &'a Value,
+    actual: &'a Value,
+    mut path: Vec<usize>,
+) -> Option<(Vec<usize>, &'a Value, &'a Value)> {
+    if expected == actual {
+        return None;
+    }
+    if let (Value::List(expected_items), Value::List(actual_items)) = (expected, actual) {
+        if let Some(i) = (0..expected_items.len().min(actual_items.len()))
+            .find(|&i| expected_items[i] != actual_items[i])
+        {
+            path.push(i);
+            return difference(&expected_items[i], &actual_items[i], path);
+        }
+    }
+    Some((path, expected, actual))
+}
+
+/// Loads the file at `path` into `interpreter` and runs the tests it
+/// defines, in the order they are defined. Fails if the file does.
+pub fn run_file(interpreter: &mut Interpreter, path: &str) -> Result<Vec<Outcome>, EvalError> {
+    interpreter.load_file(path)?;
+    Ok(run(interpreter))
+}
+
+/// Runs the tests defined in `interpreter`, in the order they were
+/// defined.
+pub fn run(interpreter: &mut Interpreter) -> Vec<Outcome> {
+    let tests = match interpreter.get_global("*tests*") {
+        Some(Value::List(tests)) => tests,
+        _ => return Vec::new(),
+    };
+    tests
+        .iter()
+        .rev()
+        .filter_map(|test| match test {
+            Value::List(test) => match test.as_slice() {
+                [name, body] => Some((name.to_string(), body.clone())),
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|(name, body)| {
+            let failure = interpreter
+                .apply(&body, Vec::new())
+                .err()
+                .map(|err| failure(interpreter, err));
+            Outcome { name, failure }
+        })
+        .collect()
+}
+
+/// The failure for `err`, with what was checked if an assertion raised it.
+fn failure(interpreter: &mut Interpreter, err: EvalError) -> Failure {
+    if err.condition_type() != Some("assertion-failed") {
+        return Failure::Error(err);
+    }
+    let condition = interpreter.caught(&err);
+    let data = field(&condition, ":data");
+    match (
+        field(&data, ":expected"),
+        field(&data, ":actual"),
+        field(&data, ":form"),
+    ) {
+        (Value::Nil, Value::Nil, Value::Nil) => Failure::Error(err),
+        (_, _, Value::Nil) => Failure::NotEqual {
+            expected: field(&data, ":expected"),
+            actual: field(&data, ":actual"),
+        },
+        (_, _, form) => Failure::False { form },
+    }
+}
+
+/// The value of `key` in the alist `alist`, or nil.
+fn field(alist: &Value, key: &str) -> Value {
+    let Value::List(pairs) = alist else {
+        return Value::Nil;
+    };
+    pairs
+        .iter()
+        .find_map(|pair| match pair {
+            Value::List(pair) => match pair.as_slice() {
+                [Value::Symbol(name), value] if name == key => Some(value.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .unwrap_or(Value::Nil)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_tests() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval_str(
+                "(deftest adds (is (= 4 (+ 2 2))))
+                 (deftest lists (assert-equal '(1 (2 3)) (list 1 (list 2 4))))
+                 (deftest compares (is (< 2 1)))
+                 (deftest breaks (car 1))",
+            )
+            .unwrap();
+        let outcomes = run(&mut interpreter);
+        let names: Vec<_> = outcomes
+            .iter()
+            .map(|outcome| outcome.name.as_str())
+            .collect();
+        assert_eq!(names, ["adds", "lists", "compares", "breaks"]);
+        assert_eq!(outcomes[0].failure, None);
+        assert_eq!(
+            outcomes[1]
+                .failure
+                .as_ref()
+                .map(ToString::to_string)
+                .as_deref(),
+            Some(
+                "expected: (1 (2 3))\n  actual: (1 (2 4))\n  first difference at index 1.1: 3 != 4"
+            )
+        );
+        assert_eq!(
+            outcomes[2]
+                .failure
+                .as_ref()
+                .map(ToString::to_string)
+                .as_deref(),
+            Some("(< 2 1) is false")
+        );
+        assert!(matches!(outcomes[3].failure, Some(Failure::Error(_))));
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::{self, Write};
+
+use crate::builtins;
+use crate::eval::{EvalError, Hook, Interpreter};
+use crate::value::Value;
+
+/// How many characters of an argument or result a trace line shows.
+const PREVIEW_WIDTH: usize = 40;
+
+/// A hook that logs calls to the functions marked with `(trace f)`, or to
+/// every function with `--trace`, indented by how many traced calls are
+/// still running.
+pub struct Tracer<W> {
+    all: bool,
+    /// For each call being evaluated, whether it is traced.
+    calls: Vec<bool>,
+    output: W,
+}
+
+impl Tracer<io::Stderr> {
+    pub fn stderr(all: bool) -> Self {
+        Self::new(io::stderr(), all)
+    }
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(output: W, all: bool) -> Self {
+        Self {
+            all,
+            calls: Vec::new(),
+            output,
+        }
+    }
+
+    fn indent(&self) -> String {
+        "  ".repeat(self.calls.iter().filter(|traced| **traced).count())
+    }
+}
+
+impl<W: Write> Hook for Tracer<W> {
+    fn enter_call(&mut self, interpreter: &mut Interpreter, callee: &Value, args: &[Value]) {
+        let name = builtins::function_name(callee).unwrap_or_default();
+        let traced =
+            interpreter.is_traced(&name) || (self.all && matches!(callee, Value::Lambda(_)));
+        if traced {
+            let mut call = vec![name];
+            call.extend(args.iter().map(|arg| arg.preview(PREVIEW_WIDTH)));
+            let _ = writeln!(self.output, "{}({})", self.indent(), call.join(" "));
+        }
+        self.calls.push(traced);
+    }
+
+    fn exit_call(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _callee: &Value,
+        result: &Result<Value, EvalError>,
+    ) {
+        if self.calls.pop() != Some(true) {
+            return;
+        }
+        let _ = match result {
+            Ok(value) => writeln!(
+                self.output,
+                "{}=> {}",
+                self.indent(),
+                value.preview(PREVIEW_WIDTH)
+            ),
+            Err(err) => writeln!(self.output, "{}=> error: {}", self.indent(), err),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(program: &str, all: bool) -> String {
+        let output = Shared::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.add_hook(Box::new(Tracer::new(output.clone(), all)));
+        let mut scanner = Scanner::new(program);
+        let mut parser = Parser::new(&mut scanner);
+        for expr in parser.parse_program().unwrap() {
+            let _ = interpreter.eval(&expr);
+        }
+        let text = String::from_utf8(output.0.borrow().clone()).unwrap();
+        text
+    }
+
+    #[test]
+    fn test_trace_marked_function() {
+        assert_eq!(
+            trace(
+                "(def (fact n) (if (<= n 1) 1 (* n (fact (- n 1))))) (trace fact) (fact 3)",
+                false
+            ),
+            "(fact 3)
+  (fact 2)
+    (fact 1)
+    => 1
+  => 2
+=> 6
+"
+        );
+    }
+
+    #[test]
+    fn test_trace_all_and_errors() {
+        assert_eq!(
+            trace("(def (f x) (car x)) (f 1)", true),
+            "(f 1)\n=> error: Expected list, found number\n"
+        );
+        assert_eq!(trace("(car '(1))", true), "");
+    }
+}
@@ -0,0 +1,338 @@
+use alloc::{
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{any::Any, cell::RefCell, fmt};
+
+use crate::complex::Complex;
+use crate::decimal::Decimal;
+use crate::env::Env;
+use crate::eval::{EvalError, Interpreter};
+use crate::parser::{Atom, BorrowedAtom, BorrowedExpr, Expr};
+
+pub type NativeFn = fn(&mut Interpreter, Vec<Value>) -> Result<Value, EvalError>;
+
+/// A function implemented by the host, which may capture state. It must be
+/// shareable across threads, as [`Interpreter::clone_isolated`] copies
+/// share it.
+pub type HostFn = dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Value, EvalError> + Send + Sync;
+
+#[derive(Clone)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: Arc<HostFn>,
+    /// Whether a call only computes a result from its arguments, so that
+    /// `pmap` may make it on another thread.
+    pub pure: bool,
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#<builtin {}>", self.name)
+    }
+}
+
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+/// An opaque value the host made, which scripts can pass around, compare
+/// by identity, and hand back to host functions. Protocols dispatch on its
+/// type name, which `type-of` returns.
+#[derive(Clone)]
+pub struct HostValue {
+    type_name: &'static str,
+    value: Arc<dyn Any + Send + Sync>,
+    close: Option<Arc<Closer>>,
+}
+
+/// Releases a host resource, given the value it was made with.
+type Closer = dyn Fn(&(dyn Any + Send + Sync)) -> Result<(), EvalError> + Send + Sync;
+
+impl HostValue {
+    pub fn new<T: Any + Send + Sync>(type_name: &'static str, value: T) -> Self {
+        Self {
+            type_name,
+            value: Arc::new(value),
+            close: None,
+        }
+    }
+
+    /// A host value for a resource, such as a file or socket, that
+    /// `close` and `with-open` release by calling `close` on it.
+    pub fn closeable<T, F>(type_name: &'static str, value: T, close: F) -> Self
+    where
+        T: Any + Send + Sync,
+        F: Fn(&T) -> Result<(), EvalError> + Send + Sync + 'static,
+    {
+        let close = move |value: &(dyn Any + Send + Sync)| match value.downcast_ref() {
+            Some(value) => close(value),
+            None => Ok(()),
+        };
+        Self {
+            close: Some(Arc::new(close)),
+            ..Self::new(type_name, value)
+        }
+    }
+
+    /// Releases the resource, failing if the host made the value without
+    /// a way to close it.
+    pub fn close(&self) -> Result<(), EvalError> {
+        match &self.close {
+            Some(close) => close(&*self.value),
+            None => Err(EvalError::Host(format!("{:?} cannot be closed", self))),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// The value the host made, if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+
+impl fmt::Debug for HostValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#<host {}>", self.type_name)
+    }
+}
+
+impl PartialEq for HostValue {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.value, &other.value)
+    }
+}
+
+/// A user defined function or macro, closing over the environment it was
+/// created in.
+pub struct Lambda {
+    pub name: Option<String>,
+    pub params: Vec<String>,
+    pub rest: Option<String>,
+    pub body: Vec<Value>,
+    pub env: Rc<RefCell<Env>>,
+}
+
+impl Lambda {
+    /// The parameters as they were written, with `&` before the rest
+    /// parameter if there is one.
+    pub fn param_list(&self) -> Vec<Value> {
+        let mut params: Vec<Value> = self.params.iter().cloned().map(Value::Symbol).collect();
+        if let Some(rest) = &self.rest {
+            params.extend([Value::Symbol("&".into()), Value::Symbol(rest.clone())]);
+        }
+        params
+    }
+}
+
+impl fmt::Debug for Lambda {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "#<lambda {}>", name),
+            None => write!(f, "#<lambda>"),
+        }
+    }
+}
+
+/// A jon value. Numbers, booleans and nil are stored inline rather than
+/// NaN-boxed into one word: the tree-walking evaluator matches on these
+/// variants throughout, so a compact representation waits for a bytecode VM
+/// whose inner loop would gain from it.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f32),
+    Decimal(Decimal),
+    Complex(Complex),
+    String(String),
+    Bytes(Vec<u8>),
+    Symbol(String),
+    List(Vec<Value>),
+    Builtin(Builtin),
+    Lambda(Rc<Lambda>),
+    Macro(Rc<Lambda>),
+    Host(HostValue),
+    #[cfg(feature = "std")]
+    Actor(crate::actor::Actor),
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Decimal(a), Value::Decimal(b)) => a == b,
+            (Value::Complex(a), Value::Complex(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Symbol(a), Value::Symbol(b)) => a == b,
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Builtin(a), Value::Builtin(b)) => a == b,
+            (Value::Lambda(a), Value::Lambda(b)) => Rc::ptr_eq(a, b),
+            (Value::Macro(a), Value::Macro(b)) => Rc::ptr_eq(a, b),
+            (Value::Host(a), Value::Host(b)) => a == b,
+            #[cfg(feature = "std")]
+            (Value::Actor(a), Value::Actor(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Number(_) => "number",
+            Value::Decimal(_) => "decimal",
+            Value::Complex(_) => "complex",
+            Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Symbol(_) => "symbol",
+            Value::List(_) => "list",
+            Value::Builtin(_) | Value::Lambda(_) => "function",
+            Value::Macro(_) => "macro",
+            Value::Host(host) => host.type_name(),
+            #[cfg(feature = "std")]
+            Value::Actor(_) => "actor",
+        }
+    }
+
+    /// The printed form of the value, cut to at most `width` characters
+    /// and ending in `...` when cut. Long lists are not printed in full.
+    pub fn preview(&self, width: usize) -> String {
+        let text = match self {
+            Value::List(list) => {
+                let mut text = String::from("(");
+                for (i, item) in list.iter().enumerate() {
+                    if i > 0 {
+                        text.push(' ');
+                    }
+                    let room = width.saturating_sub(text.chars().count());
+                    text.push_str(&item.preview(room));
+                    if text.chars().count() >= width {
+                        break;
+                    }
+                }
+                text.push(')');
+                text
+            }
+            other => other.to_string(),
+        };
+        if text.chars().count() <= width {
+            return text;
+        }
+        let mut cut: String = text.chars().take(width.saturating_sub(3)).collect();
+        cut.push_str("...");
+        cut
+    }
+}
+
+impl From<&Expr> for Value {
+    fn from(expr: &Expr) -> Self {
+        match expr {
+            Expr::Atom(Atom::Symbol(s)) => Value::Symbol(s.clone()),
+            Expr::Atom(Atom::Number(n)) => Value::Number(*n),
+            Expr::Atom(Atom::Decimal(d)) => Value::Decimal(d.clone()),
+            Expr::Atom(Atom::Complex(c)) => Value::Complex(*c),
+            Expr::Atom(Atom::String(s)) => Value::String(s.clone()),
+            Expr::Atom(Atom::Bytes(bytes)) => Value::Bytes(bytes.clone()),
+            Expr::List(list) => Value::List(list.iter().map(Value::from).collect()),
+        }
+    }
+}
+
+impl From<BorrowedExpr<'_>> for Value {
+    fn from(expr: BorrowedExpr<'_>) -> Self {
+        match expr {
+            BorrowedExpr::Atom(BorrowedAtom::Symbol(s)) => Value::Symbol(s.into()),
+            BorrowedExpr::Atom(BorrowedAtom::Number(n)) => Value::Number(n),
+            BorrowedExpr::Atom(BorrowedAtom::Decimal(d)) => Value::Decimal(d),
+            BorrowedExpr::Atom(BorrowedAtom::Complex(c)) => Value::Complex(c),
+            BorrowedExpr::Atom(BorrowedAtom::Bytes(bytes)) => Value::Bytes(bytes),
+            BorrowedExpr::Atom(BorrowedAtom::String(s)) => Value::String(s.into_owned()),
+            BorrowedExpr::List(list) => Value::List(list.into_iter().map(Value::from).collect()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Decimal(d) => write!(f, "{}m", d),
+            Value::Complex(c) => write!(f, "{}", c),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Bytes(bytes) => {
+                write!(f, "#u8(")?;
+                for (i, byte) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", byte)?;
+                }
+                write!(f, ")")
+            }
+            Value::Symbol(s) => write!(f, "{}", s),
+            Value::List(list) => {
+                write!(f, "(")?;
+                for (i, item) in list.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
+            Value::Builtin(builtin) => write!(f, "{:?}", builtin),
+            Value::Lambda(lambda) => write!(f, "{:?}", lambda),
+            Value::Macro(lambda) => match &lambda.name {
+                Some(name) => write!(f, "#<macro {}>", name),
+                None => write!(f, "#<macro>"),
+            },
+            Value::Host(host) => write!(f, "{:?}", host),
+            #[cfg(feature = "std")]
+            Value::Actor(actor) => write!(f, "{:?}", actor),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview() {
+        let list = Value::List((0..100).map(|n| Value::Number(n as f32)).collect());
+        assert_eq!(list.preview(12), "(0 1 2 3 ...");
+        assert_eq!(Value::String("abc".into()).preview(12), "\"abc\"");
+    }
+
+    #[test]
+    fn test_display() {
+        let list = Value::List(vec![
+            Value::Number(3.0),
+            Value::Number(0.5),
+            Value::String("abc".into()),
+            Value::Nil,
+            Value::List(vec![]),
+        ]);
+        assert_eq!(list.to_string(), "(3 0.5 \"abc\" nil ())");
+    }
+}
@@ -0,0 +1,84 @@
+//! JavaScript bindings for a browser playground, built with the `wasm`
+//! feature for `wasm32-unknown-unknown`:
+//!
+//! ```text
+//! cargo rustc --lib --release --target wasm32-unknown-unknown \
+//!     --no-default-features --features wasm --crate-type cdylib
+//! wasm-bindgen --target web target/wasm32-unknown-unknown/release/jon.wasm --out-dir pkg
+//! ```
+//!
+//! There is no terminal in a browser: `print` output is collected until
+//! `takeOutput` is called, and `read-line` always sees the end of input.
+
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::eval::Interpreter;
+
+/// Output collected from `print`.
+#[derive(Clone, Default)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[wasm_bindgen(js_name = Interpreter)]
+pub struct JsInterpreter {
+    interpreter: Interpreter,
+    output: Buffer,
+}
+
+impl Default for JsInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen(js_class = Interpreter)]
+impl JsInterpreter {
+    /// An interpreter with the prelude loaded.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        let output = Buffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(output.clone());
+        interpreter.set_input(io::empty());
+        Self {
+            interpreter,
+            output,
+        }
+    }
+
+    /// Evaluates every form of `source` and returns the printed value of
+    /// the last one. A failure is thrown as an `Error` whose message is the
+    /// diagnostic rendered against `source`.
+    pub fn eval(&mut self, source: &str) -> Result<String, JsError> {
+        match self.interpreter.eval_source(source) {
+            Ok(value) => Ok(value.to_string()),
+            Err(diagnostic) => Err(JsError::new(&diagnostic.render("<input>", source, false))),
+        }
+    }
+
+    /// What `print` has written since the last call.
+    #[wasm_bindgen(js_name = takeOutput)]
+    pub fn take_output(&mut self) -> String {
+        let bytes = std::mem::take(&mut *self.output.0.borrow_mut());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+/// Evaluates `source` in a fresh interpreter, like [`JsInterpreter::eval`].
+#[wasm_bindgen(js_name = evalStr)]
+pub fn eval_str(source: &str) -> Result<String, JsError> {
+    JsInterpreter::new().eval(source)
+}
@@ -0,0 +1,27 @@
+#![cfg(feature = "repl")]
+
+use std::process::{Command, Output};
+
+const DOWN: &str = "(def (down n) (+ 1 (down (- n 1))))";
+
+fn jon(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jon"))
+        .args(args)
+        .output()
+        .expect("failed to run jon")
+}
+
+#[test]
+fn test_runaway_recursion_is_a_condition() {
+    let caught = format!(
+        "{} (handler-case (down 0) ((stack-overflow e) 'caught))",
+        DOWN
+    );
+    let output = jon(&["eval", &caught]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "caught");
+
+    let output = jon(&["eval", &format!("{} (down 0)", DOWN)]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("E0113"));
+}